@@ -0,0 +1,255 @@
+//! First-fit heap backed by an address ordered free list
+
+use core::alloc::Layout;
+use core::mem::{align_of, size_of};
+use core::ptr::{self, NonNull};
+
+/// Header stored at the start of every free block
+struct Hole {
+    size: usize,
+    next: Option<NonNull<Hole>>,
+}
+
+/// Smallest block the heap hands out; a freed block must be able to hold a `Hole`
+const HOLE_SIZE: usize = size_of::<Hole>();
+
+/// Every block starts on, and is a multiple of, this many bytes
+///
+/// `Hole` is 2-byte aligned on the 68000, which is also the alignment word and long accesses
+/// need, so even `u8` allocations end up on an even address.
+const UNIT: usize = if align_of::<Hole>() > 2 {
+    align_of::<Hole>()
+} else {
+    2
+};
+
+/// A heap over a single contiguous region of memory
+///
+/// This is not thread safe by itself; [`LockedHeap`](crate::LockedHeap) wraps it in a critical
+/// section for use as the global allocator.
+pub struct Heap {
+    head: Option<NonNull<Hole>>,
+    size: usize,
+    used: usize,
+}
+
+unsafe impl Send for Heap {}
+
+impl Heap {
+    /// Creates a heap with no memory
+    ///
+    /// Every allocation fails until [`init`](Heap::init) is called.
+    #[inline]
+    pub const fn empty() -> Heap {
+        Heap {
+            head: None,
+            size: 0,
+            used: 0,
+        }
+    }
+
+    /// Gives the heap the `size` bytes starting at `start`
+    ///
+    /// The region is shrunk to start and end on an even address. Any memory previously given to
+    /// the heap is forgotten.
+    ///
+    /// # Safety
+    ///
+    /// The region must be valid for reads and writes, must not be used for anything else for as
+    /// long as the heap is, and must not wrap around the end of the address space.
+    pub unsafe fn init(&mut self, start: usize, size: usize) {
+        let end = align_down(start + size, UNIT);
+        let start = align_up(start, UNIT);
+
+        self.head = None;
+        self.size = 0;
+        self.used = 0;
+
+        if end > start && end - start >= HOLE_SIZE {
+            let hole = start as *mut Hole;
+            ptr::write(
+                hole,
+                Hole {
+                    size: end - start,
+                    next: None,
+                },
+            );
+            self.head = NonNull::new(hole);
+            self.size = end - start;
+        }
+    }
+
+    /// Returns the number of bytes managed by the heap
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of bytes currently allocated, including rounding
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Returns the number of bytes not currently allocated
+    ///
+    /// Free memory may be fragmented, so an allocation of this size can still fail.
+    #[inline]
+    pub fn free(&self) -> usize {
+        self.size - self.used
+    }
+
+    /// Allocates a block for `layout`, returning `None` if no free block is large enough
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let (size, align) = block_layout(layout);
+
+        let mut prev: Option<NonNull<Hole>> = None;
+        let mut cur = self.head;
+
+        while let Some(hole) = cur {
+            let hole_start = hole.as_ptr() as usize;
+            let (hole_size, next) = unsafe { ((*hole.as_ptr()).size, (*hole.as_ptr()).next) };
+            let hole_end = hole_start + hole_size;
+
+            if let Some(start) = fit(hole_start, hole_end, size, align) {
+                let end = start + size;
+
+                // Whatever follows the block stays free
+                let mut rest = next;
+                if end < hole_end {
+                    let back = end as *mut Hole;
+                    unsafe {
+                        ptr::write(
+                            back,
+                            Hole {
+                                size: hole_end - end,
+                                next: rest,
+                            },
+                        );
+                    }
+                    rest = NonNull::new(back);
+                }
+
+                if start > hole_start {
+                    // Keep the padding in front of the block as a smaller hole
+                    unsafe {
+                        (*hole.as_ptr()).size = start - hole_start;
+                        (*hole.as_ptr()).next = rest;
+                    }
+                } else {
+                    match prev {
+                        Some(prev) => unsafe { (*prev.as_ptr()).next = rest },
+                        None => self.head = rest,
+                    }
+                }
+
+                self.used += size;
+                return NonNull::new(start as *mut u8);
+            }
+
+            prev = cur;
+            cur = next;
+        }
+
+        None
+    }
+
+    /// Frees a block previously returned by [`allocate`](Heap::allocate)
+    ///
+    /// The block is merged with any free neighbours.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `allocate` on this heap with the same `layout`, and must
+    /// not have been freed already.
+    pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        let addr = ptr.as_ptr() as usize;
+
+        self.used -= size;
+
+        // Find the holes on either side of the block
+        let mut prev: Option<NonNull<Hole>> = None;
+        let mut next = self.head;
+        while let Some(hole) = next {
+            if hole.as_ptr() as usize > addr {
+                break;
+            }
+            prev = next;
+            next = (*hole.as_ptr()).next;
+        }
+
+        let mut hole_size = size;
+
+        if let Some(following) = next {
+            if addr + size == following.as_ptr() as usize {
+                hole_size += (*following.as_ptr()).size;
+                next = (*following.as_ptr()).next;
+            }
+        }
+
+        if let Some(prev) = prev {
+            let prev = prev.as_ptr();
+            if prev as usize + (*prev).size == addr {
+                (*prev).size += hole_size;
+                (*prev).next = next;
+                return;
+            }
+        }
+
+        let hole = addr as *mut Hole;
+        ptr::write(
+            hole,
+            Hole {
+                size: hole_size,
+                next,
+            },
+        );
+
+        match prev {
+            Some(prev) => (*prev.as_ptr()).next = NonNull::new(hole),
+            None => self.head = NonNull::new(hole),
+        }
+    }
+}
+
+/// Returns the size and alignment of the block that holds `layout`
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let size = align_up(layout.size().max(HOLE_SIZE), UNIT);
+    let align = layout.align().max(UNIT);
+
+    (size, align)
+}
+
+/// Returns where a block of `size` bytes aligned to `align` starts inside the hole, if it fits
+///
+/// Padding in front of or behind the block must either be empty or big enough to become a hole
+/// itself, otherwise it could never be given back.
+fn fit(hole_start: usize, hole_end: usize, size: usize, align: usize) -> Option<usize> {
+    let mut start = align_up(hole_start, align);
+    if start != hole_start && start - hole_start < HOLE_SIZE {
+        start = align_up(hole_start + HOLE_SIZE, align);
+    }
+
+    let end = start.checked_add(size)?;
+    if end > hole_end {
+        return None;
+    }
+
+    let back = hole_end - end;
+    if back != 0 && back < HOLE_SIZE {
+        return None;
+    }
+
+    Some(start)
+}
+
+#[inline]
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+#[inline]
+fn align_down(addr: usize, align: usize) -> usize {
+    addr & !(align - 1)
+}
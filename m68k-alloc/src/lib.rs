@@ -0,0 +1,99 @@
+//! Global allocator for m68k processors
+//!
+//! [`LockedHeap`] is a first-fit allocator over a single region of memory, usually the heap
+//! `m68k-rt` reserves after `.bss` when `_heap_size` is set in `memory.x`. Every block is at least
+//! 2-byte aligned, as the 68000 requires for word and long accesses. Allocations run inside a
+//! critical section, so the heap can be used from interrupt handlers; enable the
+//! `critical-section-single-core` feature of the `m68k` crate to provide the implementation.
+//!
+//! # Example
+//!
+//! ``` ignore
+//! #![no_main]
+//! #![no_std]
+//!
+//! extern crate alloc;
+//!
+//! use m68k_alloc::LockedHeap;
+//! use m68k_rt::entry;
+//!
+//! #[global_allocator]
+//! static HEAP: LockedHeap = LockedHeap::empty();
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     unsafe {
+//!         let start = m68k_rt::heap_start() as usize;
+//!         let end = m68k_rt::heap_end() as usize;
+//!         HEAP.init(start, end - start);
+//!     }
+//!
+//!     let v = alloc::vec![1u8, 2, 3];
+//!
+//!     loop {}
+//! }
+//! ```
+
+#![no_std]
+
+mod heap;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::RefCell;
+use core::ptr::{self, NonNull};
+
+use critical_section::Mutex;
+
+pub use crate::heap::Heap;
+
+/// A [`Heap`] behind a critical section, usable as the `#[global_allocator]`
+pub struct LockedHeap {
+    heap: Mutex<RefCell<Heap>>,
+}
+
+impl LockedHeap {
+    /// Creates an allocator with no memory
+    ///
+    /// Every allocation fails until [`init`](LockedHeap::init) is called.
+    #[inline]
+    pub const fn empty() -> LockedHeap {
+        LockedHeap {
+            heap: Mutex::new(RefCell::new(Heap::empty())),
+        }
+    }
+
+    /// Gives the allocator the `size` bytes starting at `start`
+    ///
+    /// # Safety
+    ///
+    /// Must be called at most once, before the first allocation. See [`Heap::init`] for the
+    /// requirements on the region.
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).init(start, size));
+    }
+
+    /// Returns the number of bytes currently allocated
+    pub fn used(&self) -> usize {
+        critical_section::with(|cs| self.heap.borrow_ref(cs).used())
+    }
+
+    /// Returns the number of bytes not currently allocated
+    pub fn free(&self) -> usize {
+        critical_section::with(|cs| self.heap.borrow_ref(cs).free())
+    }
+}
+
+unsafe impl GlobalAlloc for LockedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        critical_section::with(|cs| self.heap.borrow_ref_mut(cs).allocate(layout))
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        critical_section::with(|cs| {
+            self.heap
+                .borrow_ref_mut(cs)
+                .deallocate(NonNull::new_unchecked(ptr), layout)
+        });
+    }
+}
@@ -0,0 +1,235 @@
+use std::alloc::{GlobalAlloc, Layout};
+use std::ptr::NonNull;
+
+use m68k_alloc::{Heap, LockedHeap};
+
+const ARENA: usize = 4096;
+
+/// Backing memory for a heap, aligned more strictly than anything the tests ask for
+#[repr(C, align(64))]
+struct Arena([u8; ARENA]);
+
+fn arena() -> Box<Arena> {
+    Box::new(Arena([0; ARENA]))
+}
+
+fn heap(arena: &mut Arena) -> Heap {
+    let mut heap = Heap::empty();
+    unsafe { heap.init(arena.0.as_mut_ptr() as usize, ARENA) };
+    heap
+}
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+#[test]
+fn empty_heap_fails() {
+    let mut heap = Heap::empty();
+    assert!(heap.allocate(layout(1, 1)).is_none());
+    assert_eq!(heap.size(), 0);
+}
+
+#[test]
+fn init_rounds_to_even_addresses() {
+    let mut arena = arena();
+    let start = arena.0.as_mut_ptr() as usize;
+
+    let mut heap = Heap::empty();
+    unsafe { heap.init(start + 1, ARENA - 2) };
+
+    assert!(heap.size() <= ARENA - 2);
+    let p = heap.allocate(layout(1, 1)).unwrap().as_ptr() as usize;
+    assert!(p > start);
+    assert_eq!(p % 2, 0);
+}
+
+#[test]
+fn byte_allocations_are_word_aligned() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+
+    for _ in 0..16 {
+        let p = heap.allocate(layout(1, 1)).unwrap();
+        assert_eq!(p.as_ptr() as usize % 2, 0);
+    }
+}
+
+#[test]
+fn large_alignments_are_honoured() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+
+    // Push the free list off the arena's natural alignment first
+    heap.allocate(layout(2, 2)).unwrap();
+
+    for align in [4, 8, 16, 32, 64, 128] {
+        let p = heap.allocate(layout(3, align)).unwrap();
+        assert_eq!(p.as_ptr() as usize % align, 0, "align {}", align);
+    }
+}
+
+#[test]
+fn allocations_stay_inside_the_arena_and_do_not_overlap() {
+    let mut arena = arena();
+    let start = arena.0.as_mut_ptr() as usize;
+    let mut heap = heap(&mut arena);
+
+    let mut blocks = Vec::new();
+    while let Some(p) = heap.allocate(layout(24, 2)) {
+        blocks.push(p.as_ptr() as usize);
+    }
+
+    assert!(!blocks.is_empty());
+    blocks.sort();
+    for pair in blocks.windows(2) {
+        assert!(pair[0] + 24 <= pair[1]);
+    }
+    assert!(*blocks.first().unwrap() >= start);
+    assert!(*blocks.last().unwrap() + 24 <= start + ARENA);
+}
+
+#[test]
+fn out_of_memory_returns_none() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+
+    assert!(heap.allocate(layout(ARENA + 2, 2)).is_none());
+    assert!(heap.allocate(layout(ARENA, 2)).is_some());
+    assert!(heap.allocate(layout(2, 2)).is_none());
+}
+
+#[test]
+fn freeing_everything_restores_one_block() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+    let size = heap.size();
+
+    let l = layout(40, 4);
+    let mut blocks = Vec::new();
+    while let Some(p) = heap.allocate(l) {
+        blocks.push(p);
+    }
+    assert!(heap.free() < 40);
+
+    // Free every other block first so both directions of merging are exercised
+    let (even, odd): (Vec<_>, Vec<_>) = blocks.iter().enumerate().partition(|(i, _)| i % 2 == 0);
+    for (_, p) in even.into_iter().chain(odd) {
+        unsafe { heap.deallocate(*p, l) };
+    }
+
+    assert_eq!(heap.used(), 0);
+    assert_eq!(heap.free(), size);
+    assert!(heap.allocate(layout(size, 2)).is_some());
+}
+
+#[test]
+fn freed_hole_is_reused() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+
+    let a = heap.allocate(layout(64, 2)).unwrap();
+    let _b = heap.allocate(layout(64, 2)).unwrap();
+    unsafe { heap.deallocate(a, layout(64, 2)) };
+
+    let c = heap.allocate(layout(64, 2)).unwrap();
+    assert_eq!(a, c);
+}
+
+#[test]
+fn fragmented_heap_fails_large_allocation() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+
+    let l = layout(256, 2);
+    let mut blocks = Vec::new();
+    while let Some(p) = heap.allocate(l) {
+        blocks.push(p);
+    }
+    for p in blocks.iter().step_by(2) {
+        unsafe { heap.deallocate(*p, l) };
+    }
+
+    assert!(heap.free() >= 512);
+    assert!(heap.allocate(layout(512, 2)).is_none());
+    assert!(heap.allocate(l).is_some());
+}
+
+#[test]
+fn alignment_padding_is_given_back() {
+    let mut arena = arena();
+    let mut heap = heap(&mut arena);
+    let size = heap.size();
+
+    let a = heap.allocate(layout(2, 2)).unwrap();
+    let b = heap.allocate(layout(100, 64)).unwrap();
+    unsafe {
+        heap.deallocate(a, layout(2, 2));
+        heap.deallocate(b, layout(100, 64));
+    }
+
+    assert_eq!(heap.free(), size);
+    assert!(heap.allocate(layout(size, 2)).is_some());
+}
+
+#[test]
+fn random_workload() {
+    let mut arena = arena();
+    let start = arena.0.as_mut_ptr() as usize;
+    let mut heap = heap(&mut arena);
+    let size = heap.size();
+
+    // Small LCG so the test is reproducible without extra dependencies
+    let mut seed = 0x1234_5678u32;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as usize
+    };
+
+    let mut live: Vec<(NonNull<u8>, Layout, u8)> = Vec::new();
+    for round in 0..5000 {
+        if live.is_empty() || next() % 3 != 0 {
+            let l = layout(1 + next() % 200, 1 << (next() % 5));
+            if let Some(p) = heap.allocate(l) {
+                let addr = p.as_ptr() as usize;
+                assert_eq!(addr % l.align().max(2), 0);
+                assert!(addr >= start && addr + l.size() <= start + ARENA);
+
+                // Stamp the block so overlapping allocations are caught when it is freed
+                let stamp = round as u8;
+                unsafe { std::ptr::write_bytes(p.as_ptr(), stamp, l.size()) };
+                live.push((p, l, stamp));
+            }
+        } else {
+            let (p, l, stamp) = live.swap_remove(next() % live.len());
+            let bytes = unsafe { std::slice::from_raw_parts(p.as_ptr(), l.size()) };
+            assert!(bytes.iter().all(|&b| b == stamp));
+            unsafe { heap.deallocate(p, l) };
+        }
+    }
+
+    for (p, l, _) in live.drain(..) {
+        unsafe { heap.deallocate(p, l) };
+    }
+    assert_eq!(heap.used(), 0);
+    assert!(heap.allocate(layout(size, 2)).is_some());
+}
+
+#[test]
+fn locked_heap_as_global_alloc() {
+    static HEAP: LockedHeap = LockedHeap::empty();
+
+    let mut arena = arena();
+    unsafe { HEAP.init(arena.0.as_mut_ptr() as usize, ARENA) };
+
+    let l = layout(10, 4);
+    let p = unsafe { HEAP.alloc(l) };
+    assert!(!p.is_null());
+    assert_eq!(p as usize % 4, 0);
+    assert!(HEAP.used() >= 10);
+
+    unsafe { HEAP.dealloc(p, l) };
+    assert_eq!(HEAP.used(), 0);
+
+    assert!(unsafe { HEAP.alloc(layout(ARENA * 2, 2)) }.is_null());
+}
@@ -0,0 +1,427 @@
+//! The address space: RAM, ROM and memory-mapped devices
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use m68k_image::MemoryMap;
+
+/// Size of an access or an operation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Word,
+    Long,
+}
+
+impl Size {
+    /// Number of bytes
+    pub fn bytes(self) -> u32 {
+        match self {
+            Size::Byte => 1,
+            Size::Word => 2,
+            Size::Long => 4,
+        }
+    }
+
+    /// Mask of the bits an operation of this size affects
+    pub fn mask(self) -> u32 {
+        match self {
+            Size::Byte => 0xff,
+            Size::Word => 0xffff,
+            Size::Long => 0xffff_ffff,
+        }
+    }
+
+    /// The sign bit
+    pub fn msb(self) -> u32 {
+        match self {
+            Size::Byte => 0x80,
+            Size::Word => 0x8000,
+            Size::Long => 0x8000_0000,
+        }
+    }
+
+    /// Sign-extends the low bits of `value` to 32 bits
+    pub fn sign_extend(self, value: u32) -> u32 {
+        match self {
+            Size::Byte => value as u8 as i8 as u32,
+            Size::Word => value as u16 as i16 as u32,
+            Size::Long => value,
+        }
+    }
+}
+
+/// An access the bus doesn't answer, which the CPU turns into a bus error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusError;
+
+/// The accesses a watchpoint catches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    Access,
+}
+
+/// A range of addresses whose data accesses stop the machine, after the instruction that made them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u32,
+    pub len: u32,
+    pub kind: Watch,
+}
+
+impl Watchpoint {
+    fn catches(&self, address: u32, len: u32, write: bool) -> bool {
+        let kind = match self.kind {
+            Watch::Read => !write,
+            Watch::Write => write,
+            Watch::Access => true,
+        };
+        let end = u64::from(self.address) + u64::from(self.len);
+        kind && u64::from(address) < end && self.address < address.saturating_add(len)
+    }
+}
+
+/// How a device answers an interrupt acknowledge cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Acknowledge {
+    /// Use the autovector for the level (vectors 25 to 31)
+    Autovector,
+    /// Supply a vector number, usually 64 or higher
+    Vector(u8),
+    /// Nobody answers: spurious interrupt (vector 24)
+    Spurious,
+}
+
+/// A memory-mapped device
+///
+/// Offsets are relative to the start of the device's region. Accesses are never split: a long
+/// word access reaches the device as one call, even if the guest is a 68000 whose bus is 16 bits
+/// wide.
+pub trait Device {
+    /// Reads a register
+    fn read(&mut self, offset: u32, size: Size) -> u32;
+
+    /// Writes a register
+    fn write(&mut self, offset: u32, size: Size, value: u32);
+
+    /// Lets `cycles` CPU clock cycles pass
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Interrupt priority level the device requests, 0 for none
+    fn interrupt_level(&self) -> u8 {
+        0
+    }
+
+    /// Called when the CPU takes the interrupt the device requested at `level`
+    fn acknowledge(&mut self, _level: u8) -> Acknowledge {
+        Acknowledge::Autovector
+    }
+}
+
+/// A device shared with the test that created it, which can look at it while the guest runs
+impl<D: Device> Device for Rc<RefCell<D>> {
+    fn read(&mut self, offset: u32, size: Size) -> u32 {
+        self.borrow_mut().read(offset, size)
+    }
+
+    fn write(&mut self, offset: u32, size: Size, value: u32) {
+        self.borrow_mut().write(offset, size, value)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.borrow_mut().tick(cycles)
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        self.borrow().interrupt_level()
+    }
+
+    fn acknowledge(&mut self, level: u8) -> Acknowledge {
+        self.borrow_mut().acknowledge(level)
+    }
+}
+
+enum Contents {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>),
+    Device(Box<dyn Device>),
+}
+
+struct Region {
+    name: String,
+    start: u32,
+    size: u32,
+    contents: Contents,
+}
+
+impl Region {
+    fn offset(&self, address: u32, len: u32) -> Option<u32> {
+        let offset = address.wrapping_sub(self.start);
+        if offset < self.size && len <= self.size - offset {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+}
+
+/// The address space of the guest
+///
+/// Addresses that no region covers cause bus errors, and so do writes to ROM. All 32 address bits
+/// are decoded unless [`Bus::set_address_bits`] says otherwise: a plain 68000 only has 24.
+pub struct Bus {
+    regions: Vec<Region>,
+    mask: u32,
+    watchpoints: Vec<Watchpoint>,
+    /// The watchpoint the last instruction hit
+    hit: Option<Watchpoint>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    /// Creates an empty address space
+    pub fn new() -> Self {
+        Bus {
+            regions: Vec::new(),
+            mask: 0xffff_ffff,
+            watchpoints: Vec::new(),
+            hit: None,
+        }
+    }
+
+    /// Creates RAM and ROM for the regions of a `memory.x`
+    ///
+    /// Regions whose name starts with `ROM` are read-only, the others are RAM.
+    pub fn from_memory_map(map: &MemoryMap) -> Self {
+        let mut bus = Bus::new();
+        for region in &map.regions {
+            if region.name.starts_with("ROM") {
+                bus.add_rom(&region.name, region.origin, region.length);
+            } else {
+                bus.add_ram(&region.name, region.origin, region.length);
+            }
+        }
+        bus
+    }
+
+    /// Only decodes the low `bits` bits of addresses
+    pub fn set_address_bits(&mut self, bits: u32) {
+        self.mask = if bits >= 32 {
+            0xffff_ffff
+        } else {
+            (1 << bits) - 1
+        };
+    }
+
+    /// Adds zero-filled RAM
+    pub fn add_ram(&mut self, name: &str, start: u32, size: u32) -> &mut Self {
+        self.add(name, start, size, Contents::Ram(vec![0; size as usize]))
+    }
+
+    /// Adds ROM filled with `0xff`, the contents of an erased EPROM
+    ///
+    /// The guest can't write to it; [`Bus::load`] can.
+    pub fn add_rom(&mut self, name: &str, start: u32, size: u32) -> &mut Self {
+        self.add(name, start, size, Contents::Rom(vec![0xff; size as usize]))
+    }
+
+    /// Maps a device at `start..start + size`
+    pub fn add_device<D: Device + 'static>(
+        &mut self,
+        name: &str,
+        start: u32,
+        size: u32,
+        device: D,
+    ) -> &mut Self {
+        self.add(name, start, size, Contents::Device(Box::new(device)))
+    }
+
+    fn add(&mut self, name: &str, start: u32, size: u32, contents: Contents) -> &mut Self {
+        let end = u64::from(start) + u64::from(size);
+        if let Some(other) = self.regions.iter().find(|r| {
+            u64::from(r.start) < end && u64::from(start) < u64::from(r.start) + u64::from(r.size)
+        }) {
+            panic!("{} overlaps {}", name, other.name);
+        }
+        self.regions.push(Region {
+            name: name.into(),
+            start,
+            size,
+            contents,
+        });
+        self
+    }
+
+    /// Returns the name of the region `address` is in
+    pub fn region_at(&self, address: u32) -> Option<&str> {
+        let address = address & self.mask;
+        self.regions
+            .iter()
+            .find(|r| r.offset(address, 1).is_some())
+            .map(|r| r.name.as_str())
+    }
+
+    fn find(&mut self, address: u32, len: u32) -> Option<(&mut Region, u32)> {
+        self.regions.iter_mut().find_map(|r| {
+            let offset = r.offset(address, len)?;
+            Some((r, offset))
+        })
+    }
+
+    /// Reads from the guest's point of view
+    pub fn read(&mut self, address: u32, size: Size) -> Result<u32, BusError> {
+        let address = address & self.mask;
+        match self.find(address, size.bytes()) {
+            Some((region, offset)) => Ok(match &mut region.contents {
+                Contents::Ram(data) | Contents::Rom(data) => {
+                    let offset = offset as usize;
+                    data[offset..offset + size.bytes() as usize]
+                        .iter()
+                        .fold(0, |value, &byte| value << 8 | u32::from(byte))
+                }
+                Contents::Device(device) => device.read(offset, size) & size.mask(),
+            }),
+            // A misaligned access that crosses regions, on a 68020 or later
+            None if size != Size::Byte => (0..size.bytes()).try_fold(0, |value, i| {
+                let byte = self.read(address.wrapping_add(i), Size::Byte)?;
+                Ok(value << 8 | byte)
+            }),
+            None => Err(BusError),
+        }
+    }
+
+    /// Writes from the guest's point of view
+    pub fn write(&mut self, address: u32, size: Size, value: u32) -> Result<(), BusError> {
+        let address = address & self.mask;
+        match self.find(address, size.bytes()) {
+            Some((region, offset)) => match &mut region.contents {
+                Contents::Ram(data) => {
+                    let len = size.bytes() as usize;
+                    let offset = offset as usize;
+                    data[offset..offset + len].copy_from_slice(&value.to_be_bytes()[4 - len..]);
+                    Ok(())
+                }
+                Contents::Rom(_) => Err(BusError),
+                Contents::Device(device) => {
+                    device.write(offset, size, value & size.mask());
+                    Ok(())
+                }
+            },
+            None if size != Size::Byte => {
+                for (i, &byte) in value.to_be_bytes()[4 - size.bytes() as usize..]
+                    .iter()
+                    .enumerate()
+                {
+                    self.write(address.wrapping_add(i as u32), Size::Byte, byte.into())?;
+                }
+                Ok(())
+            }
+            None => Err(BusError),
+        }
+    }
+
+    /// Stores `data` in RAM or ROM, as a programmer or debugger would
+    ///
+    /// Fails if part of the range isn't RAM or ROM.
+    pub fn load(&mut self, address: u32, data: &[u8]) -> Result<(), BusError> {
+        for (i, &byte) in data.iter().enumerate() {
+            *self.memory(address.wrapping_add(i as u32))? = byte;
+        }
+        Ok(())
+    }
+
+    /// Copies RAM or ROM contents into `buffer`, without touching devices
+    pub fn peek(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), BusError> {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = *self.memory(address.wrapping_add(i as u32))?;
+        }
+        Ok(())
+    }
+
+    fn memory(&mut self, address: u32) -> Result<&mut u8, BusError> {
+        let address = address & self.mask;
+        match self.find(address, 1) {
+            Some((region, offset)) => match &mut region.contents {
+                Contents::Ram(data) | Contents::Rom(data) => Ok(&mut data[offset as usize]),
+                Contents::Device(_) => Err(BusError),
+            },
+            None => Err(BusError),
+        }
+    }
+
+    /// Adds a watchpoint on the data accesses of instructions
+    ///
+    /// Instruction fetches, exception processing and the accesses of [`Bus::read`] and
+    /// [`Bus::write`] themselves don't hit watchpoints.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Removes a watchpoint, returning whether there was one
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    /// Returns the watchpoint hit since the last call, if any
+    pub fn take_hit(&mut self) -> Option<Watchpoint> {
+        self.hit.take()
+    }
+
+    /// Checks a data access of an instruction against the watchpoints
+    pub(crate) fn watch(&mut self, address: u32, size: Size, write: bool) {
+        if self.hit.is_none() {
+            self.hit = self
+                .watchpoints
+                .iter()
+                .find(|w| w.catches(address, size.bytes(), write))
+                .copied();
+        }
+    }
+
+    /// Lets time pass for the devices
+    pub fn tick(&mut self, cycles: u64) {
+        for region in &mut self.regions {
+            if let Contents::Device(device) = &mut region.contents {
+                device.tick(cycles);
+            }
+        }
+    }
+
+    /// Highest interrupt level any device requests
+    pub fn interrupt_level(&self) -> u8 {
+        self.regions
+            .iter()
+            .filter_map(|r| match &r.contents {
+                Contents::Device(device) => Some(device.interrupt_level().min(7)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Runs an interrupt acknowledge cycle for `level`
+    ///
+    /// The first device requesting that level answers.
+    pub fn acknowledge(&mut self, level: u8) -> Acknowledge {
+        self.regions
+            .iter_mut()
+            .find_map(|r| match &mut r.contents {
+                Contents::Device(device) if device.interrupt_level() == level => {
+                    Some(device.acknowledge(level))
+                }
+                _ => None,
+            })
+            .unwrap_or(Acknowledge::Spurious)
+    }
+}
@@ -0,0 +1,461 @@
+//! Processor state and exception processing
+
+use crate::bus::{Acknowledge, Bus, BusError, Size};
+use crate::execute::Exec;
+
+/// Processor models
+///
+/// They differ in the exception stack frames they push and in the instructions they know. The
+/// 68020 and 68040 are emulated without caches, MMU or FPU, and without the master stack pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Model {
+    M68000,
+    M68010,
+    M68020,
+    M68040,
+}
+
+/// Status register bits
+pub mod sr {
+    pub const C: u16 = 1 << 0;
+    pub const V: u16 = 1 << 1;
+    pub const Z: u16 = 1 << 2;
+    pub const N: u16 = 1 << 3;
+    pub const X: u16 = 1 << 4;
+    /// Interrupt priority mask
+    pub const IPL: u16 = 7 << 8;
+    /// Supervisor state
+    pub const S: u16 = 1 << 13;
+    /// Trace on every instruction
+    pub const T: u16 = 1 << 15;
+}
+
+/// Exception vector numbers
+pub mod vector {
+    pub const BUS_ERROR: u8 = 2;
+    pub const ADDRESS_ERROR: u8 = 3;
+    pub const ILLEGAL_INSTRUCTION: u8 = 4;
+    pub const ZERO_DIVIDE: u8 = 5;
+    pub const CHK: u8 = 6;
+    pub const TRAPV: u8 = 7;
+    pub const PRIVILEGE_VIOLATION: u8 = 8;
+    pub const TRACE: u8 = 9;
+    pub const LINE_1010: u8 = 10;
+    pub const LINE_1111: u8 = 11;
+    pub const FORMAT_ERROR: u8 = 14;
+    pub const SPURIOUS_INTERRUPT: u8 = 24;
+    /// Autovector for interrupt level 1; level `n` uses `AUTOVECTOR + n - 1`
+    pub const AUTOVECTOR: u8 = 25;
+    /// `TRAP #0`; `TRAP #n` uses `TRAP + n`
+    pub const TRAP: u8 = 32;
+}
+
+/// The access that caused a bus or address error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub address: u32,
+    pub size: Size,
+    pub write: bool,
+    /// Instruction fetch rather than data access
+    pub program: bool,
+}
+
+/// An exception the processor is about to take
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exception {
+    pub vector: u8,
+    /// Program counter pushed in the frame, where `rte` returns to
+    pub pc: u32,
+    /// Address of the instruction that caused the exception, or that was last executed for
+    /// interrupts and trace
+    pub instruction: u32,
+    /// Its first word
+    pub opcode: u16,
+    /// For bus and address errors
+    pub access: Option<Access>,
+    /// For interrupts, the level
+    pub level: Option<u8>,
+}
+
+/// What [`Cpu::step`] did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Executed an instruction, or waited for an interrupt after `stop`
+    Executed,
+    /// An instruction, an interrupt or tracing calls for an exception, which is not taken yet
+    Exception(Exception),
+    /// The processor is halted by a double fault, or was never reset successfully
+    Halted,
+}
+
+/// The processor
+#[derive(Clone, Debug)]
+pub struct Cpu {
+    pub model: Model,
+    /// Data registers
+    pub d: [u32; 8],
+    /// Address registers; `a[7]` is the stack pointer of the current mode
+    pub a: [u32; 8],
+    pub pc: u32,
+    sr: u16,
+    /// The stack pointer of the other mode
+    other_sp: u32,
+    /// Vector base register, 68010 and later
+    pub vbr: u32,
+    /// Alternate function code registers, 68010 and later
+    pub sfc: u8,
+    pub dfc: u8,
+    /// Cache control register, 68020 and later; stored but without effect
+    pub cacr: u32,
+    /// Clock cycles since power-on, counted as on the 68000 whatever the model
+    pub cycles: u64,
+    /// Instructions executed since power-on
+    pub instructions: u64,
+    /// Waiting for an interrupt, after `stop`
+    pub stopped: bool,
+    pub halted: bool,
+    /// Interrupt level seen by the last poll, to detect the edge of a level 7 interrupt
+    last_level: u8,
+}
+
+impl Cpu {
+    /// Creates a processor in the state it has before reset: halted
+    pub fn new(model: Model) -> Self {
+        Cpu {
+            model,
+            d: [0; 8],
+            a: [0; 8],
+            pc: 0,
+            sr: sr::S | sr::IPL,
+            other_sp: 0,
+            vbr: 0,
+            sfc: 0,
+            dfc: 0,
+            cacr: 0,
+            cycles: 0,
+            instructions: 0,
+            stopped: false,
+            halted: true,
+            last_level: 0,
+        }
+    }
+
+    /// Status register
+    pub fn sr(&self) -> u16 {
+        self.sr
+    }
+
+    /// Sets the status register, switching stack pointers if the supervisor bit changes
+    pub fn set_sr(&mut self, value: u16) {
+        let value = value & self.sr_mask();
+        if (value ^ self.sr) & sr::S != 0 {
+            std::mem::swap(&mut self.a[7], &mut self.other_sp);
+        }
+        self.sr = value;
+    }
+
+    /// Bits of the status register that exist on the model
+    pub(crate) fn sr_mask(&self) -> u16 {
+        match self.model {
+            Model::M68000 | Model::M68010 => 0xa71f,
+            // T0 and M can be set, but don't do anything
+            Model::M68020 | Model::M68040 => 0xf71f,
+        }
+    }
+
+    pub(crate) fn set_ccr(&mut self, value: u16) {
+        self.sr = self.sr & 0xff00 | value & 0x1f;
+    }
+
+    pub fn supervisor(&self) -> bool {
+        self.sr & sr::S != 0
+    }
+
+    /// User stack pointer
+    pub fn usp(&self) -> u32 {
+        if self.supervisor() {
+            self.other_sp
+        } else {
+            self.a[7]
+        }
+    }
+
+    pub fn set_usp(&mut self, value: u32) {
+        if self.supervisor() {
+            self.other_sp = value;
+        } else {
+            self.a[7] = value;
+        }
+    }
+
+    /// Supervisor stack pointer
+    pub fn ssp(&self) -> u32 {
+        if self.supervisor() {
+            self.a[7]
+        } else {
+            self.other_sp
+        }
+    }
+
+    pub fn set_ssp(&mut self, value: u32) {
+        if self.supervisor() {
+            self.a[7] = value;
+        } else {
+            self.other_sp = value;
+        }
+    }
+
+    /// Resets the processor: loads the stack pointer and program counter from vectors 0 and 1
+    ///
+    /// The processor halts if they can't be read.
+    pub fn reset(&mut self, bus: &mut Bus) {
+        self.sr = sr::S | sr::IPL;
+        self.vbr = 0;
+        self.cacr = 0;
+        self.stopped = false;
+        self.halted = false;
+        self.cycles += 40;
+        match (bus.read(0, Size::Long), bus.read(4, Size::Long)) {
+            (Ok(ssp), Ok(pc)) => {
+                self.a[7] = ssp;
+                self.pc = pc;
+            }
+            _ => self.halted = true,
+        }
+    }
+
+    /// Runs one instruction, or looks for an interrupt to take
+    ///
+    /// Exceptions are returned rather than taken, so the caller can decide what to do with them:
+    /// usually [`Cpu::take`].
+    pub fn step(&mut self, bus: &mut Bus) -> Step {
+        if self.halted {
+            return Step::Halted;
+        }
+        if let Some(exception) = self.poll_interrupt(bus) {
+            return Step::Exception(exception);
+        }
+        if self.stopped {
+            self.cycles += 4;
+            return Step::Executed;
+        }
+
+        let trace = self.sr & sr::T != 0;
+        let start = self.pc;
+        let mut exec = Exec::new(self, bus);
+        let result = exec.execute();
+        let opcode = exec.opcode;
+        self.instructions += 1;
+        match result {
+            Ok(()) if trace => Step::Exception(Exception {
+                vector: vector::TRACE,
+                pc: self.pc,
+                instruction: start,
+                opcode,
+                access: None,
+                level: None,
+            }),
+            Ok(()) => Step::Executed,
+            Err(exception) => Step::Exception(exception),
+        }
+    }
+
+    fn poll_interrupt(&mut self, bus: &mut Bus) -> Option<Exception> {
+        let level = bus.interrupt_level();
+        let edge = level == 7 && self.last_level != 7;
+        self.last_level = level;
+        let mask = ((self.sr & sr::IPL) >> 8) as u8;
+        if level == 0 || (level <= mask && !edge) {
+            return None;
+        }
+
+        self.stopped = false;
+        self.cycles += 44;
+        let vector = match bus.acknowledge(level) {
+            Acknowledge::Autovector => vector::AUTOVECTOR + level - 1,
+            Acknowledge::Vector(vector) => vector,
+            Acknowledge::Spurious => vector::SPURIOUS_INTERRUPT,
+        };
+        Some(Exception {
+            vector,
+            pc: self.pc,
+            instruction: self.pc,
+            opcode: 0,
+            access: None,
+            level: Some(level),
+        })
+    }
+
+    /// Takes an exception: pushes its stack frame and jumps to its handler
+    ///
+    /// A bus or address error while doing so halts the processor, like the double bus fault of
+    /// the real thing.
+    pub fn take(&mut self, bus: &mut Bus, exception: &Exception) {
+        let old = self.sr;
+        self.set_sr((old | sr::S) & !(sr::T | 1 << 14));
+        if let Some(level) = exception.level {
+            self.sr = self.sr & !sr::IPL | u16::from(level) << 8;
+        }
+        self.stopped = false;
+        self.cycles += match exception.vector {
+            vector::BUS_ERROR | vector::ADDRESS_ERROR => 50,
+            vector::CHK => 40,
+            vector::ZERO_DIVIDE => 38,
+            _ => 34,
+        };
+
+        if self.push_frame(bus, exception, old).is_err() {
+            self.halted = true;
+            return;
+        }
+        match bus.read(
+            self.vbr.wrapping_add(u32::from(exception.vector) * 4),
+            Size::Long,
+        ) {
+            Ok(pc) => self.pc = pc,
+            Err(BusError) => self.halted = true,
+        }
+    }
+
+    fn push_frame(&mut self, bus: &mut Bus, e: &Exception, sr: u16) -> Result<(), BusError> {
+        let offset = u16::from(e.vector) * 4;
+        let access = e.access.unwrap_or(Access {
+            address: 0,
+            size: Size::Word,
+            write: false,
+            program: false,
+        });
+        let function_code = match (sr & sr::S != 0, access.program) {
+            (false, false) => 1,
+            (false, true) => 2,
+            (true, false) => 5,
+            (true, true) => 6,
+        };
+        let group_0 = matches!(e.vector, vector::BUS_ERROR | vector::ADDRESS_ERROR);
+        let format_2 = matches!(
+            e.vector,
+            vector::ZERO_DIVIDE | vector::CHK | vector::TRAPV | vector::TRACE
+        );
+
+        match self.model {
+            Model::M68000 => {
+                self.push(bus, Size::Long, e.pc)?;
+                self.push(bus, Size::Word, sr.into())?;
+                if group_0 {
+                    self.push(bus, Size::Word, e.opcode.into())?;
+                    self.push(bus, Size::Long, access.address)?;
+                    let info = u32::from(!access.write) << 4
+                        | u32::from(!access.program) << 3
+                        | function_code;
+                    self.push(bus, Size::Word, info)?;
+                }
+            }
+            Model::M68010 if group_0 => {
+                // Format 8: the internal state that would let `rte` finish the access is left out
+                for _ in 0..16 {
+                    self.push(bus, Size::Word, 0)?;
+                }
+                self.push(bus, Size::Word, e.opcode.into())?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Long, access.address)?;
+                let ssw = u32::from(!access.write) << 8
+                    | u32::from(access.size == Size::Byte) << 9
+                    | u32::from(access.program) << 13
+                    | function_code;
+                self.push(bus, Size::Word, ssw)?;
+                self.push_format(bus, 0x8, offset, e.pc, sr)?;
+            }
+            Model::M68010 => self.push_format(bus, 0x0, offset, e.pc, sr)?,
+            Model::M68020 if group_0 => {
+                // Format A, short bus cycle fault
+                self.push(bus, Size::Long, 0)?;
+                self.push(bus, Size::Long, 0)?;
+                self.push(bus, Size::Long, 0)?;
+                self.push(bus, Size::Long, access.address)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push(bus, Size::Word, e.opcode.into())?;
+                let size = match access.size {
+                    Size::Byte => 1,
+                    Size::Word => 2,
+                    Size::Long => 0,
+                };
+                let ssw = u32::from(!access.program) << 8
+                    | u32::from(!access.write) << 6
+                    | size << 4
+                    | function_code;
+                self.push(bus, Size::Word, ssw)?;
+                self.push(bus, Size::Word, 0)?;
+                self.push_format(bus, 0xa, offset, e.pc, sr)?;
+            }
+            Model::M68040 if e.vector == vector::BUS_ERROR => {
+                // Format 7, access error: no pending write-backs
+                for _ in 0..9 {
+                    self.push(bus, Size::Long, 0)?;
+                }
+                self.push(bus, Size::Long, access.address)?;
+                for _ in 0..3 {
+                    self.push(bus, Size::Word, 0)?;
+                }
+                let size = match access.size {
+                    Size::Byte => 1,
+                    Size::Word => 2,
+                    Size::Long => 0,
+                };
+                let ssw = u32::from(!access.write) << 8 | size << 5 | function_code;
+                self.push(bus, Size::Word, ssw)?;
+                self.push(bus, Size::Long, access.address)?;
+                self.push_format(bus, 0x7, offset, e.pc, sr)?;
+            }
+            // The 68040 reports address errors with format 2 and the address that was accessed
+            Model::M68040 if e.vector == vector::ADDRESS_ERROR => {
+                self.push(bus, Size::Long, access.address & !1)?;
+                self.push_format(bus, 0x2, offset, e.pc, sr)?;
+            }
+            Model::M68020 | Model::M68040 if format_2 => {
+                self.push(bus, Size::Long, e.instruction)?;
+                self.push_format(bus, 0x2, offset, e.pc, sr)?;
+            }
+            Model::M68020 | Model::M68040 => self.push_format(bus, 0x0, offset, e.pc, sr)?,
+        }
+        Ok(())
+    }
+
+    fn push_format(
+        &mut self,
+        bus: &mut Bus,
+        format: u16,
+        offset: u16,
+        pc: u32,
+        sr: u16,
+    ) -> Result<(), BusError> {
+        self.push(bus, Size::Word, u32::from(format << 12 | offset))?;
+        self.push(bus, Size::Long, pc)?;
+        self.push(bus, Size::Word, sr.into())
+    }
+
+    fn push(&mut self, bus: &mut Bus, size: Size, value: u32) -> Result<(), BusError> {
+        self.a[7] = self.a[7].wrapping_sub(size.bytes());
+        if self.a[7] & 1 != 0 && self.model < Model::M68020 {
+            return Err(BusError);
+        }
+        bus.write(self.a[7], size, value)
+    }
+
+    /// Number of bytes of the frame `rte` pops for a format, or `None` if the format doesn't
+    /// exist on the model
+    pub(crate) fn frame_size(&self, format: u16) -> Option<u32> {
+        match (self.model, format) {
+            (_, 0x0) => Some(8),
+            (Model::M68010, 0x8) => Some(58),
+            (Model::M68020 | Model::M68040, 0x2) => Some(12),
+            (Model::M68020, 0xa) => Some(32),
+            (Model::M68020, 0xb) => Some(92),
+            (Model::M68040, 0x7) => Some(60),
+            _ => None,
+        }
+    }
+}
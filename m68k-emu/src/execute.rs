@@ -0,0 +1,1711 @@
+//! Instruction decoding and execution
+
+use crate::bus::{Bus, Size};
+use crate::cpu::{sr, vector, Access, Cpu, Exception, Model};
+
+type Result<T> = std::result::Result<T, Exception>;
+
+// Effective address modes, as bits of a set of allowed modes
+const DN: u16 = 1 << 0;
+const AN: u16 = 1 << 1;
+const IND: u16 = 1 << 2;
+const POST: u16 = 1 << 3;
+const PRE: u16 = 1 << 4;
+const DISP: u16 = 1 << 5;
+const INDEX: u16 = 1 << 6;
+const ABS_W: u16 = 1 << 7;
+const ABS_L: u16 = 1 << 8;
+const PC_DISP: u16 = 1 << 9;
+const PC_INDEX: u16 = 1 << 10;
+const IMM: u16 = 1 << 11;
+
+const ALL: u16 = 0xfff;
+const DATA: u16 = ALL & !AN;
+const CONTROL: u16 = IND | DISP | INDEX | ABS_W | ABS_L | PC_DISP | PC_INDEX;
+const ALTERABLE: u16 = DN | AN | IND | POST | PRE | DISP | INDEX | ABS_W | ABS_L;
+const DATA_ALTERABLE: u16 = ALTERABLE & !AN;
+const MEMORY_ALTERABLE: u16 = ALTERABLE & !(DN | AN);
+const CONTROL_ALTERABLE: u16 = CONTROL & ALTERABLE;
+
+fn mode_bit(mode: u16, reg: u16) -> u16 {
+    match (mode, reg) {
+        (0..=6, _) => 1 << mode,
+        (7, 0) => ABS_W,
+        (7, 1) => ABS_L,
+        (7, 2) => PC_DISP,
+        (7, 3) => PC_INDEX,
+        (7, 4) => IMM,
+        _ => 0,
+    }
+}
+
+/// A decoded effective address
+#[derive(Clone, Copy)]
+enum Ea {
+    D(usize),
+    A(usize),
+    Memory(u32),
+    Immediate(u32),
+}
+
+/// Operation size in bits 7-6, as most instructions encode it
+fn size_76(opcode: u16) -> Option<Size> {
+    match opcode >> 6 & 3 {
+        0 => Some(Size::Byte),
+        1 => Some(Size::Word),
+        2 => Some(Size::Long),
+        _ => None,
+    }
+}
+
+/// One instruction being executed
+pub(crate) struct Exec<'a> {
+    cpu: &'a mut Cpu,
+    bus: &'a mut Bus,
+    start: u32,
+    pub opcode: u16,
+}
+
+impl<'a> Exec<'a> {
+    pub fn new(cpu: &'a mut Cpu, bus: &'a mut Bus) -> Self {
+        let start = cpu.pc;
+        Exec {
+            cpu,
+            bus,
+            start,
+            opcode: 0,
+        }
+    }
+
+    // Exceptions
+
+    fn exception(&self, vector: u8, pc: u32) -> Exception {
+        Exception {
+            vector,
+            pc,
+            instruction: self.start,
+            opcode: self.opcode,
+            access: None,
+            level: None,
+        }
+    }
+
+    /// An exception that returns to the instruction that caused it
+    fn fault(&self, vector: u8) -> Exception {
+        self.exception(vector, self.start)
+    }
+
+    fn illegal(&self) -> Exception {
+        self.fault(vector::ILLEGAL_INSTRUCTION)
+    }
+
+    /// An exception that returns to the next instruction
+    fn trap(&self, vector: u8) -> Exception {
+        self.exception(vector, self.cpu.pc)
+    }
+
+    fn access_error(&self, vector: u8, access: Access) -> Exception {
+        // The 68010 and later restart the instruction; the 68000 reports how far it got
+        let pc = if self.cpu.model == Model::M68000 {
+            self.cpu.pc
+        } else {
+            self.start
+        };
+        Exception {
+            access: Some(access),
+            ..self.exception(vector, pc)
+        }
+    }
+
+    fn supervisor(&self) -> Result<()> {
+        if self.cpu.supervisor() {
+            Ok(())
+        } else {
+            Err(self.fault(vector::PRIVILEGE_VIOLATION))
+        }
+    }
+
+    fn require(&self, model: Model) -> Result<()> {
+        if self.cpu.model >= model {
+            Ok(())
+        } else {
+            Err(self.illegal())
+        }
+    }
+
+    // Bus accesses
+
+    fn access(&mut self, address: u32, size: Size, write: bool, program: bool) -> Result<()> {
+        let access = Access {
+            address,
+            size,
+            write,
+            program,
+        };
+        let odd = size != Size::Byte && address & 1 != 0;
+        if odd && (program || self.cpu.model < Model::M68020) {
+            return Err(self.access_error(vector::ADDRESS_ERROR, access));
+        }
+        self.cpu.cycles += if size == Size::Long { 8 } else { 4 };
+        Ok(())
+    }
+
+    fn bus_error(&self, address: u32, size: Size, write: bool, program: bool) -> Exception {
+        self.access_error(
+            vector::BUS_ERROR,
+            Access {
+                address,
+                size,
+                write,
+                program,
+            },
+        )
+    }
+
+    fn read(&mut self, address: u32, size: Size) -> Result<u32> {
+        self.access(address, size, false, false)?;
+        self.bus.watch(address, size, false);
+        self.bus
+            .read(address, size)
+            .map_err(|_| self.bus_error(address, size, false, false))
+    }
+
+    fn write(&mut self, address: u32, size: Size, value: u32) -> Result<()> {
+        self.access(address, size, true, false)?;
+        self.bus.watch(address, size, true);
+        self.bus
+            .write(address, size, value & size.mask())
+            .map_err(|_| self.bus_error(address, size, true, false))
+    }
+
+    fn fetch(&mut self) -> Result<u16> {
+        let pc = self.cpu.pc;
+        self.access(pc, Size::Word, false, true)?;
+        let word = self
+            .bus
+            .read(pc, Size::Word)
+            .map_err(|_| self.bus_error(pc, Size::Word, false, true))?;
+        self.cpu.pc = pc.wrapping_add(2);
+        Ok(word as u16)
+    }
+
+    fn fetch_long(&mut self) -> Result<u32> {
+        let high = self.fetch()?;
+        let low = self.fetch()?;
+        Ok(u32::from(high) << 16 | u32::from(low))
+    }
+
+    fn push(&mut self, size: Size, value: u32) -> Result<()> {
+        let sp = self.cpu.a[7].wrapping_sub(size.bytes());
+        self.cpu.a[7] = sp;
+        self.write(sp, size, value)
+    }
+
+    fn pop(&mut self, size: Size) -> Result<u32> {
+        let sp = self.cpu.a[7];
+        let value = self.read(sp, size)?;
+        self.cpu.a[7] = sp.wrapping_add(size.bytes());
+        Ok(value)
+    }
+
+    // Registers and flags
+
+    fn set_d(&mut self, reg: usize, size: Size, value: u32) {
+        let mask = size.mask();
+        self.cpu.d[reg] = self.cpu.d[reg] & !mask | value & mask;
+    }
+
+    fn flag(&self, bit: u16) -> bool {
+        self.cpu.sr() & bit != 0
+    }
+
+    fn set_flags(&mut self, n: bool, z: bool, v: bool, c: bool) {
+        let mut ccr = self.cpu.sr() & sr::X;
+        for (set, bit) in [(n, sr::N), (z, sr::Z), (v, sr::V), (c, sr::C)] {
+            if set {
+                ccr |= bit;
+            }
+        }
+        self.cpu.set_ccr(ccr);
+    }
+
+    fn set_x(&mut self, x: bool) {
+        let ccr = self.cpu.sr() & 0xf;
+        self.cpu.set_ccr(if x { ccr | sr::X } else { ccr });
+    }
+
+    /// N and Z from the result, V and C cleared, as logical operations and moves do
+    fn set_logic_flags(&mut self, value: u32, size: Size) {
+        self.set_flags(
+            value & size.msb() != 0,
+            value & size.mask() == 0,
+            false,
+            false,
+        );
+    }
+
+    fn add(&mut self, src: u32, dst: u32, size: Size, extend: bool) -> u32 {
+        let x = u32::from(extend && self.flag(sr::X));
+        let (src, dst) = (src & size.mask(), dst & size.mask());
+        let result = dst.wrapping_add(src).wrapping_add(x) & size.mask();
+        let carry = (src & dst | !result & (src | dst)) & size.msb() != 0;
+        let overflow = (src ^ result) & (dst ^ result) & size.msb() != 0;
+        let zero = result == 0 && (!extend || self.flag(sr::Z));
+        self.set_flags(result & size.msb() != 0, zero, overflow, carry);
+        self.set_x(carry);
+        result
+    }
+
+    /// `dst - src`; the flags of `cmp` if `compare`, which leaves X alone
+    fn sub(&mut self, src: u32, dst: u32, size: Size, extend: bool, compare: bool) -> u32 {
+        let x = u32::from(extend && self.flag(sr::X));
+        let (src, dst) = (src & size.mask(), dst & size.mask());
+        let result = dst.wrapping_sub(src).wrapping_sub(x) & size.mask();
+        let borrow = (src & !dst | result & !dst | src & result) & size.msb() != 0;
+        let overflow = (src ^ dst) & (result ^ dst) & size.msb() != 0;
+        let zero = result == 0 && (!extend || self.flag(sr::Z));
+        self.set_flags(result & size.msb() != 0, zero, overflow, borrow);
+        if !compare {
+            self.set_x(borrow);
+        }
+        result
+    }
+
+    fn condition(&self, condition: u16) -> bool {
+        let (c, v, z, n) = (
+            self.flag(sr::C),
+            self.flag(sr::V),
+            self.flag(sr::Z),
+            self.flag(sr::N),
+        );
+        match condition & 0xf {
+            0x0 => true,
+            0x1 => false,
+            0x2 => !c && !z,
+            0x3 => c || z,
+            0x4 => !c,
+            0x5 => c,
+            0x6 => !z,
+            0x7 => z,
+            0x8 => !v,
+            0x9 => v,
+            0xa => !n,
+            0xb => n,
+            0xc => n == v,
+            0xd => n != v,
+            0xe => !z && n == v,
+            _ => z || n != v,
+        }
+    }
+
+    // Effective addresses
+
+    /// Decodes the effective address in the low six bits of the opcode
+    fn ea(&mut self, allowed: u16, size: Size) -> Result<Ea> {
+        self.ea_at(self.opcode >> 3 & 7, self.opcode & 7, allowed, size)
+    }
+
+    fn ea_at(&mut self, mode: u16, reg: u16, allowed: u16, size: Size) -> Result<Ea> {
+        let bit = mode_bit(mode, reg);
+        if bit & allowed == 0 || (bit == AN && size == Size::Byte) {
+            return Err(self.illegal());
+        }
+        let r = usize::from(reg);
+        Ok(match bit {
+            DN => Ea::D(r),
+            AN => Ea::A(r),
+            IND => Ea::Memory(self.cpu.a[r]),
+            POST => {
+                let address = self.cpu.a[r];
+                self.cpu.a[r] = address.wrapping_add(self.step(r, size));
+                Ea::Memory(address)
+            }
+            PRE => {
+                let address = self.cpu.a[r].wrapping_sub(self.step(r, size));
+                self.cpu.a[r] = address;
+                self.cpu.cycles += 2;
+                Ea::Memory(address)
+            }
+            DISP => {
+                let displacement = self.fetch()? as i16 as u32;
+                Ea::Memory(self.cpu.a[r].wrapping_add(displacement))
+            }
+            INDEX => {
+                let base = self.cpu.a[r];
+                Ea::Memory(self.index(base)?)
+            }
+            ABS_W => Ea::Memory(self.fetch()? as i16 as u32),
+            ABS_L => Ea::Memory(self.fetch_long()?),
+            PC_DISP => {
+                let base = self.cpu.pc;
+                let displacement = self.fetch()? as i16 as u32;
+                Ea::Memory(base.wrapping_add(displacement))
+            }
+            PC_INDEX => {
+                let base = self.cpu.pc;
+                Ea::Memory(self.index(base)?)
+            }
+            _ => Ea::Immediate(match size {
+                Size::Byte => u32::from(self.fetch()? & 0xff),
+                Size::Word => u32::from(self.fetch()?),
+                Size::Long => self.fetch_long()?,
+            }),
+        })
+    }
+
+    /// Address increment of `(An)+` and `-(An)`: the stack pointer stays even
+    fn step(&self, reg: usize, size: Size) -> u32 {
+        if reg == 7 && size == Size::Byte {
+            2
+        } else {
+            size.bytes()
+        }
+    }
+
+    /// Indexed addressing, from the extension word on
+    fn index(&mut self, base: u32) -> Result<u32> {
+        let extension = self.fetch()?;
+        self.cpu.cycles += 2;
+        let full = extension & 0x100 != 0 && self.cpu.model >= Model::M68020;
+        let index = {
+            let reg = usize::from(extension >> 12 & 7);
+            let value = if extension & 0x8000 != 0 {
+                self.cpu.a[reg]
+            } else {
+                self.cpu.d[reg]
+            };
+            let value = if extension & 0x800 != 0 {
+                value
+            } else {
+                value as u16 as i16 as u32
+            };
+            // The 68000 and 68010 ignore the scale
+            if self.cpu.model >= Model::M68020 {
+                value << (extension >> 9 & 3)
+            } else {
+                value
+            }
+        };
+        if !full {
+            let displacement = extension as u8 as i8 as u32;
+            return Ok(base.wrapping_add(displacement).wrapping_add(index));
+        }
+
+        // Full extension word
+        let base = if extension & 0x80 != 0 { 0 } else { base };
+        let index = if extension & 0x40 != 0 { 0 } else { index };
+        let base_displacement = match extension >> 4 & 3 {
+            1 => 0,
+            2 => self.fetch()? as i16 as u32,
+            3 => self.fetch_long()?,
+            _ => return Err(self.illegal()),
+        };
+        let indirect = extension & 7;
+        let outer = |exec: &mut Self| -> Result<u32> {
+            match indirect & 3 {
+                2 => Ok(exec.fetch()? as i16 as u32),
+                3 => exec.fetch_long(),
+                _ => Ok(0),
+            }
+        };
+        let address = base.wrapping_add(base_displacement);
+        match (extension & 0x40 != 0, indirect) {
+            (_, 0) => Ok(address.wrapping_add(index)),
+            // Pre-indexed, or without index
+            (_, 1..=3) => {
+                let outer = outer(self)?;
+                let pointer = self.read(address.wrapping_add(index), Size::Long)?;
+                Ok(pointer.wrapping_add(outer))
+            }
+            // Post-indexed
+            (false, 5..=7) => {
+                let outer = outer(self)?;
+                let pointer = self.read(address, Size::Long)?;
+                Ok(pointer.wrapping_add(index).wrapping_add(outer))
+            }
+            _ => Err(self.illegal()),
+        }
+    }
+
+    fn read_ea(&mut self, ea: Ea, size: Size) -> Result<u32> {
+        match ea {
+            Ea::D(reg) => Ok(self.cpu.d[reg] & size.mask()),
+            Ea::A(reg) => Ok(self.cpu.a[reg] & size.mask()),
+            Ea::Memory(address) => self.read(address, size),
+            Ea::Immediate(value) => Ok(value),
+        }
+    }
+
+    fn write_ea(&mut self, ea: Ea, size: Size, value: u32) -> Result<()> {
+        match ea {
+            Ea::D(reg) => {
+                self.set_d(reg, size, value);
+                Ok(())
+            }
+            Ea::A(reg) => {
+                self.cpu.a[reg] = size.sign_extend(value);
+                Ok(())
+            }
+            Ea::Memory(address) => self.write(address, size, value),
+            Ea::Immediate(_) => Err(self.illegal()),
+        }
+    }
+
+    fn address(&self, ea: Ea) -> Result<u32> {
+        match ea {
+            Ea::Memory(address) => Ok(address),
+            _ => Err(self.illegal()),
+        }
+    }
+
+    // Execution
+
+    pub fn execute(&mut self) -> Result<()> {
+        self.opcode = self.fetch()?;
+        match self.opcode >> 12 {
+            0x0 => self.group_0(),
+            0x1..=0x3 => self.mov(),
+            0x4 => self.group_4(),
+            0x5 => self.group_5(),
+            0x6 => self.branch(),
+            0x7 => self.moveq(),
+            0x8 => self.group_8(),
+            0x9 | 0xd => self.add_sub(),
+            0xa => Err(self.fault(vector::LINE_1010)),
+            0xb => self.group_b(),
+            0xc => self.group_c(),
+            0xe => self.shift(),
+            _ => Err(self.fault(vector::LINE_1111)),
+        }
+    }
+
+    fn reg_9(&self) -> usize {
+        usize::from(self.opcode >> 9 & 7)
+    }
+
+    fn reg_0(&self) -> usize {
+        usize::from(self.opcode & 7)
+    }
+
+    // Bit manipulation, MOVEP, immediate operations
+
+    fn group_0(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        if opcode & 0x0138 == 0x0108 {
+            return self.movep();
+        }
+        if opcode & 0x0100 != 0 {
+            let bit = self.cpu.d[self.reg_9()];
+            return self.bit_op(bit, true);
+        }
+        if opcode & 0x0f00 == 0x0800 {
+            let bit = u32::from(self.fetch()? & 0xff);
+            return self.bit_op(bit, false);
+        }
+        match opcode {
+            0x003c | 0x023c | 0x0a3c => {
+                let value = self.fetch()? & 0x1f;
+                let ccr = self.cpu.sr() & 0x1f;
+                self.cpu.set_ccr(match opcode {
+                    0x003c => ccr | value,
+                    0x023c => ccr & value,
+                    _ => ccr ^ value,
+                });
+                return Ok(());
+            }
+            0x007c | 0x027c | 0x0a7c => {
+                self.supervisor()?;
+                let value = self.fetch()?;
+                let old = self.cpu.sr();
+                self.cpu.set_sr(match opcode {
+                    0x007c => old | value,
+                    0x027c => old & value,
+                    _ => old ^ value,
+                });
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let Some(size) = size_76(opcode) else {
+            return match opcode & 0x0f00 {
+                0x0000 | 0x0200 | 0x0400 if opcode & 0x0800 == 0 => self.cmp2(),
+                0x0a00 | 0x0c00 | 0x0e00 => self.cas(),
+                _ => Err(self.illegal()),
+            };
+        };
+        if opcode & 0x0f00 == 0x0e00 {
+            return self.moves(size);
+        }
+
+        let source = match size {
+            Size::Long => self.fetch_long()?,
+            _ => u32::from(self.fetch()?) & size.mask(),
+        };
+        let operation = opcode >> 9 & 7;
+        let allowed = if operation == 6 && self.cpu.model >= Model::M68020 {
+            DATA & !IMM
+        } else {
+            DATA_ALTERABLE
+        };
+        let ea = self.ea(allowed, size)?;
+        let destination = self.read_ea(ea, size)?;
+        let result = match operation {
+            0 => destination | source,
+            1 => destination & source,
+            2 => self.sub(source, destination, size, false, false),
+            3 => self.add(source, destination, size, false),
+            5 => destination ^ source,
+            6 => {
+                self.sub(source, destination, size, false, true);
+                return Ok(());
+            }
+            _ => return Err(self.illegal()),
+        };
+        if matches!(operation, 0 | 1 | 5) {
+            self.set_logic_flags(result, size);
+        }
+        if let Ea::D(_) = ea {
+            if size == Size::Long {
+                self.cpu.cycles += 4;
+            }
+        }
+        self.write_ea(ea, size, result)
+    }
+
+    fn bit_op(&mut self, bit: u32, dynamic: bool) -> Result<()> {
+        let operation = self.opcode >> 6 & 3;
+        let allowed = match (operation, dynamic) {
+            (0, true) => DATA,
+            (0, false) => DATA & !IMM,
+            _ => DATA_ALTERABLE,
+        };
+        let mode = self.opcode >> 3 & 7;
+        let size = if mode == 0 { Size::Long } else { Size::Byte };
+        let ea = self.ea(allowed, size)?;
+        let mask = 1 << (bit % (size.bytes() * 8));
+        let value = self.read_ea(ea, size)?;
+        let ccr = self.cpu.sr() & !sr::Z & 0x1f;
+        self.cpu
+            .set_ccr(if value & mask == 0 { ccr | sr::Z } else { ccr });
+        let result = match operation {
+            0 => return Ok(()),
+            1 => value ^ mask,
+            2 => value & !mask,
+            _ => value | mask,
+        };
+        self.cpu.cycles += 2;
+        self.write_ea(ea, size, result)
+    }
+
+    fn movep(&mut self) -> Result<()> {
+        let displacement = self.fetch()? as i16 as u32;
+        let address = self.cpu.a[self.reg_0()].wrapping_add(displacement);
+        let reg = self.reg_9();
+        let bytes = if self.opcode & 0x40 != 0 { 4 } else { 2 };
+        if self.opcode & 0x80 != 0 {
+            let value = self.cpu.d[reg];
+            for i in 0..bytes {
+                let byte = value >> (8 * (bytes - 1 - i));
+                self.write(address.wrapping_add(2 * i), Size::Byte, byte)?;
+            }
+        } else {
+            let mut value = 0;
+            for i in 0..bytes {
+                value = value << 8 | self.read(address.wrapping_add(2 * i), Size::Byte)?;
+            }
+            let size = if bytes == 4 { Size::Long } else { Size::Word };
+            self.set_d(reg, size, value);
+        }
+        Ok(())
+    }
+
+    fn moves(&mut self, size: Size) -> Result<()> {
+        self.require(Model::M68010)?;
+        self.supervisor()?;
+        let extension = self.fetch()?;
+        let ea = self.ea(MEMORY_ALTERABLE, size)?;
+        let reg = usize::from(extension >> 12 & 7);
+        let address_register = extension & 0x8000 != 0;
+        // Function codes aren't decoded: all spaces are the same memory
+        if extension & 0x800 != 0 {
+            let value = if address_register {
+                self.cpu.a[reg]
+            } else {
+                self.cpu.d[reg]
+            };
+            self.write_ea(ea, size, value)
+        } else {
+            let value = self.read_ea(ea, size)?;
+            if address_register {
+                self.cpu.a[reg] = size.sign_extend(value);
+            } else {
+                self.set_d(reg, size, value);
+            }
+            Ok(())
+        }
+    }
+
+    fn cmp2(&mut self) -> Result<()> {
+        self.require(Model::M68020)?;
+        let size = match self.opcode >> 9 & 3 {
+            0 => Size::Byte,
+            1 => Size::Word,
+            _ => Size::Long,
+        };
+        let extension = self.fetch()?;
+        let ea = self.ea(CONTROL, size)?;
+        let address = self.address(ea)?;
+        let lower = self.read(address, size)?;
+        let upper = self.read(address.wrapping_add(size.bytes()), size)?;
+        let reg = usize::from(extension >> 12 & 7);
+
+        // Address registers are compared as long words, with sign-extended bounds
+        let (value, lower, upper, size) = if extension & 0x8000 != 0 {
+            let lower = size.sign_extend(lower);
+            let upper = size.sign_extend(upper);
+            (self.cpu.a[reg], lower, upper, Size::Long)
+        } else {
+            (self.cpu.d[reg] & size.mask(), lower, upper, size)
+        };
+        let in_bounds = if lower <= upper {
+            lower <= value && value <= upper
+        } else {
+            // The bounds are signed
+            let signed = |v: u32| size.sign_extend(v) as i32;
+            signed(lower) <= signed(value) && signed(value) <= signed(upper)
+        };
+        let ccr = self.cpu.sr() & (sr::X | sr::N | sr::V);
+        let mut flags = ccr;
+        if value == lower || value == upper {
+            flags |= sr::Z;
+        }
+        if !in_bounds {
+            flags |= sr::C;
+        }
+        self.cpu.set_ccr(flags);
+        if extension & 0x800 != 0 && !in_bounds {
+            return Err(self.trap(vector::CHK));
+        }
+        Ok(())
+    }
+
+    fn cas(&mut self) -> Result<()> {
+        self.require(Model::M68020)?;
+        let size = match self.opcode >> 9 & 3 {
+            1 => Size::Byte,
+            2 => Size::Word,
+            _ => Size::Long,
+        };
+        let extension = self.fetch()?;
+        if extension & 0xfe38 != 0 {
+            return Err(self.illegal());
+        }
+        let ea = self.ea(MEMORY_ALTERABLE, size)?;
+        let compare = usize::from(extension & 7);
+        let update = usize::from(extension >> 6 & 7);
+        let value = self.read_ea(ea, size)?;
+        self.sub(self.cpu.d[compare], value, size, false, true);
+        if self.flag(sr::Z) {
+            self.write_ea(ea, size, self.cpu.d[update])
+        } else {
+            self.set_d(compare, size, value);
+            Ok(())
+        }
+    }
+
+    fn mov(&mut self) -> Result<()> {
+        let size = match self.opcode >> 12 {
+            1 => Size::Byte,
+            3 => Size::Word,
+            _ => Size::Long,
+        };
+        let source = self.ea(ALL, size)?;
+        let value = self.read_ea(source, size)?;
+        let mode = self.opcode >> 6 & 7;
+        let reg = self.opcode >> 9 & 7;
+        if mode == 1 {
+            if size == Size::Byte {
+                return Err(self.illegal());
+            }
+            self.cpu.a[usize::from(reg)] = size.sign_extend(value);
+            return Ok(());
+        }
+        let destination = self.ea_at(mode, reg, DATA_ALTERABLE, size)?;
+        self.set_logic_flags(value, size);
+        self.write_ea(destination, size, value)
+    }
+
+    fn moveq(&mut self) -> Result<()> {
+        if self.opcode & 0x100 != 0 {
+            return Err(self.illegal());
+        }
+        let value = self.opcode as u8 as i8 as u32;
+        self.cpu.d[self.reg_9()] = value;
+        self.set_logic_flags(value, Size::Long);
+        Ok(())
+    }
+
+    // Miscellaneous
+
+    fn group_4(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        match opcode {
+            0x4afc => return Err(self.illegal()),
+            0x4e70 => {
+                self.supervisor()?;
+                self.cpu.cycles += 128;
+                return Ok(());
+            }
+            0x4e71 => return Ok(()),
+            0x4e72 => {
+                self.supervisor()?;
+                let value = self.fetch()?;
+                self.cpu.set_sr(value);
+                self.cpu.stopped = true;
+                return Ok(());
+            }
+            0x4e73 => return self.rte(),
+            0x4e74 => {
+                self.require(Model::M68010)?;
+                let displacement = self.fetch()? as i16 as u32;
+                self.cpu.pc = self.pop(Size::Long)?;
+                self.cpu.a[7] = self.cpu.a[7].wrapping_add(displacement);
+                return Ok(());
+            }
+            0x4e75 => {
+                self.cpu.pc = self.pop(Size::Long)?;
+                return Ok(());
+            }
+            0x4e76 => {
+                if self.flag(sr::V) {
+                    return Err(self.trap(vector::TRAPV));
+                }
+                return Ok(());
+            }
+            0x4e77 => {
+                let ccr = self.pop(Size::Word)?;
+                self.cpu.set_ccr(ccr as u16);
+                self.cpu.pc = self.pop(Size::Long)?;
+                return Ok(());
+            }
+            0x4e7a | 0x4e7b => return self.movec(),
+            _ => {}
+        }
+
+        match opcode & 0xfff8 {
+            0x4e40 | 0x4e48 => {
+                return Err(self.trap(vector::TRAP + (opcode & 0xf) as u8));
+            }
+            0x4e50 => {
+                let displacement = self.fetch()? as i16 as u32;
+                return self.link(displacement);
+            }
+            0x4808 => {
+                self.require(Model::M68020)?;
+                let displacement = self.fetch_long()?;
+                return self.link(displacement);
+            }
+            0x4e58 => {
+                let reg = self.reg_0();
+                self.cpu.a[7] = self.cpu.a[reg];
+                self.cpu.a[reg] = self.pop(Size::Long)?;
+                return Ok(());
+            }
+            0x4e60 => {
+                self.supervisor()?;
+                self.cpu.set_usp(self.cpu.a[self.reg_0()]);
+                return Ok(());
+            }
+            0x4e68 => {
+                self.supervisor()?;
+                self.cpu.a[self.reg_0()] = self.cpu.usp();
+                return Ok(());
+            }
+            0x4840 => {
+                let reg = self.reg_0();
+                self.cpu.d[reg] = self.cpu.d[reg].rotate_left(16);
+                self.set_logic_flags(self.cpu.d[reg], Size::Long);
+                return Ok(());
+            }
+            // BKPT: there is no debugger hardware to answer
+            0x4848 => return Err(self.illegal()),
+            0x4880 | 0x48c0 | 0x49c0 => {
+                if opcode & 0xfff8 == 0x49c0 {
+                    self.require(Model::M68020)?;
+                }
+                let reg = self.reg_0();
+                let (from, to) = match opcode & 0xfff8 {
+                    0x4880 => (Size::Byte, Size::Word),
+                    0x48c0 => (Size::Word, Size::Long),
+                    _ => (Size::Byte, Size::Long),
+                };
+                let value = from.sign_extend(self.cpu.d[reg]);
+                self.set_d(reg, to, value);
+                self.set_logic_flags(value, to);
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        match opcode & 0xffc0 {
+            0x4e80 | 0x4ec0 => {
+                let ea = self.ea(CONTROL, Size::Long)?;
+                let target = self.address(ea)?;
+                if opcode & 0x40 == 0 {
+                    self.push(Size::Long, self.cpu.pc)?;
+                }
+                self.cpu.pc = target;
+                return Ok(());
+            }
+            0x4840 => {
+                let ea = self.ea(CONTROL, Size::Long)?;
+                let address = self.address(ea)?;
+                return self.push(Size::Long, address);
+            }
+            0x4880 | 0x48c0 => return self.movem_to_memory(),
+            0x4c80 | 0x4cc0 => return self.movem_to_registers(),
+            0x4c00 => return self.mull(),
+            0x4c40 => return self.divl(),
+            0x4ac0 => {
+                let ea = self.ea(DATA_ALTERABLE, Size::Byte)?;
+                let value = self.read_ea(ea, Size::Byte)?;
+                self.set_logic_flags(value, Size::Byte);
+                return self.write_ea(ea, Size::Byte, value | 0x80);
+            }
+            0x40c0 => {
+                if self.cpu.model >= Model::M68010 {
+                    self.supervisor()?;
+                }
+                let ea = self.ea(DATA_ALTERABLE, Size::Word)?;
+                let value = self.cpu.sr();
+                return self.write_ea(ea, Size::Word, value.into());
+            }
+            0x42c0 => {
+                self.require(Model::M68010)?;
+                let ea = self.ea(DATA_ALTERABLE, Size::Word)?;
+                let value = self.cpu.sr() & 0x1f;
+                return self.write_ea(ea, Size::Word, value.into());
+            }
+            0x44c0 => {
+                let ea = self.ea(DATA, Size::Word)?;
+                let value = self.read_ea(ea, Size::Word)?;
+                self.cpu.set_ccr(value as u16);
+                return Ok(());
+            }
+            0x46c0 => {
+                self.supervisor()?;
+                let ea = self.ea(DATA, Size::Word)?;
+                let value = self.read_ea(ea, Size::Word)?;
+                self.cpu.set_sr(value as u16);
+                return Ok(());
+            }
+            0x4800 => {
+                let ea = self.ea(DATA_ALTERABLE, Size::Byte)?;
+                let value = self.read_ea(ea, Size::Byte)?;
+                let result = self.sbcd(value, 0);
+                return self.write_ea(ea, Size::Byte, result);
+            }
+            _ => {}
+        }
+
+        match opcode & 0xf1c0 {
+            0x41c0 => {
+                let ea = self.ea(CONTROL, Size::Long)?;
+                self.cpu.a[self.reg_9()] = self.address(ea)?;
+                return Ok(());
+            }
+            0x4180 => return self.chk(Size::Word),
+            0x4100 => {
+                self.require(Model::M68020)?;
+                return self.chk(Size::Long);
+            }
+            _ => {}
+        }
+
+        let size = size_76(opcode).ok_or_else(|| self.illegal())?;
+        match opcode & 0xff00 {
+            0x4a00 => {
+                let allowed = if self.cpu.model >= Model::M68020 {
+                    ALL
+                } else {
+                    DATA_ALTERABLE
+                };
+                let ea = self.ea(allowed, size)?;
+                let value = self.read_ea(ea, size)?;
+                self.set_logic_flags(value, size);
+                Ok(())
+            }
+            0x4000 | 0x4200 | 0x4400 | 0x4600 => {
+                let ea = self.ea(DATA_ALTERABLE, size)?;
+                let value = self.read_ea(ea, size)?;
+                let result = match opcode & 0xff00 {
+                    0x4000 => self.sub(value, 0, size, true, false),
+                    0x4200 => {
+                        self.set_logic_flags(0, size);
+                        0
+                    }
+                    0x4400 => self.sub(value, 0, size, false, false),
+                    _ => {
+                        let result = !value & size.mask();
+                        self.set_logic_flags(result, size);
+                        result
+                    }
+                };
+                if let (Ea::D(_), Size::Long) = (ea, size) {
+                    self.cpu.cycles += 2;
+                }
+                self.write_ea(ea, size, result)
+            }
+            _ => Err(self.illegal()),
+        }
+    }
+
+    fn link(&mut self, displacement: u32) -> Result<()> {
+        let reg = self.reg_0();
+        // `link a7` pushes the value a7 has after the push
+        self.push(Size::Long, self.cpu.a[reg])?;
+        if reg == 7 {
+            let sp = self.cpu.a[7];
+            self.write(sp, Size::Long, sp)?;
+        }
+        self.cpu.a[reg] = self.cpu.a[7];
+        self.cpu.a[7] = self.cpu.a[7].wrapping_add(displacement);
+        Ok(())
+    }
+
+    fn rte(&mut self) -> Result<()> {
+        self.supervisor()?;
+        let sp = self.cpu.a[7];
+        let sr = self.read(sp, Size::Word)? as u16;
+        let pc = self.read(sp.wrapping_add(2), Size::Long)?;
+        let size = if self.cpu.model == Model::M68000 {
+            6
+        } else {
+            let format = self.read(sp.wrapping_add(6), Size::Word)? as u16 >> 12;
+            match self.cpu.frame_size(format) {
+                Some(size) => size,
+                None => return Err(self.fault(vector::FORMAT_ERROR)),
+            }
+        };
+        self.cpu.a[7] = sp.wrapping_add(size);
+        self.cpu.set_sr(sr);
+        self.cpu.pc = pc;
+        self.cpu.cycles += 4;
+        Ok(())
+    }
+
+    fn movec(&mut self) -> Result<()> {
+        self.require(Model::M68010)?;
+        self.supervisor()?;
+        let extension = self.fetch()?;
+        let reg = usize::from(extension >> 12 & 7);
+        let address_register = extension & 0x8000 != 0;
+        let control = extension & 0xfff;
+        let known = match control {
+            0x000 | 0x001 | 0x800 | 0x801 => true,
+            0x002 | 0x803 | 0x804 => self.cpu.model >= Model::M68020,
+            0x802 => self.cpu.model == Model::M68020,
+            0x003..=0x007 | 0x805..=0x807 => self.cpu.model == Model::M68040,
+            _ => false,
+        };
+        if !known {
+            return Err(self.illegal());
+        }
+
+        if self.opcode & 1 == 0 {
+            let value = match control {
+                0x000 => u32::from(self.cpu.sfc),
+                0x001 => u32::from(self.cpu.dfc),
+                0x002 => self.cpu.cacr,
+                0x800 => self.cpu.usp(),
+                0x801 => self.cpu.vbr,
+                0x804 => self.cpu.ssp(),
+                // The master stack pointer and the MMU registers aren't emulated
+                _ => 0,
+            };
+            if address_register {
+                self.cpu.a[reg] = value;
+            } else {
+                self.cpu.d[reg] = value;
+            }
+        } else {
+            let value = if address_register {
+                self.cpu.a[reg]
+            } else {
+                self.cpu.d[reg]
+            };
+            match control {
+                0x000 => self.cpu.sfc = value as u8 & 7,
+                0x001 => self.cpu.dfc = value as u8 & 7,
+                0x002 => self.cpu.cacr = value,
+                0x800 => self.cpu.set_usp(value),
+                0x801 => self.cpu.vbr = value,
+                0x804 => self.cpu.set_ssp(value),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn movem_to_memory(&mut self) -> Result<()> {
+        let size = if self.opcode & 0x40 != 0 {
+            Size::Long
+        } else {
+            Size::Word
+        };
+        let mask = self.fetch()?;
+        let mode = self.opcode >> 3 & 7;
+        let reg = self.reg_0();
+        let registers: Vec<u32> = self.cpu.d.iter().chain(&self.cpu.a).copied().collect();
+
+        if mode == 4 {
+            // -(An): the mask is reversed, and registers are stored from a7 down to d0
+            let mut address = self.cpu.a[reg];
+            for i in (0..16).rev() {
+                if mask & 1 << (15 - i) != 0 {
+                    address = address.wrapping_sub(size.bytes());
+                    // The 68020 and later store the decremented address register
+                    let value = if i == 8 + reg && self.cpu.model >= Model::M68020 {
+                        self.cpu.a[reg].wrapping_sub(size.bytes())
+                    } else {
+                        registers[i]
+                    };
+                    self.write(address, size, value)?;
+                }
+            }
+            self.cpu.a[reg] = address;
+            return Ok(());
+        }
+
+        let ea = self.ea(CONTROL_ALTERABLE, size)?;
+        let mut address = self.address(ea)?;
+        for (i, &value) in registers.iter().enumerate() {
+            if mask & 1 << i != 0 {
+                self.write(address, size, value)?;
+                address = address.wrapping_add(size.bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn movem_to_registers(&mut self) -> Result<()> {
+        let size = if self.opcode & 0x40 != 0 {
+            Size::Long
+        } else {
+            Size::Word
+        };
+        let mask = self.fetch()?;
+        let mode = self.opcode >> 3 & 7;
+        let reg = self.reg_0();
+        let mut address = if mode == 3 {
+            self.cpu.a[reg]
+        } else {
+            let ea = self.ea(CONTROL, size)?;
+            self.address(ea)?
+        };
+        for i in 0..16 {
+            if mask & 1 << i != 0 {
+                let value = size.sign_extend(self.read(address, size)?);
+                if i < 8 {
+                    self.cpu.d[i] = value;
+                } else {
+                    self.cpu.a[i - 8] = value;
+                }
+                address = address.wrapping_add(size.bytes());
+            }
+        }
+        if mode == 3 {
+            self.cpu.a[reg] = address;
+        }
+        Ok(())
+    }
+
+    fn chk(&mut self, size: Size) -> Result<()> {
+        let ea = self.ea(DATA, size)?;
+        let bound = size.sign_extend(self.read_ea(ea, size)?) as i32;
+        let value = size.sign_extend(self.cpu.d[self.reg_9()]) as i32;
+        self.cpu.cycles += 6;
+        let ccr = self.cpu.sr() & (sr::X | sr::Z | sr::V | sr::C);
+        if value < 0 {
+            self.cpu.set_ccr(ccr | sr::N);
+            Err(self.trap(vector::CHK))
+        } else if value > bound {
+            self.cpu.set_ccr(ccr);
+            Err(self.trap(vector::CHK))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn mull(&mut self) -> Result<()> {
+        self.require(Model::M68020)?;
+        let extension = self.fetch()?;
+        let ea = self.ea(DATA, Size::Long)?;
+        let source = self.read_ea(ea, Size::Long)?;
+        let low = usize::from(extension >> 12 & 7);
+        let high = usize::from(extension & 7);
+        let signed = extension & 0x800 != 0;
+        let quad = extension & 0x400 != 0;
+        let destination = self.cpu.d[low];
+        let product = if signed {
+            (i64::from(source as i32) * i64::from(destination as i32)) as u64
+        } else {
+            u64::from(source) * u64::from(destination)
+        };
+        self.cpu.cycles += 40;
+        if quad {
+            self.cpu.d[low] = product as u32;
+            self.cpu.d[high] = (product >> 32) as u32;
+            self.set_flags(product >> 63 != 0, product == 0, false, false);
+        } else {
+            let result = product as u32;
+            let overflow = if signed {
+                product as i64 != i64::from(result as i32)
+            } else {
+                product >> 32 != 0
+            };
+            self.cpu.d[low] = result;
+            self.set_flags(result >> 31 != 0, result == 0, overflow, false);
+        }
+        Ok(())
+    }
+
+    fn divl(&mut self) -> Result<()> {
+        self.require(Model::M68020)?;
+        let extension = self.fetch()?;
+        let ea = self.ea(DATA, Size::Long)?;
+        let divisor = self.read_ea(ea, Size::Long)?;
+        let quotient_reg = usize::from(extension >> 12 & 7);
+        let remainder_reg = usize::from(extension & 7);
+        let signed = extension & 0x800 != 0;
+        let quad = extension & 0x400 != 0;
+        if divisor == 0 {
+            return Err(self.trap(vector::ZERO_DIVIDE));
+        }
+        self.cpu.cycles += 130;
+
+        let dividend = if quad {
+            u64::from(self.cpu.d[remainder_reg]) << 32 | u64::from(self.cpu.d[quotient_reg])
+        } else if signed {
+            self.cpu.d[quotient_reg] as i32 as i64 as u64
+        } else {
+            u64::from(self.cpu.d[quotient_reg])
+        };
+        let result = if signed {
+            let dividend = dividend as i64;
+            let divisor = i64::from(divisor as i32);
+            match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+                (Some(q), Some(r)) if q == i64::from(q as i32) => Some((q as u32, r as u32)),
+                _ => None,
+            }
+        } else {
+            let q = dividend / u64::from(divisor);
+            let r = dividend % u64::from(divisor);
+            if q >> 32 == 0 {
+                Some((q as u32, r as u32))
+            } else {
+                None
+            }
+        };
+        match result {
+            Some((quotient, remainder)) => {
+                if remainder_reg != quotient_reg {
+                    self.cpu.d[remainder_reg] = remainder;
+                }
+                self.cpu.d[quotient_reg] = quotient;
+                self.set_flags(quotient >> 31 != 0, quotient == 0, false, false);
+            }
+            None => {
+                let ccr = self.cpu.sr() & (sr::X | sr::N | sr::Z);
+                self.cpu.set_ccr(ccr | sr::V);
+            }
+        }
+        Ok(())
+    }
+
+    // ADDQ, SUBQ, Scc, DBcc, TRAPcc
+
+    fn group_5(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        let condition = opcode >> 8 & 0xf;
+        let Some(size) = size_76(opcode) else {
+            let mode = opcode >> 3 & 7;
+            if mode == 1 {
+                let displacement = self.fetch()? as i16 as u32;
+                if !self.condition(condition) {
+                    let reg = self.reg_0();
+                    let count = (self.cpu.d[reg] as u16).wrapping_sub(1);
+                    self.set_d(reg, Size::Word, count.into());
+                    if count != 0xffff {
+                        self.cpu.pc = self.start.wrapping_add(2).wrapping_add(displacement);
+                        self.cpu.cycles += 2;
+                    } else {
+                        self.cpu.cycles += 6;
+                    }
+                }
+                return Ok(());
+            }
+            if mode == 7 && matches!(opcode & 7, 2..=4) {
+                self.require(Model::M68020)?;
+                match opcode & 7 {
+                    2 => {
+                        self.fetch()?;
+                    }
+                    3 => {
+                        self.fetch_long()?;
+                    }
+                    _ => {}
+                }
+                if self.condition(condition) {
+                    return Err(self.trap(vector::TRAPV));
+                }
+                return Ok(());
+            }
+            let ea = self.ea(DATA_ALTERABLE, Size::Byte)?;
+            let value = if self.condition(condition) { 0xff } else { 0 };
+            return self.write_ea(ea, Size::Byte, value);
+        };
+
+        let data = match opcode >> 9 & 7 {
+            0 => 8,
+            n => u32::from(n),
+        };
+        let ea = self.ea(ALTERABLE, size)?;
+        if let Ea::A(reg) = ea {
+            // Address registers are changed as a whole and the flags are left alone
+            self.cpu.a[reg] = if opcode & 0x100 != 0 {
+                self.cpu.a[reg].wrapping_sub(data)
+            } else {
+                self.cpu.a[reg].wrapping_add(data)
+            };
+            self.cpu.cycles += 4;
+            return Ok(());
+        }
+        let value = self.read_ea(ea, size)?;
+        let result = if opcode & 0x100 != 0 {
+            self.sub(data, value, size, false, false)
+        } else {
+            self.add(data, value, size, false)
+        };
+        if let (Ea::D(_), Size::Long) = (ea, size) {
+            self.cpu.cycles += 4;
+        }
+        self.write_ea(ea, size, result)
+    }
+
+    // Bcc, BRA, BSR
+
+    fn branch(&mut self) -> Result<()> {
+        let condition = self.opcode >> 8 & 0xf;
+        let displacement = match self.opcode & 0xff {
+            0 => self.fetch()? as i16 as u32,
+            0xff if self.cpu.model >= Model::M68020 => self.fetch_long()?,
+            byte => byte as u8 as i8 as u32,
+        };
+        let target = self.start.wrapping_add(2).wrapping_add(displacement);
+        // A taken branch costs ten cycles with the refill of the prefetch queue, however long
+        // its displacement
+        let taken = if self.opcode & 0xff == 0 { 2 } else { 6 };
+        if condition == 1 {
+            self.push(Size::Long, self.cpu.pc)?;
+            self.cpu.pc = target;
+            self.cpu.cycles += taken;
+        } else if self.condition(condition) {
+            self.cpu.pc = target;
+            self.cpu.cycles += taken;
+        } else {
+            self.cpu.cycles += 4;
+        }
+        Ok(())
+    }
+
+    // OR, DIVU, DIVS, SBCD
+
+    fn group_8(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        if opcode & 0x01f0 == 0x0100 {
+            return self.bcd(false);
+        }
+        if opcode & 0x01f0 == 0x0140 || opcode & 0x01f0 == 0x0180 {
+            // PACK and UNPK
+            return Err(self.illegal());
+        }
+        match size_76(opcode) {
+            Some(size) => self.logic(size),
+            None => self.div(opcode & 0x100 != 0),
+        }
+    }
+
+    /// OR, AND and EOR
+    fn logic(&mut self, size: Size) -> Result<()> {
+        let reg = self.reg_9();
+        let to_memory = self.opcode & 0x100 != 0;
+        let eor = self.opcode >> 12 == 0xb;
+        let allowed = match (eor, to_memory) {
+            (true, _) => DATA_ALTERABLE,
+            (false, true) => MEMORY_ALTERABLE,
+            (false, false) => DATA,
+        };
+        let ea = self.ea(allowed, size)?;
+        let a = self.read_ea(ea, size)?;
+        let b = self.cpu.d[reg] & size.mask();
+        let result = match self.opcode >> 12 {
+            0x8 => a | b,
+            0xc => a & b,
+            _ => a ^ b,
+        };
+        self.set_logic_flags(result, size);
+        if size == Size::Long && matches!(ea, Ea::D(_) | Ea::Immediate(_)) {
+            self.cpu.cycles += 4;
+        }
+        if to_memory || eor {
+            self.write_ea(ea, size, result)
+        } else {
+            self.set_d(reg, size, result);
+            Ok(())
+        }
+    }
+
+    fn div(&mut self, signed: bool) -> Result<()> {
+        let ea = self.ea(DATA, Size::Word)?;
+        let divisor = self.read_ea(ea, Size::Word)?;
+        if divisor == 0 {
+            return Err(self.trap(vector::ZERO_DIVIDE));
+        }
+        let reg = self.reg_9();
+        let dividend = self.cpu.d[reg];
+        let result = if signed {
+            self.cpu.cycles += 150;
+            let dividend = dividend as i32;
+            let divisor = i32::from(divisor as u16 as i16);
+            match (dividend.checked_div(divisor), dividend.checked_rem(divisor)) {
+                (Some(q), Some(r)) if q == i32::from(q as i16) => Some((q as u32, r as u32)),
+                _ => None,
+            }
+        } else {
+            self.cpu.cycles += 130;
+            let q = dividend / divisor;
+            (q <= 0xffff).then(|| (q, dividend % divisor))
+        };
+        match result {
+            Some((quotient, remainder)) => {
+                self.cpu.d[reg] = (remainder & 0xffff) << 16 | quotient & 0xffff;
+                self.set_logic_flags(quotient, Size::Word);
+            }
+            None => {
+                let ccr = self.cpu.sr() & (sr::X | sr::N | sr::Z);
+                self.cpu.set_ccr(ccr | sr::V);
+            }
+        }
+        Ok(())
+    }
+
+    fn mul(&mut self, signed: bool) -> Result<()> {
+        let ea = self.ea(DATA, Size::Word)?;
+        let source = self.read_ea(ea, Size::Word)?;
+        let reg = self.reg_9();
+        let destination = self.cpu.d[reg] & 0xffff;
+        let result = if signed {
+            (source as u16 as i16 as i32).wrapping_mul(destination as u16 as i16 as i32) as u32
+        } else {
+            source * destination
+        };
+        self.cpu.cycles += 34 + 2 * u64::from(source.count_ones());
+        self.cpu.d[reg] = result;
+        self.set_logic_flags(result, Size::Long);
+        Ok(())
+    }
+
+    /// ABCD or SBCD
+    fn bcd(&mut self, add: bool) -> Result<()> {
+        let (x, y) = (self.reg_0(), self.reg_9());
+        let memory = self.opcode & 8 != 0;
+        let (source, destination, address) = if memory {
+            self.cpu.a[x] = self.cpu.a[x].wrapping_sub(self.step(x, Size::Byte));
+            let source = self.read(self.cpu.a[x], Size::Byte)?;
+            self.cpu.a[y] = self.cpu.a[y].wrapping_sub(self.step(y, Size::Byte));
+            let address = self.cpu.a[y];
+            (source, self.read(address, Size::Byte)?, Some(address))
+        } else {
+            (self.cpu.d[x] & 0xff, self.cpu.d[y] & 0xff, None)
+        };
+        let result = if add {
+            self.abcd(source, destination)
+        } else {
+            self.sbcd(source, destination)
+        };
+        self.cpu.cycles += 2;
+        match address {
+            Some(address) => self.write(address, Size::Byte, result),
+            None => {
+                self.set_d(y, Size::Byte, result);
+                Ok(())
+            }
+        }
+    }
+
+    fn set_bcd_flags(&mut self, result: u32, carry: bool) {
+        let zero = result & 0xff == 0 && self.flag(sr::Z);
+        self.set_flags(result & 0x80 != 0, zero, false, carry);
+        self.set_x(carry);
+    }
+
+    fn abcd(&mut self, source: u32, destination: u32) -> u32 {
+        let x = u32::from(self.flag(sr::X));
+        let mut result = (source & 0xf) + (destination & 0xf) + x;
+        if result > 9 {
+            result += 6;
+        }
+        result += (source & 0xf0) + (destination & 0xf0);
+        let carry = result > 0x99;
+        if carry {
+            result -= 0xa0;
+        }
+        let result = result & 0xff;
+        self.set_bcd_flags(result, carry);
+        result
+    }
+
+    fn sbcd(&mut self, source: u32, destination: u32) -> u32 {
+        let x = u32::from(self.flag(sr::X));
+        let mut result = (destination & 0xf)
+            .wrapping_sub(source & 0xf)
+            .wrapping_sub(x);
+        if result > 9 {
+            result = result.wrapping_sub(6);
+        }
+        result = result
+            .wrapping_add(destination & 0xf0)
+            .wrapping_sub(source & 0xf0);
+        let carry = result > 0x99;
+        if carry {
+            result = result.wrapping_add(0xa0);
+        }
+        let result = result & 0xff;
+        self.set_bcd_flags(result, carry);
+        result
+    }
+
+    // ADD, ADDA, ADDX, SUB, SUBA, SUBX
+
+    fn add_sub(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        let add = opcode >> 12 == 0xd;
+        let reg = self.reg_9();
+        let Some(size) = size_76(opcode) else {
+            let size = if opcode & 0x100 != 0 {
+                Size::Long
+            } else {
+                Size::Word
+            };
+            let ea = self.ea(ALL, size)?;
+            let source = size.sign_extend(self.read_ea(ea, size)?);
+            self.cpu.a[reg] = if add {
+                self.cpu.a[reg].wrapping_add(source)
+            } else {
+                self.cpu.a[reg].wrapping_sub(source)
+            };
+            self.cpu.cycles += 4;
+            return Ok(());
+        };
+
+        if opcode & 0x130 == 0x100 {
+            // ADDX and SUBX
+            let x = self.reg_0();
+            if opcode & 8 != 0 {
+                self.cpu.a[x] = self.cpu.a[x].wrapping_sub(self.step(x, size));
+                let source = self.read(self.cpu.a[x], size)?;
+                self.cpu.a[reg] = self.cpu.a[reg].wrapping_sub(self.step(reg, size));
+                let address = self.cpu.a[reg];
+                let destination = self.read(address, size)?;
+                let result = if add {
+                    self.add(source, destination, size, true)
+                } else {
+                    self.sub(source, destination, size, true, false)
+                };
+                return self.write(address, size, result);
+            }
+            let (source, destination) = (self.cpu.d[x], self.cpu.d[reg]);
+            let result = if add {
+                self.add(source, destination, size, true)
+            } else {
+                self.sub(source, destination, size, true, false)
+            };
+            self.set_d(reg, size, result);
+            return Ok(());
+        }
+
+        if opcode & 0x100 != 0 {
+            let ea = self.ea(MEMORY_ALTERABLE, size)?;
+            let destination = self.read_ea(ea, size)?;
+            let source = self.cpu.d[reg];
+            let result = if add {
+                self.add(source, destination, size, false)
+            } else {
+                self.sub(source, destination, size, false, false)
+            };
+            self.write_ea(ea, size, result)
+        } else {
+            let ea = self.ea(ALL, size)?;
+            let source = self.read_ea(ea, size)?;
+            let destination = self.cpu.d[reg];
+            let result = if add {
+                self.add(source, destination, size, false)
+            } else {
+                self.sub(source, destination, size, false, false)
+            };
+            if size == Size::Long {
+                self.cpu.cycles += 2;
+            }
+            self.set_d(reg, size, result);
+            Ok(())
+        }
+    }
+
+    // CMP, CMPA, CMPM, EOR
+
+    fn group_b(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        let reg = self.reg_9();
+        let Some(size) = size_76(opcode) else {
+            let size = if opcode & 0x100 != 0 {
+                Size::Long
+            } else {
+                Size::Word
+            };
+            let ea = self.ea(ALL, size)?;
+            let source = size.sign_extend(self.read_ea(ea, size)?);
+            self.sub(source, self.cpu.a[reg], Size::Long, false, true);
+            self.cpu.cycles += 2;
+            return Ok(());
+        };
+        if opcode & 0x100 == 0 {
+            let ea = self.ea(ALL, size)?;
+            let source = self.read_ea(ea, size)?;
+            self.sub(source, self.cpu.d[reg], size, false, true);
+            return Ok(());
+        }
+        if opcode & 0x38 == 0x08 {
+            let x = self.reg_0();
+            let source_address = self.cpu.a[x];
+            self.cpu.a[x] = source_address.wrapping_add(self.step(x, size));
+            let source = self.read(source_address, size)?;
+            let destination_address = self.cpu.a[reg];
+            self.cpu.a[reg] = destination_address.wrapping_add(self.step(reg, size));
+            let destination = self.read(destination_address, size)?;
+            self.sub(source, destination, size, false, true);
+            return Ok(());
+        }
+        self.logic(size)
+    }
+
+    // AND, MULU, MULS, ABCD, EXG
+
+    fn group_c(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        if opcode & 0x01f0 == 0x0100 {
+            return self.bcd(true);
+        }
+        let (x, y) = (self.reg_9(), self.reg_0());
+        match opcode & 0x01f8 {
+            0x0140 => {
+                self.cpu.d.swap(x, y);
+                self.cpu.cycles += 2;
+                return Ok(());
+            }
+            0x0148 => {
+                self.cpu.a.swap(x, y);
+                self.cpu.cycles += 2;
+                return Ok(());
+            }
+            0x0188 => {
+                std::mem::swap(&mut self.cpu.d[x], &mut self.cpu.a[y]);
+                self.cpu.cycles += 2;
+                return Ok(());
+            }
+            _ => {}
+        }
+        match size_76(opcode) {
+            Some(size) => self.logic(size),
+            None => self.mul(opcode & 0x100 != 0),
+        }
+    }
+
+    // Shifts and rotations
+
+    fn shift(&mut self) -> Result<()> {
+        let opcode = self.opcode;
+        let left = opcode & 0x100 != 0;
+        let Some(size) = size_76(opcode) else {
+            if opcode & 0x800 != 0 {
+                // Bit field instructions
+                return Err(self.illegal());
+            }
+            let ea = self.ea(MEMORY_ALTERABLE, Size::Word)?;
+            let value = self.read_ea(ea, Size::Word)?;
+            let result = self.rotate(opcode >> 9 & 3, left, value, 1, Size::Word);
+            return self.write_ea(ea, Size::Word, result);
+        };
+
+        let reg = self.reg_0();
+        let count = if opcode & 0x20 != 0 {
+            self.cpu.d[self.reg_9()] & 63
+        } else {
+            match self.reg_9() {
+                0 => 8,
+                n => n as u32,
+            }
+        };
+        self.cpu.cycles += 2 + 2 * u64::from(count) + if size == Size::Long { 2 } else { 0 };
+        let value = self.cpu.d[reg] & size.mask();
+        let result = self.rotate(opcode >> 3 & 3, left, value, count, size);
+        self.set_d(reg, size, result);
+        Ok(())
+    }
+
+    /// Shifts or rotates `value` by `count` bits and sets the flags
+    ///
+    /// `kind` is 0 for arithmetic shifts, 1 for logical shifts, 2 for rotations through X and 3
+    /// for rotations.
+    fn rotate(&mut self, kind: u16, left: bool, mut value: u32, count: u32, size: Size) -> u32 {
+        let msb = size.msb();
+        let mut x = self.flag(sr::X);
+        let mut carry = false;
+        let mut overflow = false;
+        for _ in 0..count {
+            let out = if left {
+                value & msb != 0
+            } else {
+                value & 1 != 0
+            };
+            let fill = match (kind, left) {
+                (0, false) => value & msb != 0,
+                (0 | 1, _) => false,
+                (2, _) => x,
+                _ => out,
+            };
+            let shifted = if left {
+                value << 1 | u32::from(fill)
+            } else {
+                value >> 1 | if fill { msb } else { 0 }
+            } & size.mask();
+            if kind == 0 && left && (shifted ^ value) & msb != 0 {
+                overflow = true;
+            }
+            value = shifted;
+            carry = out;
+            if kind != 3 {
+                x = out;
+            }
+        }
+        if kind == 2 && count == 0 {
+            carry = x;
+        }
+        self.set_flags(value & msb != 0, value == 0, overflow, carry);
+        if kind != 3 {
+            self.set_x(x);
+        }
+        value
+    }
+}
@@ -0,0 +1,469 @@
+//! GDB remote serial protocol server
+//!
+//! [`serve`] lets `m68k-elf-gdb` debug a [`Machine`] over TCP (`target remote :1234`), the way
+//! `m68k-gdbstub` does on hardware, but without touching the program: breakpoints are compared
+//! with the program counter instead of being written into the code, and the bus watches the
+//! watchpoints.
+//!
+//! - `g`/`G` and `p`/`P`: the registers in GDB's order, `d0`-`d7`, `a0`-`a7`, `sr` and `pc`, 32
+//!   bits each. `a7` is the stack pointer of the current mode.
+//! - `m`/`M`: RAM and ROM, which GDB writes like a programmer would. Devices are out of reach, as
+//!   reading their registers has side effects.
+//! - `c`/`s`, and `C`/`S` whose signal is ignored. Ctrl-C stops the running program.
+//! - `Z0`/`Z1` breakpoints, and `Z2`/`Z3`/`Z4` write, read and access watchpoints.
+//! - `?`, `D` (detach) and `k` (kill).
+//!
+//! The errors in [`CAUGHT`] stop the program with the signal `m68k-gdbstub` reports for them. The
+//! exception is taken when the program resumes, so its handler runs then. Other exceptions are
+//! left to the program, and to the hook of the machine, as without GDB.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+
+use m68k_gdbstub::signal;
+
+use crate::bus::{Watch, Watchpoint};
+use crate::cpu::{vector, Exception};
+use crate::machine::{Machine, Stop};
+
+/// Vectors of the exceptions that stop the program
+pub const CAUGHT: [u8; 9] = [
+    vector::BUS_ERROR,
+    vector::ADDRESS_ERROR,
+    vector::ILLEGAL_INSTRUCTION,
+    vector::ZERO_DIVIDE,
+    vector::PRIVILEGE_VIOLATION,
+    vector::LINE_1010,
+    vector::LINE_1111,
+    vector::FORMAT_ERROR,
+    vector::SPURIOUS_INTERRUPT,
+];
+
+/// Instructions run between looks for a Ctrl-C
+const POLL: u32 = 10_000;
+
+/// Largest number of bytes an `m` packet reads
+const READ_SIZE: usize = 0x800;
+
+/// Number of registers in `g` and `G` packets
+const REGISTERS: usize = 18;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 10;
+const SIGSTOP: u8 = 17;
+
+/// How a session ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum End {
+    /// GDB detached: the program can go on without it
+    Detached,
+    /// GDB killed the program, or went away
+    Killed,
+    /// The program finished, as in [`Stop::Finished`]
+    Finished(u32),
+}
+
+/// Debugs the machine with the GDB at the other end of `stream`, until it detaches or goes away
+///
+/// The program stays where it is until GDB tells it to run. Breakpoints and watchpoints are
+/// removed at the end, and the list of [`Machine::stop_on_exceptions`] is cleared.
+pub fn serve(machine: &mut Machine, stream: TcpStream) -> io::Result<End> {
+    stream.set_nodelay(true)?;
+    machine.stop_on_exceptions(&CAUGHT);
+    let mut session = Session {
+        machine,
+        connection: Connection {
+            input: BufReader::new(stream),
+        },
+        breakpoints: BTreeMap::new(),
+        watchpoints: Vec::new(),
+        pending: None,
+        stop: format!("S{:02x}", SIGTRAP),
+    };
+    let end = match session.run() {
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::UnexpectedEof | ErrorKind::ConnectionReset
+            ) =>
+        {
+            Ok(End::Killed)
+        }
+        end => end,
+    };
+
+    let machine = session.machine;
+    for watchpoint in &session.watchpoints {
+        machine.bus.remove_watchpoint(watchpoint);
+    }
+    if let Some(exception) = session.pending {
+        machine.cpu.take(&mut machine.bus, &exception);
+    }
+    machine.stop_on_exceptions(&[]);
+    end
+}
+
+/// Packets over the TCP connection
+struct Connection {
+    input: BufReader<TcpStream>,
+}
+
+impl Connection {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self
+            .input
+            .fill_buf()?
+            .first()
+            .ok_or(ErrorKind::UnexpectedEof)?;
+        self.input.consume(1);
+        Ok(byte)
+    }
+
+    /// Returns whether GDB sent a Ctrl-C, without waiting
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.input.get_ref().set_nonblocking(true)?;
+        let byte = self.input.fill_buf().map(|data| data.first().copied());
+        self.input.get_ref().set_nonblocking(false)?;
+        match byte {
+            Ok(Some(byte)) => {
+                self.input.consume(1);
+                Ok(byte == 0x03)
+            }
+            Ok(None) => Err(ErrorKind::UnexpectedEof.into()),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits for a packet with a good checksum and acknowledges it
+    fn receive(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            // Ctrl-C while the program is stopped, or acknowledgements, are ignored
+            while self.byte()? != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match self.byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let checksum = [self.byte()?, self.byte()?];
+            let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            if parse_hex(&checksum) == Some(sum.into()) {
+                self.input.get_mut().write_all(b"+")?;
+                return Ok(data);
+            }
+            self.input.get_mut().write_all(b"-")?;
+        }
+    }
+
+    /// Sends a packet until it is acknowledged
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let packet = format!("${}#{:02x}", data, sum);
+        loop {
+            self.input.get_mut().write_all(packet.as_bytes())?;
+            loop {
+                match self.byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+struct Session<'a> {
+    machine: &'a mut Machine,
+    connection: Connection,
+    /// Addresses of the breakpoints, with the stop reason GDB is given for them
+    breakpoints: BTreeMap<u32, &'static str>,
+    watchpoints: Vec<Watchpoint>,
+    /// An exception the program stopped on, taken when it resumes
+    pending: Option<Exception>,
+    /// Reply to `?`: the last stop
+    stop: String,
+}
+
+impl Session<'_> {
+    fn run(&mut self) -> io::Result<End> {
+        loop {
+            let packet = self.connection.receive()?;
+            let Some((&command, args)) = packet.split_first() else {
+                self.connection.send("")?;
+                continue;
+            };
+            let reply = match command {
+                b'?' => Ok(self.stop.clone()),
+                b'g' => Ok(self.read_registers()),
+                b'G' => self.write_registers(args),
+                b'p' => self.read_register(args),
+                b'P' => self.write_register(args),
+                b'm' => self.read_memory(args),
+                b'M' => self.write_memory(args),
+                b'c' | b'C' | b's' | b'S' => {
+                    // `C` and `S` have a signal first
+                    let address = match command {
+                        b'C' | b'S' => args.splitn(2, |&b| b == b';').nth(1).unwrap_or(&[]),
+                        _ => args,
+                    };
+                    if !address.is_empty() {
+                        match parse_hex(address) {
+                            Some(pc) => self.machine.cpu.pc = pc,
+                            None => {
+                                self.connection.send("E01")?;
+                                continue;
+                            }
+                        }
+                    }
+                    let end = self.resume(matches!(command, b's' | b'S'))?;
+                    self.connection.send(&self.stop)?;
+                    if let Some(end) = end {
+                        return Ok(end);
+                    }
+                    continue;
+                }
+                b'Z' | b'z' => match self.breakpoint(command == b'Z', args) {
+                    Some(result) => result,
+                    // Unknown kinds of breakpoints
+                    None => Ok(String::new()),
+                },
+                b'D' => {
+                    self.connection.send("OK")?;
+                    return Ok(End::Detached);
+                }
+                b'k' => return Ok(End::Killed),
+                b'H' => Ok("OK".into()),
+                b'q' if args.starts_with(b"Supported") => Ok(format!(
+                    "PacketSize={:x};swbreak+;hwbreak+",
+                    2 * READ_SIZE + 4
+                )),
+                b'q' if args == b"Attached" => Ok("1".into()),
+                _ => Ok(String::new()),
+            };
+            self.connection
+                .send(&reply.unwrap_or_else(|Error| "E01".into()))?;
+        }
+    }
+
+    /// Runs the program until it stops, setting the stop reply; returns how the session ends if
+    /// the program finished
+    fn resume(&mut self, step: bool) -> io::Result<Option<End>> {
+        if let Some(exception) = self.pending.take() {
+            self.machine.cpu.take(&mut self.machine.bus, &exception);
+            // Stepping into the handler is a step
+            if step {
+                self.stop = format!("T{:02x}", SIGTRAP);
+                return Ok(None);
+            }
+        }
+
+        // The breakpoint the program is at, if any, is behind it
+        let mut first = true;
+        loop {
+            for _ in 0..POLL {
+                if !first {
+                    if let Some(reason) = self.breakpoints.get(&self.machine.cpu.pc) {
+                        self.stop = format!("T{:02x}{}:;", SIGTRAP, reason);
+                        return Ok(None);
+                    }
+                }
+                first = false;
+                if let Some(stop) = self.machine.step() {
+                    return Ok(self.stopped(stop));
+                }
+                if step {
+                    self.stop = format!("T{:02x}", SIGTRAP);
+                    return Ok(None);
+                }
+            }
+            if self.connection.interrupted()? {
+                self.stop = format!("T{:02x}", SIGINT);
+                return Ok(None);
+            }
+        }
+    }
+
+    fn stopped(&mut self, stop: Stop) -> Option<End> {
+        self.stop = match stop {
+            Stop::Finished(code) => {
+                self.stop = format!("W{:02x}", code as u8);
+                return Some(End::Finished(code));
+            }
+            Stop::Exception(exception) => {
+                self.pending = Some(exception);
+                format!("T{:02x}", signal(exception.vector))
+            }
+            Stop::Watchpoint(watchpoint) => {
+                let reason = match watchpoint.kind {
+                    Watch::Write => "watch",
+                    Watch::Read => "rwatch",
+                    Watch::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, reason, watchpoint.address)
+            }
+            Stop::Stopped => format!("T{:02x}", SIGSTOP),
+            Stop::Halted => format!("T{:02x}", SIGBUS),
+            Stop::Limit => format!("T{:02x}", SIGTRAP),
+        };
+        None
+    }
+
+    fn register(&self, n: usize) -> Option<u32> {
+        let cpu = &self.machine.cpu;
+        Some(match n {
+            0..=7 => cpu.d[n],
+            8..=15 => cpu.a[n - 8],
+            16 => cpu.sr().into(),
+            17 => cpu.pc,
+            _ => return None,
+        })
+    }
+
+    fn set_register(&mut self, n: usize, value: u32) -> Result<(), Error> {
+        let cpu = &mut self.machine.cpu;
+        match n {
+            0..=7 => cpu.d[n] = value,
+            8..=15 => cpu.a[n - 8] = value,
+            16 => cpu.set_sr(value as u16),
+            17 => cpu.pc = value,
+            _ => return Err(Error),
+        }
+        Ok(())
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTERS)
+            .filter_map(|n| self.register(n))
+            .map(|value| format!("{:08x}", value))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &[u8]) -> Result<String, Error> {
+        // GDB may send more registers, like the floating-point ones; they are ignored
+        if args.len() < REGISTERS * 8 {
+            return Err(Error);
+        }
+        let values = args
+            .chunks(8)
+            .take(REGISTERS)
+            .map(parse_hex)
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error)?;
+        // The status register first, as it selects the stack pointer that is `a7`
+        self.set_register(16, values[16])?;
+        for (n, &value) in values.iter().enumerate() {
+            if n != 16 {
+                self.set_register(n, value)?;
+            }
+        }
+        Ok("OK".into())
+    }
+
+    fn read_register(&self, args: &[u8]) -> Result<String, Error> {
+        let n = parse_hex(args).ok_or(Error)?;
+        let value = self.register(n as usize).ok_or(Error)?;
+        Ok(format!("{:08x}", value))
+    }
+
+    fn write_register(&mut self, args: &[u8]) -> Result<String, Error> {
+        let equals = args.iter().position(|&b| b == b'=').ok_or(Error)?;
+        let n = parse_hex(&args[..equals]).ok_or(Error)?;
+        let value = parse_hex(&args[equals + 1..]).ok_or(Error)?;
+        self.set_register(n as usize, value)?;
+        Ok("OK".into())
+    }
+
+    fn read_memory(&mut self, args: &[u8]) -> Result<String, Error> {
+        let (address, len) = address_length(args)?;
+        // Replies can be shorter than asked for
+        let mut buffer = vec![0; (len as usize).min(READ_SIZE)];
+        self.machine
+            .bus
+            .peek(address, &mut buffer)
+            .map_err(|_| Error)?;
+        Ok(buffer.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    fn write_memory(&mut self, args: &[u8]) -> Result<String, Error> {
+        let colon = args.iter().position(|&b| b == b':').ok_or(Error)?;
+        let (address, len) = address_length(&args[..colon])?;
+        let data = &args[colon + 1..];
+        if data.len() != 2 * len as usize {
+            return Err(Error);
+        }
+        let data = data
+            .chunks(2)
+            .map(|pair| parse_hex(pair).map(|byte| byte as u8))
+            .collect::<Option<Vec<_>>>()
+            .ok_or(Error)?;
+        self.machine.bus.load(address, &data).map_err(|_| Error)?;
+        Ok("OK".into())
+    }
+
+    /// Handles `Z` and `z`, or returns `None` for kinds that aren't supported
+    fn breakpoint(&mut self, insert: bool, args: &[u8]) -> Option<Result<String, Error>> {
+        let (&kind, args) = args.split_first()?;
+        let reason = match kind {
+            b'0' => "swbreak",
+            b'1' => "hwbreak",
+            b'2'..=b'4' => "",
+            _ => return None,
+        };
+        let (address, len) = match args.split_first() {
+            Some((b',', args)) => match address_length(args) {
+                Ok(address_length) => address_length,
+                Err(Error) => return Some(Err(Error)),
+            },
+            _ => return Some(Err(Error)),
+        };
+
+        if !reason.is_empty() {
+            if insert {
+                self.breakpoints.insert(address, reason);
+            } else {
+                self.breakpoints.remove(&address);
+            }
+        } else {
+            let watchpoint = Watchpoint {
+                address,
+                len,
+                kind: match kind {
+                    b'2' => Watch::Write,
+                    b'3' => Watch::Read,
+                    _ => Watch::Access,
+                },
+            };
+            if insert {
+                self.machine.bus.add_watchpoint(watchpoint);
+                self.watchpoints.push(watchpoint);
+            } else {
+                self.machine.bus.remove_watchpoint(&watchpoint);
+                self.watchpoints.retain(|w| *w != watchpoint);
+            }
+        }
+        Some(Ok("OK".into()))
+    }
+}
+
+/// A request that gets an `E01` reply
+struct Error;
+
+/// Parses a hexadecimal number of up to 8 digits
+fn parse_hex(s: &[u8]) -> Option<u32> {
+    if s.is_empty() || s.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok()
+}
+
+/// Parses `address,length`
+fn address_length(args: &[u8]) -> Result<(u32, u32), Error> {
+    let comma = args.iter().position(|&b| b == b',').ok_or(Error)?;
+    let address = parse_hex(&args[..comma]).ok_or(Error)?;
+    let len = parse_hex(&args[comma + 1..]).ok_or(Error)?;
+    Ok((address, len))
+}
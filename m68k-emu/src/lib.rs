@@ -0,0 +1,94 @@
+//! Host-side m68k emulator
+//!
+//! Runs `m68k-rt` programs on the build machine, so tests can check what they do without
+//! hardware. A [`Machine`] is a [`Cpu`] connected to a [`Bus`] that the test puts together in
+//! Rust: RAM, ROM and [`Device`]s for the memory-mapped peripherals. The program is loaded from
+//! its ELF file and started from its reset vector.
+//!
+//! ``` no_run
+//! use m68k_emu::{Bus, Limit, Machine, Model, Stop};
+//!
+//! let mut bus = Bus::new();
+//! bus.add_rom("ROM", 0, 256 * 1024)
+//!     .add_ram("RAM", 0x2000_0000, 64 * 1024);
+//! let mut machine = Machine::new(Model::M68000, bus);
+//! machine.load_file("target/m68k-unknown-none/release/examples/minimal")?;
+//! machine.finish_on_trap(0);
+//! machine.reset();
+//! assert_eq!(machine.run(Limit::Instructions(1_000_000)), Stop::Finished(0));
+//! # Ok::<(), m68k_emu::Error>(())
+//! ```
+//!
+//! The whole 68000 instruction set is emulated, with the additions of the 68010 (`movec`, `moves`,
+//! `rtd`, `move from ccr`, the vector base register and its stack frames) and the integer
+//! instructions of the 68020 and 68040 except bit fields, `cas2`, `callm`/`rtm`, `pack`/`unpk`
+//! and `move16`, which are illegal instructions here. There are no caches, MMU or FPU. Bus and
+//! address errors push the frames of the model, but `rte` from one restarts the instruction rather
+//! than finishing the access.
+//!
+//! Devices of the boards in this repository come with the crate: [`V9990`], the video chip of
+//! `m68k-nano`, and the serial ports [`Duart`] (MC68681) and [`Mfp`] (MC68901). The serial ports
+//! are connected to a [`serial::Link`]: an in-memory [`Pipe`] for tests, the emulator's standard
+//! input and output, or a pseudo-terminal.
+//!
+//! [`monitor::attach`] serves the `TRAP #15` calls of programs written for the `m68k-rom` monitor,
+//! so they run without it, and [`semihosting::attach`] the host calls of `m68k-semihosting`, which
+//! print, use the host's files and exit with a status.
+//!
+//! [`gdb::serve`] puts a machine under the control of GDB, through its remote protocol over TCP.
+//!
+//! Cycle counts follow the 68000 whatever the model: four cycles per bus access, plus the main
+//! internal delays. They are meant for rough measurements and for driving timers, not for exact
+//! timing.
+
+use std::fmt;
+
+pub mod bus;
+pub mod cpu;
+mod execute;
+pub mod gdb;
+pub mod machine;
+pub mod mc68681;
+pub mod mc68901;
+pub mod monitor;
+pub mod semihosting;
+pub mod serial;
+pub mod v9990;
+
+pub use bus::{Acknowledge, Bus, BusError, Device, Size, Watch, Watchpoint};
+pub use cpu::{Access, Cpu, Exception, Model, Step};
+pub use machine::{Control, Limit, Machine, Stop};
+pub use mc68681::{Channel, Duart};
+pub use mc68901::Mfp;
+pub use serial::{Link, Pipe};
+pub use v9990::{Frame, V9990};
+
+/// Errors returned when loading a program
+#[derive(Debug)]
+pub enum Error {
+    /// The ELF file couldn't be read
+    Image(m68k_image::Error),
+    /// A section is stored where there is no RAM or ROM
+    Unmapped { section: String, address: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Image(e) => e.fmt(f),
+            Error::Unmapped { section, address } => write!(
+                f,
+                "{} is loaded at 0x{:08x}, where there is no RAM or ROM",
+                section, address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<m68k_image::Error> for Error {
+    fn from(e: m68k_image::Error) -> Self {
+        Error::Image(e)
+    }
+}
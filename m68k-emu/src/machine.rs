@@ -0,0 +1,195 @@
+//! A processor and its address space, running a program
+
+use std::path::Path;
+
+use m68k_image::Image;
+
+use crate::bus::{Bus, Watchpoint};
+use crate::cpu::{sr, vector, Cpu, Exception, Model, Step};
+use crate::Error;
+
+/// What to do with an exception, decided by the hook set with [`Machine::on_exception`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Take it as the processor would
+    Take,
+    /// Carry on with the next instruction as if nothing happened, e.g. after emulating a service
+    /// call
+    Resume,
+    /// Stop running, with [`Stop::Exception`]
+    Stop,
+    /// End the run with [`Stop::Finished`] and this value, e.g. for an exit service call
+    Finish(u32),
+}
+
+/// Why [`Machine::run`] returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The program executed the trap set with [`Machine::finish_on_trap`], and the value is `d0`,
+    /// or the exception hook returned [`Control::Finish`]
+    Finished(u32),
+    /// The exception hook asked to stop, or the vector is one passed to
+    /// [`Machine::stop_on_exceptions`]. The exception isn't taken: [`Cpu::take`] takes it.
+    Exception(Exception),
+    /// The program executed `stop` with all interrupts masked, and waits forever
+    Stopped,
+    /// Double fault
+    Halted,
+    /// An instruction accessed data under a watchpoint; it completed
+    Watchpoint(Watchpoint),
+    /// The limit passed to [`Machine::run`] was reached
+    Limit,
+}
+
+/// How long [`Machine::run`] runs at most
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// Number of instructions; waiting for an interrupt after `stop` counts as one every four
+    /// cycles
+    Instructions(u64),
+    /// Number of clock cycles
+    Cycles(u64),
+}
+
+type Hook = Box<dyn FnMut(&mut Cpu, &mut Bus, &Exception) -> Control>;
+
+/// A processor connected to a bus
+pub struct Machine {
+    pub cpu: Cpu,
+    pub bus: Bus,
+    finish_trap: Option<u8>,
+    /// Vectors of the exceptions that stop the run before the hook sees them
+    stop_on: Vec<u8>,
+    hook: Option<Hook>,
+}
+
+impl Machine {
+    /// Creates a machine that is not reset yet
+    pub fn new(model: Model, bus: Bus) -> Self {
+        Machine {
+            cpu: Cpu::new(model),
+            bus,
+            finish_trap: None,
+            stop_on: Vec::new(),
+            hook: None,
+        }
+    }
+
+    /// Stores the load image of every section at its load address (LMA)
+    ///
+    /// Sections are put where the reset handler of an `m68k-rt` program expects them, which
+    /// copies `.data` to RAM itself.
+    pub fn load(&mut self, image: &Image) -> Result<(), Error> {
+        for section in image.sections.iter().filter(|s| s.load) {
+            if self.bus.load(section.lma, &section.data).is_err() {
+                return Err(Error::Unmapped {
+                    section: section.name.clone(),
+                    address: section.lma,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads an ELF file and loads it
+    ///
+    /// The image is returned so its symbols can be looked up.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Image, Error> {
+        let image = Image::read(path)?;
+        self.load(&image)?;
+        Ok(image)
+    }
+
+    /// Resets the processor, which starts at the reset vector
+    pub fn reset(&mut self) {
+        self.cpu.reset(&mut self.bus);
+    }
+
+    /// Makes `TRAP #trap` end the run with [`Stop::Finished`]
+    pub fn finish_on_trap(&mut self, trap: u8) {
+        self.finish_trap = Some(trap);
+    }
+
+    /// Makes exceptions with these vectors end the run with [`Stop::Exception`], replacing the
+    /// previous list
+    ///
+    /// Unlike the hook, this leaves the other exceptions alone, so a debugger can catch faults
+    /// while a hook serves the program.
+    pub fn stop_on_exceptions(&mut self, vectors: &[u8]) {
+        self.stop_on = vectors.to_vec();
+    }
+
+    /// Calls `hook` for every exception before it is taken, including interrupts
+    ///
+    /// Exceptions are taken as usual without a hook.
+    pub fn on_exception<F>(&mut self, hook: F)
+    where
+        F: FnMut(&mut Cpu, &mut Bus, &Exception) -> Control + 'static,
+    {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Runs an instruction, or handles an interrupt, and lets the devices catch up
+    ///
+    /// Returns `Some` if the program can't go on, or shouldn't.
+    pub fn step(&mut self) -> Option<Stop> {
+        let cycles = self.cpu.cycles;
+        let stop = match self.cpu.step(&mut self.bus) {
+            Step::Executed => None,
+            Step::Exception(exception) => self.exception(&exception),
+            Step::Halted => Some(Stop::Halted),
+        };
+        self.bus.tick(self.cpu.cycles - cycles);
+        let hit = self.bus.take_hit();
+        if let (None, Some(watchpoint)) = (stop, hit) {
+            return Some(Stop::Watchpoint(watchpoint));
+        }
+
+        let masked = self.cpu.sr() & sr::IPL == sr::IPL;
+        if stop.is_none() && self.cpu.stopped && masked && self.bus.interrupt_level() < 7 {
+            return Some(Stop::Stopped);
+        }
+        stop
+    }
+
+    fn exception(&mut self, exception: &Exception) -> Option<Stop> {
+        if let Some(trap) = self.finish_trap {
+            if exception.vector == vector::TRAP + trap {
+                return Some(Stop::Finished(self.cpu.d[0]));
+            }
+        }
+        if self.stop_on.contains(&exception.vector) {
+            return Some(Stop::Exception(*exception));
+        }
+        let control = match &mut self.hook {
+            Some(hook) => hook(&mut self.cpu, &mut self.bus, exception),
+            None => Control::Take,
+        };
+        match control {
+            Control::Take => {
+                self.cpu.take(&mut self.bus, exception);
+                self.cpu.halted.then_some(Stop::Halted)
+            }
+            Control::Resume => None,
+            Control::Stop => Some(Stop::Exception(*exception)),
+            Control::Finish(value) => Some(Stop::Finished(value)),
+        }
+    }
+
+    /// Runs until the program stops, or until `limit`
+    pub fn run(&mut self, limit: Limit) -> Stop {
+        let cycles = self.cpu.cycles;
+        let mut steps = 0;
+        loop {
+            if let Some(stop) = self.step() {
+                return stop;
+            }
+            steps += 1;
+            match limit {
+                Limit::Instructions(n) if steps >= n => return Stop::Limit,
+                Limit::Cycles(n) if self.cpu.cycles - cycles >= n => return Stop::Limit,
+                _ => {}
+            }
+        }
+    }
+}
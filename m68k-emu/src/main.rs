@@ -0,0 +1,230 @@
+//! `m68k-emu`: runs an `m68k-rt` program on the host, optionally under GDB
+
+use std::cell::RefCell;
+use std::env;
+use std::net::TcpListener;
+use std::process::ExitCode;
+use std::rc::Rc;
+
+use m68k_emu::gdb::{self, End};
+use m68k_emu::monitor::Monitor;
+use m68k_emu::semihosting::Semihosting;
+use m68k_emu::serial::Stdio;
+use m68k_emu::{Bus, Channel, Control, Duart, Limit, Link, Machine, Model, Stop};
+use m68k_image::memory::number;
+use m68k_image::{Image, MemoryMap};
+
+const USAGE: &str = "\
+Usage: m68k-emu [options] <elf> [args]
+
+Runs a linked m68k-rt image on an emulated board: RAM and ROM as in memory.x, and the MC68681
+DUART of m68k-rom with channel A on standard input and output. Options may also follow <elf>;
+other arguments after it are the program's, which it reads as its command line through
+semihosting.
+
+    --memory <memory.x>   RAM and ROM regions. Defaults to those recorded in the image.
+    --model <model>       68000, 68010, 68020 or 68040. Defaults to 68000.
+    --duart <addr>        Address of the DUART, which interrupts at level 5 and is
+                          autovectored. Defaults to 0x800000.
+    --pty                 Connect channel A to a new pseudo-terminal, whose path is printed,
+                          instead of standard input and output.
+    --monitor             Serve the TRAP #15 calls of m68k-monitor on the host, with the
+                          console where channel A would be. Exits with the code the program
+                          passes to `exit`, as a cargo runner for m68k-test.
+    --semihosting         Serve the TRAP #14 host calls of m68k-semihosting: the console,
+                          the host's files, the time and the command line. Exits with the
+                          code the program passes to `exit`.
+    --gdb <port>          Wait for GDB on 127.0.0.1:<port>, and run the program under its
+                          control until it detaches.
+";
+
+/// DUART address and interrupt level of the board `m68k-rom` is written for
+const DUART: u32 = 0x0080_0000;
+const DUART_LEVEL: u8 = 5;
+
+struct Options {
+    elf: String,
+    memory: Option<String>,
+    model: Model,
+    duart: u32,
+    pty: bool,
+    monitor: bool,
+    semihosting: bool,
+    gdb: Option<u16>,
+    /// The arguments after the ELF file that aren't options
+    args: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if matches!(args.first().map(String::as_str), Some("-h" | "--help")) {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = parse(&args).and_then(|options| run(&options));
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Result<Options, Box<dyn std::error::Error>> {
+    let mut elf = None;
+    let mut options = Options {
+        elf: String::new(),
+        memory: None,
+        model: Model::M68000,
+        duart: DUART,
+        pty: false,
+        monitor: false,
+        semihosting: false,
+        gdb: None,
+        args: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--memory" => options.memory = Some(value()?.to_string()),
+            "--model" => {
+                options.model = match value()? {
+                    "68000" => Model::M68000,
+                    "68010" => Model::M68010,
+                    "68020" => Model::M68020,
+                    "68040" => Model::M68040,
+                    model => return Err(format!("unknown model `{}`", model).into()),
+                }
+            }
+            "--duart" => {
+                options.duart =
+                    u32::try_from(number(value()?)?).map_err(|_| "--duart must fit in 32 bits")?
+            }
+            "--pty" => options.pty = true,
+            "--monitor" => options.monitor = true,
+            "--semihosting" => options.semihosting = true,
+            "--gdb" => options.gdb = Some(value()?.parse().map_err(|_| "bad --gdb port")?),
+            _ if elf.is_none() => elf = Some(arg.clone()),
+            _ => options.args.push(arg.clone()),
+        }
+    }
+    options.elf = elf.ok_or_else(|| format!("no ELF file given\n\n{}", USAGE))?;
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let image = Image::read(&options.elf)?;
+    let memory = match &options.memory {
+        Some(path) => MemoryMap::read(path)?,
+        None => MemoryMap::from_image(&image),
+    };
+    let mut machine = Machine::new(options.model, Bus::from_memory_map(&memory));
+    machine.load(&image)?;
+
+    let console = if options.pty {
+        #[cfg(target_os = "linux")]
+        {
+            let pty = m68k_emu::serial::Pty::open()?;
+            eprintln!("m68k-emu: the console is on {}", pty.path().display());
+            Console(Rc::new(RefCell::new(pty)))
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err("--pty is only supported on Linux".into());
+    } else {
+        Console(Rc::new(RefCell::new(Stdio::new())))
+    };
+
+    let mut duart = Duart::new(DUART_LEVEL);
+    duart.set_autovector(true);
+    let mut monitor = None;
+    if options.monitor {
+        monitor = Some(Monitor::new(console.clone()));
+    } else {
+        duart.connect(Channel::A, console.clone());
+    }
+    machine.bus.add_device("DUART", options.duart, 0x20, duart);
+
+    let mut semihosting = None;
+    if options.semihosting {
+        let mut host = Semihosting::with_console(console);
+        let command_line: Vec<&str> = std::iter::once(&options.elf)
+            .chain(&options.args)
+            .map(String::as_str)
+            .collect();
+        host.set_host_stderr(true)
+            .set_command_line(&command_line.join(" "));
+        semihosting = Some(host);
+    }
+    if monitor.is_some() || semihosting.is_some() {
+        machine.on_exception(move |cpu, bus, exception| {
+            let control = match &mut monitor {
+                Some(monitor) => monitor.serve(cpu, bus, exception),
+                None => Control::Take,
+            };
+            match (&mut semihosting, control) {
+                (Some(semihosting), Control::Take) => semihosting.serve(cpu, bus, exception),
+                _ => control,
+            }
+        });
+    }
+    machine.reset();
+
+    if let Some(port) = options.gdb {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        eprintln!("m68k-emu: waiting for GDB on {}", listener.local_addr()?);
+        let (stream, _) = listener.accept()?;
+        match gdb::serve(&mut machine, stream)? {
+            End::Detached => {}
+            End::Killed => return Ok(ExitCode::SUCCESS),
+            End::Finished(code) => return Ok(exit_code(code)),
+        }
+    }
+
+    loop {
+        match machine.run(Limit::Instructions(1_000_000)) {
+            Stop::Limit => {}
+            Stop::Finished(code) => return Ok(exit_code(code)),
+            Stop::Stopped => {
+                eprintln!(
+                    "m68k-emu: stopped with all interrupts masked at 0x{:08x}",
+                    machine.cpu.pc
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+            Stop::Halted => {
+                eprintln!("m68k-emu: double fault at 0x{:08x}", machine.cpu.pc);
+                return Ok(ExitCode::FAILURE);
+            }
+            stop => {
+                eprintln!("m68k-emu: {:?}", stop);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+}
+
+/// The console, shared by channel A and the services
+#[derive(Clone)]
+struct Console(Rc<RefCell<dyn Link>>);
+
+impl Link for Console {
+    fn receive(&mut self) -> Option<u8> {
+        self.0.borrow_mut().receive()
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.0.borrow_mut().transmit(byte);
+    }
+}
+
+/// The exit status for the exit code of the program, which doesn't fit in one unless it's small
+fn exit_code(code: u32) -> ExitCode {
+    u8::try_from(code).map_or(ExitCode::FAILURE, ExitCode::from)
+}
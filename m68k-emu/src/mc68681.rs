@@ -0,0 +1,352 @@
+//! Motorola MC68681 DUART
+//!
+//! Register `n` is at offset `2 * n` and `2 * n + 1`: the DUART sits on one half of a 16-bit data
+//! bus with A1 to A4 selecting the register, as on the board `m68k-rom` is written for. Both
+//! channels, the interrupt logic and the counter/timer are emulated. Baud rates, character
+//! formats and the modem lines are accepted and ignored: characters go to and from the [`Link`]s
+//! as soon as the guest and the other end are ready, and never get lost or garbled.
+
+use std::collections::VecDeque;
+
+use crate::bus::{Acknowledge, Device, Size};
+use crate::serial::Link;
+
+/// The DUART's crystal
+const X1: u64 = 3_686_400;
+
+/// CPU clock if not told otherwise
+const CPU_CLOCK: u64 = 8_000_000;
+
+// Registers; reads and writes of the same number reach different registers
+const MR: u32 = 0x0;
+const SR: u32 = 0x1; // CSR when written
+const CR: u32 = 0x2;
+const RB: u32 = 0x3; // TB when written
+const ACR: u32 = 0x4; // IPCR when read
+const ISR: u32 = 0x5; // IMR when written
+const CUR: u32 = 0x6; // CTUR when written
+const CLR: u32 = 0x7; // CTLR when written
+const IVR: u32 = 0xc;
+const START_COUNTER: u32 = 0xe; // set output port bits when written
+const STOP_COUNTER: u32 = 0xf; // reset output port bits when written
+
+// Status register bits
+const RXRDY: u8 = 1 << 0;
+const FFULL: u8 = 1 << 1;
+const TXRDY: u8 = 1 << 2;
+const TXEMT: u8 = 1 << 3;
+const OE: u8 = 1 << 4;
+
+// Interrupt status bits; channel B's are four bits higher
+const TXRDYA: u8 = 1 << 0;
+const RXRDYA: u8 = 1 << 1;
+const COUNTER_READY: u8 = 1 << 3;
+
+/// Depth of the receive FIFO
+const FIFO: usize = 3;
+
+/// The serial channels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    A,
+    B,
+}
+
+#[derive(Default)]
+struct Port {
+    link: Option<Box<dyn Link>>,
+    mode: [u8; 2],
+    mode_pointer: usize,
+    fifo: VecDeque<u8>,
+    overrun: bool,
+    receiver: bool,
+    transmitter: bool,
+}
+
+impl Port {
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if !self.fifo.is_empty() {
+            status |= RXRDY;
+        }
+        if self.fifo.len() == FIFO {
+            status |= FFULL;
+        }
+        if self.transmitter {
+            status |= TXRDY | TXEMT;
+        }
+        if self.overrun {
+            status |= OE;
+        }
+        status
+    }
+
+    /// Bits 0 and 1 of the interrupt status, for channel A
+    fn interrupts(&self) -> u8 {
+        let mut status = 0;
+        if self.transmitter {
+            status |= TXRDYA;
+        }
+        if !self.fifo.is_empty() {
+            status |= RXRDYA;
+        }
+        status
+    }
+
+    fn command(&mut self, value: u8) {
+        match value & 3 {
+            1 => self.receiver = true,
+            2 => self.receiver = false,
+            _ => {}
+        }
+        match value >> 2 & 3 {
+            1 => self.transmitter = true,
+            2 => self.transmitter = false,
+            _ => {}
+        }
+        match value >> 4 & 7 {
+            1 => self.mode_pointer = 0,
+            2 => {
+                self.receiver = false;
+                self.fifo.clear();
+            }
+            3 => self.transmitter = false,
+            4 => self.overrun = false,
+            _ => {}
+        }
+    }
+
+    fn poll(&mut self) {
+        if !self.receiver || self.fifo.len() == FIFO {
+            return;
+        }
+        if let Some(byte) = self.link.as_mut().and_then(|link| link.receive()) {
+            self.fifo.push_back(byte);
+        }
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        if self.transmitter {
+            if let Some(link) = &mut self.link {
+                link.transmit(byte);
+            }
+        }
+    }
+}
+
+/// The DUART, as a device on the bus
+pub struct Duart {
+    ports: [Port; 2],
+    imr: u8,
+    acr: u8,
+    ivr: u8,
+    preload: u16,
+    counter_ready: bool,
+    running: bool,
+    /// X1 clocks into the current period of the counter/timer
+    phase: u64,
+    /// Counting down from 0xffff after the first terminal count, in counter mode
+    wrapped: bool,
+    /// CPU cycles times the crystal frequency not yet turned into X1 clocks
+    remainder: u64,
+    cpu_clock: u64,
+    level: u8,
+    autovector: bool,
+}
+
+impl Duart {
+    /// Creates a DUART with its channels unconnected
+    ///
+    /// `level` is the interrupt level the board wires the INTR output to. The DUART supplies the
+    /// vector in its IVR unless [`Duart::set_autovector`] says the board autovectors it.
+    pub fn new(level: u8) -> Self {
+        Duart {
+            ports: Default::default(),
+            imr: 0,
+            acr: 0,
+            ivr: 0x0f,
+            preload: 0,
+            counter_ready: false,
+            running: false,
+            phase: 0,
+            wrapped: false,
+            remainder: 0,
+            cpu_clock: CPU_CLOCK,
+            level,
+            autovector: false,
+        }
+    }
+
+    /// Connects a channel to the outside
+    pub fn connect<L: Link + 'static>(&mut self, channel: Channel, link: L) -> &mut Self {
+        self.ports[channel as usize].link = Some(Box::new(link));
+        self
+    }
+
+    /// Makes the interrupt acknowledge cycle use the autovector of the level rather than the IVR
+    pub fn set_autovector(&mut self, autovector: bool) -> &mut Self {
+        self.autovector = autovector;
+        self
+    }
+
+    /// Sets the CPU clock, 8 MHz by default, which times the counter/timer
+    pub fn set_cpu_clock(&mut self, hz: u64) -> &mut Self {
+        self.cpu_clock = hz;
+        self
+    }
+
+    fn isr(&self) -> u8 {
+        let mut isr = self.ports[0].interrupts() | self.ports[1].interrupts() << 4;
+        if self.counter_ready {
+            isr |= COUNTER_READY;
+        }
+        isr
+    }
+
+    /// X1 clocks per count of the counter/timer, or `None` if it counts something that isn't
+    /// emulated: the IP2 input or a transmitter clock
+    fn prescaler(&self) -> Option<u64> {
+        match self.acr >> 4 & 7 {
+            0b110 => Some(1),
+            0b011 | 0b111 => Some(16),
+            _ => None,
+        }
+    }
+
+    fn timer_mode(&self) -> bool {
+        self.acr & 0x40 != 0
+    }
+
+    /// Length of the current period in X1 clocks: a whole square wave in timer mode, or up to the
+    /// next terminal count in counter mode
+    fn period(&self) -> Option<u64> {
+        let prescaler = self.prescaler()?;
+        let preload = u64::from(self.preload);
+        let counts = if self.timer_mode() {
+            2 * preload
+        } else if self.wrapped {
+            0x1_0000
+        } else {
+            preload
+        };
+        (counts != 0).then_some(counts * prescaler)
+    }
+
+    fn count(&self) -> u16 {
+        match (self.prescaler(), self.period()) {
+            (Some(prescaler), Some(period)) => ((period - self.phase) / prescaler) as u16,
+            _ => self.preload,
+        }
+    }
+
+    fn register(offset: u32) -> (usize, u32) {
+        let register = offset >> 1 & 0xf;
+        (register as usize >> 3, register & 7)
+    }
+}
+
+impl Device for Duart {
+    fn read(&mut self, offset: u32, _: Size) -> u32 {
+        let value = match offset >> 1 & 0xf {
+            IVR => self.ivr,
+            START_COUNTER => {
+                self.running = true;
+                self.phase = 0;
+                self.wrapped = false;
+                0xff
+            }
+            STOP_COUNTER => {
+                self.counter_ready = false;
+                if !self.timer_mode() {
+                    self.running = false;
+                }
+                0xff
+            }
+            // Input port: no inputs, pulled up
+            0xd => 0x7f,
+            _ => {
+                let (channel, register) = Self::register(offset);
+                let port = &mut self.ports[channel];
+                match (channel, register) {
+                    (_, MR) => {
+                        let value = port.mode[port.mode_pointer];
+                        port.mode_pointer = 1;
+                        value
+                    }
+                    (_, SR) => port.status(),
+                    (_, RB) => port.fifo.pop_front().unwrap_or(0),
+                    (0, ISR) => self.isr(),
+                    (0, CUR) => (self.count() >> 8) as u8,
+                    (0, CLR) => self.count() as u8,
+                    _ => 0xff,
+                }
+            }
+        };
+        value.into()
+    }
+
+    fn write(&mut self, offset: u32, _: Size, value: u32) {
+        let value = value as u8;
+        match offset >> 1 & 0xf {
+            IVR => self.ivr = value,
+            // Output port configuration and bits
+            0xd | START_COUNTER | STOP_COUNTER => {}
+            _ => {
+                let (channel, register) = Self::register(offset);
+                let port = &mut self.ports[channel];
+                match (channel, register) {
+                    (_, MR) => {
+                        port.mode[port.mode_pointer] = value;
+                        port.mode_pointer = 1;
+                    }
+                    (_, CR) => port.command(value),
+                    (_, RB) => port.transmit(value),
+                    (0, ACR) => self.acr = value,
+                    (0, ISR) => self.imr = value,
+                    (0, CUR) => self.preload = self.preload & 0xff | u16::from(value) << 8,
+                    (0, CLR) => self.preload = self.preload & 0xff00 | u16::from(value),
+                    // Clock select
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for port in &mut self.ports {
+            port.poll();
+        }
+
+        self.remainder += cycles * X1;
+        let clocks = self.remainder / self.cpu_clock;
+        self.remainder %= self.cpu_clock;
+        // The timer runs whether started or not
+        if !self.running && !self.timer_mode() {
+            return;
+        }
+        if let Some(period) = self.period() {
+            self.phase += clocks;
+            if self.phase >= period {
+                self.phase %= period;
+                self.counter_ready = true;
+                self.wrapped = true;
+            }
+        }
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        if self.isr() & self.imr != 0 {
+            self.level
+        } else {
+            0
+        }
+    }
+
+    fn acknowledge(&mut self, _level: u8) -> Acknowledge {
+        if self.autovector {
+            Acknowledge::Autovector
+        } else {
+            Acknowledge::Vector(self.ivr)
+        }
+    }
+}
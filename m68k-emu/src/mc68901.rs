@@ -0,0 +1,213 @@
+//! Motorola MC68901 MFP
+//!
+//! Register `n` is at offset `2 * n` and `2 * n + 1`, as on boards that put the MFP on one half
+//! of a 16-bit data bus. The USART and the interrupt controller are emulated; the timers and the
+//! general purpose I/O pins keep what is written to their registers and do nothing. As with the
+//! [`Duart`](crate::mc68681::Duart), characters go to and from the [`Link`] without delay and
+//! without errors.
+
+use crate::bus::{Acknowledge, Device, Size};
+use crate::serial::Link;
+
+// Registers
+const IERA: u32 = 0x03;
+const IERB: u32 = 0x04;
+const IPRA: u32 = 0x05;
+const IPRB: u32 = 0x06;
+const ISRA: u32 = 0x07;
+const ISRB: u32 = 0x08;
+const IMRA: u32 = 0x09;
+const IMRB: u32 = 0x0a;
+const VR: u32 = 0x0b;
+const RSR: u32 = 0x15;
+const TSR: u32 = 0x16;
+const UDR: u32 = 0x17;
+
+/// Number of registers
+const REGISTERS: usize = 0x18;
+
+// Interrupt channels of the USART
+const TRANSMIT_EMPTY: u8 = 10;
+const RECEIVE_FULL: u8 = 12;
+
+/// Software end-of-interrupt: channels stay in service until the handler clears them
+const S: u8 = 1 << 3;
+
+// Receiver and transmitter status bits
+const ENABLE: u8 = 1 << 0;
+const BUFFER_FULL: u8 = 1 << 7;
+const BUFFER_EMPTY: u8 = 1 << 7;
+
+/// The MFP, as a device on the bus
+pub struct Mfp {
+    link: Option<Box<dyn Link>>,
+    /// Registers that only hold what was written to them
+    registers: [u8; REGISTERS],
+    // Interrupt registers, channel n at bit n: the A registers are the high bytes
+    enabled: u16,
+    pending: u16,
+    in_service: u16,
+    masked: u16,
+    received: Option<u8>,
+    level: u8,
+}
+
+impl Mfp {
+    /// Creates an MFP with its USART unconnected
+    ///
+    /// `level` is the interrupt level the board wires the IRQ output to. The MFP always supplies
+    /// its own vectors, from the vector register.
+    pub fn new(level: u8) -> Self {
+        Mfp {
+            link: None,
+            registers: [0; REGISTERS],
+            enabled: 0,
+            pending: 0,
+            in_service: 0,
+            masked: 0,
+            received: None,
+            level,
+        }
+    }
+
+    /// Connects the USART to the outside
+    pub fn connect<L: Link + 'static>(&mut self, link: L) -> &mut Self {
+        self.link = Some(Box::new(link));
+        self
+    }
+
+    fn request(&mut self, channel: u8) {
+        self.pending |= self.enabled & 1 << channel;
+    }
+
+    /// The channel to interrupt for: the highest pending and unmasked one, unless a channel as
+    /// high is in service
+    fn active(&self) -> Option<u8> {
+        let requests = self.pending & self.masked;
+        if requests == 0 {
+            return None;
+        }
+        let channel = 15 - requests.leading_zeros() as u8;
+        if self.in_service >> channel != 0 {
+            return None;
+        }
+        Some(channel)
+    }
+
+    fn receiver_status(&self) -> u8 {
+        let mut status = self.registers[RSR as usize] & ENABLE;
+        if self.received.is_some() {
+            status |= BUFFER_FULL;
+        }
+        status
+    }
+
+    fn high(register: u32) -> bool {
+        matches!(register, IERA | IPRA | ISRA | IMRA)
+    }
+
+    fn interrupt_register(&mut self, register: u32) -> &mut u16 {
+        match register {
+            IERA | IERB => &mut self.enabled,
+            IPRA | IPRB => &mut self.pending,
+            ISRA | ISRB => &mut self.in_service,
+            _ => &mut self.masked,
+        }
+    }
+}
+
+impl Device for Mfp {
+    fn read(&mut self, offset: u32, _: Size) -> u32 {
+        let register = offset >> 1;
+        let value = match register {
+            IERA..=IMRB => {
+                let shift = if Self::high(register) { 8 } else { 0 };
+                (*self.interrupt_register(register) >> shift) as u8
+            }
+            RSR => self.receiver_status(),
+            TSR => self.registers[TSR as usize] & ENABLE | BUFFER_EMPTY,
+            UDR => self.received.take().unwrap_or(0),
+            _ => self
+                .registers
+                .get(register as usize)
+                .copied()
+                .unwrap_or(0xff),
+        };
+        value.into()
+    }
+
+    fn write(&mut self, offset: u32, _: Size, value: u32) {
+        let register = offset >> 1;
+        let value = value as u8;
+        match register {
+            IERA..=IMRB => {
+                let (shift, mask) = if Self::high(register) {
+                    (8, 0x00ff)
+                } else {
+                    (0, 0xff00)
+                };
+                let bits = u16::from(value) << shift | mask;
+                match register {
+                    // Writing 1s leaves pending and in-service bits alone, 0s clear them
+                    IPRA | IPRB | ISRA | ISRB => *self.interrupt_register(register) &= bits,
+                    _ => {
+                        let field = self.interrupt_register(register);
+                        *field = *field & mask | bits & !mask;
+                        // Disabling a channel drops its pending request
+                        self.pending &= self.enabled;
+                    }
+                }
+            }
+            UDR => {
+                if self.registers[TSR as usize] & ENABLE != 0 {
+                    if let Some(link) = &mut self.link {
+                        link.transmit(value);
+                    }
+                    self.request(TRANSMIT_EMPTY);
+                }
+            }
+            _ => {
+                if let Some(slot) = self.registers.get_mut(register as usize) {
+                    let was = *slot;
+                    *slot = value;
+                    if register == TSR && was & ENABLE == 0 && value & ENABLE != 0 {
+                        self.request(TRANSMIT_EMPTY);
+                    }
+                    if register == RSR && value & ENABLE == 0 {
+                        self.received = None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, _: u64) {
+        if self.registers[RSR as usize] & ENABLE == 0 || self.received.is_some() {
+            return;
+        }
+        if let Some(byte) = self.link.as_mut().and_then(|link| link.receive()) {
+            self.received = Some(byte);
+            self.request(RECEIVE_FULL);
+        }
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        if self.active().is_some() {
+            self.level
+        } else {
+            0
+        }
+    }
+
+    fn acknowledge(&mut self, _level: u8) -> Acknowledge {
+        let Some(channel) = self.active() else {
+            return Acknowledge::Spurious;
+        };
+        self.pending &= !(1 << channel);
+        let vr = self.registers[VR as usize];
+        if vr & S != 0 {
+            self.in_service |= 1 << channel;
+        }
+        Acknowledge::Vector(vr & 0xf0 | channel)
+    }
+}
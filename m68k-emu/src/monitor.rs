@@ -0,0 +1,88 @@
+//! The `TRAP #15` services of `m68k-rom`, served by the emulator
+//!
+//! Programs written against `m68k-monitor` then run without the monitor in ROM: the exception
+//! hook carries out the call on the host and the program goes on with the next instruction, as it
+//! would after the monitor's `rte`. [`EXIT`] ends the run with [`Stop::Finished`] and the exit
+//! code, which is how `m68k-test` reports the outcome of on-target tests.
+//!
+//! Function numbers and registers are those of `m68k-monitor`. The console is a [`Link`]; line
+//! endings are passed on as they are, since the host's terminal takes care of them.
+//!
+//! [`Stop::Finished`]: crate::Stop::Finished
+
+use crate::bus::Bus;
+use crate::cpu::{vector, Cpu, Exception};
+use crate::machine::{Control, Machine};
+use crate::serial::Link;
+
+/// The trap the services are called with
+pub const TRAP: u8 = 15;
+
+/// Ends the run with the exit code in `d1`
+pub const EXIT: u32 = 0;
+/// Writes `d1.b` to the console
+pub const PUTC: u32 = 1;
+/// Returns the next byte from the console in `d0`, waiting for it
+pub const GETC: u32 = 2;
+/// Writes the `d1` bytes at `a0` to the console
+pub const PUTS: u32 = 3;
+/// Returns the number of 100 Hz ticks since reset in `d0`
+pub const TICKS: u32 = 4;
+
+/// What unknown function numbers return
+pub const UNKNOWN: u32 = 0xffff_ffff;
+
+/// Cycles per tick of the 100 Hz counter, for the 8 MHz processor of `m68k-rom`'s board
+const CYCLES_PER_TICK: u64 = 80_000;
+
+/// The services, with their console
+pub struct Monitor<L: Link> {
+    console: L,
+}
+
+impl<L: Link> Monitor<L> {
+    pub fn new(console: L) -> Self {
+        Monitor { console }
+    }
+
+    /// Carries out a `TRAP #15` call, for an exception hook
+    ///
+    /// Other exceptions are left to the processor with [`Control::Take`].
+    pub fn serve(&mut self, cpu: &mut Cpu, bus: &mut Bus, exception: &Exception) -> Control {
+        if exception.vector != vector::TRAP + TRAP {
+            return Control::Take;
+        }
+        let link = &mut self.console;
+        match cpu.d[0] {
+            EXIT => return Control::Finish(cpu.d[1]),
+            PUTC => link.transmit(cpu.d[1] as u8),
+            GETC => match link.receive() {
+                Some(byte) => cpu.d[0] = byte.into(),
+                // Call again until a byte arrives, letting the devices run in between
+                None => cpu.pc = exception.instruction,
+            },
+            PUTS => {
+                // A string that runs out of memory is written up to there
+                let mut byte = [0];
+                for i in 0..cpu.d[1] {
+                    if bus.peek(cpu.a[0].wrapping_add(i), &mut byte).is_err() {
+                        break;
+                    }
+                    link.transmit(byte[0]);
+                }
+            }
+            TICKS => cpu.d[0] = (cpu.cycles / CYCLES_PER_TICK) as u32,
+            _ => cpu.d[0] = UNKNOWN,
+        }
+        Control::Resume
+    }
+}
+
+/// Serves the monitor's calls of `machine`, with the console on `link`
+///
+/// This sets the exception hook of the machine; every other exception is taken as usual. Use
+/// [`Monitor::serve`] in a hook of your own to combine it with other services.
+pub fn attach<L: Link + 'static>(machine: &mut Machine, link: L) {
+    let mut monitor = Monitor::new(link);
+    machine.on_exception(move |cpu, bus, exception| monitor.serve(cpu, bus, exception));
+}
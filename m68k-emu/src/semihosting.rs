@@ -0,0 +1,283 @@
+//! Host calls of `m68k-semihosting`, served by the emulator
+//!
+//! The program asks the host to do what it can't do itself: print, read and write the host's
+//! files, tell the time, pass the command line and exit with a status. Each call is a `TRAP #14`
+//! with the operation in `d0` and its argument in `d1`, either a value or the address of a block
+//! of long words; the result comes back in `d0`, `0xffffffff` for an error whose `errno`
+//! [`ERRNO`] returns. See `m68k-semihosting` for the table of operations.
+//!
+//! Handles 0, 1 and 2 are standard input, output and error, which go to the console: the
+//! emulator's own, or a [`Link`] such as a [`Pipe`](crate::Pipe) in tests. Reading the console
+//! waits until a byte arrives, while the devices keep running. Paths are host paths, relative to
+//! the working directory of the emulator.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bus::Bus;
+use crate::cpu::{vector, Cpu, Exception};
+use crate::machine::{Control, Machine};
+use crate::serial::{Link, Stdio};
+
+/// The trap the calls are made with
+pub const TRAP: u8 = 14;
+
+/// Ends the run with the exit code in `d1`
+pub const EXIT: u32 = 0;
+/// Opens a file: `d1` points at the path, its length and the mode
+pub const OPEN: u32 = 1;
+/// Closes the handle in `d1`
+pub const CLOSE: u32 = 2;
+/// Reads: `d1` points at the handle, the buffer and its length
+pub const READ: u32 = 3;
+/// Writes: `d1` points at the handle, the data and its length
+pub const WRITE: u32 = 4;
+/// Returns the seconds since 1970 in `d0`
+pub const TIME: u32 = 5;
+/// Returns the hundredths of a second since reset in `d0`
+pub const CLOCK: u32 = 6;
+/// Copies the command line: `d1` points at the buffer and its length
+pub const COMMAND_LINE: u32 = 7;
+/// Returns the `errno` of the last call that failed in `d0`
+pub const ERRNO: u32 = 8;
+
+/// Modes of [`OPEN`]
+pub mod mode {
+    /// An existing file, for reading
+    pub const READ: u32 = 0;
+    /// Created or truncated, for writing
+    pub const WRITE: u32 = 1;
+    /// Created if needed, for writing at the end
+    pub const APPEND: u32 = 2;
+    /// An existing file, for reading and writing
+    pub const READ_WRITE: u32 = 3;
+}
+
+/// What a failed call returns in `d0`
+pub const FAILED: u32 = 0xffff_ffff;
+
+const EBADF: i32 = 9;
+const EFAULT: i32 = 14;
+const EINVAL: i32 = 22;
+const E2BIG: i32 = 7;
+const EIO: i32 = 5;
+
+/// Cycles per hundredth of a second of the 8 MHz processor, as for the monitor's ticks
+const CYCLES_PER_CLOCK: u64 = 80_000;
+
+/// Most a [`READ`] returns at once
+const MAX_READ: u32 = 0x1_0000;
+
+/// Handles below this are the console
+const FIRST_FILE: u32 = 3;
+
+/// The host side: open files, the command line and the console
+pub struct Semihosting {
+    console: Box<dyn Link>,
+    /// Standard error goes to the emulator's rather than to the console
+    stderr: bool,
+    files: BTreeMap<u32, File>,
+    command_line: String,
+    errno: i32,
+}
+
+/// A failed call, with its `errno`
+struct Failed(i32);
+
+impl From<io::Error> for Failed {
+    fn from(e: io::Error) -> Self {
+        Failed(e.raw_os_error().unwrap_or(EIO))
+    }
+}
+
+impl Semihosting {
+    /// Serves the console from the emulator's standard input, output and error
+    pub fn new() -> Self {
+        let mut semihosting = Self::with_console(Stdio::new());
+        semihosting.set_host_stderr(true);
+        semihosting
+    }
+
+    /// Serves the console from `link`, standard error included
+    pub fn with_console<L: Link + 'static>(link: L) -> Self {
+        Semihosting {
+            console: Box::new(link),
+            stderr: false,
+            files: BTreeMap::new(),
+            command_line: String::new(),
+            errno: 0,
+        }
+    }
+
+    /// Sends standard error to the emulator's own rather than to the console
+    pub fn set_host_stderr(&mut self, host: bool) -> &mut Self {
+        self.stderr = host;
+        self
+    }
+
+    /// Sets what [`COMMAND_LINE`] returns, usually the program and its arguments separated by
+    /// spaces
+    pub fn set_command_line(&mut self, command_line: &str) -> &mut Self {
+        self.command_line = command_line.to_string();
+        self
+    }
+
+    /// Carries out a `TRAP #14` call, for an exception hook
+    ///
+    /// Other exceptions are left to the processor with [`Control::Take`].
+    pub fn serve(&mut self, cpu: &mut Cpu, bus: &mut Bus, exception: &Exception) -> Control {
+        if exception.vector != vector::TRAP + TRAP {
+            return Control::Take;
+        }
+        let result = match cpu.d[0] {
+            EXIT => return Control::Finish(cpu.d[1]),
+            OPEN => self.open(bus, cpu.d[1]),
+            CLOSE => self.close(cpu.d[1]),
+            READ => match self.read(bus, cpu.d[1]) {
+                // Call again until the console has something, letting the devices run
+                Ok(None) => {
+                    cpu.pc = exception.instruction;
+                    return Control::Resume;
+                }
+                Ok(Some(n)) => Ok(n),
+                Err(e) => Err(e),
+            },
+            WRITE => self.write(bus, cpu.d[1]),
+            TIME => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs() as u32)
+                .map_err(|_| Failed(EIO)),
+            CLOCK => Ok((cpu.cycles / CYCLES_PER_CLOCK) as u32),
+            COMMAND_LINE => self.copy_command_line(bus, cpu.d[1]),
+            ERRNO => Ok(self.errno as u32),
+            _ => Err(Failed(EINVAL)),
+        };
+        cpu.d[0] = match result {
+            Ok(value) => value,
+            Err(Failed(errno)) => {
+                self.errno = errno;
+                FAILED
+            }
+        };
+        Control::Resume
+    }
+
+    fn open(&mut self, bus: &mut Bus, block: u32) -> Result<u32, Failed> {
+        let [path, len, mode] = arguments(bus, block)?;
+        let path = String::from_utf8(bytes(bus, path, len)?).map_err(|_| Failed(EINVAL))?;
+        let mut options = OpenOptions::new();
+        match mode {
+            mode::READ => options.read(true),
+            mode::WRITE => options.write(true).create(true).truncate(true),
+            mode::APPEND => options.append(true).create(true),
+            mode::READ_WRITE => options.read(true).write(true),
+            _ => return Err(Failed(EINVAL)),
+        };
+        let file = options.open(path)?;
+        let handle = self
+            .files
+            .last_key_value()
+            .map_or(FIRST_FILE, |(&last, _)| last + 1);
+        self.files.insert(handle, file);
+        Ok(handle)
+    }
+
+    fn close(&mut self, handle: u32) -> Result<u32, Failed> {
+        match handle {
+            0..FIRST_FILE => Ok(0),
+            _ => self.files.remove(&handle).map(|_| 0).ok_or(Failed(EBADF)),
+        }
+    }
+
+    /// Returns `None` if the console has nothing to read yet
+    fn read(&mut self, bus: &mut Bus, block: u32) -> Result<Option<u32>, Failed> {
+        let [handle, buffer, len] = arguments(bus, block)?;
+        let mut data = vec![0; len.min(MAX_READ) as usize];
+        let n = match handle {
+            0..FIRST_FILE => {
+                let mut n = 0;
+                while n < data.len() {
+                    match self.console.receive() {
+                        Some(byte) => data[n] = byte,
+                        None => break,
+                    }
+                    n += 1;
+                }
+                if n == 0 && !data.is_empty() {
+                    return Ok(None);
+                }
+                n
+            }
+            _ => self.file(handle)?.read(&mut data)?,
+        };
+        bus.load(buffer, &data[..n]).map_err(|_| Failed(EFAULT))?;
+        Ok(Some(n as u32))
+    }
+
+    fn write(&mut self, bus: &mut Bus, block: u32) -> Result<u32, Failed> {
+        let [handle, data, len] = arguments(bus, block)?;
+        let data = bytes(bus, data, len)?;
+        match handle {
+            2 if self.stderr => {
+                let mut stderr = io::stderr().lock();
+                stderr.write_all(&data).and_then(|()| stderr.flush())?;
+            }
+            0..FIRST_FILE => data.iter().for_each(|&byte| self.console.transmit(byte)),
+            _ => self.file(handle)?.write_all(&data)?,
+        }
+        Ok(len)
+    }
+
+    fn copy_command_line(&mut self, bus: &mut Bus, block: u32) -> Result<u32, Failed> {
+        let [buffer, len] = arguments(bus, block)?;
+        let command_line = self.command_line.as_bytes();
+        if command_line.len() > len as usize {
+            return Err(Failed(E2BIG));
+        }
+        bus.load(buffer, command_line).map_err(|_| Failed(EFAULT))?;
+        Ok(command_line.len() as u32)
+    }
+
+    fn file(&mut self, handle: u32) -> Result<&mut File, Failed> {
+        self.files.get_mut(&handle).ok_or(Failed(EBADF))
+    }
+}
+
+impl Default for Semihosting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads `len` bytes of guest memory
+fn bytes(bus: &mut Bus, address: u32, len: u32) -> Result<Vec<u8>, Failed> {
+    // A byte at a time, so a bad length fails before it allocates much
+    let mut data = Vec::new();
+    let mut byte = [0];
+    for i in 0..len {
+        bus.peek(address.wrapping_add(i), &mut byte)
+            .map_err(|_| Failed(EFAULT))?;
+        data.push(byte[0]);
+    }
+    Ok(data)
+}
+
+/// Reads the block of long words `d1` points at
+fn arguments<const N: usize>(bus: &mut Bus, block: u32) -> Result<[u32; N], Failed> {
+    let data = bytes(bus, block, 4 * N as u32)?;
+    let mut arguments = [0; N];
+    for (argument, long) in arguments.iter_mut().zip(data.chunks_exact(4)) {
+        *argument = u32::from_be_bytes(long.try_into().unwrap());
+    }
+    Ok(arguments)
+}
+
+/// Serves the host calls of `machine`
+///
+/// This sets the exception hook of the machine; every other exception is taken as usual. Use
+/// [`Semihosting::serve`] in a hook of your own to combine it with other services.
+pub fn attach(machine: &mut Machine, mut semihosting: Semihosting) {
+    machine.on_exception(move |cpu, bus, exception| semihosting.serve(cpu, bus, exception));
+}
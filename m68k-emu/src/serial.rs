@@ -0,0 +1,198 @@
+//! The other end of the emulated serial ports
+//!
+//! A UART model sends what the guest transmits to a [`Link`], and polls it for bytes to receive.
+//! [`Pipe`] keeps both directions in memory for tests, [`Stdio`] connects the guest to the
+//! terminal the emulator runs in, and [`Pty`] to a pseudo-terminal that a terminal emulator or
+//! GDB can open.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// A byte stream to and from a serial port
+pub trait Link {
+    /// Returns the next byte for the guest, if one has arrived
+    fn receive(&mut self) -> Option<u8>;
+
+    /// Sends a byte from the guest
+    fn transmit(&mut self, byte: u8);
+}
+
+#[derive(Default)]
+struct Buffers {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+/// An in-memory link; clones share the same buffers, so the test keeps one
+#[derive(Clone, Default)]
+pub struct Pipe {
+    buffers: Rc<RefCell<Buffers>>,
+}
+
+impl Pipe {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues bytes for the guest to receive
+    pub fn send(&self, bytes: &[u8]) {
+        self.buffers.borrow_mut().input.extend(bytes);
+    }
+
+    /// Returns what the guest transmitted since the last call
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.buffers.borrow_mut().output)
+    }
+
+    /// Number of bytes sent that the guest hasn't received yet
+    pub fn pending(&self) -> usize {
+        self.buffers.borrow().input.len()
+    }
+}
+
+impl Link for Pipe {
+    fn receive(&mut self) -> Option<u8> {
+        self.buffers.borrow_mut().input.pop_front()
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        self.buffers.borrow_mut().output.push(byte);
+    }
+}
+
+/// Standard input and output
+///
+/// A thread reads standard input, so the guest never waits for it. The terminal is left as it
+/// is: in its usual line mode, the guest receives whole lines.
+pub struct Stdio {
+    input: Receiver<u8>,
+}
+
+impl Stdio {
+    pub fn new() -> Self {
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+        Stdio { input }
+    }
+}
+
+impl Default for Stdio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Link for Stdio {
+    fn receive(&mut self) -> Option<u8> {
+        self.input.try_recv().ok()
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        let mut stdout = io::stdout().lock();
+        // Nothing to do about a closed standard output but to carry on
+        let _ = stdout.write_all(&[byte]).and_then(|()| stdout.flush());
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use pty::Pty;
+
+#[cfg(target_os = "linux")]
+mod pty {
+    use std::ffi::CStr;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Write};
+    use std::mem::MaybeUninit;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::{Path, PathBuf};
+
+    use super::Link;
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// A pseudo-terminal in raw mode
+    ///
+    /// Programs open the device at [`Pty::path`] like a serial port, e.g. with `screen` or with
+    /// `target remote` in GDB. The emulator keeps it open too, so they can come and go. What the
+    /// guest transmits while the terminal's buffer is full is lost.
+    pub struct Pty {
+        master: File,
+        _slave: File,
+        path: PathBuf,
+    }
+
+    impl Pty {
+        pub fn open() -> io::Result<Self> {
+            let fd = check(unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) })?;
+            let master = unsafe { File::from_raw_fd(fd) };
+            check(unsafe { libc::grantpt(fd) })?;
+            check(unsafe { libc::unlockpt(fd) })?;
+            let mut name = [0; 64];
+            let error = unsafe { libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) };
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+            let path = unsafe { CStr::from_ptr(name.as_ptr()) };
+            let path = PathBuf::from(path.to_str().map_err(io::Error::other)?);
+
+            let slave = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NOCTTY)
+                .open(&path)?;
+            unsafe {
+                let mut termios = MaybeUninit::uninit();
+                check(libc::tcgetattr(slave.as_raw_fd(), termios.as_mut_ptr()))?;
+                let mut termios = termios.assume_init();
+                libc::cfmakeraw(&mut termios);
+                check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios))?;
+
+                let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+                check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+            }
+
+            Ok(Pty {
+                master,
+                _slave: slave,
+                path,
+            })
+        }
+
+        /// The device to open, such as `/dev/pts/3`
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Link for Pty {
+        fn receive(&mut self) -> Option<u8> {
+            let mut byte = [0];
+            match self.master.read(&mut byte) {
+                Ok(1) => Some(byte[0]),
+                _ => None,
+            }
+        }
+
+        fn transmit(&mut self, byte: u8) {
+            let _ = self.master.write(&[byte]);
+        }
+    }
+}
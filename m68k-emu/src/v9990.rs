@@ -0,0 +1,1054 @@
+//! Yamaha V9990 video display processor
+//!
+//! The model of the chip `m68k_nano::v9990` drives. Its ports are at consecutive byte addresses
+//! from the start of the region, as the driver's register block expects: VRAM data, palette data,
+//! command data, register data, register select, status, interrupt flag and system control. Word
+//! and long word accesses are taken as consecutive byte accesses.
+//!
+//! The model has the 512K of VRAM, the 64-entry palette, the P1, P2 and bitmap display modes and
+//! the command engine. [`V9990::frame`] renders the screen as a monitor would show it, and
+//! [`Frame::write_png`] saves it for a look, or as the golden image of a test.
+//!
+//! Commands run to completion as soon as they are started, or as soon as the CPU has transferred
+//! their data, so the status never shows them busy in between. Sprites, the bitmap cursors,
+//! interlace, YJK and YUV colours and the Kanji ROM aren't emulated.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::bus::{Device, Size};
+
+/// Size of the VRAM
+pub const VRAM_SIZE: usize = 512 * 1024;
+
+// Ports
+const VRAM_DATA: u32 = 0;
+const PALETTE_DATA: u32 = 1;
+const COMMAND_DATA: u32 = 2;
+const REGISTER_DATA: u32 = 3;
+const REGISTER_SELECT: u32 = 4;
+const STATUS: u32 = 5;
+const INTERRUPT_FLAG: u32 = 6;
+const SYSTEM_CONTROL: u32 = 7;
+
+// Status bits, named as in `m68k_nano::v9990`
+const S_CE: u8 = 1 << 0;
+const S_MCS: u8 = 1 << 2;
+const S_BD: u8 = 1 << 4;
+const S_HR: u8 = 1 << 5;
+const S_VR: u8 = 1 << 6;
+const S_TR: u8 = 1 << 7;
+
+// Interrupt flags; register 9 enables them with the same bits
+const I_VI_FLAG: u8 = 1 << 0;
+const I_HI_FLAG: u8 = 1 << 1;
+const I_CE_FLAG: u8 = 1 << 2;
+
+// System control bits
+const SC_MCS: u8 = 1 << 0;
+const SC_SRS: u8 = 1 << 1;
+
+// Register select bits: write and read increment inhibit
+const RS_WII: u8 = 1 << 7;
+const RS_RII: u8 = 1 << 6;
+
+// Registers
+const R_VRAM_WRITE: usize = 0; // to 2, bit 7 of 2 inhibits the increment
+const R_VRAM_READ: usize = 3; // to 5, likewise
+const R_SCREEN_MODE_0: usize = 6;
+const R_SCREEN_MODE_1: usize = 7;
+const R_CONTROL: usize = 8;
+const R_INTERRUPT_ENABLE: usize = 9;
+const R_INTERRUPT_LINE: usize = 10; // and 11
+const R_PALETTE_CONTROL: usize = 13;
+const R_PALETTE_POINTER: usize = 14;
+const R_BACK_DROP: usize = 15;
+const R_SCROLL_A_Y: usize = 17; // and 18
+const R_SCROLL_A_X: usize = 19; // and 20
+const R_SCROLL_B_Y: usize = 21; // and 22
+const R_SCROLL_B_X: usize = 23; // and 24
+const R_SX: usize = 32; // SA for the linear commands
+const R_SY: usize = 34;
+const R_DX: usize = 36; // DA for the linear commands
+const R_DY: usize = 38;
+const R_NX: usize = 40; // NA for BMLL, MJ for LINE
+const R_NY: usize = 42; // MI for LINE
+const R_ARG: usize = 44;
+const R_LOP: usize = 45;
+const R_WM: usize = 46;
+const R_FC: usize = 48;
+const R_BC: usize = 50;
+const R_OP: usize = 52;
+const R_BX: usize = 53;
+
+// R#6
+const DSPM_P1: u8 = 0;
+const DSPM_P2: u8 = 1;
+const DSPM_BITMAP: u8 = 2;
+// R#7
+const PAL: u8 = 1 << 3;
+// R#8
+const DISP: u8 = 1 << 7;
+// R#13
+const PLTAIH: u8 = 1 << 4;
+// R#44
+const DIX: u8 = 1 << 0;
+const DIY: u8 = 1 << 1;
+const NEQ: u8 = 1 << 2;
+const MAJ: u8 = 1 << 3;
+// R#45
+const TP: u8 = 1 << 4;
+
+/// Lines shown, and the first of the vertical non-display period
+const DISPLAY_LINES: u32 = 212;
+
+/// CPU cycles per frame if not told otherwise: an 8 MHz 68000 and a 60 Hz display
+const FRAME_CYCLES: u64 = 8_000_000 / 60;
+
+/// A command that waits for the CPU
+enum Command {
+    /// LMMC: pixels from the command data port
+    Pixels { rect: Rect, low: Option<u8> },
+    /// CMMC: bits from the command data port, for the foreground or background colour
+    Bits { rect: Rect },
+    /// LMCM and POINT: bytes for the command data port
+    Read(VecDeque<u8>),
+}
+
+/// The layout of the image the command engine draws on
+#[derive(Clone, Copy)]
+struct Image {
+    width: u32,
+    bpp: u32,
+}
+
+impl Image {
+    fn height(self) -> u32 {
+        VRAM_SIZE as u32 * 8 / (self.width * self.bpp)
+    }
+
+    /// Returns the address of the byte of a pixel, and the position of its bits in the byte
+    ///
+    /// Coordinates wrap around the image. The leftmost pixel of a byte is in its high bits, and
+    /// 16-bit pixels are little-endian.
+    fn locate(self, x: u32, y: u32) -> (usize, u32) {
+        let x = x & (self.width - 1);
+        let y = y & (self.height() - 1);
+        let bit = (y * self.width + x) * self.bpp;
+        let shift = if self.bpp >= 8 {
+            0
+        } else {
+            8 - self.bpp - bit % 8
+        };
+        (bit as usize / 8, shift)
+    }
+
+    fn pixel_mask(self) -> u16 {
+        ((1u32 << self.bpp) - 1) as u16
+    }
+}
+
+/// A rectangle of pixels, walked row by row in the directions of `ARG`
+struct Rect {
+    x: u32,
+    y: u32,
+    nx: u32,
+    ny: u32,
+    dix: bool,
+    diy: bool,
+    i: u32,
+    j: u32,
+}
+
+impl Iterator for Rect {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        if self.j == self.ny {
+            return None;
+        }
+        let step = |start: u32, n: u32, back: bool| {
+            if back {
+                start.wrapping_sub(n)
+            } else {
+                start.wrapping_add(n)
+            }
+        };
+        let point = (
+            step(self.x, self.i, self.dix),
+            step(self.y, self.j, self.diy),
+        );
+        self.i += 1;
+        if self.i == self.nx {
+            self.i = 0;
+            self.j += 1;
+        }
+        Some(point)
+    }
+}
+
+/// Applies a logical operation bit by bit: bit `2 * s + d` of `lop` is the result for source
+/// bit `s` and destination bit `d`
+fn logic(lop: u8, src: u8, dst: u8) -> u8 {
+    let mut result = 0;
+    if lop & 1 != 0 {
+        result |= !src & !dst;
+    }
+    if lop & 2 != 0 {
+        result |= !src & dst;
+    }
+    if lop & 4 != 0 {
+        result |= src & !dst;
+    }
+    if lop & 8 != 0 {
+        result |= src & dst;
+    }
+    result
+}
+
+/// Expands a 5-bit colour component to 8 bits
+fn expand(value: u8) -> u8 {
+    let value = value & 0x1f;
+    value << 3 | value >> 2
+}
+
+/// Splits a byte into the pixels it holds, leftmost first
+fn unpack(bpp: u32, byte: u8) -> impl Iterator<Item = u16> {
+    (0..8 / bpp).map(move |i| u16::from(byte >> (8 - bpp * (i + 1))) & ((1 << bpp) - 1))
+}
+
+/// Packs pixels into bytes, the last one padded with zeros
+fn pack(bpp: u32, pixels: impl Iterator<Item = u16>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match bpp {
+        16 => {
+            for pixel in pixels {
+                bytes.extend(pixel.to_le_bytes());
+            }
+            return bytes;
+        }
+        8 => return pixels.map(|pixel| pixel as u8).collect(),
+        _ => {}
+    }
+    let mut byte = 0;
+    let mut bits = 0;
+    for pixel in pixels {
+        byte = byte << bpp | pixel as u8;
+        bits += bpp;
+        if bits == 8 {
+            bytes.push(byte);
+            byte = 0;
+            bits = 0;
+        }
+    }
+    if bits != 0 {
+        bytes.push(byte << (8 - bits));
+    }
+    bytes
+}
+
+/// The V9990, as a device on the bus
+pub struct V9990 {
+    vram: Vec<u8>,
+    /// Red, green and blue, 5 bits each; bit 7 of red is YS
+    palette: [[u8; 3]; 64],
+    registers: [u8; 64],
+    select: u8,
+    flags: u8,
+    system: u8,
+    /// CE, BD and TR; the others are computed
+    status: u8,
+    write_address: u32,
+    read_address: u32,
+    command: Option<Command>,
+    level: u8,
+    frame_cycles: u64,
+    /// Cycles into the current frame
+    cycles: u64,
+    line: u32,
+    frames: u64,
+}
+
+impl V9990 {
+    /// Creates the chip with cleared VRAM and registers
+    ///
+    /// `level` is the interrupt level the board wires the INT output to, 0 if it isn't connected.
+    pub fn new(level: u8) -> Self {
+        V9990 {
+            vram: vec![0; VRAM_SIZE],
+            palette: [[0; 3]; 64],
+            registers: [0; 64],
+            select: 0,
+            flags: 0,
+            system: 0,
+            status: 0,
+            write_address: 0,
+            read_address: 0,
+            command: None,
+            level,
+            frame_cycles: FRAME_CYCLES,
+            cycles: 0,
+            line: 0,
+            frames: 0,
+        }
+    }
+
+    /// Sets the length of a frame in CPU cycles, 133 333 by default: 60 Hz with an 8 MHz CPU
+    pub fn set_frame_cycles(&mut self, cycles: u64) {
+        self.frame_cycles = cycles;
+    }
+
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        &mut self.vram
+    }
+
+    /// Returns register `n`
+    pub fn register(&self, n: usize) -> u8 {
+        self.registers[n]
+    }
+
+    /// Returns palette entry `n`: red, green and blue, 5 bits each
+    pub fn palette(&self, n: usize) -> [u8; 3] {
+        let [r, g, b] = self.palette[n];
+        [r & 0x1f, g, b]
+    }
+
+    /// Number of frames since power-on
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    fn word(&self, register: usize) -> u32 {
+        u32::from(self.registers[register]) | u32::from(self.registers[register + 1]) << 8
+    }
+
+    /// A 19-bit VRAM address in three registers
+    fn address(&self, register: usize) -> u32 {
+        self.word(register) | u32::from(self.registers[register + 2] & 7) << 16
+    }
+
+    fn display_mode(&self) -> u8 {
+        self.registers[R_SCREEN_MODE_0] >> 6
+    }
+
+    fn lines(&self) -> u32 {
+        if self.registers[R_SCREEN_MODE_1] & PAL != 0 {
+            313
+        } else {
+            262
+        }
+    }
+
+    fn line_cycles(&self) -> u64 {
+        (self.frame_cycles / u64::from(self.lines())).max(1)
+    }
+
+    fn read_port(&mut self, port: u32) -> u8 {
+        match port {
+            VRAM_DATA => {
+                let value = self.vram[self.read_address as usize];
+                if self.registers[R_VRAM_READ + 2] & 0x80 == 0 {
+                    self.read_address = (self.read_address + 1) % VRAM_SIZE as u32;
+                }
+                value
+            }
+            PALETTE_DATA => {
+                let pointer = self.registers[R_PALETTE_POINTER];
+                let value = match pointer & 3 {
+                    3 => 0,
+                    component => self.palette[usize::from(pointer >> 2)][usize::from(component)],
+                };
+                self.next_palette_component();
+                value
+            }
+            COMMAND_DATA => self.read_command_data(),
+            REGISTER_DATA => {
+                let value = self.registers[usize::from(self.select & 0x3f)];
+                if self.select & RS_RII == 0 {
+                    self.select = self.select & 0xc0 | (self.select + 1) & 0x3f;
+                }
+                value
+            }
+            STATUS => {
+                let mut status = self.status;
+                if self.line >= DISPLAY_LINES {
+                    status |= S_VR;
+                }
+                if self.cycles % self.line_cycles() >= self.line_cycles() * 3 / 4 {
+                    status |= S_HR;
+                }
+                if self.system & SC_MCS != 0 {
+                    status |= S_MCS;
+                }
+                status
+            }
+            INTERRUPT_FLAG => self.flags,
+            _ => 0xff,
+        }
+    }
+
+    fn write_port(&mut self, port: u32, value: u8) {
+        match port {
+            VRAM_DATA => {
+                self.vram[self.write_address as usize] = value;
+                if self.registers[R_VRAM_WRITE + 2] & 0x80 == 0 {
+                    self.write_address = (self.write_address + 1) % VRAM_SIZE as u32;
+                }
+            }
+            PALETTE_DATA => {
+                let pointer = self.registers[R_PALETTE_POINTER];
+                let entry = &mut self.palette[usize::from(pointer >> 2)];
+                match pointer & 3 {
+                    0 => entry[0] = value & 0x9f,
+                    1 => entry[1] = value & 0x1f,
+                    2 => entry[2] = value & 0x1f,
+                    _ => {}
+                }
+                self.next_palette_component();
+            }
+            COMMAND_DATA => self.write_command_data(value),
+            REGISTER_DATA => {
+                self.write_register(usize::from(self.select & 0x3f), value);
+                if self.select & RS_WII == 0 {
+                    self.select = self.select & 0xc0 | (self.select + 1) & 0x3f;
+                }
+            }
+            REGISTER_SELECT => self.select = value,
+            // Writing a one clears a flag
+            INTERRUPT_FLAG => self.flags &= !value,
+            SYSTEM_CONTROL => {
+                self.system = value;
+                if value & SC_SRS != 0 {
+                    self.registers = [0; 64];
+                    self.flags = 0;
+                    self.status = 0;
+                    self.command = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn next_palette_component(&mut self) {
+        if self.registers[R_PALETTE_CONTROL] & PLTAIH != 0 {
+            return;
+        }
+        let pointer = &mut self.registers[R_PALETTE_POINTER];
+        *pointer = if *pointer & 3 >= 2 {
+            (*pointer & !3).wrapping_add(4)
+        } else {
+            *pointer + 1
+        };
+    }
+
+    fn write_register(&mut self, n: usize, value: u8) {
+        self.registers[n] = value;
+        match n {
+            0..=2 => self.write_address = self.address(R_VRAM_WRITE),
+            3..=5 => self.read_address = self.address(R_VRAM_READ),
+            R_OP => self.start(),
+            _ => {}
+        }
+    }
+
+    /// The image the command engine works on: the pattern generators in P1 and P2 mode
+    fn image(&self) -> Image {
+        let mode = self.registers[R_SCREEN_MODE_0];
+        match mode >> 6 {
+            DSPM_P1 => Image { width: 256, bpp: 4 },
+            DSPM_P2 => Image { width: 512, bpp: 4 },
+            _ => Image {
+                width: 256 << (mode >> 2 & 3),
+                bpp: 2 << (mode & 3),
+            },
+        }
+    }
+
+    fn pixel(&self, image: Image, x: u32, y: u32) -> u16 {
+        let (address, shift) = image.locate(x, y);
+        match image.bpp {
+            16 => u16::from_le_bytes([self.vram[address], self.vram[address + 1]]),
+            8 => self.vram[address].into(),
+            _ => u16::from(self.vram[address] >> shift) & image.pixel_mask(),
+        }
+    }
+
+    /// The bits of a colour register for the pixel at `x`, `y`
+    ///
+    /// Below 16 bits per pixel, the register holds the colour repeated across the VRAM word,
+    /// and each pixel takes the bits at its position.
+    fn color(&self, register: usize, image: Image, x: u32, y: u32) -> u16 {
+        let color = self.word(register) as u16;
+        if image.bpp == 16 {
+            return color;
+        }
+        let (address, shift) = image.locate(x, y);
+        color >> ((address as u32 & 1) * 8 + shift) & image.pixel_mask()
+    }
+
+    /// Writes a byte of VRAM through the logical operation and the write mask
+    fn put_byte(&mut self, address: usize, src: u8, mask: u8) {
+        let address = address % VRAM_SIZE;
+        let lop = self.registers[R_LOP];
+        let mask = mask & (self.word(R_WM) >> (8 * (address & 1))) as u8;
+        let dst = self.vram[address];
+        self.vram[address] = dst & !mask | logic(lop, src, dst) & mask;
+    }
+
+    /// Draws a pixel through the logical operation, the transparency and the write mask
+    fn put(&mut self, image: Image, x: u32, y: u32, color: u16) {
+        if self.registers[R_LOP] & TP != 0 && color == 0 {
+            return;
+        }
+        let (address, shift) = image.locate(x, y);
+        match image.bpp {
+            16 => {
+                let [low, high] = color.to_le_bytes();
+                self.put_byte(address, low, 0xff);
+                self.put_byte(address + 1, high, 0xff);
+            }
+            8 => self.put_byte(address, color as u8, 0xff),
+            _ => self.put_byte(
+                address,
+                (color as u8) << shift,
+                (image.pixel_mask() as u8) << shift,
+            ),
+        }
+    }
+
+    fn rect(&self, origin: usize) -> Rect {
+        let arg = self.registers[R_ARG];
+        let nx = self.word(R_NX) & 0x7ff;
+        let ny = self.word(R_NY) & 0xfff;
+        Rect {
+            x: self.word(origin) & 0x7ff,
+            y: self.word(origin + 2) & 0xfff,
+            nx: if nx == 0 { 2048 } else { nx },
+            ny: if ny == 0 { 4096 } else { ny },
+            dix: arg & DIX != 0,
+            diy: arg & DIY != 0,
+            i: 0,
+            j: 0,
+        }
+    }
+
+    /// Starts the command written to `OP`
+    fn start(&mut self) {
+        let image = self.image();
+        self.command = None;
+        self.status = self.status & !(S_TR | S_BD) | S_CE;
+        match self.registers[R_OP] >> 4 {
+            // STOP
+            0 => {
+                self.status &= !S_CE;
+                return;
+            }
+            // LMMC
+            1 => {
+                let rect = self.rect(R_DX);
+                self.command = Some(Command::Pixels { rect, low: None });
+            }
+            // LMMV
+            2 => {
+                for (x, y) in self.rect(R_DX) {
+                    let color = self.color(R_FC, image, x, y);
+                    self.put(image, x, y, color);
+                }
+            }
+            // LMCM
+            3 => {
+                let pixels: Vec<u16> = self
+                    .rect(R_SX)
+                    .map(|(x, y)| self.pixel(image, x, y))
+                    .collect();
+                let bytes = pack(image.bpp, pixels.into_iter());
+                self.command = Some(Command::Read(bytes.into()));
+            }
+            // LMMM
+            4 => {
+                for ((sx, sy), (dx, dy)) in self.rect(R_SX).zip(self.rect(R_DX)) {
+                    let color = self.pixel(image, sx, sy);
+                    self.put(image, dx, dy, color);
+                }
+            }
+            // CMMC
+            5 => {
+                let rect = self.rect(R_DX);
+                self.command = Some(Command::Bits { rect });
+            }
+            // CMMM
+            7 => {
+                let mut address = self.address(R_SX) as usize;
+                let mut bits = 0;
+                let mut byte = 0;
+                for (x, y) in self.rect(R_DX) {
+                    if bits == 0 {
+                        byte = self.vram[address % VRAM_SIZE];
+                        address += 1;
+                        bits = 8;
+                    }
+                    bits -= 1;
+                    self.put_bit(image, x, y, byte >> bits & 1 != 0);
+                }
+            }
+            // BMXL
+            8 => {
+                let mut address = self.address(R_SX) as usize;
+                let mut rect = self.rect(R_DX);
+                'bytes: loop {
+                    let pixels: Vec<u16> = if image.bpp == 16 {
+                        let low = self.vram[address % VRAM_SIZE];
+                        let high = self.vram[(address + 1) % VRAM_SIZE];
+                        address += 2;
+                        vec![u16::from_le_bytes([low, high])]
+                    } else {
+                        let byte = self.vram[address % VRAM_SIZE];
+                        address += 1;
+                        unpack(image.bpp, byte).collect()
+                    };
+                    for color in pixels {
+                        match rect.next() {
+                            Some((x, y)) => self.put(image, x, y, color),
+                            None => break 'bytes,
+                        }
+                    }
+                }
+            }
+            // BMLX
+            9 => {
+                let pixels: Vec<u16> = self
+                    .rect(R_SX)
+                    .map(|(x, y)| self.pixel(image, x, y))
+                    .collect();
+                let address = self.address(R_DX) as usize;
+                for (i, byte) in pack(image.bpp, pixels.into_iter()).into_iter().enumerate() {
+                    self.put_byte(address + i, byte, 0xff);
+                }
+            }
+            // BMLL
+            10 => {
+                let source = self.address(R_SX) as usize;
+                let destination = self.address(R_DX) as usize;
+                for i in 0..self.address(R_NX) as usize {
+                    let byte = self.vram[(source + i) % VRAM_SIZE];
+                    self.put_byte(destination + i, byte, 0xff);
+                }
+            }
+            // LINE
+            11 => self.line(image),
+            // SRCH
+            12 => self.search(image),
+            // POINT
+            13 => {
+                let (x, y) = (self.word(R_SX), self.word(R_SY));
+                let color = self.pixel(image, x, y);
+                let bytes = if image.bpp == 16 {
+                    color.to_le_bytes().to_vec()
+                } else {
+                    vec![color as u8]
+                };
+                self.command = Some(Command::Read(bytes.into()));
+            }
+            // PSET
+            14 => {
+                let (x, y) = (self.word(R_DX), self.word(R_DY));
+                let color = self.color(R_FC, image, x, y);
+                self.put(image, x, y, color);
+            }
+            // ADVN
+            15 => {
+                let arg = self.registers[R_ARG];
+                let (register, back, mask) = if arg & MAJ == 0 {
+                    (R_DX, arg & DIX != 0, 0x7ff)
+                } else {
+                    (R_DY, arg & DIY != 0, 0xfff)
+                };
+                let value = self.word(register);
+                let value = if back {
+                    value.wrapping_sub(1)
+                } else {
+                    value + 1
+                } & mask;
+                self.registers[register] = value as u8;
+                self.registers[register + 1] = (value >> 8) as u8;
+            }
+            // CMMK, without a Kanji ROM
+            _ => {}
+        }
+        match self.command {
+            Some(_) => self.status |= S_TR,
+            None => self.finish(),
+        }
+    }
+
+    fn finish(&mut self) {
+        self.command = None;
+        self.status &= !(S_CE | S_TR);
+        self.flags |= I_CE_FLAG;
+    }
+
+    fn put_bit(&mut self, image: Image, x: u32, y: u32, set: bool) {
+        let register = if set { R_FC } else { R_BC };
+        let color = self.color(register, image, x, y);
+        self.put(image, x, y, color);
+    }
+
+    /// Draws `MJ + 1` pixels from `DX`, `DY` along the major axis, stepping along the minor axis
+    /// `MI` times in all
+    fn line(&mut self, image: Image) {
+        let arg = self.registers[R_ARG];
+        let major = self.word(R_NX) & 0xfff;
+        let minor = self.word(R_NY) & 0xfff;
+        let (mut x, mut y) = (self.word(R_DX), self.word(R_DY));
+        let step = |value: u32, back: bool| {
+            if back {
+                value.wrapping_sub(1)
+            } else {
+                value.wrapping_add(1)
+            }
+        };
+        let mut error = 0i64;
+        for _ in 0..=major {
+            let color = self.color(R_FC, image, x, y);
+            self.put(image, x, y, color);
+            error += i64::from(minor);
+            let carry = 2 * error >= i64::from(major) && minor != 0;
+            if carry {
+                error -= i64::from(major);
+            }
+            if arg & MAJ == 0 {
+                x = step(x, arg & DIX != 0);
+                if carry {
+                    y = step(y, arg & DIY != 0);
+                }
+            } else {
+                y = step(y, arg & DIY != 0);
+                if carry {
+                    x = step(x, arg & DIX != 0);
+                }
+            }
+        }
+    }
+
+    /// Looks along the line from `SX`, `SY` for the foreground colour, or for another colour with
+    /// `NEQ`, up to the edge of the image
+    fn search(&mut self, image: Image) {
+        let arg = self.registers[R_ARG];
+        let y = self.word(R_SY);
+        let mut x = self.word(R_SX) & (image.width - 1);
+        loop {
+            let found =
+                (self.pixel(image, x, y) == self.color(R_FC, image, x, y)) != (arg & NEQ != 0);
+            if found {
+                self.status |= S_BD;
+                self.registers[R_BX] = x as u8;
+                self.registers[R_BX + 1] = (x >> 8) as u8;
+                return;
+            }
+            if arg & DIX != 0 {
+                if x == 0 {
+                    return;
+                }
+                x -= 1;
+            } else {
+                if x == image.width - 1 {
+                    return;
+                }
+                x += 1;
+            }
+        }
+    }
+
+    fn write_command_data(&mut self, value: u8) {
+        let image = self.image();
+        let Some(command) = self.command.take() else {
+            return;
+        };
+        let command = match command {
+            Command::Pixels { mut rect, low } => {
+                let (pixels, low) = match (image.bpp, low) {
+                    (16, None) => (Vec::new(), Some(value)),
+                    (16, Some(low)) => (vec![u16::from_le_bytes([low, value])], None),
+                    (bpp, _) => (unpack(bpp, value).collect(), None),
+                };
+                let mut done = false;
+                for color in pixels {
+                    match rect.next() {
+                        Some((x, y)) => self.put(image, x, y, color),
+                        None => done = true,
+                    }
+                }
+                let done = done || rect.j == rect.ny;
+                (!done).then_some(Command::Pixels { rect, low })
+            }
+            Command::Bits { mut rect } => {
+                for bit in (0..8).rev() {
+                    match rect.next() {
+                        Some((x, y)) => self.put_bit(image, x, y, value >> bit & 1 != 0),
+                        None => break,
+                    }
+                }
+                (rect.j != rect.ny).then_some(Command::Bits { rect })
+            }
+            read => Some(read),
+        };
+        match command {
+            Some(command) => self.command = Some(command),
+            None => self.finish(),
+        }
+    }
+
+    fn read_command_data(&mut self) -> u8 {
+        let Some(Command::Read(bytes)) = &mut self.command else {
+            return 0;
+        };
+        let value = bytes.pop_front().unwrap_or(0);
+        if bytes.is_empty() {
+            self.finish();
+        }
+        value
+    }
+
+    fn start_line(&mut self) {
+        if self.line == DISPLAY_LINES {
+            self.flags |= I_VI_FLAG;
+        }
+        if self.line == self.word(R_INTERRUPT_LINE) & 0x3ff {
+            self.flags |= I_HI_FLAG;
+        }
+    }
+
+    fn rgb(&self, entry: usize) -> [u8; 3] {
+        let [r, g, b] = self.palette[entry];
+        [expand(r), expand(g), expand(b)]
+    }
+
+    /// Renders what the chip would display now
+    ///
+    /// P1 and the 256-pixel bitmap modes are 256 pixels wide, P2 and the 512-pixel bitmap modes
+    /// 512, and the bitmap mode with the fastest dot clock 1024. Frames are 212 lines high. With
+    /// the display disabled, the whole frame is the back drop colour.
+    pub fn frame(&self) -> Frame {
+        let mode = self.display_mode();
+        let width = match mode {
+            DSPM_P1 => 256,
+            DSPM_P2 => 512,
+            _ => match self.registers[R_SCREEN_MODE_0] >> 4 & 3 {
+                0 => 1024,
+                1 => 512,
+                _ => 256,
+            },
+        };
+        let back_drop = self.rgb(usize::from(self.registers[R_BACK_DROP] & 0x3f));
+        let mut frame = Frame {
+            width,
+            height: DISPLAY_LINES,
+            pixels: vec![back_drop; (width * DISPLAY_LINES) as usize],
+        };
+        if self.registers[R_CONTROL] & DISP == 0 {
+            return frame;
+        }
+        for y in 0..DISPLAY_LINES {
+            for x in 0..width {
+                let color = match mode {
+                    DSPM_P1 => self.p1(x, y),
+                    DSPM_P2 => self.p2(x, y),
+                    DSPM_BITMAP => Some(self.bitmap(x, y)),
+                    // Stand-by
+                    _ => Some([0; 3]),
+                };
+                if let Some(color) = color {
+                    frame.pixels[(y * width + x) as usize] = color;
+                }
+            }
+        }
+        frame
+    }
+
+    fn scroll(&self, y: usize, x: usize, y_mask: u32) -> (u32, u32) {
+        let scroll_y = self.word(y) & y_mask;
+        let scroll_x = u32::from(self.registers[x] & 7) | u32::from(self.registers[x + 1]) << 3;
+        (scroll_x, scroll_y)
+    }
+
+    /// Colour number of a pixel of a pattern layer, from its name table
+    ///
+    /// Pattern `n` of a generator is at `n % per_row * 8`, `n / per_row * 8` in an image of
+    /// `per_row * 8` 4-bit pixels wide.
+    fn pattern_pixel(
+        &self,
+        names: usize,
+        columns: u32,
+        patterns: usize,
+        per_row: u32,
+        x: u32,
+        y: u32,
+    ) -> u8 {
+        let entry = names + ((y / 8 * columns + x / 8) * 2) as usize;
+        let pattern =
+            u32::from(u16::from_le_bytes([self.vram[entry], self.vram[entry + 1]]) & 0x1fff);
+        let px = pattern % per_row * 8 + x % 8;
+        let py = pattern / per_row * 8 + y % 8;
+        let byte = self.vram[(patterns + (py * per_row * 4 + px / 2) as usize) % VRAM_SIZE];
+        if px & 1 == 0 {
+            byte >> 4
+        } else {
+            byte & 0xf
+        }
+    }
+
+    /// Layer A in front of layer B, 512 by 512 pixels each; colour 0 is transparent
+    fn p1(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        let offsets = self.registers[R_PALETTE_CONTROL];
+        let (scroll_x, scroll_y) = self.scroll(R_SCROLL_A_Y, R_SCROLL_A_X, 0x1fff);
+        let a = self.pattern_pixel(
+            0x7c000,
+            64,
+            0,
+            32,
+            (x + scroll_x) & 511,
+            (y + scroll_y) & 511,
+        );
+        if a != 0 {
+            return Some(self.rgb(usize::from((offsets & 3) << 4 | a)));
+        }
+        let (scroll_x, scroll_y) = self.scroll(R_SCROLL_B_Y, R_SCROLL_B_X, 0x1ff);
+        let b = self.pattern_pixel(
+            0x7e000,
+            64,
+            0x40000,
+            32,
+            (x + scroll_x) & 511,
+            (y + scroll_y) & 511,
+        );
+        (b != 0).then(|| self.rgb(usize::from((offsets >> 2 & 3) << 4 | b)))
+    }
+
+    /// One layer of 1024 by 512 pixels; colour 0 is transparent
+    fn p2(&self, x: u32, y: u32) -> Option<[u8; 3]> {
+        let offsets = self.registers[R_PALETTE_CONTROL];
+        let (scroll_x, scroll_y) = self.scroll(R_SCROLL_A_Y, R_SCROLL_A_X, 0x1fff);
+        let a = self.pattern_pixel(
+            0x7c000,
+            128,
+            0,
+            64,
+            (x + scroll_x) & 1023,
+            (y + scroll_y) & 511,
+        );
+        (a != 0).then(|| self.rgb(usize::from((offsets & 3) << 4 | a)))
+    }
+
+    fn bitmap(&self, x: u32, y: u32) -> [u8; 3] {
+        let image = self.image();
+        let offset = self.registers[R_PALETTE_CONTROL] & 0xf;
+        let (scroll_x, scroll_y) = self.scroll(R_SCROLL_A_Y, R_SCROLL_A_X, 0x1fff);
+        let color = self.pixel(image, x + scroll_x, y + scroll_y);
+        match image.bpp {
+            2 => self.rgb(usize::from(offset << 2) | usize::from(color)),
+            4 => self.rgb(usize::from(offset & 0xc) << 2 | usize::from(color)),
+            // 256 colours: GGGRRRBB
+            8 => {
+                let color = color as u8;
+                let three = |v: u8| (u32::from(v & 7) * 255 / 7) as u8;
+                [three(color >> 2), three(color >> 5), (color & 3) * 85]
+            }
+            // 32768 colours: YS, 5 bits of green, red and blue
+            _ => [
+                expand((color >> 5) as u8),
+                expand((color >> 10) as u8),
+                expand(color as u8),
+            ],
+        }
+    }
+}
+
+impl Device for V9990 {
+    fn read(&mut self, offset: u32, size: Size) -> u32 {
+        (0..size.bytes()).fold(0, |value, i| {
+            value << 8 | u32::from(self.read_port(offset + i))
+        })
+    }
+
+    fn write(&mut self, offset: u32, size: Size, value: u32) {
+        for i in 0..size.bytes() {
+            let shift = 8 * (size.bytes() - 1 - i);
+            self.write_port(offset + i, (value >> shift) as u8);
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let line_cycles = self.line_cycles();
+        self.cycles += cycles;
+        while self.cycles / line_cycles > u64::from(self.line) {
+            self.line += 1;
+            if self.line == self.lines() {
+                self.line = 0;
+                self.cycles -= u64::from(self.lines()) * line_cycles;
+                self.frames += 1;
+            }
+            self.start_line();
+        }
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        if self.flags & self.registers[R_INTERRUPT_ENABLE] & 7 != 0 {
+            self.level
+        } else {
+            0
+        }
+    }
+}
+
+/// A rendered screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// Red, green and blue, row by row from the top left
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Frame {
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Saves the frame as an 8-bit RGB PNG file
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(self.pixels.as_flattened())
+            .map_err(io::Error::other)
+    }
+
+    /// Reads a PNG file written by [`Frame::write_png`]
+    pub fn read_png<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        let mut reader = decoder.read_info().map_err(io::Error::other)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).map_err(io::Error::other)?;
+        if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an 8-bit RGB image",
+            ));
+        }
+        data.truncate(info.buffer_size());
+        Ok(Frame {
+            width: info.width,
+            height: info.height,
+            pixels: data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+        })
+    }
+}
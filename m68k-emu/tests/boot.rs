@@ -0,0 +1,133 @@
+//! Boots the firmware of the workspace
+//!
+//! `minimal` is the prebuilt one in `m68k-image/tests/firmware`. The others need the firmware built
+//! first, with `cargo xbuild -p m68k-rt --examples --release` and
+//! `cargo xbuild -p m68k-rom --release`.
+
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use common::Probe;
+use m68k_emu::semihosting::{self, Semihosting};
+use m68k_emu::{Bus, Channel, Control, Duart, Limit, Machine, Model, Pipe, Size, Stop};
+use m68k_image::MemoryMap;
+
+const MINIMAL: &str = "../m68k-image/tests/firmware/minimal";
+const HELLO: &str = "../target/m68k-unknown-none/release/examples/hello";
+const PANIC: &str = "../target/m68k-unknown-none/release/examples/panic";
+const ROM: &str = "../target/m68k-unknown-none/release/m68k-rom";
+
+fn boot(model: Model, path: &str) -> Machine {
+    let map = MemoryMap::read("../memory.x").unwrap();
+    let mut machine = Machine::new(model, Bus::from_memory_map(&map));
+    machine.load_file(path).unwrap();
+    machine.reset();
+    machine
+}
+
+#[test]
+fn minimal_68000() {
+    let mut machine = boot(Model::M68000, MINIMAL);
+    machine.on_exception(|_, _, _| Control::Stop);
+    let exception = match machine.run(Limit::Instructions(1_000_000)) {
+        Stop::Exception(exception) => exception,
+        stop => panic!("{:?}", stop),
+    };
+    let access = exception.access.unwrap();
+    assert_eq!(exception.vector, 3);
+    assert_eq!(access.address, 0xdead_beef);
+    assert!(access.write);
+}
+
+#[test]
+fn minimal_68020() {
+    let mut machine = boot(Model::M68020, MINIMAL);
+    let probe = Rc::new(RefCell::new(Probe::default()));
+    machine
+        .bus
+        .add_device("PROBE", 0xdead_beec, 0x10, probe.clone());
+    // The program loops once it's done
+    assert_eq!(machine.run(Limit::Instructions(1_000_000)), Stop::Limit);
+    assert_eq!(probe.borrow().writes, [(3, Size::Long, 420)]);
+}
+
+#[test]
+#[ignore = "needs the m68k-rt examples built"]
+fn hello() {
+    let mut machine = boot(Model::M68000, HELLO);
+    let console = Pipe::new();
+    let mut host = Semihosting::with_console(console.clone());
+    host.set_command_line("hello --loud");
+    semihosting::attach(&mut machine, host);
+    assert_eq!(
+        machine.run(Limit::Instructions(1_000_000)),
+        Stop::Finished(0)
+    );
+    assert_eq!(
+        String::from_utf8(console.take()).unwrap(),
+        "Hello, world!\ncommand line: hello --loud\n"
+    );
+}
+
+#[test]
+#[ignore = "needs the m68k-rt examples built"]
+fn panic() {
+    let mut machine = boot(Model::M68000, PANIC);
+    let console = Pipe::new();
+    semihosting::attach(&mut machine, Semihosting::with_console(console.clone()));
+    assert_eq!(machine.run(Limit::Instructions(1_000_000)), Stop::Stopped);
+    let output = String::from_utf8(console.take()).unwrap();
+    let mut lines = output.lines();
+    let location = lines.next().unwrap();
+    assert!(
+        location.starts_with("panicked at ") && location.contains("examples/panic.rs:"),
+        "{}",
+        output
+    );
+    assert_eq!(lines.next(), Some("the answer is 42"));
+    // Supervisor mode with interrupts masked, as after reset; the condition codes vary
+    assert!(lines.next().unwrap().starts_with("SR: 0x27"), "{}", output);
+    assert_eq!(lines.next(), Some("backtrace:"));
+    assert!(lines.next().is_some(), "{}", output);
+}
+
+/// Runs until the monitor has printed `expected`, and returns what it printed
+fn expect(machine: &mut Machine, console: &Pipe, expected: &str) -> String {
+    let mut output = String::new();
+    for _ in 0..100 {
+        machine.run(Limit::Instructions(100_000));
+        output.push_str(&String::from_utf8_lossy(&console.take()));
+        if output.contains(expected) {
+            return output;
+        }
+    }
+    panic!("expected {:?}, got {:?}", expected, output);
+}
+
+#[test]
+#[ignore = "needs m68k-rom built"]
+fn monitor() {
+    let mut machine = boot(Model::M68000, ROM);
+    let console = Pipe::new();
+    // Autovectored at level 5, as the monitor expects
+    let mut duart = Duart::new(5);
+    duart
+        .set_autovector(true)
+        .connect(Channel::A, console.clone());
+    machine.bus.add_device("DUART", 0x0080_0000, 0x20, duart);
+
+    let banner = expect(&mut machine, &console, "> ");
+    assert!(banner.contains("m68k-rom 0.1.0"));
+    assert!(banner.contains("type `help` for a list of commands"));
+
+    console.send(b"help\r");
+    let help = expect(&mut machine, &console, "> ");
+    assert!(help.contains("d [addr] [len]            dump memory"));
+
+    // A call to an odd address
+    console.send(b"c 1\r");
+    let fault = expect(&mut machine, &console, "> ");
+    assert!(fault.contains("*** address error at "), "{:?}", fault);
+}
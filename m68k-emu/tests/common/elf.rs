@@ -0,0 +1,190 @@
+//! Builds small m68k ELF executables
+
+use object::elf::{
+    EM_68K, ET_EXEC, PF_R, PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHN_ABS, SHT_NOBITS,
+    SHT_PROGBITS, STB_GLOBAL, STT_NOTYPE,
+};
+use object::write::elf::{FileHeader, ProgramHeader, SectionHeader, Sym, Writer};
+use object::Endianness;
+
+pub const TEXT: u32 = SHF_ALLOC | SHF_EXECINSTR;
+pub const RODATA: u32 = SHF_ALLOC;
+pub const DATA: u32 = SHF_ALLOC | SHF_WRITE;
+
+struct Section {
+    name: String,
+    addr: u32,
+    lma: u32,
+    flags: u32,
+    data: Vec<u8>,
+    nobits: Option<u32>,
+}
+
+pub struct ElfBuilder {
+    entry: u32,
+    sections: Vec<Section>,
+    symbols: Vec<(String, u32)>,
+}
+
+impl ElfBuilder {
+    pub fn new(entry: u32) -> Self {
+        ElfBuilder {
+            entry,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Adds a section whose contents are loaded at `lma` and run at `addr`
+    pub fn section(mut self, name: &str, addr: u32, lma: u32, flags: u32, data: Vec<u8>) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            lma,
+            flags,
+            data,
+            nobits: None,
+        });
+        self
+    }
+
+    /// Adds a `NOLOAD` section
+    pub fn nobits(mut self, name: &str, addr: u32, size: u32) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            lma: addr,
+            flags: DATA,
+            data: Vec::new(),
+            nobits: Some(size),
+        });
+        self
+    }
+
+    pub fn symbol(mut self, name: &str, value: u32) -> Self {
+        self.symbols.push((name.into(), value));
+        self
+    }
+
+    /// Replaces the contents of a section
+    pub fn replace(mut self, name: &str, data: Vec<u8>) -> Self {
+        let section = self.sections.iter_mut().find(|s| s.name == name).unwrap();
+        section.data = data;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut w = Writer::new(Endianness::Big, false, &mut buffer);
+
+        let loaded: Vec<&Section> = self
+            .sections
+            .iter()
+            .filter(|s| s.nobits.is_none())
+            .collect();
+
+        w.reserve_file_header();
+        w.reserve_program_headers(loaded.len() as u32);
+        let offsets: Vec<usize> = loaded.iter().map(|s| w.reserve(s.data.len(), 4)).collect();
+
+        w.reserve_null_section_index();
+        let names: Vec<_> = self
+            .sections
+            .iter()
+            .map(|s| {
+                w.reserve_section_index();
+                w.add_section_name(s.name.as_bytes())
+            })
+            .collect();
+
+        w.reserve_null_symbol_index();
+        let strings: Vec<_> = self
+            .symbols
+            .iter()
+            .map(|(name, _)| {
+                w.reserve_symbol_index(None);
+                w.add_string(name.as_bytes())
+            })
+            .collect();
+        w.reserve_symtab_section_index();
+        w.reserve_symtab();
+        w.reserve_strtab_section_index();
+        w.reserve_strtab();
+        w.reserve_shstrtab_section_index();
+        w.reserve_shstrtab();
+        w.reserve_section_headers();
+
+        w.write_file_header(&FileHeader {
+            os_abi: 0,
+            abi_version: 0,
+            e_type: ET_EXEC,
+            e_machine: EM_68K,
+            e_entry: self.entry.into(),
+            e_flags: 0,
+        })
+        .unwrap();
+
+        w.write_align_program_headers();
+        for (section, &offset) in loaded.iter().zip(&offsets) {
+            w.write_program_header(&ProgramHeader {
+                p_type: PT_LOAD,
+                p_flags: PF_R,
+                p_offset: offset as u64,
+                p_vaddr: section.addr.into(),
+                p_paddr: section.lma.into(),
+                p_filesz: section.data.len() as u64,
+                p_memsz: section.data.len() as u64,
+                p_align: 4,
+            });
+        }
+        for section in &loaded {
+            w.write_align(4);
+            w.write(&section.data);
+        }
+
+        w.write_null_symbol();
+        for ((_, value), &name) in self.symbols.iter().zip(&strings) {
+            w.write_symbol(&Sym {
+                name: Some(name),
+                section: None,
+                st_info: (STB_GLOBAL << 4) | STT_NOTYPE,
+                st_other: 0,
+                st_shndx: SHN_ABS,
+                st_value: (*value).into(),
+                st_size: 0,
+            });
+        }
+        w.write_strtab();
+        w.write_shstrtab();
+
+        w.write_null_section_header();
+        let mut offsets = offsets.iter();
+        for (section, &name) in self.sections.iter().zip(&names) {
+            let (sh_type, sh_offset, sh_size) = match section.nobits {
+                Some(size) => (SHT_NOBITS, 0, size),
+                None => (
+                    SHT_PROGBITS,
+                    *offsets.next().unwrap(),
+                    section.data.len() as u32,
+                ),
+            };
+            w.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type,
+                sh_flags: section.flags.into(),
+                sh_addr: section.addr.into(),
+                sh_offset: sh_offset as u64,
+                sh_size: sh_size.into(),
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        w.write_symtab_section_header(1);
+        w.write_strtab_section_header();
+        w.write_shstrtab_section_header();
+
+        buffer
+    }
+}
@@ -0,0 +1,145 @@
+//! Hand-assembled test programs, and a machine to run them on
+
+#![allow(dead_code)]
+
+pub mod elf;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use m68k_emu::{Acknowledge, Bus, Device, Limit, Machine, Model, Size, Stop};
+
+pub const ROM: u32 = 0;
+pub const ROM_SIZE: u32 = 0x1_0000;
+pub const RAM: u32 = 0x2000_0000;
+pub const RAM_SIZE: u32 = 0x1_0000;
+pub const STACK: u32 = RAM + RAM_SIZE;
+
+/// Where every vector but the reset vector points: `trap #0`, which ends the run
+pub const HANDLER: u32 = 0x300;
+/// Where programs start
+pub const START: u32 = 0x400;
+
+pub const TRAP_0: u16 = 0x4e40;
+pub const NOP: u16 = 0x4e71;
+pub const RTE: u16 = 0x4e73;
+pub const RTS: u16 = 0x4e75;
+
+pub fn bytes(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+/// Splits a long word for use in a program
+pub fn long(value: u32) -> [u16; 2] {
+    [(value >> 16) as u16, value as u16]
+}
+
+/// A vector table whose vectors all point at [`HANDLER`]
+pub fn vectors() -> Vec<u8> {
+    let mut table = vec![STACK, START];
+    table.resize(256, HANDLER);
+    table.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+pub fn bus() -> Bus {
+    let mut bus = Bus::new();
+    bus.add_rom("ROM", ROM, ROM_SIZE)
+        .add_ram("RAM", RAM, RAM_SIZE);
+    bus
+}
+
+/// A machine with `program` at [`START`], reset
+pub fn machine(model: Model, program: &[u16]) -> Machine {
+    let mut machine = Machine::new(model, bus());
+    machine.bus.load(ROM, &vectors()).unwrap();
+    machine.bus.load(HANDLER, &bytes(&[TRAP_0])).unwrap();
+    machine.bus.load(START, &bytes(program)).unwrap();
+    machine.finish_on_trap(0);
+    machine.reset();
+    machine
+}
+
+/// Runs `program` until it executes `trap #0`
+pub fn run(model: Model, program: &[u16]) -> Machine {
+    let mut machine = machine(model, program);
+    assert!(
+        matches!(machine.run(Limit::Instructions(10_000)), Stop::Finished(_)),
+        "the program didn't finish"
+    );
+    machine
+}
+
+/// Puts a routine somewhere in ROM and points `vector` at it
+pub fn handler(machine: &mut Machine, vector: u8, address: u32, code: &[u16]) {
+    machine.bus.load(address, &bytes(code)).unwrap();
+    machine
+        .bus
+        .load(u32::from(vector) * 4, &address.to_be_bytes())
+        .unwrap();
+}
+
+pub fn read(machine: &mut Machine, address: u32, size: Size) -> u32 {
+    machine.bus.read(address, size).unwrap()
+}
+
+/// A device that records accesses and requests an interrupt when told to
+#[derive(Default)]
+pub struct Probe {
+    pub reads: Vec<(u32, Size)>,
+    pub writes: Vec<(u32, Size, u32)>,
+    pub cycles: u64,
+    pub level: u8,
+    pub vector: Option<u8>,
+    pub acknowledged: Vec<u8>,
+}
+
+impl Device for Probe {
+    fn read(&mut self, offset: u32, size: Size) -> u32 {
+        self.reads.push((offset, size));
+        0x1234_5678
+    }
+
+    fn write(&mut self, offset: u32, size: Size, value: u32) {
+        self.writes.push((offset, size, value));
+        // Writing the first register sets the interrupt level
+        if offset == 0 {
+            self.level = value as u8;
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    fn interrupt_level(&self) -> u8 {
+        self.level
+    }
+
+    fn acknowledge(&mut self, level: u8) -> Acknowledge {
+        self.acknowledged.push(level);
+        match self.vector {
+            Some(vector) => Acknowledge::Vector(vector),
+            None => Acknowledge::Autovector,
+        }
+    }
+}
+
+pub const PROBE: u32 = 0x0080_0000;
+
+/// Maps a [`Probe`] at [`PROBE`]
+pub fn probe(machine: &mut Machine) -> Rc<RefCell<Probe>> {
+    let probe = Rc::new(RefCell::new(Probe::default()));
+    machine.bus.add_device("PROBE", PROBE, 0x100, probe.clone());
+    probe
+}
+
+/// Records the vectors of the exceptions the machine takes
+pub fn record(machine: &mut Machine) -> Rc<RefCell<Vec<u8>>> {
+    let taken = Rc::new(RefCell::new(Vec::new()));
+    let log = taken.clone();
+    machine.on_exception(move |_, _, exception| {
+        log.borrow_mut().push(exception.vector);
+        m68k_emu::Control::Take
+    });
+    taken
+}
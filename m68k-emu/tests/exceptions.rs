@@ -0,0 +1,235 @@
+mod common;
+
+use common::*;
+use m68k_emu::{Limit, Model, Size, Stop};
+
+const ILLEGAL: u16 = 0x4afc;
+
+/// Reads the frame the handler got, from the stack pointer on
+fn stacked(machine: &mut m68k_emu::Machine, len: u32) -> Vec<u8> {
+    let mut frame = vec![0; len as usize];
+    machine.bus.peek(machine.cpu.a[7], &mut frame).unwrap();
+    frame
+}
+
+fn word(frame: &[u8], offset: usize) -> u32 {
+    u32::from(u16::from_be_bytes([frame[offset], frame[offset + 1]]))
+}
+
+fn long(frame: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(frame[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn illegal_instruction() {
+    let mut machine = run(Model::M68000, &[NOP, ILLEGAL]);
+    assert_eq!(machine.cpu.a[7], STACK - 6);
+    let frame = stacked(&mut machine, 6);
+    assert_eq!(word(&frame, 0), 0x2700);
+    assert_eq!(long(&frame, 2), START + 2);
+}
+
+#[test]
+fn trap_and_rte() {
+    for model in [Model::M68000, Model::M68010, Model::M68020, Model::M68040] {
+        let mut machine = machine(
+            model,
+            &[
+                0x4e41, // trap #1
+                0x2001, // move.l d1,d0
+                TRAP_0,
+            ],
+        );
+        // moveq #42,d1; rte
+        handler(&mut machine, 33, 0x340, &[0x722a, RTE]);
+        assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(42));
+        assert_eq!(machine.cpu.a[7], STACK, "{:?}", model);
+    }
+}
+
+#[test]
+fn address_error_68000() {
+    let mut machine = run(
+        Model::M68000,
+        &[
+            0x7000, // moveq #0,d0
+            0x33c0, 0x2000, 0x0001, // move.w d0,(RAM+1).l
+        ],
+    );
+    let frame = stacked(&mut machine, 14);
+    // Write, not an instruction fetch, supervisor data space
+    assert_eq!(word(&frame, 0), 0x0d);
+    assert_eq!(long(&frame, 2), RAM + 1);
+    assert_eq!(word(&frame, 6), 0x33c0);
+    // Z, from the `moveq`
+    assert_eq!(word(&frame, 8), 0x2704);
+}
+
+#[test]
+fn rom_writes_are_bus_errors() {
+    let mut machine = run(
+        Model::M68000,
+        &[0x23c0, 0x0000, 0x0100], // move.l d0,($100).l
+    );
+    let frame = stacked(&mut machine, 14);
+    assert_eq!(word(&frame, 0), 0x0d);
+    assert_eq!(long(&frame, 2), 0x100);
+}
+
+#[test]
+fn bus_error_frames() {
+    // move.l ($1000000).l,d0
+    let program = [0x2039, 0x0100, 0x0000];
+
+    let mut machine = run(Model::M68010, &program);
+    assert_eq!(machine.cpu.a[7], STACK - 58);
+    let frame = stacked(&mut machine, 58);
+    assert_eq!(long(&frame, 2), START);
+    assert_eq!(word(&frame, 6), 0x8008);
+    // Read, supervisor data space
+    assert_eq!(word(&frame, 8), 0x0105);
+    assert_eq!(long(&frame, 10), 0x0100_0000);
+
+    let mut machine = run(Model::M68020, &program);
+    assert_eq!(machine.cpu.a[7], STACK - 32);
+    let frame = stacked(&mut machine, 32);
+    assert_eq!(word(&frame, 6), 0xa008);
+    assert_eq!(word(&frame, 10), 0x0145);
+    assert_eq!(long(&frame, 16), 0x0100_0000);
+
+    let mut machine = run(Model::M68040, &program);
+    assert_eq!(machine.cpu.a[7], STACK - 60);
+    let frame = stacked(&mut machine, 60);
+    assert_eq!(word(&frame, 6), 0x7008);
+    assert_eq!(word(&frame, 12), 0x0105);
+    assert_eq!(long(&frame, 20), 0x0100_0000);
+}
+
+#[test]
+fn zero_divide_frames() {
+    let program = [
+        0x7200, // moveq #0,d1
+        0x80c1, // divu.w d1,d0
+    ];
+
+    let mut machine = run(Model::M68000, &program);
+    let frame = stacked(&mut machine, 6);
+    assert_eq!(long(&frame, 2), START + 4);
+
+    // Format 2 has the address of the instruction too
+    let mut machine = run(Model::M68020, &program);
+    assert_eq!(machine.cpu.a[7], STACK - 12);
+    let frame = stacked(&mut machine, 12);
+    assert_eq!(long(&frame, 2), START + 4);
+    assert_eq!(word(&frame, 6), 0x2014);
+    assert_eq!(long(&frame, 8), START + 2);
+}
+
+#[test]
+fn vector_base_register() {
+    let program = [
+        0x203c, 0x2000, 0x0000, // move.l #RAM,d0
+        0x4e7b, 0x0801, // movec d0,vbr
+        ILLEGAL,
+    ];
+
+    let mut machine = machine(Model::M68010, &program);
+    // Vector 4 of the table in RAM: moveq #99,d0; trap #0
+    machine
+        .bus
+        .load(RAM + 4 * 4, &0x360u32.to_be_bytes())
+        .unwrap();
+    machine.bus.load(0x360, &bytes(&[0x7063, TRAP_0])).unwrap();
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(99));
+    assert_eq!(machine.cpu.vbr, RAM);
+
+    // `movec` is an illegal instruction on the 68000
+    let mut machine = common::machine(Model::M68000, &program);
+    let taken = record(&mut machine);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(RAM));
+    assert_eq!(*taken.borrow(), [4]);
+}
+
+#[test]
+fn format_error() {
+    let mut machine = machine(
+        Model::M68010,
+        &[
+            0x3f3c, 0xf000, // move.w #$f000,-(sp)
+            0x2f3c, 0x0000, 0x0400, // move.l #START,-(sp)
+            0x3f3c, 0x2700, // move.w #$2700,-(sp)
+            RTE,
+        ],
+    );
+    let taken = record(&mut machine);
+    assert!(matches!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(_)
+    ));
+    assert_eq!(*taken.borrow(), [14]);
+}
+
+#[test]
+fn privilege_violation() {
+    let mut machine = run(
+        Model::M68000,
+        &[
+            0x207c, 0x2000, 0x0100, // movea.l #RAM+$100,a0
+            0x4e60, // move.l a0,usp
+            0x027c, 0xdfff, // andi.w #$dfff,sr
+            0x4e72, 0x2700, // stop #$2700
+        ],
+    );
+    let frame = stacked(&mut machine, 6);
+    assert_eq!(word(&frame, 0), 0x0700);
+    assert_eq!(long(&frame, 2), START + 12);
+    assert_eq!(machine.cpu.usp(), RAM + 0x100);
+    assert_eq!(machine.cpu.ssp(), STACK - 6);
+}
+
+#[test]
+fn trace() {
+    let mut machine = machine(
+        Model::M68000,
+        &[
+            0x007c, 0x8000, // ori.w #$8000,sr
+            NOP, NOP,
+        ],
+    );
+    let taken = record(&mut machine);
+    assert!(matches!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(_)
+    ));
+    assert_eq!(*taken.borrow(), [9]);
+    let frame = stacked(&mut machine, 6);
+    assert_eq!(word(&frame, 0), 0xa700);
+    assert_eq!(long(&frame, 2), START + 6);
+}
+
+#[test]
+fn double_fault_halts() {
+    let mut machine = machine(Model::M68000, &[ILLEGAL]);
+    machine.cpu.a[7] = 0x0100_0000;
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Halted);
+    assert!(machine.cpu.halted);
+    assert_eq!(machine.step(), Some(Stop::Halted));
+}
+
+#[test]
+fn misaligned_accesses() {
+    let program = [
+        0x203c, 0x1122, 0x3344, // move.l #$11223344,d0
+        0x23c0, 0x2000, 0x0001, // move.l d0,(RAM+1).l
+        0x2239, 0x2000, 0x0001, // move.l (RAM+1).l,d1
+        TRAP_0,
+    ];
+    let mut machine = run(Model::M68020, &program);
+    assert_eq!(machine.cpu.d[1], 0x1122_3344);
+    assert_eq!(read(&mut machine, RAM, Size::Long), 0x0011_2233);
+
+    let mut machine = common::machine(Model::M68000, &program);
+    let taken = record(&mut machine);
+    machine.run(Limit::Instructions(100));
+    assert_eq!(*taken.borrow(), [3]);
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use common::*;
+use m68k_emu::gdb::{self, End};
+use m68k_emu::{Machine, Model, Watch, Watchpoint};
+
+/// The GDB end of the connection
+struct Gdb {
+    stream: TcpStream,
+}
+
+impl Gdb {
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(self.byte(), b'+');
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(
+            std::str::from_utf8(&checksum).unwrap(),
+            format!("{:02x}", sum)
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+}
+
+/// Serves the machine to `script`, which runs in another thread
+fn debug<F>(machine: &mut Machine, script: F) -> End
+where
+    F: FnOnce(&mut Gdb) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut gdb = Gdb { stream };
+        script(&mut gdb);
+    });
+    let (stream, _) = listener.accept().unwrap();
+    let end = gdb::serve(machine, stream).unwrap();
+    client.join().unwrap();
+    end
+}
+
+#[test]
+fn registers_breakpoints_and_watchpoints() {
+    let mut program = vec![
+        0x7001, // moveq #1,d0
+        0x7202, // moveq #2,d1
+        0xd081, // add.l d1,d0
+        0x23c0, // move.l d0,(RAM).l
+    ];
+    program.extend(long(RAM));
+    program.push(TRAP_0);
+    let mut machine = common::machine(Model::M68000, &program);
+
+    let end = debug(&mut machine, |gdb| {
+        assert_eq!(
+            gdb.request("qSupported:swbreak+"),
+            "PacketSize=1004;swbreak+;hwbreak+"
+        );
+        assert_eq!(gdb.request("?"), "S05");
+        let registers = gdb.request("g");
+        assert_eq!(registers.len(), 18 * 8);
+        assert_eq!(&registers[15 * 8..16 * 8], "20010000");
+        assert_eq!(&registers[16 * 8..], "0000270000000400");
+
+        assert_eq!(gdb.request("s"), "T05");
+        assert_eq!(gdb.request("p0"), "00000001");
+        assert_eq!(gdb.request("p11"), "00000402");
+
+        assert_eq!(gdb.request("Z0,404,2"), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.request("p11"), "00000404");
+        assert_eq!(gdb.request("p1"), "00000002");
+
+        // Stops after the instruction that wrote
+        assert_eq!(gdb.request("Z2,20000000,4"), "OK");
+        assert_eq!(gdb.request("c"), "T05watch:20000000;");
+        assert_eq!(gdb.request("p11"), "0000040c");
+        assert_eq!(gdb.request("m20000000,4"), "00000003");
+        assert_eq!(gdb.request("m30000000,4"), "E01");
+
+        assert_eq!(gdb.request("P0=2a"), "OK");
+        assert_eq!(gdb.request("c"), "W2a");
+    });
+    assert_eq!(end, End::Finished(0x2a));
+}
+
+#[test]
+fn faults_and_interrupts() {
+    let mut program = vec![
+        0x60fe, // bra.s *
+        0x33c0, // move.w d0,(RAM + 1).l
+    ];
+    program.extend(long(RAM + 1));
+    let mut machine = common::machine(Model::M68000, &program);
+
+    let end = debug(&mut machine, |gdb| {
+        gdb.send("c");
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.receive(), "T02");
+        assert_eq!(gdb.request("p11"), "00000400");
+
+        assert_eq!(gdb.request("M20000000,2:abcd"), "OK");
+        assert_eq!(gdb.request("m20000000,2"), "abcd");
+        assert_eq!(gdb.request("M20000000,2:ab"), "E01");
+
+        // The address error stops the program before it is taken
+        assert_eq!(gdb.request("P11=402"), "OK");
+        assert_eq!(gdb.request("c"), "T0a");
+        assert_eq!(gdb.request("?"), "T0a");
+        assert_eq!(gdb.request("D"), "OK");
+    });
+    assert_eq!(end, End::Detached);
+    // Detaching takes the exception
+    assert_eq!(machine.cpu.pc, HANDLER);
+}
+
+#[test]
+fn disconnecting() {
+    let mut machine = common::machine(Model::M68000, &[0x60fe]);
+    let end = debug(&mut machine, |gdb| {
+        assert_eq!(gdb.request("Z4,20000000,2"), "OK");
+        assert_eq!(gdb.request("vMustReplyEmpty"), "");
+    });
+    assert_eq!(end, End::Killed);
+    // Left behind by GDB, and removed
+    assert!(!machine.bus.remove_watchpoint(&Watchpoint {
+        address: RAM,
+        len: 2,
+        kind: Watch::Access,
+    }));
+}
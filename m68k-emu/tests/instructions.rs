@@ -0,0 +1,302 @@
+mod common;
+
+use common::*;
+use m68k_emu::{Limit, Model, Size, Stop};
+
+const X: u32 = 0x10;
+const N: u32 = 0x08;
+const Z: u32 = 0x04;
+const V: u32 = 0x02;
+const C: u32 = 0x01;
+
+/// Runs `program` followed by `move.w sr,d7` and returns the condition codes
+fn ccr(program: &[u16]) -> u32 {
+    let mut program = program.to_vec();
+    program.extend([0x40c7, TRAP_0]);
+    run(Model::M68000, &program).cpu.d[7] & 0x1f
+}
+
+#[test]
+fn arithmetic_flags() {
+    // moveq #-1,d0; moveq #1,d1; add.l d1,d0
+    assert_eq!(ccr(&[0x70ff, 0x7201, 0xd081]), X | Z | C);
+    // moveq #0,d0; moveq #1,d1; sub.l d1,d0
+    assert_eq!(ccr(&[0x7000, 0x7201, 0x9081]), X | N | C);
+    // move.l #$7fffffff,d0; moveq #1,d1; add.l d1,d0
+    assert_eq!(ccr(&[0x203c, 0x7fff, 0xffff, 0x7201, 0xd081]), N | V);
+    // moveq #5,d0; moveq #7,d1; cmp.l d1,d0: X is left alone
+    assert_eq!(ccr(&[0x7005, 0x7207, 0xb081]), N | C);
+    // moveq #0,d0; moveq #1,d1; sub.l d1,d0; cmp.l d1,d1
+    assert_eq!(ccr(&[0x7000, 0x7201, 0x9081, 0xb281]), X | Z);
+    // moveq #-128,d0; tst.b d0
+    assert_eq!(ccr(&[0x7080, 0x4a00]), N);
+}
+
+#[test]
+fn extended_arithmetic() {
+    let machine = run(
+        Model::M68000,
+        &[
+            0x203c, 0xffff, 0xffff, // move.l #$ffffffff,d0
+            0x7201, // moveq #1,d1
+            0x7401, // moveq #1,d2
+            0x7600, // moveq #0,d3
+            0xd082, // add.l d2,d0
+            0xd383, // addx.l d3,d1
+            0x40c7, // move.w sr,d7
+            TRAP_0,
+        ],
+    );
+    assert_eq!(machine.cpu.d[0], 0);
+    assert_eq!(machine.cpu.d[1], 2);
+    // Z is cleared by the non-zero high word
+    assert_eq!(machine.cpu.d[7] & 0x1f, 0);
+}
+
+#[test]
+fn multiply_divide() {
+    let machine = run(
+        Model::M68000,
+        &[
+            0x203c, 0x0000, 0xffff, // move.l #$ffff,d0
+            0x2200, // move.l d0,d1
+            0xc0c1, // mulu.w d1,d0
+            0x243c, 0x0001, 0x86a3, // move.l #100003,d2
+            0x760a, // moveq #10,d3
+            0x84c3, // divu.w d3,d2
+            0x78f9, // moveq #-7,d4
+            0x7a02, // moveq #2,d5
+            0x89c5, // divs.w d5,d4
+            0x2c3c, 0x0001, 0x0000, // move.l #$10000,d6
+            0x7a01, // moveq #1,d5
+            0x8cc5, // divu.w d5,d6: overflows
+            0x40c7, // move.w sr,d7
+            0x72fe, // moveq #-2,d1
+            0x7603, // moveq #3,d3
+            0xc3c3, // muls.w d3,d1
+            TRAP_0,
+        ],
+    );
+    assert_eq!(machine.cpu.d[0], 0xfffe_0001);
+    assert_eq!(machine.cpu.d[2], 0x0003_2710);
+    assert_eq!(machine.cpu.d[4], 0xffff_fffd);
+    assert_eq!(machine.cpu.d[6], 0x0001_0000);
+    assert_eq!(machine.cpu.d[7] & V, V);
+    assert_eq!(machine.cpu.d[1], -6i32 as u32);
+}
+
+#[test]
+fn bcd() {
+    let machine = run(
+        Model::M68000,
+        &[
+            0x7810, // moveq #$10,d4
+            0x7a01, // moveq #1,d5
+            0x44fc, 0x0000, // move.w #0,ccr
+            0x8905, // sbcd d5,d4
+            0x7019, // moveq #$19,d0
+            0x7228, // moveq #$28,d1
+            0xc101, // abcd d1,d0
+            0x7499, // moveq #$99,d2
+            0x7601, // moveq #1,d3
+            0xc503, // abcd d3,d2
+            0x40c7, // move.w sr,d7
+            TRAP_0,
+        ],
+    );
+    assert_eq!(machine.cpu.d[4] & 0xff, 0x09);
+    assert_eq!(machine.cpu.d[0] & 0xff, 0x47);
+    assert_eq!(machine.cpu.d[2] & 0xff, 0x00);
+    assert_eq!(machine.cpu.d[7] & (X | C), X | C);
+}
+
+#[test]
+fn addressing_modes() {
+    let program = [
+        0x207c, 0x2000, 0x0000, // movea.l #RAM,a0
+        0x203c, 0x1111, 0x1111, // move.l #$11111111,d0
+        0x223c, 0x2222, 0x2222, // move.l #$22222222,d1
+        0x20c0, // move.l d0,(a0)+
+        0x20c1, // move.l d1,(a0)+
+        0x2420, // move.l -(a0),d2
+        0x2628, 0xfffc, // move.l (-4,a0),d3
+        0x7202, // moveq #2,d1
+        0x3830, 0x10fa, // move.w (-6,a0,d1.w),d4
+        0x43fa, 0x000c, // lea (data,pc),a1
+        0x2a11, // move.l (a1),d5
+        0x3c39, 0x2000, 0x0006, // move.w (RAM+6).l,d6
+        TRAP_0, // trap #0
+        0xcafe, 0xf00d, // data: .long $cafef00d
+    ];
+    let mut machine = run(Model::M68000, &program);
+    assert_eq!(machine.cpu.a[0], RAM + 4);
+    assert_eq!(machine.cpu.d[2], 0x2222_2222);
+    assert_eq!(machine.cpu.d[3], 0x1111_1111);
+    assert_eq!(machine.cpu.d[4] & 0xffff, 0x1111);
+    assert_eq!(machine.cpu.a[1], START + 48);
+    assert_eq!(machine.cpu.d[5], 0xcafe_f00d);
+    assert_eq!(machine.cpu.d[6] & 0xffff, 0x2222);
+    assert_eq!(read(&mut machine, RAM, Size::Long), 0x1111_1111);
+}
+
+#[test]
+fn shifts_and_rotations() {
+    let machine = run(
+        Model::M68000,
+        &[
+            0x7040, // moveq #$40,d0
+            0xe300, // asl.b #1,d0
+            0x40c7, // move.w sr,d7
+            0x243c, 0x8000, 0x0001, // move.l #$80000001,d2
+            0x7204, // moveq #4,d1
+            0xe2aa, // lsr.l d1,d2
+            0x44fc, 0x0010, // move.w #X,ccr
+            0x7680, // moveq #-128,d3
+            0xe313, // roxl.b #1,d3
+            0x40c6, // move.w sr,d6
+            0x383c, 0x1234, // move.w #$1234,d4
+            0xe85c, // ror.w #4,d4
+            0x3a3c, 0x8000, // move.w #$8000,d5
+            0xe445, // asr.w #2,d5
+            TRAP_0,
+        ],
+    );
+    assert_eq!(machine.cpu.d[0] & 0xff, 0x80);
+    assert_eq!(machine.cpu.d[7] & 0x1f, N | V);
+    assert_eq!(machine.cpu.d[2], 0x0800_0000);
+    assert_eq!(machine.cpu.d[3] & 0xff, 0x01);
+    assert_eq!(machine.cpu.d[6] & 0x1f, X | C);
+    assert_eq!(machine.cpu.d[4] & 0xffff, 0x4123);
+    assert_eq!(machine.cpu.d[5] & 0xffff, 0xe000);
+}
+
+#[test]
+fn subroutines_and_loops() {
+    let machine = run(
+        Model::M68000,
+        &[
+            0x7000, // moveq #0,d0
+            0x7209, // moveq #9,d1
+            0x5280, // loop: addq.l #1,d0
+            0x51c9, 0xfffc, // dbra d1,loop
+            0x6104, // bsr.s sub
+            0x2602, // move.l d2,d3
+            TRAP_0, 0x4e56, 0xfff8, // sub: link a6,#-8
+            0x7407, // moveq #7,d2
+            0x2d42, 0xfffc, // move.l d2,(-4,a6)
+            0x48e7, 0xe080, // movem.l d0-d2/a0,-(sp)
+            0x7000, // moveq #0,d0
+            0x7200, // moveq #0,d1
+            0x7400, // moveq #0,d2
+            0x4cdf, 0x0107, // movem.l (sp)+,d0-d2/a0
+            0x242e, 0xfffc, // move.l (-4,a6),d2
+            0x4e5e, // unlk a6
+            RTS,
+        ],
+    );
+    assert_eq!(machine.cpu.d[0], 10);
+    assert_eq!(machine.cpu.d[1], 0xffff);
+    assert_eq!(machine.cpu.d[3], 7);
+    assert_eq!(machine.cpu.a[6], 0);
+    assert_eq!(machine.cpu.a[7], STACK);
+}
+
+#[test]
+fn cycle_counts() {
+    let mut machine = machine(
+        Model::M68000,
+        &[
+            NOP, 0x7000, // moveq #0,d0
+            0x207c, 0x2000, 0x0000, // movea.l #RAM,a0
+            0x2080, // move.l d0,(a0)
+            0x7201, // moveq #1,d1
+            0x51c9, 0xfffe, // dbra d1,*
+        ],
+    );
+    let mut cycles = Vec::new();
+    for _ in 0..7 {
+        let before = machine.cpu.cycles;
+        assert_eq!(machine.step(), None);
+        cycles.push(machine.cpu.cycles - before);
+    }
+    // `dbra` taken once, then falling through
+    assert_eq!(cycles, [4, 4, 12, 12, 4, 10, 14]);
+}
+
+#[test]
+fn m68020_instructions() {
+    let mut program = vec![
+        0x103c, 0x0080, // move.b #$80,d0
+        0x49c0, // extb.l d0
+        0x223c, 0x0001, 0x0000, // move.l #$10000,d1
+        0x243c, 0x0001, 0x0000, // move.l #$10000,d2
+        0x4c01, 0x2000, // mulu.l d1,d2: overflows
+        0x40c7, // move.w sr,d7
+        0x2a3c, 0x0001, 0x0000, // move.l #$10000,d5
+        0x4c01, 0x5406, // mulu.l d1,d6:d5
+        0x7664, // moveq #100,d3
+        0x7807, // moveq #7,d4
+        0x4c44, 0x3002, // divul.l d4,d2:d3
+        0x60ff, 0x0000, 0x0006, // bra.l over the next instruction
+        0x4afc, // illegal
+        0x41fa, 0x0000, // lea (table,pc),a0
+        0x7202, // moveq #2,d1
+        0x2830, 0x1c00, // move.l (0,a0,d1.l*4),d4
+        0x2a30, 0x0152, 0x0004, // move.l ([a0],4),d5
+        TRAP_0,
+    ];
+    let lea = program.iter().position(|&w| w == 0x41fa).unwrap();
+    let table = START + 2 * program.len() as u32;
+    program[lea + 1] = (table - (START + 2 * lea as u32 + 2)) as u16;
+    program.extend(long(table));
+    program.extend(long(0x1111_1111));
+    program.extend(long(0x2222_2222));
+
+    let cpu = run(Model::M68020, &program).cpu;
+    assert_eq!(cpu.d[0], 0xffff_ff80);
+    assert_eq!(cpu.d[7] & V, V);
+    assert_eq!((cpu.d[6], cpu.d[5]), (1, 0x1111_1111));
+    assert_eq!((cpu.d[3], cpu.d[2]), (14, 2));
+    assert_eq!(cpu.d[4], 0x2222_2222);
+
+    // The 68000 doesn't know `extb`
+    let mut machine = machine(Model::M68000, &program);
+    let taken = record(&mut machine);
+    assert!(matches!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(_)
+    ));
+    assert_eq!(*taken.borrow(), [4]);
+}
+
+#[test]
+fn m68020_compare_and_check() {
+    let mut machine = machine(
+        Model::M68020,
+        &[
+            0x227c, 0x2000, 0x0000, // movea.l #RAM,a1
+            0x22bc, 0x0000, 0x0005, // move.l #5,(a1)
+            0x7005, // moveq #5,d0
+            0x7209, // moveq #9,d1
+            0x0ed1, 0x0040, // cas.l d0,d1,(a1)
+            0x2411, // move.l (a1),d2
+            0x0ed1, 0x0040, // cas.l d0,d1,(a1): fails
+            0x51fc, // trapf
+            0x237c, 0x0000, 0x000a, 0x0008, // move.l #10,(8,a1)
+            0x237c, 0x0000, 0x0014, 0x000c, // move.l #20,(12,a1)
+            0x760f, // moveq #15,d3
+            0x04e9, 0x3800, 0x0008, // chk2.l (8,a1),d3
+            0x761e, // moveq #30,d3
+            0x04e9, 0x3800, 0x0008, // chk2.l (8,a1),d3: out of bounds
+            TRAP_0,
+        ],
+    );
+    let taken = record(&mut machine);
+    assert!(matches!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(_)
+    ));
+    assert_eq!(machine.cpu.d[2], 9);
+    assert_eq!(machine.cpu.d[0], 9);
+    assert_eq!(*taken.borrow(), [6]);
+}
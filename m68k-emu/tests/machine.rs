@@ -0,0 +1,295 @@
+mod common;
+
+use common::elf::{ElfBuilder, DATA, RODATA, TEXT};
+use common::*;
+use m68k_emu::{Bus, Control, Error, Limit, Machine, Model, Size, Stop, Watch, Watchpoint};
+use m68k_image::{Image, MemoryMap};
+
+const ILLEGAL: u16 = 0x4afc;
+
+/// Raises the probe's interrupt to level `level`, then finishes with `d0` as set by the handler
+fn interrupt(level: u16) -> Vec<u16> {
+    let mut program = vec![
+        0x46fc, 0x2000, // move.w #$2000,sr
+        0x13fc, level, // move.b #level,(PROBE).l
+    ];
+    program.extend(long(PROBE));
+    program.extend([NOP, TRAP_0]);
+    program
+}
+
+/// An interrupt handler that clears the probe's request and puts `vector` in `d0`
+fn acknowledge(machine: &mut Machine, vector: u8) {
+    let mut code = vec![0x4239]; // clr.b (PROBE).l
+    code.extend(long(PROBE));
+    code.extend([0x7000 | u16::from(vector), RTE]); // moveq #vector,d0
+    handler(machine, vector, 0x340, &code);
+}
+
+#[test]
+fn autovectored_interrupts() {
+    let mut machine = common::machine(Model::M68000, &interrupt(3));
+    let probe = probe(&mut machine);
+    acknowledge(&mut machine, 27);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(27));
+    assert_eq!(probe.borrow().acknowledged, [3]);
+    assert_eq!(machine.cpu.sr() & 0x0700, 0);
+}
+
+#[test]
+fn vectored_interrupts() {
+    let mut machine = common::machine(Model::M68010, &interrupt(4));
+    let probe = probe(&mut machine);
+    probe.borrow_mut().vector = Some(64);
+    acknowledge(&mut machine, 64);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(64));
+    assert_eq!(probe.borrow().acknowledged, [4]);
+}
+
+#[test]
+fn interrupt_mask() {
+    let mut program = vec![
+        0x7000, // moveq #0,d0
+        0x13fc, 0x0003, // move.b #3,(PROBE).l
+    ];
+    program.extend(long(PROBE));
+    program.extend([NOP, TRAP_0]);
+
+    // Masked by the reset value of the status register
+    let mut machine = common::machine(Model::M68000, &program);
+    let probe = probe(&mut machine);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(0));
+    assert!(probe.borrow().acknowledged.is_empty());
+
+    // Level 7 can't be masked
+    program[2] = 7;
+    let mut machine = common::machine(Model::M68000, &program);
+    let probe = common::probe(&mut machine);
+    acknowledge(&mut machine, 31);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(31));
+    assert_eq!(probe.borrow().acknowledged, [7]);
+}
+
+#[test]
+fn non_maskable_interrupt_is_edge_triggered() {
+    // bra ., with all interrupts masked
+    let mut machine = common::machine(Model::M68000, &[0x60fe]);
+    let probe = probe(&mut machine);
+    // Returns without clearing the request
+    handler(&mut machine, 31, 0x340, &[RTE]);
+    probe.borrow_mut().level = 7;
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Limit);
+    assert_eq!(probe.borrow().acknowledged, [7]);
+}
+
+#[test]
+fn stop_waits_for_interrupts() {
+    // stop #$2000
+    let mut machine = common::machine(Model::M68000, &[0x4e72, 0x2000, TRAP_0]);
+    let probe = probe(&mut machine);
+    acknowledge(&mut machine, 26);
+
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Limit);
+    assert!(machine.cpu.stopped);
+    assert_eq!(machine.cpu.instructions, 1);
+
+    probe.borrow_mut().level = 2;
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(26));
+    assert!(!machine.cpu.stopped);
+}
+
+#[test]
+fn stop_with_interrupts_masked() {
+    // stop #$2700
+    let mut machine = common::machine(Model::M68000, &[0x4e72, 0x2700]);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Stopped);
+}
+
+#[test]
+fn limits() {
+    // bra .
+    let mut machine = common::machine(Model::M68000, &[0x60fe]);
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Limit);
+    assert_eq!(machine.cpu.instructions, 100);
+    assert_eq!(machine.cpu.pc, START);
+
+    let cycles = machine.cpu.cycles;
+    assert_eq!(machine.run(Limit::Cycles(1000)), Stop::Limit);
+    // 10 cycles per branch
+    assert_eq!(machine.cpu.cycles - cycles, 1000);
+    assert_eq!(machine.cpu.instructions, 200);
+}
+
+#[test]
+fn hook_emulates_service_calls() {
+    let mut machine = common::machine(
+        Model::M68000,
+        &[
+            0x7205, // moveq #5,d1
+            0x4e4f, // trap #15
+            TRAP_0,
+        ],
+    );
+    machine.on_exception(|cpu, _, exception| {
+        if exception.vector == 47 {
+            cpu.d[0] = cpu.d[1] * 2;
+            Control::Resume
+        } else {
+            Control::Take
+        }
+    });
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(10));
+    assert_eq!(machine.cpu.a[7], STACK);
+}
+
+#[test]
+fn hook_stops() {
+    let mut machine = common::machine(Model::M68000, &[NOP, ILLEGAL]);
+    machine.on_exception(|_, _, _| Control::Stop);
+    let exception = match machine.run(Limit::Instructions(100)) {
+        Stop::Exception(exception) => exception,
+        stop => panic!("{:?}", stop),
+    };
+    assert_eq!(exception.vector, 4);
+    assert_eq!(exception.pc, START + 2);
+    assert_eq!(exception.opcode, ILLEGAL);
+    // Not taken
+    assert_eq!(machine.cpu.a[7], STACK);
+
+    machine.cpu.take(&mut machine.bus, &exception);
+    assert_eq!(machine.cpu.pc, HANDLER);
+    assert_eq!(machine.cpu.a[7], STACK - 6);
+}
+
+#[test]
+fn stop_on_exceptions() {
+    let mut machine = common::machine(Model::M68000, &[0x4e4f, ILLEGAL]); // trap #15
+    machine.on_exception(|cpu, _, exception| {
+        cpu.d[1] = exception.vector.into();
+        Control::Resume
+    });
+    machine.stop_on_exceptions(&[4]);
+    let exception = match machine.run(Limit::Instructions(100)) {
+        Stop::Exception(exception) => exception,
+        stop => panic!("{:?}", stop),
+    };
+    assert_eq!(exception.vector, 4);
+    // The hook still served the trap
+    assert_eq!(machine.cpu.d[1], 47);
+}
+
+#[test]
+fn watchpoints() {
+    let mut program = vec![0x2039]; // move.l (RAM).l,d0
+    program.extend(long(RAM));
+    program.push(0x13c0); // move.b d0,(RAM + 3).l
+    program.extend(long(RAM + 3));
+    program.push(TRAP_0);
+    let mut machine = common::machine(Model::M68000, &program);
+    let write = Watchpoint {
+        address: RAM + 2,
+        len: 2,
+        kind: Watch::Write,
+    };
+    machine.bus.add_watchpoint(write);
+    assert_eq!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Watchpoint(write)
+    );
+    assert_eq!(machine.cpu.pc, START + 12);
+
+    let access = Watchpoint {
+        kind: Watch::Access,
+        ..write
+    };
+    machine.reset();
+    machine.bus.add_watchpoint(access);
+    assert!(machine.bus.remove_watchpoint(&write));
+    assert_eq!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Watchpoint(access)
+    );
+    assert_eq!(machine.cpu.pc, START + 6);
+}
+
+#[test]
+fn devices() {
+    let mut program = vec![0x3039]; // move.w (PROBE+4).l,d0
+    program.extend(long(PROBE + 4));
+    program.push(0x23c0); // move.l d0,(PROBE+8).l
+    program.extend(long(PROBE + 8));
+    program.push(TRAP_0);
+
+    let mut machine = common::machine(Model::M68000, &program);
+    let probe = probe(&mut machine);
+    assert_eq!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(0x5678)
+    );
+
+    let probe = probe.borrow();
+    assert_eq!(probe.reads, [(4, Size::Word)]);
+    assert_eq!(probe.writes, [(8, Size::Long, 0x5678)]);
+    // Everything but the reset, which came before the probe
+    assert_eq!(probe.cycles, machine.cpu.cycles - 40);
+}
+
+#[test]
+fn load_elf() {
+    let vectors = vectors();
+    let elf = ElfBuilder::new(START)
+        .section(".vector_table", 0, 0, RODATA, vectors.clone())
+        // moveq #7,d0; trap #0
+        .section(".text", START, START, TEXT, bytes(&[0x7007, TRAP_0]))
+        .section(".data", RAM, 0x800, DATA, vec![1, 2, 3, 4])
+        .nobits(".bss", RAM + 4, 0x10)
+        .build();
+    let image = Image::parse(&elf).unwrap();
+
+    let mut machine = Machine::new(Model::M68000, bus());
+    machine.load(&image).unwrap();
+    machine.finish_on_trap(0);
+    machine.reset();
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(7));
+
+    // `.data` is left for the program to copy
+    assert_eq!(read(&mut machine, 0x800, Size::Long), 0x0102_0304);
+    assert_eq!(read(&mut machine, RAM, Size::Long), 0);
+
+    let elf = ElfBuilder::new(START)
+        .section(".vector_table", 0, 0, RODATA, vectors)
+        .section(".text", 0x4000_0000, 0x4000_0000, TEXT, bytes(&[TRAP_0]))
+        .build();
+    let image = Image::parse(&elf).unwrap();
+    let mut machine = Machine::new(Model::M68000, bus());
+    match machine.load(&image) {
+        Err(Error::Unmapped { section, address }) => {
+            assert_eq!(section, ".text");
+            assert_eq!(address, 0x4000_0000);
+        }
+        result => panic!("{:?}", result),
+    }
+}
+
+#[test]
+fn memory_map() {
+    let map = MemoryMap::read("../memory.x").unwrap();
+    let mut bus = Bus::from_memory_map(&map);
+    assert_eq!(bus.region_at(0), Some("ROM"));
+    assert_eq!(bus.region_at(0x2000_ffff), Some("RAM"));
+    assert_eq!(bus.region_at(0x0004_0000), None);
+
+    assert!(bus.write(0, Size::Long, 1).is_err());
+    bus.write(0x2000_0000, Size::Long, 1).unwrap();
+    assert_eq!(bus.read(0x2000_0000, Size::Long), Ok(1));
+}
+
+#[test]
+fn address_bits() {
+    let mut bus = Bus::new();
+    bus.add_ram("RAM", 0x10_0000, 0x1000);
+    bus.set_address_bits(24);
+    bus.write(0xff10_0000, Size::Word, 0xbeef).unwrap();
+    assert_eq!(bus.read(0x0010_0000, Size::Word), Ok(0xbeef));
+    assert_eq!(bus.region_at(0x8010_0fff), Some("RAM"));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::*;
+use m68k_emu::{monitor, Limit, Model, Pipe, Stop};
+
+const MESSAGE: u32 = 0x500;
+
+#[test]
+fn services() {
+    let mut program = vec![
+        0x7001, // moveq #PUTC,d0
+        0x7268, // moveq #'h',d1
+        0x4e4f, // trap #15
+        0x7003, // moveq #PUTS,d0
+        0x41f9, // lea MESSAGE,a0
+    ];
+    program.extend(long(MESSAGE));
+    program.extend([
+        0x7203, // moveq #3,d1
+        0x4e4f, // trap #15
+        0x7002, // moveq #GETC,d0
+        0x4e4f, // trap #15
+        0x2400, // move.l d0,d2
+        0x7004, // moveq #TICKS,d0
+        0x4e4f, // trap #15
+        0x2600, // move.l d0,d3
+        0x7009, // moveq #9,d0
+        0x4e4f, // trap #15
+        0x2800, // move.l d0,d4
+        0x7000, // moveq #EXIT,d0
+        0x722a, // moveq #42,d1
+        0x4e4f, // trap #15
+        TRAP_0,
+    ]);
+    let mut machine = common::machine(Model::M68000, &program);
+    machine.bus.load(MESSAGE, b"ey\n").unwrap();
+    let pipe = Pipe::new();
+    monitor::attach(&mut machine, pipe.clone());
+
+    // GETC waits for input, and the time it takes shows in the ticks
+    assert_eq!(machine.run(Limit::Cycles(400_000)), Stop::Limit);
+    assert_eq!(pipe.take(), b"hey\n");
+    pipe.send(b"x");
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(42));
+    assert_eq!(machine.cpu.d[2], u32::from(b'x'));
+    assert!(machine.cpu.d[3] >= 5, "{}", machine.cpu.d[3]);
+    assert_eq!(machine.cpu.d[4], monitor::UNKNOWN);
+    assert_eq!(machine.cpu.a[7], STACK);
+}
+
+#[test]
+fn other_exceptions_are_taken() {
+    let mut machine = common::machine(Model::M68000, &[0x4e41]); // trap #1
+    monitor::attach(&mut machine, Pipe::new());
+    assert_eq!(machine.run(Limit::Instructions(10)), Stop::Finished(0));
+    assert_eq!(machine.cpu.a[7], STACK - 6);
+}
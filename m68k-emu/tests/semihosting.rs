@@ -0,0 +1,181 @@
+mod common;
+
+use std::fs;
+
+use common::*;
+use m68k_emu::semihosting::{self, mode, Semihosting, FAILED};
+use m68k_emu::{Limit, Machine, Model, Pipe, Size, Stop};
+
+/// Where the argument blocks and the data they point at go
+const BLOCKS: u32 = RAM;
+/// Where the results of the calls are stored, a long word each
+const RESULTS: u32 = RAM + 0x8000;
+
+/// A program that makes the calls and stores their results, then exits with 0
+fn program(calls: &[(u32, u32)]) -> Vec<u16> {
+    let mut program = Vec::new();
+    for (i, &(operation, argument)) in calls.iter().enumerate() {
+        program.push(0x7000 | operation as u16); // moveq #operation,d0
+        program.push(0x223c); // move.l #argument,d1
+        program.extend(long(argument));
+        program.push(0x4e4e); // trap #14
+        program.push(0x23c0); // move.l d0,(RESULTS + 4 * i).l
+        program.extend(long(RESULTS + 4 * i as u32));
+    }
+    program.extend([
+        0x7000 | semihosting::EXIT as u16, // moveq #EXIT,d0
+        0x7200,                            // moveq #0,d1
+        0x4e4e,                            // trap #14
+    ]);
+    program
+}
+
+/// Lays out the argument blocks in RAM, each followed by the data it points at
+struct Blocks {
+    next: u32,
+}
+
+impl Blocks {
+    fn new() -> Self {
+        Blocks { next: BLOCKS }
+    }
+
+    fn put(&mut self, machine: &mut Machine, bytes: &[u8]) -> u32 {
+        let address = self.next;
+        machine.bus.load(address, bytes).unwrap();
+        self.next += (bytes.len() as u32 + 3) & !3;
+        address
+    }
+
+    fn block(&mut self, machine: &mut Machine, arguments: &[u32]) -> u32 {
+        let bytes: Vec<u8> = arguments.iter().flat_map(|a| a.to_be_bytes()).collect();
+        self.put(machine, &bytes)
+    }
+
+    /// A buffer of `len` bytes
+    fn buffer(&mut self, machine: &mut Machine, len: u32) -> u32 {
+        self.put(machine, &vec![0; len as usize])
+    }
+}
+
+fn results(machine: &mut Machine, n: usize) -> Vec<u32> {
+    (0..n as u32)
+        .map(|i| read(machine, RESULTS + 4 * i, Size::Long))
+        .collect()
+}
+
+fn bytes_at(machine: &mut Machine, address: u32, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    machine.bus.peek(address, &mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn console_and_command_line() {
+    // The blocks are laid out before the program is known, so load it afterwards
+    let mut machine = common::machine(Model::M68000, &[]);
+    let mut blocks = Blocks::new();
+    let hello = blocks.put(&mut machine, b"hello\n");
+    let write = blocks.block(&mut machine, &[1, hello, 6]);
+    let input = blocks.buffer(&mut machine, 16);
+    let read = blocks.block(&mut machine, &[0, input, 16]);
+    let line = blocks.buffer(&mut machine, 32);
+    let command_line = blocks.block(&mut machine, &[line, 32]);
+    let short = blocks.block(&mut machine, &[line, 2]);
+    let calls = [
+        (semihosting::WRITE, write),
+        (semihosting::READ, read),
+        (semihosting::COMMAND_LINE, command_line),
+        (semihosting::COMMAND_LINE, short),
+        (semihosting::ERRNO, 0),
+        (semihosting::CLOSE, 9),
+        (semihosting::ERRNO, 0),
+        (9, 0),
+    ];
+    machine.bus.load(START, &bytes(&program(&calls))).unwrap();
+
+    let console = Pipe::new();
+    let mut host = Semihosting::with_console(console.clone());
+    host.set_command_line("prog -v");
+    semihosting::attach(&mut machine, host);
+
+    // Reading waits for input
+    assert_eq!(machine.run(Limit::Instructions(1000)), Stop::Limit);
+    assert_eq!(console.take(), b"hello\n");
+    console.send(b"abc");
+    assert_eq!(machine.run(Limit::Instructions(1000)), Stop::Finished(0));
+
+    assert_eq!(
+        results(&mut machine, calls.len()),
+        [6, 3, 7, FAILED, 7, FAILED, 9, FAILED]
+    );
+    assert_eq!(bytes_at(&mut machine, input, 3), b"abc");
+    assert_eq!(bytes_at(&mut machine, line, 7), b"prog -v");
+}
+
+#[test]
+fn files() {
+    let dir = std::env::temp_dir().join(format!("m68k-emu-semihosting-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("file.txt");
+    let missing = dir.join("missing.txt");
+    let file = file.to_str().unwrap().as_bytes();
+    let missing = missing.to_str().unwrap().as_bytes();
+
+    let mut machine = common::machine(Model::M68000, &[]);
+    let mut blocks = Blocks::new();
+    let path = blocks.put(&mut machine, file);
+    let len = file.len() as u32;
+    let create = blocks.block(&mut machine, &[path, len, mode::WRITE]);
+    let data = blocks.put(&mut machine, b"12345");
+    let write = blocks.block(&mut machine, &[3, data, 5]);
+    let open = blocks.block(&mut machine, &[path, len, mode::READ]);
+    let buffer = blocks.buffer(&mut machine, 16);
+    let read = blocks.block(&mut machine, &[3, buffer, 16]);
+    let append = blocks.block(&mut machine, &[path, len, mode::APPEND]);
+    let write_4 = blocks.block(&mut machine, &[4, data, 2]);
+    let missing_path = blocks.put(&mut machine, missing);
+    let open_missing = blocks.block(
+        &mut machine,
+        &[missing_path, missing.len() as u32, mode::READ],
+    );
+    let calls = [
+        (semihosting::OPEN, create),
+        (semihosting::WRITE, write),
+        (semihosting::CLOSE, 3),
+        (semihosting::OPEN, open),
+        (semihosting::READ, read),
+        (semihosting::READ, read),
+        // Handles are given out after the highest one open
+        (semihosting::OPEN, append),
+        (semihosting::WRITE, write_4),
+        (semihosting::CLOSE, 3),
+        (semihosting::CLOSE, 4),
+        (semihosting::OPEN, open_missing),
+        (semihosting::ERRNO, 0),
+    ];
+    machine.bus.load(START, &bytes(&program(&calls))).unwrap();
+    semihosting::attach(&mut machine, Semihosting::with_console(Pipe::new()));
+
+    assert_eq!(machine.run(Limit::Instructions(1000)), Stop::Finished(0));
+    let contents = fs::read(dir.join("file.txt")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        results(&mut machine, calls.len()),
+        [3, 5, 0, 3, 5, 0, 4, 2, 0, 0, FAILED, 2]
+    );
+    assert_eq!(bytes_at(&mut machine, buffer, 5), b"12345");
+    assert_eq!(contents, b"1234512");
+}
+
+#[test]
+fn time() {
+    let calls = [(semihosting::TIME, 0), (semihosting::CLOCK, 0)];
+    let mut machine = common::machine(Model::M68000, &program(&calls));
+    semihosting::attach(&mut machine, Semihosting::with_console(Pipe::new()));
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Finished(0));
+    let results = results(&mut machine, calls.len());
+    // After 2020, and a moment after reset
+    assert!(results[0] > 1_577_836_800, "{}", results[0]);
+    assert_eq!(results[1], 0);
+}
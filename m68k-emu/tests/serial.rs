@@ -0,0 +1,272 @@
+mod common;
+
+use common::*;
+use m68k_emu::{Acknowledge, Channel, Device, Duart, Limit, Mfp, Model, Pipe, Size, Stop};
+
+/// Echoes what channel A of a DUART at [`PROBE`] receives until an EOT, polling
+fn echo() -> Vec<u16> {
+    let mut program = vec![0x41f9]; // lea PROBE,a0
+    program.extend(long(PROBE));
+    program.extend([
+        0x117c, 0x0005, 0x0005, // move.b #$05,5(a0): enable the receiver and transmitter
+        0x0828, 0x0000, 0x0003, // btst #0,3(a0): RXRDY
+        0x67f8, // beq.s *-6
+        0x1028, 0x0007, // move.b 7(a0),d0
+        0x0c00, 0x0004, // cmp.b #4,d0
+        0x6706, // beq.s done
+        0x1140, 0x0007, // move.b d0,7(a0)
+        0x60e8, // bra.s *-22
+        TRAP_0,
+    ]);
+    program
+}
+
+#[test]
+fn duart_polled() {
+    let mut machine = common::machine(Model::M68000, &echo());
+    let pipe = Pipe::new();
+    let mut duart = Duart::new(4);
+    duart.connect(Channel::A, pipe.clone());
+    machine.bus.add_device("DUART", PROBE, 0x20, duart);
+
+    pipe.send(b"hello\x04");
+    assert_eq!(machine.run(Limit::Instructions(1000)), Stop::Finished(4));
+    assert_eq!(pipe.take(), b"hello");
+    assert_eq!(pipe.pending(), 0);
+}
+
+#[test]
+fn duart_receive_interrupt() {
+    let mut program = vec![0x41f9]; // lea PROBE,a0
+    program.extend(long(PROBE));
+    program.extend([
+        0x117c, 0x0040, 0x0019, // move.b #64,25(a0): IVR
+        0x117c, 0x0002, 0x000b, // move.b #$02,11(a0): IMR = RxRDYA
+        0x117c, 0x0005, 0x0005, // move.b #$05,5(a0)
+        0x46fc, 0x2000, // move.w #$2000,sr
+        0x60fe, // bra.s *
+    ]);
+    let mut machine = common::machine(Model::M68000, &program);
+    handler(&mut machine, 64, 0x340, &[0x1028, 0x0007, TRAP_0]); // move.b 7(a0),d0
+    let pipe = Pipe::new();
+    let mut duart = Duart::new(4);
+    duart.connect(Channel::A, pipe.clone());
+    machine.bus.add_device("DUART", PROBE, 0x20, duart);
+
+    assert_eq!(machine.run(Limit::Instructions(100)), Stop::Limit);
+    pipe.send(b"x");
+    assert_eq!(
+        machine.run(Limit::Instructions(100)),
+        Stop::Finished(u32::from(b'x'))
+    );
+}
+
+#[test]
+fn duart_timer() {
+    // 100 interrupts a second, as m68k-rom sets it up
+    let mut program = vec![0x41f9]; // lea PROBE,a0
+    program.extend(long(PROBE));
+    program.extend([
+        0x7e00, // moveq #0,d7
+        0x117c, 0x0070, 0x0009, // move.b #$70,9(a0): timer mode, crystal / 16
+        0x117c, 0x0004, 0x000d, // move.b #$04,13(a0): preload 1152
+        0x117c, 0x0080, 0x000f, // move.b #$80,15(a0)
+        0x4a28, 0x001d, // tst.b 29(a0): start
+        0x117c, 0x0008, 0x000b, // move.b #$08,11(a0): IMR = counter ready
+        0x46fc, 0x2000, // move.w #$2000,sr
+        0x60fe, // bra.s *
+    ]);
+    let mut machine = common::machine(Model::M68000, &program);
+    handler(
+        &mut machine,
+        29,
+        0x340,
+        &[
+            0x5287, // addq.l #1,d7
+            0x4a28, 0x001f, // tst.b 31(a0): acknowledge
+            RTE,
+        ],
+    );
+    let mut duart = Duart::new(5);
+    duart.set_autovector(true);
+    machine.bus.add_device("DUART", PROBE, 0x20, duart);
+
+    // A tenth of a second at 8 MHz
+    assert_eq!(machine.run(Limit::Cycles(800_000)), Stop::Limit);
+    assert!((9..=10).contains(&machine.cpu.d[7]), "{}", machine.cpu.d[7]);
+}
+
+#[test]
+fn duart_counter() {
+    let mut duart = Duart::new(2);
+    duart.write(0x09, Size::Byte, 0x30); // counter mode, crystal / 16
+    duart.write(0x0d, Size::Byte, 0);
+    duart.write(0x0f, Size::Byte, 16);
+    duart.write(0x0b, Size::Byte, 0x08);
+    duart.read(0x1d, Size::Byte);
+
+    // 256 crystal clocks are 555.6 cycles at 8 MHz
+    duart.tick(500);
+    assert_eq!(duart.read(0x0f, Size::Byte), 1);
+    assert_eq!(duart.interrupt_level(), 0);
+    duart.tick(56);
+    assert_eq!(duart.read(0x0b, Size::Byte) & 0x08, 0x08);
+    assert_eq!(duart.interrupt_level(), 2);
+    assert_eq!(duart.acknowledge(2), Acknowledge::Vector(0x0f));
+
+    // Stopping clears the interrupt and freezes the count
+    duart.read(0x1f, Size::Byte);
+    assert_eq!(duart.interrupt_level(), 0);
+    let count = duart.read(0x0f, Size::Byte);
+    duart.tick(1000);
+    assert_eq!(duart.read(0x0f, Size::Byte), count);
+}
+
+#[test]
+fn duart_channels() {
+    let a = Pipe::new();
+    let b = Pipe::new();
+    let mut duart = Duart::new(1);
+    duart
+        .connect(Channel::A, a.clone())
+        .connect(Channel::B, b.clone());
+
+    // Mode registers: MR1 then MR2, until the pointer is reset
+    duart.write(0x01, Size::Byte, 0x13);
+    duart.write(0x01, Size::Byte, 0x07);
+    duart.write(0x05, Size::Byte, 0x10);
+    assert_eq!(duart.read(0x01, Size::Byte), 0x13);
+    assert_eq!(duart.read(0x01, Size::Byte), 0x07);
+
+    // Disabled channels neither receive nor transmit
+    b.send(b"abcd");
+    duart.tick(4);
+    assert_eq!(duart.read(0x13, Size::Byte), 0);
+    duart.write(0x17, Size::Byte, u32::from(b'z'));
+    assert!(b.take().is_empty());
+
+    // The receive FIFO holds three characters
+    duart.write(0x15, Size::Byte, 0x05);
+    for _ in 0..4 {
+        duart.tick(4);
+    }
+    assert_eq!(duart.read(0x13, Size::Byte), 0x0f);
+    assert_eq!(b.pending(), 1);
+    assert_eq!(duart.read(0x17, Size::Byte), u32::from(b'a'));
+    assert_eq!(duart.read(0x0b, Size::Byte), 0x30);
+    duart.write(0x17, Size::Byte, u32::from(b'z'));
+    assert_eq!(b.take(), b"z");
+    assert!(a.take().is_empty());
+}
+
+#[test]
+fn mfp_interrupts() {
+    let mut program = vec![0x41f9]; // lea PROBE,a0
+    program.extend(long(PROBE));
+    program.extend([
+        0x117c, 0x0048, 0x0017, // move.b #$48,23(a0): VR, software end of interrupt
+        0x117c, 0x0010, 0x0007, // move.b #$10,7(a0): enable receive buffer full
+        0x117c, 0x0010, 0x0013, // move.b #$10,19(a0): and unmask it
+        0x117c, 0x0001, 0x002b, // move.b #$01,43(a0): enable the receiver
+        0x117c, 0x0001, 0x002d, // move.b #$01,45(a0): and the transmitter
+        0x46fc, 0x2000, // move.w #$2000,sr
+        0x60fe, // bra.s *
+    ]);
+    let mut machine = common::machine(Model::M68000, &program);
+    handler(
+        &mut machine,
+        76,
+        0x340,
+        &[
+            0x1028, 0x002f, // move.b 47(a0),d0
+            0x0c00, 0x0004, // cmp.b #4,d0
+            0x670c, // beq.s done
+            0x1140, 0x002f, // move.b d0,47(a0)
+            0x117c, 0x00ef, 0x000f, // move.b #$ef,15(a0): end of interrupt
+            RTE, TRAP_0, // done
+        ],
+    );
+    let pipe = Pipe::new();
+    let mut mfp = Mfp::new(6);
+    mfp.connect(pipe.clone());
+    machine.bus.add_device("MFP", PROBE, 0x30, mfp);
+
+    pipe.send(b"mfp\x04");
+    assert_eq!(machine.run(Limit::Instructions(1000)), Stop::Finished(4));
+    assert_eq!(pipe.take(), b"mfp");
+}
+
+#[test]
+fn mfp_priorities() {
+    let pipe = Pipe::new();
+    let mut mfp = Mfp::new(6);
+    mfp.connect(pipe.clone());
+    mfp.write(0x17, Size::Byte, 0x48);
+    mfp.write(0x07, Size::Byte, 0x14);
+    mfp.write(0x13, Size::Byte, 0x14);
+    mfp.write(0x2b, Size::Byte, 0x01);
+    mfp.write(0x2d, Size::Byte, 0x01);
+
+    // Enabling the transmitter leaves its buffer empty
+    assert_eq!(mfp.read(0x0b, Size::Byte), 0x04);
+    pipe.send(b"a");
+    mfp.tick(4);
+    assert_eq!(mfp.read(0x0b, Size::Byte), 0x14);
+    assert_eq!(mfp.read(0x2b, Size::Byte), 0x81);
+
+    // Receive buffer full comes first, and keeps transmit buffer empty out while in service
+    assert_eq!(mfp.interrupt_level(), 6);
+    assert_eq!(mfp.acknowledge(6), Acknowledge::Vector(0x4c));
+    assert_eq!(mfp.read(0x0f, Size::Byte), 0x10);
+    assert_eq!(mfp.interrupt_level(), 0);
+    mfp.write(0x0f, Size::Byte, 0xef);
+    assert_eq!(mfp.interrupt_level(), 6);
+    assert_eq!(mfp.acknowledge(6), Acknowledge::Vector(0x4a));
+
+    // Masked requests stay pending; disabled ones are dropped
+    assert_eq!(mfp.read(0x2f, Size::Byte), u32::from(b'a'));
+    mfp.write(0x2f, Size::Byte, u32::from(b'b'));
+    mfp.write(0x13, Size::Byte, 0x00);
+    assert_eq!(mfp.interrupt_level(), 0);
+    assert_eq!(mfp.read(0x0b, Size::Byte), 0x04);
+    mfp.write(0x07, Size::Byte, 0x10);
+    assert_eq!(mfp.read(0x0b, Size::Byte), 0x00);
+    assert_eq!(pipe.take(), b"b");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pty() {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    use m68k_emu::serial::Pty;
+
+    let pty = Pty::open().unwrap();
+    let mut terminal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pty.path())
+        .unwrap();
+    let mut machine = common::machine(Model::M68000, &echo());
+    let mut duart = Duart::new(4);
+    duart.connect(Channel::A, pty);
+    machine.bus.add_device("DUART", PROBE, 0x20, duart);
+
+    terminal.write_all(b"pty\x04").unwrap();
+    // The kernel passes the bytes on in its own time
+    let mut stop = Stop::Limit;
+    for _ in 0..100 {
+        stop = machine.run(Limit::Instructions(1000));
+        if stop != Stop::Limit {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(stop, Stop::Finished(4));
+    let mut echo = [0; 3];
+    terminal.read_exact(&mut echo).unwrap();
+    assert_eq!(&echo, b"pty");
+}
@@ -0,0 +1,826 @@
+//! The V9990 model, driven through its ports as `m68k_nano::v9990` does
+//!
+//! Rendered frames are compared with the PNG files in `tests/golden`. Run the tests with
+//! `UPDATE_GOLDEN=1` to write them again after a deliberate change, and look at the difference
+//! before committing it.
+
+mod common;
+
+use std::cell::RefCell;
+use std::env;
+use std::path::Path;
+use std::rc::Rc;
+
+use common::{bytes, long, NOP, START, TRAP_0};
+use m68k_emu::v9990::VRAM_SIZE;
+use m68k_emu::{Bus, Device, Frame, Limit, Machine, Model, Size, Stop, V9990};
+use m68k_image::MemoryMap;
+
+const VRAM_DATA: u32 = 0;
+const PALETTE_DATA: u32 = 1;
+const COMMAND_DATA: u32 = 2;
+const REGISTER_DATA: u32 = 3;
+const REGISTER_SELECT: u32 = 4;
+const STATUS: u32 = 5;
+const INTERRUPT_FLAG: u32 = 6;
+const SYSTEM_CONTROL: u32 = 7;
+
+const LMMC: u8 = 1;
+const LMMV: u8 = 2;
+const LMCM: u8 = 3;
+const LMMM: u8 = 4;
+const CMMC: u8 = 5;
+const BMXL: u8 = 8;
+const BMLX: u8 = 9;
+const BMLL: u8 = 10;
+const LINE: u8 = 11;
+const SRCH: u8 = 12;
+const POINT: u8 = 13;
+const PSET: u8 = 14;
+const ADVN: u8 = 15;
+
+/// Bitmap, 256 pixels wide, 4 bits per pixel
+const B1_4BPP: u8 = 0xa1;
+
+/// Where the nano board has the V9990
+const V9990_BASE_ADDRESS: u32 = 0x0080_0000;
+
+fn out(vdp: &mut V9990, port: u32, value: u8) {
+    vdp.write(port, Size::Byte, value.into());
+}
+
+fn input(vdp: &mut V9990, port: u32) -> u8 {
+    vdp.read(port, Size::Byte) as u8
+}
+
+/// Writes consecutive registers from `first`
+fn set(vdp: &mut V9990, first: u8, values: &[u8]) {
+    out(vdp, REGISTER_SELECT, first);
+    for &value in values {
+        out(vdp, REGISTER_DATA, value);
+    }
+}
+
+fn write_vram(vdp: &mut V9990, address: u32, data: &[u8]) {
+    set(
+        vdp,
+        0,
+        &[address as u8, (address >> 8) as u8, (address >> 16) as u8],
+    );
+    for &byte in data {
+        out(vdp, VRAM_DATA, byte);
+    }
+}
+
+/// Sets palette entries from `first`
+fn palette(vdp: &mut V9990, first: u8, colors: &[[u8; 3]]) {
+    set(vdp, 14, &[first << 2]);
+    for &component in colors.as_flattened() {
+        out(vdp, PALETTE_DATA, component);
+    }
+}
+
+/// Sixteen colours, 5 bits per component
+const COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [4, 4, 8],
+    [31, 0, 0],
+    [0, 31, 0],
+    [0, 0, 31],
+    [31, 31, 0],
+    [0, 31, 31],
+    [31, 31, 31],
+    [31, 16, 0],
+    [16, 0, 31],
+    [8, 16, 8],
+    [31, 8, 16],
+    [16, 24, 31],
+    [24, 24, 24],
+    [12, 12, 12],
+    [31, 24, 16],
+];
+
+/// The arguments of a command, in registers 32 to 51
+struct Args {
+    sx: u16,
+    sy: u16,
+    dx: u16,
+    dy: u16,
+    nx: u16,
+    ny: u16,
+    arg: u8,
+    lop: u8,
+    wm: u16,
+    fc: u16,
+    bc: u16,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            sx: 0,
+            sy: 0,
+            dx: 0,
+            dy: 0,
+            nx: 0,
+            ny: 0,
+            arg: 0,
+            // Copy
+            lop: 0xc,
+            wm: 0xffff,
+            fc: 0,
+            bc: 0,
+        }
+    }
+}
+
+/// Starts a command
+fn command(vdp: &mut V9990, op: u8, args: Args) {
+    let mut registers = Vec::new();
+    for word in [args.sx, args.sy, args.dx, args.dy, args.nx, args.ny] {
+        registers.extend(word.to_le_bytes());
+    }
+    registers.extend([args.arg, args.lop]);
+    for word in [args.wm, args.fc, args.bc] {
+        registers.extend(word.to_le_bytes());
+    }
+    registers.push(op << 4);
+    set(vdp, 32, &registers);
+}
+
+/// A 256-pixel wide, 4-bit bitmap mode, with the display enabled
+fn bitmap(mode: u8) -> V9990 {
+    let mut vdp = V9990::new(0);
+    palette(&mut vdp, 0, &COLORS);
+    set(&mut vdp, 6, &[mode, 0, 0x80]);
+    vdp
+}
+
+fn golden(name: &str, frame: &Frame) {
+    let path = Path::new("tests/golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        frame.write_png(&path).unwrap();
+        return;
+    }
+    let expected = Frame::read_png(&path)
+        .unwrap_or_else(|e| panic!("{}: {}; run with UPDATE_GOLDEN=1", path.display(), e));
+    if *frame != expected {
+        let actual = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+        frame.write_png(&actual).unwrap();
+        panic!("{} differs from {}", actual.display(), path.display());
+    }
+}
+
+#[test]
+fn registers() {
+    let mut vdp = V9990::new(0);
+    set(&mut vdp, 15, &[1, 2, 3]);
+    assert_eq!(vdp.register(15), 1);
+    assert_eq!(vdp.register(17), 3);
+
+    // Write increment inhibited
+    set(&mut vdp, 0x80 | 20, &[4, 5]);
+    assert_eq!(vdp.register(20), 5);
+    assert_eq!(vdp.register(21), 0);
+
+    // Read increment inhibited
+    out(&mut vdp, REGISTER_SELECT, 0x40 | 15);
+    assert_eq!(input(&mut vdp, REGISTER_DATA), 1);
+    assert_eq!(input(&mut vdp, REGISTER_DATA), 1);
+    out(&mut vdp, REGISTER_SELECT, 15);
+    assert_eq!(input(&mut vdp, REGISTER_DATA), 1);
+    assert_eq!(input(&mut vdp, REGISTER_DATA), 2);
+
+    out(&mut vdp, SYSTEM_CONTROL, 1);
+    assert_eq!(input(&mut vdp, STATUS) & 0x04, 0x04);
+    // Software reset
+    out(&mut vdp, SYSTEM_CONTROL, 2);
+    assert_eq!(vdp.register(15), 0);
+}
+
+#[test]
+fn vram_and_palette() {
+    let mut vdp = V9990::new(0);
+    write_vram(&mut vdp, 0x7_fffe, &[1, 2, 3]);
+    // The address wraps around
+    assert_eq!(vdp.vram()[VRAM_SIZE - 2..], [1, 2]);
+    assert_eq!(vdp.vram()[0], 3);
+
+    set(&mut vdp, 3, &[0xfe, 0xff, 0x07]);
+    assert_eq!(input(&mut vdp, VRAM_DATA), 1);
+    assert_eq!(input(&mut vdp, VRAM_DATA), 2);
+    assert_eq!(input(&mut vdp, VRAM_DATA), 3);
+
+    // Without increment
+    set(&mut vdp, 0, &[0x10, 0, 0x80]);
+    out(&mut vdp, VRAM_DATA, 5);
+    out(&mut vdp, VRAM_DATA, 6);
+    assert_eq!(vdp.vram()[0x10..0x12], [6, 0]);
+
+    palette(&mut vdp, 62, &[[1, 2, 3], [0x9f, 0xff, 0x10]]);
+    assert_eq!(vdp.palette(62), [1, 2, 3]);
+    assert_eq!(vdp.palette(63), [0x1f, 0x1f, 0x10]);
+    // The pointer went past the last entry
+    assert_eq!(vdp.register(14), 0);
+
+    set(&mut vdp, 14, &[62 << 2]);
+    let read: Vec<u8> = (0..3).map(|_| input(&mut vdp, PALETTE_DATA)).collect();
+    assert_eq!(read, [1, 2, 3]);
+}
+
+#[test]
+fn interrupts() {
+    let mut vdp = V9990::new(4);
+    vdp.set_frame_cycles(262 * 100);
+
+    // Vertical non-display period
+    vdp.tick(212 * 100 - 1);
+    assert_eq!(input(&mut vdp, STATUS) & 0x40, 0);
+    assert_eq!(input(&mut vdp, INTERRUPT_FLAG), 0);
+    vdp.tick(1);
+    assert_eq!(input(&mut vdp, STATUS) & 0x40, 0x40);
+    assert_eq!(input(&mut vdp, INTERRUPT_FLAG), 1);
+    // Not enabled
+    assert_eq!(vdp.interrupt_level(), 0);
+
+    set(&mut vdp, 9, &[1]);
+    assert_eq!(vdp.interrupt_level(), 4);
+    out(&mut vdp, INTERRUPT_FLAG, 1);
+    assert_eq!(vdp.interrupt_level(), 0);
+
+    // Display position, on line 100
+    set(&mut vdp, 9, &[2, 100, 0]);
+    vdp.tick(100 * 100);
+    assert_eq!(vdp.frames(), 1);
+    assert_eq!(vdp.interrupt_level(), 0);
+    vdp.tick(50 * 100);
+    assert_eq!(input(&mut vdp, INTERRUPT_FLAG), 2);
+    assert_eq!(vdp.interrupt_level(), 4);
+    out(&mut vdp, INTERRUPT_FLAG, 2);
+
+    // Command completion
+    set(&mut vdp, 9, &[4]);
+    set(&mut vdp, 6, &[B1_4BPP]);
+    command(
+        &mut vdp,
+        LMMV,
+        Args {
+            nx: 4,
+            ny: 4,
+            ..Args::default()
+        },
+    );
+    assert_eq!(input(&mut vdp, STATUS) & 0x01, 0);
+    assert_eq!(input(&mut vdp, INTERRUPT_FLAG), 4);
+    assert_eq!(vdp.interrupt_level(), 4);
+}
+
+#[test]
+fn transfers() {
+    let mut vdp = bitmap(B1_4BPP);
+    command(
+        &mut vdp,
+        LMMC,
+        Args {
+            dx: 10,
+            dy: 20,
+            nx: 3,
+            ny: 2,
+            ..Args::default()
+        },
+    );
+    // Three bytes for six pixels
+    for byte in [0x12, 0x34, 0x56] {
+        assert_eq!(input(&mut vdp, STATUS) & 0x81, 0x81);
+        out(&mut vdp, COMMAND_DATA, byte);
+    }
+    assert_eq!(input(&mut vdp, STATUS) & 0x81, 0);
+    // Pixel 10 is the high nibble of byte 5 of the line
+    assert_eq!(vdp.vram()[20 * 128 + 5..20 * 128 + 7], [0x12, 0x30]);
+    assert_eq!(vdp.vram()[21 * 128 + 5..21 * 128 + 7], [0x45, 0x60]);
+
+    command(
+        &mut vdp,
+        LMCM,
+        Args {
+            sx: 10,
+            sy: 20,
+            nx: 3,
+            ny: 2,
+            ..Args::default()
+        },
+    );
+    let read: Vec<u8> = (0..3).map(|_| input(&mut vdp, COMMAND_DATA)).collect();
+    assert_eq!(read, [0x12, 0x34, 0x56]);
+    assert_eq!(input(&mut vdp, STATUS) & 0x81, 0);
+
+    command(
+        &mut vdp,
+        POINT,
+        Args {
+            sx: 11,
+            sy: 21,
+            ..Args::default()
+        },
+    );
+    assert_eq!(input(&mut vdp, COMMAND_DATA), 5);
+
+    // Character bits from the CPU, right to left and bottom to top
+    command(
+        &mut vdp,
+        CMMC,
+        Args {
+            dx: 7,
+            dy: 1,
+            nx: 8,
+            ny: 2,
+            arg: 3,
+            fc: 0xeeee,
+            bc: 0x1111,
+            ..Args::default()
+        },
+    );
+    out(&mut vdp, COMMAND_DATA, 0x81);
+    out(&mut vdp, COMMAND_DATA, 0x0f);
+    assert_eq!(vdp.vram()[128..132], [0xe1, 0x11, 0x11, 0x1e]);
+    assert_eq!(vdp.vram()[..4], [0xee, 0xee, 0x11, 0x11]);
+}
+
+#[test]
+fn search_and_point() {
+    let mut vdp = bitmap(B1_4BPP);
+    command(
+        &mut vdp,
+        PSET,
+        Args {
+            dx: 100,
+            dy: 5,
+            fc: 0x7777,
+            ..Args::default()
+        },
+    );
+    command(
+        &mut vdp,
+        SRCH,
+        Args {
+            sx: 10,
+            sy: 5,
+            fc: 0x7777,
+            ..Args::default()
+        },
+    );
+    assert_eq!(input(&mut vdp, STATUS) & 0x10, 0x10);
+    assert_eq!([vdp.register(53), vdp.register(54)], [100, 0]);
+
+    // To the left, for another colour than 7
+    command(
+        &mut vdp,
+        SRCH,
+        Args {
+            sx: 100,
+            sy: 5,
+            arg: 0x05,
+            fc: 0x7777,
+            ..Args::default()
+        },
+    );
+    assert_eq!(input(&mut vdp, STATUS) & 0x10, 0x10);
+    assert_eq!(vdp.register(53), 99);
+
+    // Nothing to the left of 99
+    command(
+        &mut vdp,
+        SRCH,
+        Args {
+            sx: 99,
+            sy: 5,
+            arg: 0x01,
+            fc: 0x7777,
+            ..Args::default()
+        },
+    );
+    assert_eq!(input(&mut vdp, STATUS) & 0x10, 0);
+
+    // ADVN moves DX, or DY along the major axis
+    command(
+        &mut vdp,
+        ADVN,
+        Args {
+            dx: 100,
+            dy: 5,
+            arg: 0x01,
+            ..Args::default()
+        },
+    );
+    assert_eq!(vdp.register(36), 99);
+    out(&mut vdp, REGISTER_SELECT, 44);
+    out(&mut vdp, REGISTER_DATA, 0x08);
+    set(&mut vdp, 52, &[ADVN << 4]);
+    assert_eq!(vdp.register(38), 6);
+}
+
+#[test]
+fn linear_transfers() {
+    let mut vdp = bitmap(0xa2);
+    write_vram(&mut vdp, 0x4_0000, &[1, 2, 3, 4, 5, 6]);
+
+    // Linear to rectangle, 8 bits per pixel
+    command(
+        &mut vdp,
+        BMXL,
+        Args {
+            sx: 0,
+            sy: 4,
+            dx: 10,
+            dy: 2,
+            nx: 2,
+            ny: 3,
+            ..Args::default()
+        },
+    );
+    assert_eq!(vdp.vram()[2 * 256 + 10..2 * 256 + 12], [1, 2]);
+    assert_eq!(vdp.vram()[4 * 256 + 10..4 * 256 + 12], [5, 6]);
+
+    // Rectangle to linear
+    command(
+        &mut vdp,
+        BMLX,
+        Args {
+            sx: 10,
+            sy: 3,
+            dx: 0x100,
+            dy: 4,
+            nx: 2,
+            ny: 2,
+            ..Args::default()
+        },
+    );
+    assert_eq!(vdp.vram()[0x4_0100..0x4_0104], [3, 4, 5, 6]);
+
+    // Linear to linear, through an OR and the write mask of the odd bytes
+    command(
+        &mut vdp,
+        BMLL,
+        Args {
+            sx: 0,
+            sy: 4,
+            dx: 0x100,
+            dy: 4,
+            nx: 4,
+            lop: 0xe,
+            wm: 0xff00,
+            ..Args::default()
+        },
+    );
+    assert_eq!(vdp.vram()[0x4_0100..0x4_0104], [3, 6, 5, 6]);
+}
+
+#[test]
+fn bitmap_commands() {
+    let mut vdp = bitmap(B1_4BPP);
+    set(&mut vdp, 15, &[14]);
+    let fill = |vdp: &mut V9990, dx, dy, nx, ny, color: u16, lop| {
+        command(
+            vdp,
+            LMMV,
+            Args {
+                dx,
+                dy,
+                nx,
+                ny,
+                lop,
+                fc: color * 0x1111,
+                ..Args::default()
+            },
+        )
+    };
+    fill(&mut vdp, 0, 0, 256, 212, 1, 0xc);
+    fill(&mut vdp, 16, 16, 64, 48, 2, 0xc);
+    // XOR
+    fill(&mut vdp, 48, 40, 64, 48, 3, 0x6);
+    // Only the odd pixels, through the write mask
+    command(
+        &mut vdp,
+        LMMV,
+        Args {
+            dx: 16,
+            dy: 120,
+            nx: 96,
+            ny: 32,
+            wm: 0x0f0f,
+            fc: 0x5555,
+            ..Args::default()
+        },
+    );
+
+    // Along X, then along Y to the left
+    command(
+        &mut vdp,
+        LINE,
+        Args {
+            nx: 255,
+            ny: 211,
+            fc: 0x4444,
+            ..Args::default()
+        },
+    );
+    command(
+        &mut vdp,
+        LINE,
+        Args {
+            dx: 200,
+            nx: 211,
+            ny: 100,
+            arg: 0x09,
+            fc: 0x6666,
+            ..Args::default()
+        },
+    );
+
+    // A checkerboard from the CPU, copied twice, the second time without colour 0
+    command(
+        &mut vdp,
+        LMMC,
+        Args {
+            dx: 160,
+            dy: 20,
+            nx: 8,
+            ny: 8,
+            ..Args::default()
+        },
+    );
+    for row in 0..8 {
+        for _ in 0..4 {
+            out(
+                &mut vdp,
+                COMMAND_DATA,
+                if row % 2 == 0 { 0x70 } else { 0x07 },
+            );
+        }
+    }
+    command(
+        &mut vdp,
+        LMMM,
+        Args {
+            sx: 160,
+            sy: 20,
+            dx: 176,
+            dy: 20,
+            nx: 8,
+            ny: 8,
+            ..Args::default()
+        },
+    );
+    command(
+        &mut vdp,
+        LMMM,
+        Args {
+            sx: 160,
+            sy: 20,
+            dx: 192,
+            dy: 20,
+            nx: 8,
+            ny: 8,
+            lop: 0x1c,
+            ..Args::default()
+        },
+    );
+
+    // An A, on a transparent background
+    command(
+        &mut vdp,
+        CMMC,
+        Args {
+            dx: 160,
+            dy: 100,
+            nx: 8,
+            ny: 8,
+            lop: 0x1c,
+            fc: 0x8888,
+            ..Args::default()
+        },
+    );
+    for row in [0x18, 0x24, 0x42, 0x42, 0x7e, 0x42, 0x42, 0x00] {
+        out(&mut vdp, COMMAND_DATA, row);
+    }
+
+    let frame = vdp.frame();
+    assert_eq!((frame.width, frame.height), (256, 212));
+    golden("bitmap_commands", &frame);
+}
+
+#[test]
+fn bitmap_colors() {
+    // 256 colours, 256 pixels wide
+    let mut vdp = bitmap(0xa2);
+    for color in 0..256u16 {
+        command(
+            &mut vdp,
+            LMMV,
+            Args {
+                dx: color % 16 * 16,
+                dy: color / 16 * 13,
+                nx: 16,
+                ny: 13,
+                fc: color * 0x0101,
+                ..Args::default()
+            },
+        );
+    }
+    golden("bitmap_8bpp", &vdp.frame());
+
+    // 32768 colours, 512 pixels wide, scrolled by 8 pixels both ways
+    let mut vdp = bitmap(0x97);
+    set(&mut vdp, 17, &[8, 0, 0, 1]);
+    for y in 0..220u16 {
+        let line: Vec<u8> = (0..512u16)
+            .flat_map(|x| {
+                let (r, g, b) = (x / 17, y / 7, ((x + y) / 24) & 31);
+                (g << 10 | r << 5 | b).to_le_bytes()
+            })
+            .collect();
+        write_vram(&mut vdp, u32::from(y) * 1024, &line);
+    }
+    let frame = vdp.frame();
+    assert_eq!(frame.width, 512);
+    golden("bitmap_16bpp", &frame);
+}
+
+/// Writes an 8 by 8 pattern of 4-bit pixels, given as rows of hex digits
+fn pattern(vdp: &mut V9990, base: u32, per_row: u32, n: u32, rows: [u32; 8]) {
+    for (y, row) in rows.into_iter().enumerate() {
+        let address = base + (n / per_row * 8 + y as u32) * per_row * 4 + n % per_row * 4;
+        write_vram(vdp, address, &row.to_be_bytes());
+    }
+}
+
+const BOX: [u32; 8] = [
+    0x1111_1111,
+    0x1000_0001,
+    0x1022_2201,
+    0x1020_0201,
+    0x1020_0201,
+    0x1022_2201,
+    0x1000_0001,
+    0x1111_1111,
+];
+const STRIPES: [u32; 8] = [
+    0x3344_5566,
+    0x3445_5663,
+    0x4455_6633,
+    0x4556_6334,
+    0x5566_3344,
+    0x5663_3445,
+    0x6633_4455,
+    0x6334_4556,
+];
+
+#[test]
+fn pattern_modes() {
+    // P1: layer A in front of layer B, which uses palette entries 16 to 31 and is scrolled
+    let mut vdp = V9990::new(0);
+    palette(&mut vdp, 0, &COLORS);
+    palette(&mut vdp, 16, &COLORS[8..]);
+    set(&mut vdp, 6, &[0x20, 0, 0x80]);
+    set(&mut vdp, 13, &[0x04, 0, 10]);
+    set(&mut vdp, 21, &[3, 0, 5, 0]);
+    pattern(&mut vdp, 0, 32, 1, BOX);
+    pattern(&mut vdp, 0, 32, 33, BOX.map(|row| (row * 5) & 0x5555_5555));
+    pattern(&mut vdp, 0x4_0000, 32, 1, STRIPES);
+    for y in 0..64u32 {
+        let layer_b: Vec<u8> = (0..64).flat_map(|_| 1u16.to_le_bytes()).collect();
+        write_vram(&mut vdp, 0x7e000 + y * 128, &layer_b);
+        let layer_a: Vec<u8> = (0..64u32)
+            .flat_map(|x| {
+                let n: u16 = match (x + y) % 5 {
+                    0 => 1,
+                    1 => 33,
+                    _ => 0,
+                };
+                n.to_le_bytes()
+            })
+            .collect();
+        write_vram(&mut vdp, 0x7c000 + y * 128, &layer_a);
+    }
+    golden("p1", &vdp.frame());
+
+    // P2: one layer of 1024 by 512 pixels, scrolled
+    let mut vdp = V9990::new(0);
+    palette(&mut vdp, 0, &COLORS);
+    set(&mut vdp, 6, &[0x50, 0, 0x80]);
+    set(&mut vdp, 15, &[9]);
+    set(&mut vdp, 17, &[4, 0, 2, 0]);
+    pattern(&mut vdp, 0, 64, 1, BOX);
+    pattern(&mut vdp, 0, 64, 65, STRIPES);
+    for y in 0..64u32 {
+        let names: Vec<u8> = (0..128u32)
+            .flat_map(|x| {
+                let n: u16 = match (x * y) % 3 {
+                    0 => 1,
+                    1 => 65,
+                    _ => 0,
+                };
+                n.to_le_bytes()
+            })
+            .collect();
+        write_vram(&mut vdp, 0x7c000 + y * 256, &names);
+    }
+    let frame = vdp.frame();
+    assert_eq!(frame.width, 512);
+    golden("p2", &frame);
+}
+
+#[test]
+fn display_disabled() {
+    let mut vdp = V9990::new(0);
+    palette(&mut vdp, 5, &[[31, 0, 31]]);
+    set(&mut vdp, 15, &[5]);
+    let frame = vdp.frame();
+    assert!(frame.pixels.iter().all(|&p| p == [0xff, 0, 0xff]));
+}
+
+#[test]
+fn png_round_trip() {
+    let frame = bitmap(B1_4BPP).frame();
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("v9990_round_trip.png");
+    frame.write_png(&path).unwrap();
+    assert_eq!(Frame::read_png(&path).unwrap(), frame);
+}
+
+/// A program on the nano board that writes a table of port and value pairs, ended by a negative
+/// port number
+#[test]
+fn nano_board() {
+    const TABLE: u32 = 0x800;
+    let map = MemoryMap::read("../m68k-nano/memory.x").unwrap();
+    let mut bus = Bus::from_memory_map(&map);
+    let ram = map.region("RAM").unwrap();
+    let stack = ram.origin + ram.length;
+    let vdp = Rc::new(RefCell::new(V9990::new(4)));
+    bus.add_device("V9990", V9990_BASE_ADDRESS, 0x10, vdp.clone());
+
+    let mut machine = Machine::new(Model::M68000, bus);
+    let mut vectors: Vec<u8> = [stack, START]
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect();
+    vectors.resize(0x400, 0);
+    machine.bus.load(0, &vectors).unwrap();
+
+    let mut program = vec![0x41f9]; // lea V9990_BASE_ADDRESS,a0
+    program.extend(long(V9990_BASE_ADDRESS));
+    program.push(0x43f9); // lea TABLE,a1
+    program.extend(long(TABLE));
+    program.extend([
+        0x1219, // move.b (a1)+,d1
+        0x6b08, // bmi.s done
+        0x1019, // move.b (a1)+,d0
+        0x1180, 0x1000, // move.b d0,(a0,d1.w)
+        0x60f4, // bra.s loop
+        NOP,    // done:
+        TRAP_0,
+    ]);
+    machine.bus.load(START, &bytes(&program)).unwrap();
+
+    let mut table = Vec::new();
+    let mut registers = |first: u8, values: &[u8]| {
+        table.extend([REGISTER_SELECT as u8, first]);
+        for &value in values {
+            table.extend([REGISTER_DATA as u8, value]);
+        }
+    };
+    registers(6, &[B1_4BPP, 0, 0x80]);
+    registers(14, &[0]);
+    registers(15, &[1]);
+    for (i, (dx, dy, nx, ny)) in [
+        (32u16, 32u16, 192u16, 148u16),
+        (64, 64, 128, 84),
+        (96, 96, 64, 20),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut args = Vec::new();
+        for word in [0, 0, dx, dy, nx, ny] {
+            args.extend(word.to_le_bytes());
+        }
+        args.extend([0, 0xc, 0xff, 0xff]);
+        args.extend((0x3333 * (i as u16 + 1)).to_le_bytes());
+        args.extend([0, 0, LMMV << 4]);
+        registers(32, &args);
+    }
+    for component in COLORS.as_flattened() {
+        table.extend([PALETTE_DATA as u8, *component]);
+    }
+    table.push(0xff);
+    machine.bus.load(TABLE, &table).unwrap();
+
+    machine.finish_on_trap(0);
+    machine.reset();
+    assert!(matches!(
+        machine.run(Limit::Instructions(100_000)),
+        Stop::Finished(_)
+    ));
+
+    golden("nano", &vdp.borrow().frame());
+}
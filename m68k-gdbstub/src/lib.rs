@@ -0,0 +1,423 @@
+//! GDB remote serial protocol stub for m68k targets
+//!
+//! The stub runs inside the exception handlers of the program being debugged. When an exception
+//! stops the program, [`Stub::handle`] reports it to GDB over a [`Transport`], such as a UART, and
+//! serves GDB's requests until it is told to continue or to single-step:
+//!
+//! - `g`/`G`: read and write the registers, in GDB's order: `d0`-`d7`, `a0`-`a7`, `sr` and `pc`,
+//!   32 bits each. The floating-point registers are left out, which GDB accepts.
+//! - `m`/`M`: read and write memory, through a [`Memory`] that decides which addresses are valid.
+//! - `c`/`s`: continue, or single-step by setting the trace bit of the status register. The next
+//!   instruction then ends in the `Trace` exception, which comes back to the stub.
+//! - `Z0`/`z0`: software breakpoints, which replace an instruction with a `TRAP #n` or `BKPT #n`
+//!   (see [`Breakpoint`]).
+//! - `?`, `D` (detach) and `k` (kill), which also removes the breakpoints and lets the program go.
+//!
+//! Everything else gets the empty reply that tells GDB it isn't supported.
+//!
+//! The protocol handling works on any target, so it is tested on the host against a simulated
+//! transport. On m68k, the [`target`] module provides the exception entry points.
+//!
+//! ```no_run
+//! use m68k_gdbstub::{Breakpoint, Memory, Registers, Resume, Stub, Transport};
+//! # struct Uart;
+//! # impl Transport for Uart {
+//! #     fn read(&mut self) -> u8 { 0 }
+//! #     fn write(&mut self, _: u8) {}
+//! # }
+//! # struct Ram;
+//! # impl Memory for Ram {
+//! #     fn read(&mut self, _: u32, _: &mut [u8]) -> Result<(), m68k_gdbstub::AccessError> { Ok(()) }
+//! #     fn write(&mut self, _: u32, _: &[u8]) -> Result<(), m68k_gdbstub::AccessError> { Ok(()) }
+//! # }
+//!
+//! let mut stub = Stub::new(Uart, Ram, Breakpoint::Trap(1));
+//! // In the handler of an exception, with the registers of the program that took it
+//! let mut registers = Registers::default();
+//! match stub.handle(4, &mut registers) {
+//!     Resume::Continue => {}
+//!     Resume::Step => assert!(registers.sr & 0x8000 != 0),
+//! }
+//! ```
+
+#![no_std]
+#![cfg_attr(target_arch = "m68k", feature(asm_experimental_arch))]
+
+mod packet;
+#[cfg(target_arch = "m68k")]
+pub mod target;
+
+use crate::packet::{decode_hex, parse_hex, Reply};
+
+/// A byte stream to GDB, usually a serial port
+pub trait Transport {
+    /// Waits for a byte
+    fn read(&mut self) -> u8;
+
+    /// Sends a byte
+    fn write(&mut self, byte: u8);
+}
+
+/// A memory access GDB asked for isn't allowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessError;
+
+/// The memory GDB can see
+pub trait Memory {
+    /// Reads `buffer.len()` bytes at `address`
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), AccessError>;
+
+    /// Writes `data` at `address`
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), AccessError>;
+}
+
+/// Registers of the stopped program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub d: [u32; 8],
+    /// `a[7]` is the stack pointer of the program: the user stack pointer if it was running in
+    /// user mode
+    pub a: [u32; 8],
+    pub sr: u16,
+    pub pc: u32,
+}
+
+/// The trace bit of the status register
+pub const TRACE: u16 = 1 << 15;
+
+/// Number of registers in `g` and `G` packets
+const REGISTERS: usize = 18;
+
+/// Instruction written over the code for a software breakpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// `TRAP #n`, for any processor. The program counter the exception reports is after the
+    /// instruction; the stub moves it back.
+    Trap(u8),
+    /// `BKPT #n`. The 68000 and 68010 take it as an illegal instruction.
+    Bkpt(u8),
+}
+
+impl Breakpoint {
+    /// Returns the opcode
+    pub fn opcode(self) -> u16 {
+        match self {
+            Breakpoint::Trap(n) => 0x4e40 | u16::from(n & 0xf),
+            Breakpoint::Bkpt(n) => 0x4848 | u16::from(n & 0x7),
+        }
+    }
+
+    /// Returns the vector number of the exception the instruction causes
+    pub fn vector(self) -> u8 {
+        match self {
+            Breakpoint::Trap(n) => 32 + (n & 0xf),
+            Breakpoint::Bkpt(_) => 4,
+        }
+    }
+}
+
+/// What the program does when [`Stub::handle`] returns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Runs until the next exception
+    Continue,
+    /// Runs one instruction, with the trace bit set in the status register
+    Step,
+}
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGEMT: u8 = 7;
+const SIGFPE: u8 = 8;
+const SIGBUS: u8 = 10;
+const SIGSEGV: u8 = 11;
+
+/// Returns the signal GDB is told about for an exception
+pub fn signal(vector: u8) -> u8 {
+    match vector {
+        2 | 3 => SIGBUS,
+        4 | 10 | 11 => SIGILL,
+        5..=7 => SIGFPE,
+        8 => SIGSEGV,
+        9 => SIGTRAP,
+        24..=31 => SIGINT,
+        32..=47 => SIGTRAP,
+        48..=54 => SIGFPE,
+        _ => SIGEMT,
+    }
+}
+
+/// Maximum number of software breakpoints
+pub const BREAKPOINTS: usize = 16;
+
+/// The debugger
+pub struct Stub<T, M> {
+    transport: T,
+    memory: M,
+    breakpoint: Breakpoint,
+    /// Address and replaced instruction of each breakpoint
+    breakpoints: [Option<(u32, u16)>; BREAKPOINTS],
+    /// Signal of the last stop, for `?`
+    signal: u8,
+    /// GDB is waiting for the program to stop
+    running: bool,
+    input: [u8; packet::SIZE],
+    reply: Reply,
+}
+
+impl<T: Transport, M: Memory> Stub<T, M> {
+    /// Creates a stub that uses `breakpoint` for software breakpoints
+    ///
+    /// The exception of `breakpoint` must end up in [`Stub::handle`].
+    pub const fn new(transport: T, memory: M, breakpoint: Breakpoint) -> Self {
+        Stub {
+            transport,
+            memory,
+            breakpoint,
+            breakpoints: [None; BREAKPOINTS],
+            signal: SIGTRAP,
+            running: false,
+            input: [0; packet::SIZE],
+            reply: Reply::new(),
+        }
+    }
+
+    /// Returns the memory
+    pub fn memory(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    /// Reports an exception to GDB and serves it until it resumes the program
+    ///
+    /// `vector` is the vector number of the exception and `registers` those of the program, which
+    /// GDB may change. The trace bit is cleared, and set again for [`Resume::Step`]. If the
+    /// exception came from one of the stub's breakpoints, the program counter is moved back to
+    /// the breakpoint.
+    pub fn handle(&mut self, vector: u8, registers: &mut Registers) -> Resume {
+        self.signal = signal(vector);
+        if vector == self.breakpoint.vector() {
+            if let Some(address) = self.hit(registers.pc) {
+                registers.pc = address;
+                self.signal = SIGTRAP;
+            }
+        }
+        registers.sr &= !TRACE;
+
+        // GDB only expects a stop reply after `c` or `s`; otherwise it asks with `?`
+        if self.running {
+            self.running = false;
+            self.stop_reply();
+        }
+
+        loop {
+            let len = packet::receive(&mut self.transport, &mut self.input);
+            self.reply.clear();
+            let resume = self.dispatch(len, registers);
+            match resume {
+                Some(resume) => {
+                    if resume == Resume::Step {
+                        registers.sr |= TRACE;
+                    }
+                    return resume;
+                }
+                None => packet::send(&mut self.transport, self.reply.as_bytes()),
+            }
+        }
+    }
+
+    /// Returns the address of the breakpoint the program counter points past or at
+    fn hit(&mut self, pc: u32) -> Option<u32> {
+        let address = match self.breakpoint {
+            Breakpoint::Trap(_) => pc.wrapping_sub(2),
+            Breakpoint::Bkpt(_) => pc,
+        };
+        self.breakpoints
+            .iter()
+            .flatten()
+            .any(|&(a, _)| a == address)
+            .then_some(address)
+    }
+
+    fn stop_reply(&mut self) {
+        self.reply.clear();
+        self.reply.push(b"S");
+        self.reply.push_byte(self.signal);
+        packet::send(&mut self.transport, self.reply.as_bytes());
+    }
+
+    /// Handles a packet, leaving the reply in `self.reply` or returning how to resume
+    fn dispatch(&mut self, len: usize, registers: &mut Registers) -> Option<Resume> {
+        // Take the packet out of `self` so the handlers can borrow it
+        let mut input = [0; packet::SIZE];
+        input[..len].copy_from_slice(&self.input[..len]);
+        let packet = &mut input[..len];
+        let (&mut command, args) = packet.split_first_mut()?;
+
+        let ok = match command {
+            b'?' => {
+                self.reply.push(b"S");
+                self.reply.push_byte(self.signal);
+                Ok(())
+            }
+            b'g' => {
+                self.read_registers(registers);
+                Ok(())
+            }
+            b'G' => write_registers(args, registers),
+            b'm' => self.read_memory(args),
+            b'M' => self.write_memory(args),
+            b'c' | b's' => {
+                if !args.is_empty() {
+                    match parse_hex(args) {
+                        Some(pc) => registers.pc = pc,
+                        None => {
+                            self.reply.push(b"E01");
+                            return None;
+                        }
+                    }
+                }
+                self.running = true;
+                return Some(if command == b'c' {
+                    Resume::Continue
+                } else {
+                    Resume::Step
+                });
+            }
+            b'Z' | b'z' => match args {
+                [b'0', b',', rest @ ..] => self.breakpoint(command == b'Z', rest),
+                // Other kinds of breakpoints and watchpoints aren't supported
+                _ => return None,
+            },
+            b'D' => {
+                self.remove_breakpoints();
+                packet::send(&mut self.transport, b"OK");
+                return Some(Resume::Continue);
+            }
+            b'k' => {
+                self.remove_breakpoints();
+                return Some(Resume::Continue);
+            }
+            _ => return None,
+        };
+
+        match ok {
+            Ok(()) if self.reply.as_bytes().is_empty() => self.reply.push(b"OK"),
+            Ok(()) => {}
+            Err(Error) => {
+                self.reply.clear();
+                self.reply.push(b"E01");
+            }
+        }
+        None
+    }
+
+    fn read_registers(&mut self, registers: &Registers) {
+        for &value in registers.d.iter().chain(&registers.a) {
+            self.reply.push_u32(value);
+        }
+        self.reply.push_u32(registers.sr.into());
+        self.reply.push_u32(registers.pc);
+    }
+
+    fn read_memory(&mut self, args: &[u8]) -> Result<(), Error> {
+        let (address, len) = address_length(args)?;
+        // Replies can be shorter than asked for
+        let len = (len as usize).min(packet::SIZE / 2);
+        let mut buffer = [0; packet::SIZE / 2];
+        self.memory
+            .read(address, &mut buffer[..len])
+            .map_err(|_| Error)?;
+        for &byte in &buffer[..len] {
+            self.reply.push_byte(byte);
+        }
+        Ok(())
+    }
+
+    fn write_memory(&mut self, args: &mut [u8]) -> Result<(), Error> {
+        let colon = args.iter().position(|&b| b == b':').ok_or(Error)?;
+        let (header, data) = args.split_at_mut(colon);
+        let (address, len) = address_length(header)?;
+        let data = &mut data[1..];
+        let decoded = decode_hex(data).ok_or(Error)?;
+        if decoded != len as usize {
+            return Err(Error);
+        }
+        self.memory
+            .write(address, &data[..decoded])
+            .map_err(|_| Error)
+    }
+
+    fn breakpoint(&mut self, insert: bool, args: &[u8]) -> Result<(), Error> {
+        // The kind, the length of the instruction, is always 2
+        let (address, _) = address_length(args)?;
+        if address & 1 != 0 {
+            return Err(Error);
+        }
+        let existing = self
+            .breakpoints
+            .iter()
+            .position(|b| matches!(b, Some((a, _)) if *a == address));
+
+        if insert {
+            if existing.is_some() {
+                return Ok(());
+            }
+            let free = self
+                .breakpoints
+                .iter()
+                .position(Option::is_none)
+                .ok_or(Error)?;
+            let mut original = [0; 2];
+            self.memory
+                .read(address, &mut original)
+                .map_err(|_| Error)?;
+            self.memory
+                .write(address, &self.breakpoint.opcode().to_be_bytes())
+                .map_err(|_| Error)?;
+            self.breakpoints[free] = Some((address, u16::from_be_bytes(original)));
+        } else if let Some(i) = existing {
+            if let Some((address, original)) = self.breakpoints[i].take() {
+                self.memory
+                    .write(address, &original.to_be_bytes())
+                    .map_err(|_| Error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_breakpoints(&mut self) {
+        for breakpoint in &mut self.breakpoints {
+            if let Some((address, original)) = breakpoint.take() {
+                let _ = self.memory.write(address, &original.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// A request that gets an `E01` reply
+struct Error;
+
+/// Parses `address,length`
+fn address_length(args: &[u8]) -> Result<(u32, u32), Error> {
+    let comma = args.iter().position(|&b| b == b',').ok_or(Error)?;
+    let address = parse_hex(&args[..comma]).ok_or(Error)?;
+    let len = parse_hex(&args[comma + 1..]).ok_or(Error)?;
+    Ok((address, len))
+}
+
+fn write_registers(args: &mut [u8], registers: &mut Registers) -> Result<(), Error> {
+    // GDB may send more registers, like the floating-point ones; they are ignored
+    if args.len() < REGISTERS * 8 {
+        return Err(Error);
+    }
+    let mut values = [0; REGISTERS];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = parse_hex(&args[i * 8..i * 8 + 8]).ok_or(Error)?;
+    }
+    registers.d.copy_from_slice(&values[..8]);
+    registers.a.copy_from_slice(&values[8..16]);
+    registers.sr = values[16] as u16;
+    registers.pc = values[17];
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Packet framing: `$data#checksum`, acknowledged with `+` or `-`
+
+use crate::Transport;
+
+/// Largest packet the stub accepts or sends, without the framing
+pub const SIZE: usize = 512;
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+pub fn hex_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Parses a hexadecimal number of up to 8 digits
+pub fn parse_hex(s: &[u8]) -> Option<u32> {
+    if s.is_empty() || s.len() > 8 {
+        return None;
+    }
+    s.iter()
+        .try_fold(0, |n, &byte| Some(n << 4 | u32::from(hex_digit(byte)?)))
+}
+
+/// Decodes pairs of hex digits in place, returning the number of bytes
+pub fn decode_hex(s: &mut [u8]) -> Option<usize> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    for i in 0..s.len() / 2 {
+        let high = hex_digit(s[2 * i])?;
+        let low = hex_digit(s[2 * i + 1])?;
+        s[i] = high << 4 | low;
+    }
+    Some(s.len() / 2)
+}
+
+/// Waits for a packet with a good checksum and acknowledges it
+///
+/// Returns the length of the data in `buffer`. Packets that don't fit are refused like ones with a
+/// bad checksum.
+pub fn receive<T: Transport + ?Sized>(transport: &mut T, buffer: &mut [u8; SIZE]) -> usize {
+    let mut started = false;
+    'packet: loop {
+        if !started {
+            while transport.read() != b'$' {}
+        }
+        started = false;
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut overflow = false;
+        loop {
+            match transport.read() {
+                b'#' => break,
+                // The previous packet was cut short
+                b'$' => {
+                    started = true;
+                    continue 'packet;
+                }
+                byte => {
+                    sum = sum.wrapping_add(byte);
+                    if len < SIZE {
+                        buffer[len] = byte;
+                        len += 1;
+                    } else {
+                        overflow = true;
+                    }
+                }
+            }
+        }
+
+        let high = hex_digit(transport.read());
+        let low = hex_digit(transport.read());
+        match (high, low) {
+            (Some(high), Some(low)) if high << 4 | low == sum && !overflow => {
+                transport.write(b'+');
+                return len;
+            }
+            _ => transport.write(b'-'),
+        }
+    }
+}
+
+/// Sends a packet until it is acknowledged
+pub fn send<T: Transport + ?Sized>(transport: &mut T, data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    loop {
+        transport.write(b'$');
+        for &byte in data {
+            transport.write(byte);
+        }
+        transport.write(b'#');
+        transport.write(HEX[usize::from(sum >> 4)]);
+        transport.write(HEX[usize::from(sum & 0xf)]);
+
+        // Anything else, like a stray Ctrl-C, is ignored
+        loop {
+            match transport.read() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A reply being built
+pub struct Reply {
+    buffer: [u8; SIZE],
+    len: usize,
+}
+
+impl Reply {
+    pub const fn new() -> Self {
+        Reply {
+            buffer: [0; SIZE],
+            len: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub fn push(&mut self, s: &[u8]) {
+        let len = s.len().min(SIZE - self.len);
+        self.buffer[self.len..self.len + len].copy_from_slice(&s[..len]);
+        self.len += len;
+    }
+
+    pub fn push_byte(&mut self, byte: u8) {
+        self.push(&[HEX[usize::from(byte >> 4)], HEX[usize::from(byte & 0xf)]]);
+    }
+
+    /// Appends a register, big-endian like the target
+    pub fn push_u32(&mut self, value: u32) {
+        for byte in value.to_be_bytes() {
+            self.push_byte(byte);
+        }
+    }
+}
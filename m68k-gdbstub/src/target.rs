@@ -0,0 +1,214 @@
+//! Exception entry points on m68k
+//!
+//! [`install`] makes a stub the one the entry points call. [`entry`] returns the entry point for a
+//! vector, to be put in the vector table, e.g. with `m68k_rt::app::set_vector` in a program loaded
+//! by a monitor. With the `rt-handlers` feature the entry points for vectors 2 to 11 are also the
+//! `BusError` to `Line1111Emulator` handlers of the `m68k-rt` vector table.
+//!
+//! Each entry point saves the registers, calls the stub with the registers of the program and its
+//! exception frame, and returns to the program with `rte` once GDB resumes it. The supervisor
+//! stack pointer can't be changed from GDB. A program stopped by a bus or address error can be
+//! inspected, but the 68000 can't resume the instruction that failed.
+
+use core::arch::{asm, global_asm};
+use core::ops::Range;
+use core::{mem, ptr};
+
+use m68k_rt::LowerExceptionFrame;
+
+use crate::{AccessError, Memory, Registers, Resume, Stub, Transport};
+
+global_asm!(
+    ".section .text.__gdbstub_entry, \"ax\"",
+    ".irp n, 2,3,4,5,6,7,8,9,10,11,32,33,34,35,36,37,38,39,40,41,42,43,44,45,46,47",
+    "__gdbstub_vector_\\n:",
+    "    move.l  #\\n, -(%sp)",
+    "    bra     __gdbstub_common",
+    ".endr",
+    "__gdbstub_common:",
+    "    movem.l %d0-%d7/%a0-%a6, -(%sp)",
+    "    move.l  %sp, -(%sp)",
+    "    jsr     __gdbstub_exception",
+    "    lea     (4, %sp), %sp",
+    "    movem.l (%sp)+, %d0-%d7/%a0-%a6",
+    // `__gdbstub_exception` replaced the vector number with the number of bytes to drop
+    "    adda.l  (%sp), %sp",
+    "    rte",
+    ".section .rodata.__gdbstub_entries, \"a\"",
+    "__gdbstub_entries:",
+    ".irp n, 2,3,4,5,6,7,8,9,10,11,32,33,34,35,36,37,38,39,40,41,42,43,44,45,46,47",
+    "    .long   __gdbstub_vector_\\n",
+    ".endr",
+);
+
+#[cfg(feature = "rt-handlers")]
+global_asm!(
+    ".global BusError, AddressError, IllegalInstruction, ZeroDivide, CHKInstruction",
+    ".global TRAPVInstruction, PrivilegeViolation, Trace, Line1010Emulator, Line1111Emulator",
+    ".set BusError, __gdbstub_vector_2",
+    ".set AddressError, __gdbstub_vector_3",
+    ".set IllegalInstruction, __gdbstub_vector_4",
+    ".set ZeroDivide, __gdbstub_vector_5",
+    ".set CHKInstruction, __gdbstub_vector_6",
+    ".set TRAPVInstruction, __gdbstub_vector_7",
+    ".set PrivilegeViolation, __gdbstub_vector_8",
+    ".set Trace, __gdbstub_vector_9",
+    ".set Line1010Emulator, __gdbstub_vector_10",
+    ".set Line1111Emulator, __gdbstub_vector_11",
+);
+
+/// Returns the entry point for a vector: 2 to 11 (bus error to line 1111 emulator, including
+/// `Trace`) and 32 to 47 (`TRAP #0` to `TRAP #15`)
+pub fn entry(vector: u8) -> Option<usize> {
+    extern "C" {
+        static __gdbstub_entries: [u32; 26];
+    }
+
+    let index = match vector {
+        2..=11 => vector - 2,
+        32..=47 => vector - 22,
+        _ => return None,
+    };
+    let entries = unsafe { &*ptr::addr_of!(__gdbstub_entries) };
+    Some(entries[usize::from(index)] as usize)
+}
+
+trait Handler {
+    fn handle(&mut self, vector: u8, registers: &mut Registers) -> Resume;
+}
+
+impl<T: Transport, M: Memory> Handler for Stub<T, M> {
+    fn handle(&mut self, vector: u8, registers: &mut Registers) -> Resume {
+        Stub::handle(self, vector, registers)
+    }
+}
+
+static mut STUB: Option<&'static mut dyn Handler> = None;
+
+/// Makes `stub` the one the entry points call
+///
+/// # Safety
+///
+/// Must not be called while an entry point is running, e.g. from an exception handler.
+pub unsafe fn install<T, M>(stub: &'static mut Stub<T, M>)
+where
+    T: Transport + 'static,
+    M: Memory + 'static,
+{
+    *ptr::addr_of_mut!(STUB) = Some(stub);
+}
+
+/// What `__gdbstub_common` pushes on top of the exception frame
+#[repr(C)]
+struct Saved {
+    d: [u32; 8],
+    a: [u32; 7],
+    vector: u32,
+}
+
+const SUPERVISOR: u16 = 1 << 13;
+
+#[no_mangle]
+unsafe extern "C" fn __gdbstub_exception(saved: &mut Saved) {
+    // Bus and address errors push 8 more bytes in front of the status register and program
+    // counter
+    let extra = if matches!(saved.vector, 2 | 3) { 8 } else { 0 };
+    let address = saved as *mut Saved as usize + mem::size_of::<Saved>() + extra;
+    let frame = &mut *(address as *mut LowerExceptionFrame);
+    let supervisor = frame.sr() & SUPERVISOR != 0;
+
+    let mut registers = Registers {
+        d: saved.d,
+        a: [0; 8],
+        sr: frame.sr(),
+        pc: frame.pc(),
+    };
+    registers.a[..7].copy_from_slice(&saved.a);
+    registers.a[7] = if supervisor {
+        (address + mem::size_of::<LowerExceptionFrame>()) as u32
+    } else {
+        usp()
+    };
+
+    match &mut *ptr::addr_of_mut!(STUB) {
+        Some(stub) => {
+            stub.handle(saved.vector as u8, &mut registers);
+        }
+        None => loop {
+            // stop #0x2700
+            asm!(".short 0x4e72, 0x2700", options(nomem, nostack));
+        },
+    }
+
+    saved.d = registers.d;
+    saved.a.copy_from_slice(&registers.a[..7]);
+    if !supervisor {
+        set_usp(registers.a[7]);
+    }
+    frame.set_sr(registers.sr);
+    frame.set_pc(registers.pc);
+    saved.vector = (4 + extra) as u32;
+}
+
+fn usp() -> u32 {
+    let usp;
+    // move.l %usp, %a0
+    unsafe { asm!(".short 0x4e68", out("a0") usp, options(nomem, nostack)) };
+    usp
+}
+
+fn set_usp(usp: u32) {
+    // move.l %a0, %usp
+    unsafe { asm!(".short 0x4e60", in("a0") usp, options(nomem, nostack)) };
+}
+
+/// The program's own memory, limited to some address ranges
+///
+/// Addresses outside the ranges would cause a bus error in the stub. Writes are read back, so
+/// breakpoints in ROM fail instead of being silently lost.
+pub struct Direct {
+    ranges: &'static [Range<u32>],
+}
+
+impl Direct {
+    pub const fn new(ranges: &'static [Range<u32>]) -> Self {
+        Direct { ranges }
+    }
+
+    fn check(&self, address: u32, len: usize) -> Result<(), AccessError> {
+        let end = address.checked_add(len as u32).ok_or(AccessError)?;
+        if self
+            .ranges
+            .iter()
+            .any(|range| range.start <= address && end <= range.end)
+        {
+            Ok(())
+        } else {
+            Err(AccessError)
+        }
+    }
+}
+
+impl Memory for Direct {
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), AccessError> {
+        self.check(address, buffer.len())?;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile((address as usize + i) as *const u8) };
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), AccessError> {
+        self.check(address, data.len())?;
+        for (i, &byte) in data.iter().enumerate() {
+            let p = (address as usize + i) as *mut u8;
+            unsafe {
+                ptr::write_volatile(p, byte);
+                if ptr::read_volatile(p) != byte {
+                    return Err(AccessError);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//! A simulated serial line, a RAM for the stub to debug and the GDB side of the protocol
+
+#![allow(dead_code)]
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use m68k_gdbstub::{AccessError, Breakpoint, Memory, Registers, Resume, Stub, Transport};
+
+/// One end of a serial line
+pub struct Pipe {
+    rx: Receiver<u8>,
+    tx: Sender<u8>,
+}
+
+impl Transport for Pipe {
+    fn read(&mut self) -> u8 {
+        self.rx.recv().expect("the other end hung up")
+    }
+
+    fn write(&mut self, byte: u8) {
+        let _ = self.tx.send(byte);
+    }
+}
+
+pub fn pipe() -> (Pipe, Pipe) {
+    let (a_tx, a_rx) = channel();
+    let (b_tx, b_rx) = channel();
+    (Pipe { rx: a_rx, tx: b_tx }, Pipe { rx: b_rx, tx: a_tx })
+}
+
+pub const RAM_BASE: u32 = 0x1000;
+pub const RAM_SIZE: usize = 0x100;
+
+pub struct Ram {
+    pub data: Vec<u8>,
+}
+
+impl Ram {
+    fn range(&self, address: u32, len: usize) -> Result<std::ops::Range<usize>, AccessError> {
+        let start = address.checked_sub(RAM_BASE).ok_or(AccessError)? as usize;
+        if start + len > self.data.len() {
+            return Err(AccessError);
+        }
+        Ok(start..start + len)
+    }
+}
+
+impl Memory for Ram {
+    fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), AccessError> {
+        let range = self.range(address, buffer.len())?;
+        buffer.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<(), AccessError> {
+        let range = self.range(address, data.len())?;
+        self.data[range].copy_from_slice(data);
+        Ok(())
+    }
+}
+
+pub type TestStub = Stub<Pipe, Ram>;
+
+/// Creates a stub over RAM filled with `nop`s, and the GDB end of its serial line
+pub fn stub(breakpoint: Breakpoint) -> (TestStub, Client) {
+    let (target, host) = pipe();
+    let ram = Ram {
+        data: [0x4e, 0x71].repeat(RAM_SIZE / 2),
+    };
+    (Stub::new(target, ram, breakpoint), Client { pipe: host })
+}
+
+/// Registers with recognizable values
+pub fn registers() -> Registers {
+    Registers {
+        d: [0, 1, 2, 3, 4, 5, 6, 0xdead_beef],
+        a: [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x2000_fff0],
+        sr: 0x2704,
+        pc: RAM_BASE + 0x10,
+    }
+}
+
+/// Runs [`Stub::handle`] on a thread, as if the program had taken exception `vector`
+pub fn stop(
+    mut stub: TestStub,
+    vector: u8,
+    mut registers: Registers,
+) -> JoinHandle<(TestStub, Registers, Resume)> {
+    thread::spawn(move || {
+        let resume = stub.handle(vector, &mut registers);
+        (stub, registers, resume)
+    })
+}
+
+/// The GDB end of the line
+pub struct Client {
+    pipe: Pipe,
+}
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn checksum(data: &str) -> String {
+    let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("{:02x}", sum)
+}
+
+impl Client {
+    pub fn read(&mut self) -> u8 {
+        self.pipe
+            .rx
+            .recv_timeout(TIMEOUT)
+            .expect("no answer from the stub")
+    }
+
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.pipe.write(byte);
+        }
+    }
+
+    /// Sends a packet and checks that it is acknowledged
+    pub fn send(&mut self, data: &str) {
+        self.write_raw(format!("${}#{}", data, checksum(data)).as_bytes());
+        assert_eq!(self.read(), b'+', "packet {:?} wasn't acknowledged", data);
+    }
+
+    /// Reads a packet, checks its checksum and acknowledges it
+    pub fn receive(&mut self) -> String {
+        let data = self.receive_unacknowledged();
+        self.pipe.write(b'+');
+        data
+    }
+
+    pub fn receive_unacknowledged(&mut self) -> String {
+        assert_eq!(self.read(), b'$');
+        let mut data = String::new();
+        loop {
+            match self.read() {
+                b'#' => break,
+                byte => data.push(byte as char),
+            }
+        }
+        let sum = [self.read(), self.read()];
+        assert_eq!(
+            std::str::from_utf8(&sum).unwrap(),
+            checksum(&data),
+            "checksum of {:?}",
+            data
+        );
+        data
+    }
+
+    /// Sends a packet and returns the reply
+    pub fn request(&mut self, data: &str) -> String {
+        self.send(data);
+        self.receive()
+    }
+}
+
+/// Parses a `g` reply
+pub fn parse_registers(reply: &str) -> Vec<u32> {
+    assert_eq!(reply.len(), 18 * 8, "{}", reply);
+    (0..18)
+        .map(|i| u32::from_str_radix(&reply[i * 8..i * 8 + 8], 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,302 @@
+mod common;
+
+use common::*;
+use m68k_gdbstub::{signal, Breakpoint, Resume, BREAKPOINTS, TRACE};
+
+#[test]
+fn read_registers() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    let values = parse_registers(&gdb.request("g"));
+    assert_eq!(&values[..8], &registers().d);
+    assert_eq!(&values[8..16], &registers().a);
+    assert_eq!(values[16], 0x2704);
+    assert_eq!(values[17], RAM_BASE + 0x10);
+    assert!(gdb.request("g").starts_with("0000000000000001"));
+
+    gdb.send("c");
+    let (_, _, resume) = target.join().unwrap();
+    assert_eq!(resume, Resume::Continue);
+}
+
+#[test]
+fn write_registers() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    let mut values: Vec<u32> = (0..18).map(|i| 0x0101_0101 * i).collect();
+    values[16] = 0x0000_2015;
+    values[17] = 0x0000_1234;
+    let hex: String = values.iter().map(|v| format!("{:08x}", v)).collect();
+    assert_eq!(gdb.request(&format!("G{}", hex)), "OK");
+    assert_eq!(parse_registers(&gdb.request("g")), values);
+
+    // The floating-point registers are ignored
+    let fp = "0".repeat(8 * 24 + 3 * 8);
+    assert_eq!(gdb.request(&format!("G{}{}", hex, fp)), "OK");
+    // Too short, or not hex
+    assert_eq!(gdb.request(&format!("G{}", &hex[..hex.len() - 2])), "E01");
+    assert_eq!(
+        gdb.request(&format!("G{}", hex.replacen('0', "x", 1))),
+        "E01"
+    );
+
+    gdb.send("c");
+    let (_, registers, _) = target.join().unwrap();
+    assert_eq!(registers.d[1], 0x0101_0101);
+    assert_eq!(registers.a[7], 0x0f0f_0f0f);
+    assert_eq!(registers.sr, 0x2015);
+    assert_eq!(registers.pc, 0x1234);
+}
+
+#[test]
+fn memory() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    assert_eq!(gdb.request("m1000,4"), "4e714e71");
+    assert_eq!(gdb.request("M1002,3:a1b2C3"), "OK");
+    assert_eq!(gdb.request("m1000,6"), "4e71a1b2c371");
+    assert_eq!(gdb.request("m0,0"), "E01");
+    assert_eq!(gdb.request("m10fe,4"), "E01");
+    assert_eq!(gdb.request("M10ff,2:0000"), "E01");
+    // Malformed
+    assert_eq!(gdb.request("m1000"), "E01");
+    assert_eq!(gdb.request("m1000,"), "E01");
+    assert_eq!(gdb.request("mxyz,1"), "E01");
+    assert_eq!(gdb.request("M1000,2:00"), "E01");
+    assert_eq!(gdb.request("M1000,1:0"), "E01");
+    assert_eq!(gdb.request("M1000,1"), "E01");
+
+    gdb.send("c");
+    let (mut stub, _, _) = target.join().unwrap();
+    assert_eq!(
+        &stub.memory().data[..6],
+        [0x4e, 0x71, 0xa1, 0xb2, 0xc3, 0x71]
+    );
+}
+
+#[test]
+fn long_memory_reads_are_shortened() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    let reply = gdb.request(&format!("m{:x},{:x}", RAM_BASE, RAM_SIZE));
+    assert!(!reply.is_empty() && reply.len() <= 512, "{}", reply.len());
+    assert!(reply.starts_with("4e714e71"));
+
+    gdb.send("c");
+    target.join().unwrap();
+}
+
+#[test]
+fn continue_at_address() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let mut traced = registers();
+    traced.sr |= TRACE;
+    let target = stop(stub, 9, traced);
+
+    gdb.send("c1020");
+    let (_, registers, resume) = target.join().unwrap();
+    assert_eq!(resume, Resume::Continue);
+    assert_eq!(registers.pc, 0x1020);
+    assert_eq!(registers.sr & TRACE, 0);
+}
+
+#[test]
+fn step() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    gdb.send("s");
+    let (stub, mut registers, resume) = target.join().unwrap();
+    assert_eq!(resume, Resume::Step);
+    assert_eq!(registers.sr, 0x2704 | TRACE);
+
+    // The next instruction ends in the trace exception, which GDB is waiting for
+    registers.pc += 2;
+    let target = stop(stub, 9, registers);
+    assert_eq!(gdb.receive(), "S05");
+    assert_eq!(gdb.request("?"), "S05");
+    let values = parse_registers(&gdb.request("g"));
+    assert_eq!(values[16], 0x2704);
+    assert_eq!(values[17], RAM_BASE + 0x12);
+
+    gdb.send("s1000");
+    let (_, registers, resume) = target.join().unwrap();
+    assert_eq!(resume, Resume::Step);
+    assert_eq!(registers.pc, 0x1000);
+    assert!(registers.sr & TRACE != 0);
+}
+
+#[test]
+fn trap_breakpoints() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    assert_eq!(gdb.request("Z0,1020,2"), "OK");
+    assert_eq!(gdb.request("m1020,2"), "4e41");
+    gdb.send("c");
+    let (stub, mut registers, _) = target.join().unwrap();
+
+    // TRAP #1 reports the address after the instruction
+    registers.pc = 0x1022;
+    let target = stop(stub, 33, registers);
+    assert_eq!(gdb.receive(), "S05");
+    assert_eq!(parse_registers(&gdb.request("g"))[17], 0x1020);
+    assert_eq!(gdb.request("z0,1020,2"), "OK");
+    assert_eq!(gdb.request("m1020,2"), "4e71");
+    // Removing it again is fine
+    assert_eq!(gdb.request("z0,1020,2"), "OK");
+
+    gdb.send("c");
+    let (stub, registers, _) = target.join().unwrap();
+    assert_eq!(registers.pc, 0x1020);
+
+    // A TRAP #1 of the program itself is left alone
+    let mut registers = registers;
+    registers.pc = 0x1042;
+    let target = stop(stub, 33, registers);
+    assert_eq!(gdb.receive(), "S05");
+    assert_eq!(parse_registers(&gdb.request("g"))[17], 0x1042);
+    gdb.send("c");
+    target.join().unwrap();
+}
+
+#[test]
+fn bkpt_breakpoints() {
+    let (stub, mut gdb) = stub(Breakpoint::Bkpt(0));
+    let target = stop(stub, 4, registers());
+
+    assert_eq!(gdb.request("Z0,1030,2"), "OK");
+    assert_eq!(gdb.request("m1030,2"), "4848");
+    gdb.send("c");
+    let (stub, mut registers, _) = target.join().unwrap();
+
+    // The 68000 takes BKPT as an illegal instruction, at the instruction
+    registers.pc = 0x1030;
+    let target = stop(stub, 4, registers);
+    assert_eq!(gdb.receive(), "S05");
+    gdb.send("c");
+    let (stub, registers, _) = target.join().unwrap();
+    assert_eq!(registers.pc, 0x1030);
+
+    // A real illegal instruction
+    let mut registers = registers;
+    registers.pc = 0x1040;
+    let target = stop(stub, 4, registers);
+    assert_eq!(gdb.receive(), "S04");
+    gdb.send("c");
+    target.join().unwrap();
+}
+
+#[test]
+fn breakpoint_errors() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    // Odd or outside memory
+    assert_eq!(gdb.request("Z0,1021,2"), "E01");
+    assert_eq!(gdb.request("Z0,2000,2"), "E01");
+
+    for i in 0..BREAKPOINTS {
+        assert_eq!(gdb.request(&format!("Z0,{:x},2", 0x1000 + 2 * i)), "OK");
+    }
+    // Already there
+    assert_eq!(gdb.request("Z0,1000,2"), "OK");
+    assert_eq!(
+        gdb.request(&format!("Z0,{:x},2", 0x1000 + 2 * BREAKPOINTS)),
+        "E01"
+    );
+    assert_eq!(gdb.request("z0,1000,2"), "OK");
+    assert_eq!(
+        gdb.request(&format!("Z0,{:x},2", 0x1000 + 2 * BREAKPOINTS)),
+        "OK"
+    );
+
+    // Hardware breakpoints and watchpoints aren't supported
+    assert_eq!(gdb.request("Z1,1000,2"), "");
+    assert_eq!(gdb.request("Z2,1000,4"), "");
+
+    gdb.send("c");
+    target.join().unwrap();
+}
+
+#[test]
+fn detach_removes_breakpoints() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(2));
+    let target = stop(stub, 4, registers());
+
+    assert_eq!(gdb.request("Z0,1000,2"), "OK");
+    assert_eq!(gdb.request("Z0,1008,2"), "OK");
+    assert_eq!(gdb.request("D"), "OK");
+    let (mut stub, _, resume) = target.join().unwrap();
+    assert_eq!(resume, Resume::Continue);
+    assert!(stub.memory().data.chunks(2).all(|w| w == [0x4e, 0x71]));
+}
+
+#[test]
+fn stop_reasons() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 5, registers());
+    // Nothing is sent until GDB asks
+    assert_eq!(gdb.request("?"), "S08");
+    gdb.send("c");
+    let (stub, registers, _) = target.join().unwrap();
+
+    let target = stop(stub, 2, registers);
+    assert_eq!(gdb.receive(), "S0a");
+    assert_eq!(gdb.request("?"), "S0a");
+    gdb.send("k");
+    target.join().unwrap();
+
+    assert_eq!(signal(3), 10);
+    assert_eq!(signal(8), 11);
+    assert_eq!(signal(9), 5);
+    assert_eq!(signal(11), 4);
+    assert_eq!(signal(25), 2);
+    assert_eq!(signal(47), 5);
+    assert_eq!(signal(64), 7);
+}
+
+#[test]
+fn unsupported_packets() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    assert_eq!(gdb.request("qSupported:multiprocess+;swbreak+"), "");
+    assert_eq!(gdb.request("vMustReplyEmpty"), "");
+    assert_eq!(gdb.request(""), "");
+    assert_eq!(gdb.request("cxyz"), "E01");
+
+    gdb.send("c");
+    target.join().unwrap();
+}
+
+#[test]
+fn framing() {
+    let (stub, mut gdb) = stub(Breakpoint::Trap(1));
+    let target = stop(stub, 4, registers());
+
+    // Bad checksums are refused, and GDB sends the packet again
+    gdb.write_raw(b"$m1000,2#00");
+    assert_eq!(gdb.read(), b'-');
+    // Noise, a packet cut short and an acknowledgement are skipped
+    gdb.write_raw(b"+\x03$m10");
+    assert_eq!(gdb.request("m1000,2"), "4e71");
+
+    // A reply that isn't acknowledged is sent again
+    gdb.send("m1002,2");
+    assert_eq!(gdb.receive_unacknowledged(), "4e71");
+    gdb.write_raw(b"-");
+    assert_eq!(gdb.receive(), "4e71");
+
+    // Packets that don't fit
+    let long = format!("M1000,100:{}", "00".repeat(0x100));
+    gdb.write_raw(format!("${}#{}", long, checksum(&long)).as_bytes());
+    assert_eq!(gdb.read(), b'-');
+
+    gdb.send("c");
+    target.join().unwrap();
+}
@@ -0,0 +1,332 @@
+//! Post-link sanity checks
+//!
+//! The linker script already refuses some broken layouts, but it can't see what ends up in the
+//! vector table or whether the image matches the board's `memory.x`. [`check`] looks at the final
+//! ELF:
+//!
+//! - vector 0 (the initial stack pointer) is even and inside RAM
+//! - vector 1 (the initial program counter) points at `Reset`
+//! - every other vector points into `.text` or `.boot`, or is zero where the vector is reserved
+//! - `.data` is 4-byte aligned both where it is stored and where it runs, and matches `__sidata`
+//!   and `__sdata`
+//! - `.got` is empty
+//! - every section fits in a memory region, both where it runs and where it is stored
+//!
+//! Images linked with the `ram-app` feature have no vector table; only the remaining checks apply.
+
+use std::fmt;
+
+use crate::elf::Image;
+use crate::memory::MemoryMap;
+
+/// How bad a finding is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in an image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// How much of a memory region the image uses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Usage {
+    pub region: String,
+    pub used: u64,
+    pub length: u32,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = if self.length == 0 {
+            0.0
+        } else {
+            self.used as f64 * 100.0 / f64::from(self.length)
+        };
+        write!(
+            f,
+            "{:<8} {:>10} / {:>10} bytes ({:.1}%)",
+            self.region, self.used, self.length, percent
+        )
+    }
+}
+
+/// Result of [`check`]
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+    pub usage: Vec<Usage>,
+}
+
+impl Report {
+    /// Returns `true` if no errors were found
+    pub fn is_ok(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .all(|d| d.severity != Severity::Error)
+    }
+
+    /// Returns the errors
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+}
+
+/// Returns `true` if a zero in vector `number` is deliberate
+///
+/// These are the vectors the 68000 family reserves, plus the coprocessor and MMU vectors which are
+/// never taken on processors without them, and the uninitialized interrupt vector, which
+/// `m68k-rt` leaves empty.
+pub fn is_reserved(number: usize) -> bool {
+    matches!(number, 12 | 13 | 15 | 16..=23 | 48..=63)
+}
+
+/// Checks `image` against `memory`
+pub fn check(image: &Image, memory: &MemoryMap) -> Report {
+    let mut report = Report::default();
+
+    // `__eapp` is only defined by the `ram-app` linker script
+    if image.symbol("__eapp").is_some() {
+        check_app_entry(image, &mut report);
+    } else {
+        check_vectors(image, memory, &mut report);
+    }
+    check_data(image, &mut report);
+    check_got(image, &mut report);
+    check_fit(image, memory, &mut report);
+
+    report
+}
+
+fn check_vectors(image: &Image, memory: &MemoryMap, report: &mut Report) {
+    let Some(table) = image.section(".vector_table") else {
+        report.error("no .vector_table section".into());
+        return;
+    };
+    if table.size < 8 || !table.size.is_multiple_of(4) || table.data.len() != table.size as usize {
+        report.error(format!(
+            ".vector_table has an invalid size of {} bytes",
+            table.size
+        ));
+        return;
+    }
+    let vectors: Vec<u32> = table
+        .data
+        .chunks_exact(4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .collect();
+
+    // Vector 0: initial supervisor stack pointer
+    let sp = vectors[0];
+    if !sp.is_multiple_of(2) {
+        report.error(format!("initial stack pointer 0x{:08x} is odd", sp));
+    }
+    match memory.region("RAM") {
+        // The stack pointer is pre-decremented, so it may point one past the end of RAM
+        Some(ram) if u64::from(sp) > u64::from(ram.origin) && u64::from(sp) <= ram.end() => {}
+        Some(ram) => report.error(format!(
+            "initial stack pointer 0x{:08x} is outside RAM (0x{:08x}..0x{:08x})",
+            sp,
+            ram.origin,
+            ram.end()
+        )),
+        None => report.warning("no RAM region; the initial stack pointer wasn't checked".into()),
+    }
+
+    // Vector 1: initial program counter
+    let pc = vectors[1];
+    let reset = image.symbol("Reset");
+    // With `rom-shadow` the vector points at `Reset` as seen through the boot overlay
+    let boot_reset = image.symbol("__boot_reset");
+    match reset {
+        None => report.error("no Reset symbol".into()),
+        Some(reset) if pc != reset && Some(pc) != boot_reset => report.error(format!(
+            "reset vector 0x{:08x} doesn't point at Reset (0x{:08x})",
+            pc, reset
+        )),
+        Some(_) => {}
+    }
+
+    for (number, &vector) in vectors.iter().enumerate().skip(2) {
+        if vector == 0 {
+            if !is_reserved(number) {
+                report.error(format!("vector {} is zero", number));
+            }
+            continue;
+        }
+        match image.section_at(vector) {
+            Some(s) if matches!(s.name.as_str(), ".text" | ".boot" | ".ramtext") => {
+                if !vector.is_multiple_of(2) {
+                    report.error(format!("vector {} (0x{:08x}) is odd", number, vector));
+                }
+            }
+            Some(s) => report.error(format!(
+                "vector {} (0x{:08x}) points into {} instead of .text",
+                number, vector, s.name
+            )),
+            None => report.error(format!(
+                "vector {} (0x{:08x}) doesn't point into any section",
+                number, vector
+            )),
+        }
+    }
+}
+
+fn check_app_entry(image: &Image, report: &mut Report) {
+    let start = image.symbol("__stext");
+    match image.symbol("Reset") {
+        None => report.error("no Reset symbol".into()),
+        Some(reset) => {
+            if image.entry != reset {
+                report.error(format!(
+                    "entry point 0x{:08x} isn't Reset (0x{:08x})",
+                    image.entry, reset
+                ));
+            }
+            if Some(reset) != start {
+                report.warning(format!(
+                    "Reset (0x{:08x}) isn't at the load address of the program",
+                    reset
+                ));
+            }
+        }
+    }
+}
+
+fn check_data(image: &Image, report: &mut Report) {
+    let Some(data) = image.section(".data") else {
+        return;
+    };
+    if !data.addr.is_multiple_of(4) {
+        report.error(format!(
+            ".data VMA 0x{:08x} is not 4-byte aligned",
+            data.addr
+        ));
+    }
+    if !data.lma.is_multiple_of(4) {
+        report.error(format!(
+            ".data LMA 0x{:08x} is not 4-byte aligned",
+            data.lma
+        ));
+    }
+    if !data.size.is_multiple_of(4) {
+        report.error(format!(".data size {} is not a multiple of 4", data.size));
+    }
+    // Empty sections aren't necessarily placed where the symbols say
+    if data.size != 0 {
+        if let Some(sdata) = image.symbol("__sdata") {
+            if sdata != data.addr {
+                report.error(format!(
+                    "__sdata (0x{:08x}) doesn't match the .data VMA (0x{:08x})",
+                    sdata, data.addr
+                ));
+            }
+        }
+        if let Some(sidata) = image.symbol("__sidata") {
+            if sidata != data.lma {
+                report.error(format!(
+                    "__sidata (0x{:08x}) doesn't match the .data LMA (0x{:08x})",
+                    sidata, data.lma
+                ));
+            }
+        }
+    }
+}
+
+fn check_got(image: &Image, report: &mut Report) {
+    if let Some(got) = image.section(".got") {
+        if got.size != 0 {
+            report.error(format!(
+                ".got is not empty ({} bytes); the image contains position independent code",
+                got.size
+            ));
+        }
+    }
+}
+
+fn check_fit(image: &Image, memory: &MemoryMap, report: &mut Report) {
+    if memory.regions.is_empty() {
+        report.warning("no memory regions; section placement wasn't checked".into());
+        return;
+    }
+
+    let mut used = vec![0u64; memory.regions.len()];
+    let mut place = |what: &str, name: &str, start: u32, size: u32, report: &mut Report| {
+        let start = u64::from(start);
+        let end = start + u64::from(size);
+        match memory
+            .regions
+            .iter()
+            .position(|r| r.contains_range(start, end))
+        {
+            Some(i) => used[i] += end - start,
+            None => match memory
+                .regions
+                .iter()
+                .find(|r| start >= u64::from(r.origin) && start < r.end())
+            {
+                Some(r) => report.error(format!(
+                    "{} of {} (0x{:08x}..0x{:08x}) overflows {} by {} bytes",
+                    what,
+                    name,
+                    start,
+                    end,
+                    r.name,
+                    end - r.end()
+                )),
+                None => report.error(format!(
+                    "{} of {} (0x{:08x}..0x{:08x}) is outside every memory region",
+                    what, name, start, end
+                )),
+            },
+        }
+    };
+
+    for section in image.sections.iter().filter(|s| s.alloc && s.size != 0) {
+        place("VMA", &section.name, section.addr, section.size, report);
+        if section.load && section.lma != section.addr {
+            place("LMA", &section.name, section.lma, section.size, report);
+        }
+    }
+
+    report.usage = memory
+        .regions
+        .iter()
+        .zip(used)
+        .map(|(r, used)| Usage {
+            region: r.name.clone(),
+            used,
+            length: r.length,
+        })
+        .collect();
+}
@@ -0,0 +1,269 @@
+//! Reading `m68k_rt::coredump` streams and writing ELF core files
+//!
+//! The stream is a series of frames, each starting with [`SYNC`] and ending with a CRC-32, so a
+//! capture may have other output around it and frames that were corrupted on the way are dropped
+//! on their own. The core file holds the memory as `PT_LOAD` segments and the registers in an
+//! `NT_PRSTATUS` note laid out like GNU/Linux m68k's, which is what GDB reads them from.
+
+use std::collections::BTreeMap;
+
+use crate::crc32::crc32;
+use crate::Error;
+
+/// Start of every frame, `m68k_rt::coredump::SYNC`
+pub const SYNC: [u8; 4] = *b"CORE";
+
+/// Version of the stream that is understood
+pub const VERSION: u8 = 1;
+
+const START: u8 = 1;
+const REGISTERS: u8 = 2;
+const MEMORY: u8 = 3;
+const END: u8 = 4;
+
+/// A core dump, as received
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoreDump {
+    /// The exception being handled
+    pub vector: Option<u8>,
+    /// Data registers
+    pub d: [u32; 8],
+    /// Address registers
+    pub a: [u32; 8],
+    /// Program counter
+    pub pc: u32,
+    /// Status register
+    pub sr: u16,
+    /// User stack pointer
+    pub usp: u32,
+    /// Memory contents by address, with adjacent frames joined
+    pub memory: Vec<(u32, Vec<u8>)>,
+    /// Frames dropped for a bad checksum or length
+    pub bad_frames: usize,
+    /// Frames the end of the stream counts that were not received; `None` if the end of the
+    /// stream was lost
+    pub missing_frames: Option<u32>,
+}
+
+impl CoreDump {
+    /// Reads the last core dump in a capture of the stream
+    pub fn parse(data: &[u8]) -> Result<CoreDump, Error> {
+        let mut dump = None;
+        let mut registers = false;
+        let mut memory = BTreeMap::new();
+        let mut received = 0;
+        let mut bad_frames = 0;
+
+        let mut i = 0;
+        while i + SYNC.len() <= data.len() {
+            if data[i..i + SYNC.len()] != SYNC {
+                i += 1;
+                continue;
+            }
+            let Some((kind, payload)) = frame(&data[i + SYNC.len()..]) else {
+                // Not a frame after all, or a corrupted one
+                if dump.is_some() {
+                    bad_frames += 1;
+                }
+                i += 1;
+                continue;
+            };
+            i += SYNC.len() + 1 + 2 + payload.len() + 4;
+
+            let word = |offset: usize| u16::from_be_bytes([payload[offset], payload[offset + 1]]);
+            let long =
+                |offset: usize| u32::from_be_bytes(payload[offset..offset + 4].try_into().unwrap());
+            match (kind, payload.len()) {
+                (START, 2) => {
+                    if payload[0] != VERSION {
+                        return Err(Error::Core(format!("unknown version {}", payload[0])));
+                    }
+                    // A later dump in the same capture replaces the earlier one
+                    dump = Some(CoreDump {
+                        vector: Some(payload[1]).filter(|&v| v != 0),
+                        ..CoreDump::default()
+                    });
+                    registers = false;
+                    memory.clear();
+                    received = 1;
+                    bad_frames = 0;
+                }
+                (REGISTERS, 74) => {
+                    if let Some(dump) = &mut dump {
+                        for (n, register) in dump.d.iter_mut().chain(&mut dump.a).enumerate() {
+                            *register = long(4 * n);
+                        }
+                        dump.pc = long(64);
+                        dump.sr = word(68);
+                        dump.usp = long(70);
+                        registers = true;
+                        received += 1;
+                    }
+                }
+                (MEMORY, 4..) => {
+                    if dump.is_some() {
+                        memory.insert(long(0), payload[4..].to_vec());
+                        received += 1;
+                    }
+                }
+                (END, 4) => {
+                    if let Some(dump) = &mut dump {
+                        dump.missing_frames = Some(long(0).saturating_sub(received));
+                    }
+                }
+                _ if dump.is_some() => bad_frames += 1,
+                _ => {}
+            }
+        }
+
+        let mut dump = dump.ok_or_else(|| Error::Core("no core dump found".into()))?;
+        if !registers {
+            return Err(Error::Core("the registers were lost".into()));
+        }
+        dump.bad_frames = bad_frames;
+
+        for (address, bytes) in memory {
+            match dump.memory.last_mut() {
+                Some((start, block)) if *start as usize + block.len() == address as usize => {
+                    block.extend(bytes)
+                }
+                _ => dump.memory.push((address, bytes)),
+            }
+        }
+        Ok(dump)
+    }
+
+    /// Returns the signal GNU/Linux would report for the exception
+    pub fn signal(&self) -> u32 {
+        match self.vector {
+            Some(2 | 3) => SIGBUS,
+            Some(4 | 8 | 10 | 11) => SIGILL,
+            Some(5..=7) => SIGFPE,
+            Some(9 | 32..=47) => SIGTRAP,
+            Some(_) => SIGSEGV,
+            None => SIGABRT,
+        }
+    }
+
+    /// Writes an ELF core file
+    pub fn to_elf(&self) -> Vec<u8> {
+        let note = self.prstatus_note();
+        let segments = 1 + self.memory.len();
+        let mut offset = EHSIZE + segments * PHENTSIZE;
+
+        let mut out = Vec::new();
+        // e_ident: 32-bit, big-endian, GNU/Linux
+        out.extend(b"\x7fELF\x01\x02\x01\x03");
+        out.resize(16, 0);
+        half(&mut out, ET_CORE);
+        half(&mut out, EM_68K);
+        word(&mut out, 1); // e_version
+        word(&mut out, 0); // e_entry
+        word(&mut out, EHSIZE as u32); // e_phoff
+        word(&mut out, 0); // e_shoff
+        word(&mut out, 0); // e_flags
+        half(&mut out, EHSIZE as u16);
+        half(&mut out, PHENTSIZE as u16);
+        half(&mut out, segments as u16);
+        half(&mut out, 40); // e_shentsize
+        half(&mut out, 0); // e_shnum
+        half(&mut out, 0); // e_shstrndx
+
+        let mut program_header = |p_type, address: u32, size: usize, flags, align| {
+            word(&mut out, p_type);
+            word(&mut out, offset as u32);
+            word(&mut out, address); // p_vaddr
+            word(&mut out, address); // p_paddr
+            word(&mut out, size as u32); // p_filesz
+            word(&mut out, size as u32); // p_memsz
+            word(&mut out, flags);
+            word(&mut out, align);
+            offset += size;
+        };
+        program_header(PT_NOTE, 0, note.len(), 0, 4);
+        for (address, bytes) in &self.memory {
+            program_header(PT_LOAD, *address, bytes.len(), PF_R | PF_W | PF_X, 1);
+        }
+
+        out.extend(note);
+        for (_, bytes) in &self.memory {
+            out.extend(bytes);
+        }
+        out
+    }
+
+    // `NT_PRSTATUS`, with the 2-byte alignment of the m68k ABI
+    fn prstatus_note(&self) -> Vec<u8> {
+        let signal = self.signal();
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        prstatus[0..4].copy_from_slice(&signal.to_be_bytes()); // si_signo
+        prstatus[12..14].copy_from_slice(&(signal as u16).to_be_bytes()); // pr_cursig
+        prstatus[22..26].copy_from_slice(&1u32.to_be_bytes()); // pr_pid
+
+        // d1-d7, a0-a6, d0, sp, orig_d0, sr, pc and the format and vector word
+        let mut registers = Vec::with_capacity(20);
+        registers.extend(&self.d[1..]);
+        registers.extend(&self.a[..7]);
+        registers.extend([
+            self.d[0],
+            self.a[7],
+            0xffff_ffff,
+            self.sr.into(),
+            self.pc,
+            // Format 0 and the vector offset, in the upper half of the last slot
+            (u32::from(self.vector.unwrap_or(0)) * 4) << 16,
+        ]);
+        for (n, register) in registers.iter().enumerate() {
+            let offset = PR_REG + 4 * n;
+            prstatus[offset..offset + 4].copy_from_slice(&register.to_be_bytes());
+        }
+
+        let mut note = Vec::new();
+        word(&mut note, 5); // namesz
+        word(&mut note, PRSTATUS_SIZE as u32); // descsz
+        word(&mut note, NT_PRSTATUS);
+        note.extend(b"CORE\0\0\0\0");
+        note.extend(prstatus);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note
+    }
+}
+
+// Returns the kind and payload of the frame after a `SYNC`, if it is intact
+fn frame(data: &[u8]) -> Option<(u8, &[u8])> {
+    let length = u16::from_be_bytes([*data.get(1)?, *data.get(2)?]) as usize;
+    let body = data.get(..3 + length)?;
+    let crc = data.get(3 + length..3 + length + 4)?;
+    (crc32(body).to_be_bytes() == crc).then_some((body[0], &body[3..]))
+}
+
+fn half(out: &mut Vec<u8>, value: u16) {
+    out.extend(value.to_be_bytes());
+}
+
+fn word(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_be_bytes());
+}
+
+const EHSIZE: usize = 52;
+const PHENTSIZE: usize = 32;
+const ET_CORE: u16 = 4;
+const EM_68K: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+/// Size of `struct elf_prstatus` on GNU/Linux m68k
+const PRSTATUS_SIZE: usize = 154;
+/// Offset of `pr_reg` in it
+const PR_REG: usize = 70;
+
+const SIGILL: u32 = 4;
+const SIGTRAP: u32 = 5;
+const SIGABRT: u32 = 6;
+const SIGBUS: u32 = 7;
+const SIGFPE: u32 = 8;
+const SIGSEGV: u32 = 11;
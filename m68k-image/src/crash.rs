@@ -0,0 +1,447 @@
+//! Reading and symbolizing crash dumps
+//!
+//! Dumps come from `m68k_rt::crash`, in its text or binary form, or from the panic handlers of
+//! `panic-m68k-serial` and `panic-m68k-semihosting`. The text is read from a console log, so
+//! other output around it is skipped: a `panicked at` line, `SR: 0x..` and `backtrace:` are picked
+//! up from panic reports, and register lines like the ones of the `m68k-rom` monitor are picked
+//! up wherever they appear.
+
+use std::fmt::{self, Write};
+
+use crate::frame::{vector_name, Frame};
+use crate::symbol::Symbolizer;
+use crate::Error;
+
+/// Start of a binary dump, `m68k_rt::crash::MAGIC`
+pub const MAGIC: [u8; 4] = *b"CRSH";
+
+/// Version of the binary form that is understood
+pub const VERSION: u8 = 1;
+
+/// A crash dump, with whatever the target put in it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dump {
+    /// The exception being handled
+    pub vector: Option<u8>,
+    /// The location and message of a panic
+    pub panic: Vec<String>,
+    /// Data registers
+    pub d: [Option<u32>; 8],
+    /// Address registers
+    pub a: [Option<u32>; 8],
+    /// User stack pointer
+    pub usp: Option<u32>,
+    /// Status register
+    pub sr: Option<u16>,
+    /// Program counter
+    pub pc: Option<u32>,
+    /// The exception frame, as pushed
+    pub frame: Vec<u8>,
+    /// Return addresses, innermost first
+    pub backtrace: Vec<u32>,
+    /// Address of the first byte of `stack`
+    pub stack_address: u32,
+    /// The top of the stack
+    pub stack: Vec<u8>,
+}
+
+impl Dump {
+    /// Parses a dump in either form
+    ///
+    /// A binary dump may be preceded by other data, such as console output before the crash.
+    pub fn parse(data: &[u8]) -> Result<Dump, Error> {
+        let binary = data
+            .windows(MAGIC.len() + 1)
+            .position(|w| w[..MAGIC.len()] == MAGIC && w[MAGIC.len()] == VERSION);
+        match binary {
+            Some(start) => Dump::parse_binary(&data[start..]),
+            None => Dump::parse_text(&String::from_utf8_lossy(data)),
+        }
+    }
+
+    /// Parses the binary form
+    pub fn parse_binary(data: &[u8]) -> Result<Dump, Error> {
+        let mut data = Reader(data);
+        if data.take(4)? != MAGIC {
+            return Err(Error::Crash("not a binary crash dump".into()));
+        }
+        let version = data.u8()?;
+        if version != VERSION {
+            return Err(Error::Crash(format!("unknown version {}", version)));
+        }
+
+        let mut dump = Dump {
+            vector: Some(data.u8()?).filter(|&v| v != 0),
+            ..Dump::default()
+        };
+        for register in dump.d.iter_mut().chain(&mut dump.a) {
+            *register = Some(data.u32()?);
+        }
+        dump.pc = Some(data.u32()?);
+        dump.sr = Some(data.u16()?);
+        dump.usp = Some(data.u32()?);
+
+        let length = data.u16()?;
+        dump.frame = data.take(length.into())?.to_vec();
+        for _ in 0..data.u16()? {
+            dump.backtrace.push(data.u32()?);
+        }
+        dump.stack_address = data.u32()?;
+        let length = data.u16()?;
+        dump.stack = data.take(length.into())?.to_vec();
+        Ok(dump)
+    }
+
+    /// Parses the text form, or a panic report, out of a log
+    pub fn parse_text(text: &str) -> Result<Dump, Error> {
+        #[derive(PartialEq)]
+        enum State {
+            Outside,
+            Panic,
+            Backtrace,
+            Stack,
+        }
+
+        let mut dump = Dump::default();
+        let mut state = State::Outside;
+        let mut found = false;
+        for (n, line) in text.lines().enumerate() {
+            let invalid = |what: &str| Error::Crash(format!("line {}: invalid {}", n + 1, what));
+            let line = line.trim();
+
+            if let Some(rest) = line.strip_prefix("crash dump") {
+                // A later dump in the same log replaces the earlier one
+                dump = Dump::default();
+                found = true;
+                state = State::Outside;
+                if let Some(vector) = rest.trim().strip_prefix(": vector ") {
+                    dump.vector = Some(vector.trim().parse().map_err(|_| invalid("vector"))?);
+                }
+                continue;
+            }
+            if line.starts_with("panicked") {
+                dump = Dump {
+                    panic: vec![line.to_string()],
+                    ..Dump::default()
+                };
+                found = true;
+                state = State::Panic;
+                continue;
+            }
+            if line == "end of crash dump" {
+                state = State::Outside;
+                continue;
+            }
+            if line == "backtrace:" {
+                state = State::Backtrace;
+                found = true;
+                continue;
+            }
+            if let Some(address) = line.strip_prefix("stack: ") {
+                dump.stack_address = hex(address).ok_or_else(|| invalid("stack address"))?;
+                dump.stack.clear();
+                state = State::Stack;
+                continue;
+            }
+            if let Some(words) = line.strip_prefix("frame:") {
+                let mut frame = Vec::new();
+                for word in words.split_whitespace() {
+                    let word = u16::from_str_radix(word, 16).map_err(|_| invalid("frame"))?;
+                    frame.extend(word.to_be_bytes());
+                }
+                dump.frame = frame;
+                continue;
+            }
+            if let Some(sr) = line.strip_prefix("SR: ") {
+                dump.sr = Some(
+                    hex(sr)
+                        .and_then(|sr| u16::try_from(sr).ok())
+                        .ok_or_else(|| invalid("SR"))?,
+                );
+                state = State::Outside;
+                continue;
+            }
+
+            match state {
+                State::Backtrace => {
+                    if let Some((index, address)) = line.split_once(':') {
+                        if index.trim().parse::<usize>().is_ok() {
+                            let address = hex(address).ok_or_else(|| invalid("return address"))?;
+                            dump.backtrace.push(address);
+                            continue;
+                        }
+                    }
+                    state = State::Outside;
+                }
+                State::Stack => {
+                    if let Some((address, bytes)) = line.split_once(':') {
+                        let next = dump.stack_address.wrapping_add(dump.stack.len() as u32);
+                        if hex(address) == Some(next) {
+                            for byte in bytes.split_whitespace() {
+                                let byte = u8::from_str_radix(byte, 16)
+                                    .map_err(|_| invalid("stack contents"))?;
+                                dump.stack.push(byte);
+                            }
+                            continue;
+                        }
+                    }
+                    state = State::Outside;
+                }
+                _ => {}
+            }
+
+            if registers(line, &mut dump) {
+                found = true;
+                state = State::Outside;
+            } else if state == State::Panic {
+                dump.panic.push(line.to_string());
+            }
+        }
+
+        if !found {
+            return Err(Error::Crash("no crash dump or panic report found".into()));
+        }
+        Ok(dump)
+    }
+
+    /// Decodes the exception frame, if the dump has one
+    pub fn exception_frame(&self) -> Option<Result<Frame, String>> {
+        (!self.frame.is_empty()).then(|| Frame::parse(&self.frame))
+    }
+}
+
+/// Picks up `D0 00000000  D1 ..` register values from a line, returning `false` if it has none
+///
+/// Only lines made of name and value pairs count, save for annotations in parentheses like the
+/// condition codes that follow the status register in the `m68k-rom` monitor.
+fn registers(line: &str, dump: &mut Dump) -> bool {
+    let mut values = Vec::new();
+    let mut tokens = line.split_whitespace();
+    while let Some(name) = tokens.next() {
+        if name.starts_with('(') {
+            // Skip to the end of the annotation
+            let mut token = name;
+            while !token.ends_with(')') {
+                match tokens.next() {
+                    Some(next) => token = next,
+                    None => return false,
+                }
+            }
+            continue;
+        }
+        let Some(value) = tokens.next().and_then(hex) else {
+            return false;
+        };
+        values.push((name, value));
+    }
+    if values.is_empty() {
+        return false;
+    }
+
+    let mut update = dump.clone();
+    for (name, value) in values {
+        let index = |name: &str| name.parse::<usize>().ok().filter(|&n| n < 8);
+        if let Some(n) = name.strip_prefix('D').and_then(index) {
+            update.d[n] = Some(value);
+        } else if let Some(n) = name.strip_prefix('A').and_then(index) {
+            update.a[n] = Some(value);
+        } else {
+            match name {
+                "PC" => update.pc = Some(value),
+                "SR" => update.sr = Some(value as u16),
+                "USP" => update.usp = Some(value),
+                "SP" | "SSP" => update.a[7] = Some(value),
+                _ => return false,
+            }
+        }
+    }
+    *dump = update;
+    true
+}
+
+fn hex(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(digits, 16).ok()
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(Error::Crash("binary crash dump cut short".into()));
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Writes a report of `dump`, with its addresses symbolized
+///
+/// Return addresses are looked up one byte back, in the call instruction, so that the reported
+/// line is the call rather than what follows it. Stack contents are shown where they point into
+/// code: without frame pointers, that is where the callers are.
+pub fn report(dump: &Dump, symbols: &Symbolizer) -> String {
+    let mut out = String::new();
+    write_report(&mut out, dump, symbols).unwrap();
+    out
+}
+
+fn write_report(out: &mut String, dump: &Dump, symbols: &Symbolizer) -> fmt::Result {
+    for line in &dump.panic {
+        writeln!(out, "{}", line)?;
+    }
+    let frame = dump.exception_frame();
+    let vector = dump
+        .vector
+        .or_else(|| frame.as_ref().and_then(|f| f.as_ref().ok()?.vector));
+    if let Some(vector) = vector {
+        writeln!(out, "{} (vector {})", vector_name(vector), vector)?;
+    }
+
+    match &frame {
+        Some(Ok(frame)) => {
+            writeln!(out, "{}", frame.kind())?;
+            if let Some(access) = &frame.access {
+                write!(out, "  {}", access)?;
+                location(out, symbols.symbol(access.address))?;
+                writeln!(out)?;
+            }
+            if let Some(ir) = frame.instruction_register {
+                writeln!(out, "  instruction register 0x{:04x}", ir)?;
+            }
+            if let Some(address) = frame.instruction {
+                writeln!(out, "  instruction at 0x{:08x}", address)?;
+                lines(out, symbols, address, "    ")?;
+            }
+            if let Some(address) = frame.effective_address {
+                write!(out, "  effective address 0x{:08x}", address)?;
+                location(out, symbols.symbol(address))?;
+                writeln!(out)?;
+            }
+        }
+        Some(Err(e)) => writeln!(out, "exception frame: {}", e)?,
+        None => {}
+    }
+
+    if let Some(pc) = dump.pc {
+        writeln!(out, "PC 0x{:08x}", pc)?;
+        lines(out, symbols, pc, "    ")?;
+    }
+    if let Some(sr) = dump.sr {
+        writeln!(out, "SR 0x{:04x}  {}", sr, status(sr))?;
+    }
+
+    let value = |v: Option<u32>| v.map_or("--------".into(), |v| format!("{:08x}", v));
+    for (name, registers) in [('D', &dump.d), ('A', &dump.a)] {
+        if registers.iter().any(Option::is_some) {
+            for (i, &register) in registers.iter().enumerate() {
+                let end = if i % 4 == 3 { "\n" } else { "  " };
+                write!(out, "{}{} {}{}", name, i, value(register), end)?;
+            }
+        }
+    }
+    if let Some(usp) = dump.usp {
+        writeln!(out, "USP {:08x}", usp)?;
+    }
+
+    if !dump.backtrace.is_empty() {
+        writeln!(out, "backtrace:")?;
+        for (i, &address) in dump.backtrace.iter().enumerate() {
+            writeln!(out, "{:>4}: 0x{:08x}", i, address)?;
+            // The program counter of an exception is where it happened, not a return address
+            let exception_pc = i == 0 && Some(address) == dump.pc && !dump.frame.is_empty();
+            let lookup = if exception_pc {
+                address
+            } else {
+                address.wrapping_sub(1)
+            };
+            lines(out, symbols, lookup, "      ")?;
+        }
+    }
+
+    if !dump.stack.is_empty() {
+        writeln!(
+            out,
+            "stack at 0x{:08x}, {} bytes, pointers into code:",
+            dump.stack_address,
+            dump.stack.len()
+        )?;
+        for offset in (0..dump.stack.len().saturating_sub(3)).step_by(2) {
+            let value = u32::from_be_bytes(dump.stack[offset..offset + 4].try_into().unwrap());
+            if !symbols.is_code(value) {
+                continue;
+            }
+            let address = dump.stack_address.wrapping_add(offset as u32);
+            write!(out, "  0x{:08x}: 0x{:08x}", address, value)?;
+            match symbols.locate(value.wrapping_sub(1)).first() {
+                Some(frame) => writeln!(out, "  {}", frame)?,
+                None => writeln!(out)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// Writes the frames at `address`, one per line, inlined functions first
+fn lines(out: &mut String, symbols: &Symbolizer, address: u32, indent: &str) -> fmt::Result {
+    let frames = symbols.locate(address);
+    if frames.is_empty() {
+        return writeln!(out, "{}??", indent);
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let inlined = if i + 1 < frames.len() {
+            " (inlined)"
+        } else {
+            ""
+        };
+        writeln!(out, "{}{}{}", indent, frame, inlined)?;
+    }
+    Ok(())
+}
+
+fn location(out: &mut String, symbol: Option<(&str, u32)>) -> fmt::Result {
+    match symbol {
+        Some((name, 0)) => write!(out, " ({})", name),
+        Some((name, offset)) => write!(out, " ({} + 0x{:x})", name, offset),
+        None => Ok(()),
+    }
+}
+
+// The mode, interrupt mask and condition codes of a status register
+fn status(sr: u16) -> String {
+    let mut s = String::new();
+    if sr & 0xc000 != 0 {
+        s.push_str("trace, ");
+    }
+    s.push_str(if sr & 0x2000 != 0 {
+        "supervisor"
+    } else {
+        "user"
+    });
+    if sr & 0x1000 != 0 {
+        s.push_str(", master");
+    }
+    write!(s, ", interrupt mask {}, ", (sr >> 8) & 7).unwrap();
+    for (bit, flag) in [(4, 'X'), (3, 'N'), (2, 'Z'), (1, 'V'), (0, 'C')] {
+        s.push(if sr & (1 << bit) != 0 { flag } else { '-' });
+    }
+    s
+}
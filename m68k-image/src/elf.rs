@@ -0,0 +1,163 @@
+//! Reading linked m68k-rt images
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use object::elf::{
+    FileHeader32, EM_68K, PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS, SHT_SYMTAB,
+};
+use object::read::elf::{FileHeader, ProgramHeader, SectionHeader, Sym};
+use object::Endianness;
+
+use crate::Error;
+
+/// A section of a linked image
+#[derive(Clone, Debug)]
+pub struct Section {
+    /// Section name, e.g. `.text`
+    pub name: String,
+    /// Address the section runs at (VMA)
+    pub addr: u32,
+    /// Address the section is stored at (LMA). Equal to `addr` unless the section is copied
+    /// somewhere else by the reset handler.
+    pub lma: u32,
+    /// Size in bytes
+    pub size: u32,
+    /// Occupies memory at run time
+    pub alloc: bool,
+    /// Contains code
+    pub exec: bool,
+    /// Writable at run time
+    pub write: bool,
+    /// Contents are stored in the image; `false` for `.bss` and other `NOLOAD` sections
+    pub load: bool,
+    /// Contents, empty unless `load` is set
+    pub data: Vec<u8>,
+    /// Offset of the contents in the ELF file
+    pub offset: u64,
+}
+
+impl Section {
+    /// Returns `true` if `addr` is within the run time address range of the section
+    pub fn contains(&self, addr: u32) -> bool {
+        addr >= self.addr && u64::from(addr) < u64::from(self.addr) + u64::from(self.size)
+    }
+}
+
+/// A linked m68k ELF executable
+#[derive(Clone, Debug, Default)]
+pub struct Image {
+    /// Entry point
+    pub entry: u32,
+    /// Sections, in file order
+    pub sections: Vec<Section>,
+    /// Symbol values, by name
+    pub symbols: BTreeMap<String, u32>,
+}
+
+impl Image {
+    /// Reads an image from a file
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| Error::Io(path.display().to_string(), e))?;
+        Self::parse(&data)
+    }
+
+    /// Parses an image from the contents of an ELF file
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let header = FileHeader32::<Endianness>::parse(data)?;
+        let endian = header.endian()?;
+        if header.e_machine(endian) != EM_68K {
+            return Err(Error::NotM68k);
+        }
+
+        let segments = header.program_headers(endian, data)?;
+        let table = header.sections(endian, data)?;
+
+        let mut sections = Vec::new();
+        for section in table.iter() {
+            let name = String::from_utf8_lossy(table.section_name(endian, section)?).into_owned();
+            if name.is_empty() {
+                continue;
+            }
+
+            let flags = section.sh_flags(endian);
+            let addr = section.sh_addr(endian);
+            let size = section.sh_size(endian);
+            let alloc = flags & SHF_ALLOC != 0;
+            let load = alloc && section.sh_type(endian) != SHT_NOBITS;
+
+            // The load address comes from the segment the section's contents are in
+            let offset = section.sh_offset(endian);
+            let lma = segments
+                .iter()
+                .filter(|p| load && p.p_type(endian) == PT_LOAD)
+                .find(|p| {
+                    offset >= p.p_offset(endian)
+                        && offset + size <= p.p_offset(endian) + p.p_filesz(endian)
+                })
+                .map_or(addr, |p| p.p_paddr(endian) + (offset - p.p_offset(endian)));
+
+            let data = if load {
+                section.data(endian, data)?.to_vec()
+            } else {
+                Vec::new()
+            };
+
+            sections.push(Section {
+                name,
+                addr,
+                lma,
+                size,
+                alloc,
+                exec: flags & SHF_EXECINSTR != 0,
+                write: flags & SHF_WRITE != 0,
+                load,
+                data,
+                offset: offset.into(),
+            });
+        }
+
+        let mut symbols = BTreeMap::new();
+        let table = table.symbols(endian, data, SHT_SYMTAB)?;
+        for symbol in table.iter() {
+            let name = symbol.name(endian, table.strings())?;
+            if !name.is_empty() {
+                symbols.insert(
+                    String::from_utf8_lossy(name).into_owned(),
+                    symbol.st_value(endian),
+                );
+            }
+        }
+
+        Ok(Image {
+            entry: header.e_entry(endian),
+            sections,
+            symbols,
+        })
+    }
+
+    /// Returns the section called `name`
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Returns the value of the symbol called `name`
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Returns the section `addr` points into, if any
+    pub fn section_at(&self, addr: u32) -> Option<&Section> {
+        self.sections.iter().find(|s| s.alloc && s.contains(addr))
+    }
+
+    /// Reads a big-endian long word from the stored contents at load address `lma`
+    pub fn read_u32(&self, lma: u32) -> Option<u32> {
+        self.sections.iter().filter(|s| s.load).find_map(|s| {
+            let offset = lma.checked_sub(s.lma)? as usize;
+            let bytes = s.data.get(offset..offset + 4)?;
+            Some(u32::from_be_bytes(bytes.try_into().unwrap()))
+        })
+    }
+}
@@ -0,0 +1,243 @@
+//! Exception frames, as the processor pushes them
+//!
+//! The 68000 pushes the status register and program counter, plus an access description, the
+//! address and the instruction register for bus and address errors. Later processors add a format
+//! and vector word after the program counter, whose format tells how much more follows. The
+//! frames are told apart by their length: the 68000's are 6 and 14 bytes, which no format has.
+
+use std::fmt;
+
+/// A decoded exception frame
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    /// Status register at the time of the exception
+    pub sr: u16,
+    /// Program counter pushed with it: the next instruction, or near the one that faulted
+    pub pc: u32,
+    /// Format of the frame, `None` for the 68000's
+    pub format: Option<u8>,
+    /// Vector number from the format word
+    pub vector: Option<u8>,
+    /// Address of the instruction that caused the exception, when `pc` is past it
+    pub instruction: Option<u32>,
+    /// Effective address of the instruction, for floating-point and access error frames
+    pub effective_address: Option<u32>,
+    /// The bus cycle that failed, for bus and address errors
+    pub access: Option<Access>,
+    /// The instruction register, in 68000 bus and address error frames
+    pub instruction_register: Option<u16>,
+}
+
+/// A failed bus cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    /// Address the cycle was for
+    pub address: u32,
+    /// A read rather than a write
+    pub read: bool,
+    /// An instruction fetch rather than a data access
+    pub fetch: bool,
+    /// Function code of the cycle, if the frame records it
+    pub function_code: Option<u8>,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} at 0x{:08x}",
+            if self.read { "read" } else { "write" },
+            if self.fetch { "instruction" } else { "data" },
+            self.address
+        )?;
+        if let Some(fc) = self.function_code {
+            write!(f, ", function code {}", fc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Length in bytes of the frames of each format, from the format word
+pub fn format_length(format: u8) -> Option<usize> {
+    Some(match format {
+        // Four-word frames: normal, and throwaway on the 68020 and later
+        0x0 | 0x1 => 8,
+        // Six-word frames: instruction address (68020+), or effective address (68040 floating
+        // point post-instruction)
+        0x2 | 0x3 => 12,
+        // 68040 floating-point unimplemented or disabled
+        0x4 => 16,
+        // 68040 access error
+        0x7 => 60,
+        // 68010 bus and address error
+        0x8 => 58,
+        // 68020/68030 coprocessor mid-instruction
+        0x9 => 20,
+        // 68020/68030 short and long bus cycle faults
+        0xa => 32,
+        0xb => 92,
+        _ => return None,
+    })
+}
+
+impl Frame {
+    /// Decodes the bytes of a frame
+    pub fn parse(bytes: &[u8]) -> Result<Frame, String> {
+        let word = |offset: usize| u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+        let long =
+            |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+        match bytes.len() {
+            6 => {
+                return Ok(Frame {
+                    sr: word(0),
+                    pc: long(2),
+                    ..Frame::default()
+                })
+            }
+            14 => {
+                let status = word(0);
+                return Ok(Frame {
+                    sr: word(8),
+                    pc: long(10),
+                    access: Some(Access {
+                        address: long(2),
+                        read: status & (1 << 4) != 0,
+                        fetch: status & (1 << 3) == 0,
+                        function_code: Some((status & 0b111) as u8),
+                    }),
+                    instruction_register: Some(word(6)),
+                    ..Frame::default()
+                });
+            }
+            len if len < 8 => return Err(format!("a {}-byte exception frame is too short", len)),
+            _ => {}
+        }
+
+        let format = bytes[6] >> 4;
+        let length = format_length(format)
+            .ok_or_else(|| format!("unknown exception frame format {:x}", format))?;
+        if bytes.len() != length {
+            return Err(format!(
+                "a format {:x} exception frame is {} bytes, not {}",
+                format,
+                length,
+                bytes.len()
+            ));
+        }
+
+        let mut frame = Frame {
+            sr: word(0),
+            pc: long(2),
+            format: Some(format),
+            vector: Some(((word(6) & 0x0fff) / 4) as u8),
+            ..Frame::default()
+        };
+        match format {
+            0x2 | 0x9 => frame.instruction = Some(long(8)),
+            0x3 => frame.effective_address = Some(long(8)),
+            0x4 => {
+                frame.effective_address = Some(long(8));
+                frame.instruction = Some(long(12));
+            }
+            0x7 => {
+                let ssw = word(12);
+                // The transfer modifier is the function code for normal accesses
+                let tm = (ssw & 0b111) as u8;
+                frame.effective_address = Some(long(8));
+                frame.access = Some(Access {
+                    address: long(20),
+                    read: ssw & (1 << 8) != 0,
+                    fetch: tm & 0b11 == 0b10,
+                    function_code: Some(tm),
+                });
+            }
+            0x8 => {
+                let ssw = word(8);
+                frame.access = Some(Access {
+                    address: long(10),
+                    read: ssw & (1 << 8) != 0,
+                    fetch: ssw & (1 << 13) != 0,
+                    function_code: Some((ssw & 0b111) as u8),
+                });
+            }
+            0xa | 0xb => {
+                let ssw = word(10);
+                let function_code = Some((ssw & 0b111) as u8);
+                // A data fault, or a fault on stage C or B of the instruction pipe
+                let (c, b, data) = (ssw & (1 << 15) != 0, ssw & (1 << 14) != 0, ssw & (1 << 8));
+                frame.access = if data != 0 {
+                    Some(Access {
+                        address: long(16),
+                        read: ssw & (1 << 6) != 0,
+                        fetch: false,
+                        function_code,
+                    })
+                } else if c || b {
+                    // Stage B is the word after stage C. Short frames don't record its address,
+                    // which is then 4 past the program counter.
+                    let stage_b = if format == 0xb {
+                        long(36)
+                    } else {
+                        frame.pc.wrapping_add(4)
+                    };
+                    Some(Access {
+                        address: if c { stage_b.wrapping_sub(2) } else { stage_b },
+                        read: true,
+                        fetch: true,
+                        function_code,
+                    })
+                } else {
+                    None
+                };
+            }
+            _ => {}
+        }
+        Ok(frame)
+    }
+
+    /// A description of the frame's kind
+    pub fn kind(&self) -> &'static str {
+        match self.format {
+            None if self.access.is_some() => "68000 bus or address error frame",
+            None => "68000 frame",
+            Some(0x0) => "format 0 frame",
+            Some(0x1) => "format 1 (throwaway) frame",
+            Some(0x2) => "format 2 frame",
+            Some(0x3) => "format 3 (68040 floating-point post-instruction) frame",
+            Some(0x4) => "format 4 (68040 floating-point) frame",
+            Some(0x7) => "format 7 (68040 access error) frame",
+            Some(0x8) => "format 8 (68010 bus or address error) frame",
+            Some(0x9) => "format 9 (coprocessor mid-instruction) frame",
+            Some(0xa) => "format A (short bus cycle fault) frame",
+            Some(0xb) => "format B (long bus cycle fault) frame",
+            Some(_) => "frame",
+        }
+    }
+}
+
+/// Returns the name of an exception vector
+pub fn vector_name(vector: u8) -> String {
+    match vector {
+        2 => "bus error".into(),
+        3 => "address error".into(),
+        4 => "illegal instruction".into(),
+        5 => "divide by zero".into(),
+        6 => "CHK instruction".into(),
+        7 => "TRAPV instruction".into(),
+        8 => "privilege violation".into(),
+        9 => "trace".into(),
+        10 => "line 1010 emulator".into(),
+        11 => "line 1111 emulator".into(),
+        13 => "coprocessor protocol violation".into(),
+        14 => "format error".into(),
+        15 => "uninitialized interrupt".into(),
+        24 => "spurious interrupt".into(),
+        25..=31 => format!("level {} interrupt", vector - 24),
+        32..=47 => format!("TRAP #{}", vector - 32),
+        48..=54 => "floating-point exception".into(),
+        56..=58 => "MMU exception".into(),
+        64.. => format!("user interrupt {}", vector),
+        _ => format!("exception {}", vector),
+    }
+}
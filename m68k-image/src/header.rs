@@ -0,0 +1,162 @@
+//! The image header
+//!
+//! `m68k-rt` reserves a header in the `.image_header` section, right after the vector table (or
+//! after the reset handler of a `ram-app` image). It is all zeros when linked. [`stamp`] fills it
+//! in place in the ELF file with the build information and the length and CRC-32 of the image, so
+//! `m68k_rt::image::info` can report them and the `verify-image` feature can check the ROM at
+//! boot.
+//!
+//! The layout, all big-endian:
+//!
+//! | Offset | Size | Field                                      |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | magic, `"IMAG"`                            |
+//! | 4      | 4    | length of the image in bytes               |
+//! | 8      | 4    | CRC-32 of the image, skipping this field   |
+//! | 12     | 4    | build time, seconds since the Unix epoch   |
+//! | 16     | 20   | SHA-1 of the git commit                    |
+//! | 36     | 32   | version, NUL padded                        |
+//!
+//! The image starts at `__image_start` and ends with the last section stored after it. Gaps
+//! between sections are counted as `fill`, which must match what the ROM is programmed with.
+
+use std::fmt;
+
+use crate::crc32::crc32;
+use crate::elf::Image;
+use crate::rom::Rom;
+use crate::Error;
+
+/// Name of the section holding the header
+pub const SECTION: &str = ".image_header";
+/// Value of the magic field of a stamped header
+pub const MAGIC: u32 = 0x494D_4147;
+/// Size of the header in bytes
+pub const SIZE: usize = 68;
+
+const CRC: usize = 8;
+const VERSION_LEN: usize = 32;
+
+/// What the image was built from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Version string, at most 32 bytes
+    pub version: String,
+    /// SHA-1 of the git commit, or zeros if unknown
+    pub git_hash: [u8; 20],
+    /// Build time, in seconds since the Unix epoch
+    pub timestamp: u32,
+}
+
+/// A stamped header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub build: BuildInfo,
+    /// Length of the image in bytes
+    pub length: u32,
+    /// CRC-32 of the image
+    pub crc: u32,
+}
+
+impl Header {
+    /// Decodes a header, or returns `None` if it was never stamped
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..SIZE)?;
+        let long =
+            |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        if long(0) != MAGIC {
+            return None;
+        }
+        let version = &bytes[36..36 + VERSION_LEN];
+        let len = version.iter().position(|&b| b == 0).unwrap_or(VERSION_LEN);
+        Some(Header {
+            build: BuildInfo {
+                version: String::from_utf8_lossy(&version[..len]).into_owned(),
+                git_hash: bytes[16..36].try_into().unwrap(),
+                timestamp: long(12),
+            },
+            length: long(4),
+            crc: long(CRC),
+        })
+    }
+
+    /// Encodes the header
+    pub fn to_bytes(&self) -> [u8; SIZE] {
+        let mut bytes = [0; SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.length.to_be_bytes());
+        bytes[CRC..CRC + 4].copy_from_slice(&self.crc.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.build.timestamp.to_be_bytes());
+        bytes[16..36].copy_from_slice(&self.build.git_hash);
+        let version = self.build.version.as_bytes();
+        bytes[36..36 + version.len().min(VERSION_LEN)].copy_from_slice(version);
+        bytes
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "version {:?}, commit ", self.build.version)?;
+        for b in &self.build.git_hash {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(
+            f,
+            ", built at {}, {} bytes, CRC-32 0x{:08x}",
+            self.build.timestamp, self.length, self.crc
+        )
+    }
+}
+
+/// Returns the header of `image`, or `None` if it has none or it was never stamped
+pub fn read(image: &Image) -> Option<Header> {
+    Header::parse(&image.section(SECTION)?.data)
+}
+
+/// Fills in the header of the ELF file `elf` and returns it
+///
+/// Stamping an image again replaces the previous header.
+pub fn stamp(elf: &mut [u8], build: BuildInfo, fill: u8) -> Result<Header, Error> {
+    if build.version.len() > VERSION_LEN {
+        return Err(Error::Header(format!(
+            "the version `{}` is longer than {} bytes",
+            build.version, VERSION_LEN
+        )));
+    }
+
+    let image = Image::parse(elf)?;
+    let start = image
+        .symbol("__image_start")
+        .ok_or_else(|| Error::Header("no __image_start symbol".into()))?;
+    let section = image
+        .section(SECTION)
+        .ok_or_else(|| Error::Header(format!("no {} section", SECTION)))?;
+    if section.size as usize != SIZE || section.data.len() != SIZE {
+        return Err(Error::Header(format!(
+            "{} is {} bytes instead of {}",
+            SECTION, section.size, SIZE
+        )));
+    }
+    let offset = section
+        .lma
+        .checked_sub(start)
+        .ok_or_else(|| Error::Header(format!("{} is before __image_start", SECTION)))?
+        as usize;
+
+    let mut rom = Rom::from_image(&image, Some(start), None, fill)?;
+    let mut header = Header {
+        build,
+        length: u32::try_from(rom.data.len())
+            .map_err(|_| Error::Header("the image is larger than 4 GiB".into()))?,
+        crc: 0,
+    };
+
+    // The header is part of the image, except for the CRC field
+    rom.data[offset..offset + SIZE].copy_from_slice(&header.to_bytes());
+    rom.data.drain(offset + CRC..offset + CRC + 4);
+    header.crc = crc32(&rom.data);
+
+    let file_offset = section.offset as usize;
+    elf[file_offset..file_offset + SIZE].copy_from_slice(&header.to_bytes());
+    Ok(header)
+}
@@ -0,0 +1,84 @@
+//! Host tools for linked `m68k-rt` images
+//!
+//! This crate reads the ELF files produced by linking against `m68k-rt`, checks them, stamps them
+//! with build information and turns them into ROM images. It also symbolizes the crash dumps of the
+//! programs they hold, and turns their core dumps into ELF core files. The `cargo-m68k` binary
+//! exposes it as a cargo subcommand:
+//!
+//! ``` text
+//! $ cargo m68k check target/m68k-unknown-none/release/examples/minimal
+//! $ cargo m68k stamp target/m68k-unknown-none/release/examples/minimal --version 1.0.0
+//! $ cargo m68k rom target/m68k-unknown-none/release/examples/minimal -o minimal.s37 --format srec
+//! $ cargo m68k crash target/m68k-unknown-none/release/examples/minimal console.log
+//! $ cargo m68k core capture.bin -o core
+//! ```
+
+use std::fmt;
+use std::io;
+
+pub mod check;
+pub mod coredump;
+pub mod crash;
+pub mod crc32;
+pub mod elf;
+pub mod frame;
+pub mod header;
+pub mod memory;
+pub mod rom;
+pub mod symbol;
+
+pub use elf::{Image, Section};
+pub use memory::{MemoryMap, Region};
+
+/// Errors returned by the tools
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read or written
+    Io(String, io::Error),
+    /// The ELF file is malformed
+    Elf(object::Error),
+    /// The ELF file is not for the m68k
+    NotM68k,
+    /// `memory.x` couldn't be parsed
+    Memory(String),
+    /// The image doesn't fit the requested ROM layout
+    Rom(String),
+    /// The image header couldn't be filled in
+    Header(String),
+    /// The debug information is malformed
+    Dwarf(gimli::Error),
+    /// A crash dump couldn't be read
+    Crash(String),
+    /// A core dump couldn't be read
+    Core(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {}", path, e),
+            Error::Elf(e) => write!(f, "invalid ELF file: {}", e),
+            Error::NotM68k => f.write_str("not an m68k ELF file"),
+            Error::Memory(e) => write!(f, "invalid memory.x: {}", e),
+            Error::Rom(e) => f.write_str(e),
+            Error::Header(e) => write!(f, "can't stamp the image: {}", e),
+            Error::Dwarf(e) => write!(f, "invalid debug information: {}", e),
+            Error::Crash(e) => write!(f, "invalid crash dump: {}", e),
+            Error::Core(e) => write!(f, "invalid core dump: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<object::Error> for Error {
+    fn from(e: object::Error) -> Self {
+        Error::Elf(e)
+    }
+}
+
+impl From<gimli::Error> for Error {
+    fn from(e: gimli::Error) -> Self {
+        Error::Dwarf(e)
+    }
+}
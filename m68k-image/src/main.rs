@@ -0,0 +1,412 @@
+//! `cargo m68k`: checks linked `m68k-rt` images, stamps them, turns them into ROM images,
+//! symbolizes crash dumps and writes core files
+
+use std::env;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::time::SystemTime;
+
+use m68k_image::coredump::CoreDump;
+use m68k_image::crash::{self, Dump};
+use m68k_image::header::{self, BuildInfo};
+use m68k_image::memory::number;
+use m68k_image::rom::{Rom, SRecord};
+use m68k_image::symbol::Symbolizer;
+use m68k_image::{check, Image, MemoryMap};
+
+const USAGE: &str = "\
+Usage: cargo m68k <command> [options]
+
+Commands:
+    check <elf> [--memory <memory.x>]
+        Check the vector table, section placement and memory usage of a linked image.
+        Without --memory, the ROM and RAM regions recorded in the image are used.
+
+    stamp <elf> [options]
+        Fill in the image header with build information and the CRC-32 of the image.
+
+        -o <output>               Write the stamped image here instead of replacing <elf>.
+        --version <string>        Version, at most 32 bytes. Defaults to the output of
+                                  `git describe --tags --always --dirty`.
+        --git-hash <hex>          Commit the image was built from. Defaults to the output of
+                                  `git rev-parse HEAD`, or zeros outside a git repository.
+        --timestamp <seconds>     Build time. Defaults to $SOURCE_DATE_EPOCH, or the current
+                                  time.
+        --fill <byte>             Value the gaps in the ROM are programmed with. Defaults to
+                                  0xff.
+
+    rom <elf> -o <output> [options]
+        Write the ROM contents of a linked image.
+
+        --format <bin|srec|ihex>  Output format. By default it is guessed from the extension
+                                  of <output>: .s19, .s28, .s37, .srec and .mot are S-records,
+                                  .hex and .ihex Intel HEX, anything else raw binary.
+        --srec <s19|s28|s37>      S-record address size. By default the smallest that fits.
+        --memory <memory.x>       Take the ROM region from memory.x instead of the image.
+        --base <addr>             Address of the first byte. Defaults to the ROM origin.
+        --size <bytes>            Pad or limit the image to this size. Raw binaries default
+                                  to the ROM length, the other formats to the end of the data.
+        --fill <byte>             Value for gaps and padding. Defaults to 0xff.
+        --split <2|4>             Write one image per byte lane, for 8-bit EPROMs on a 16- or
+                                  32-bit bus. 2-way lanes are named .even and .odd, 4-way
+                                  lanes .0 to .3, inserted before the extension.
+
+    crash <elf> [<dump>]
+        Symbolize a crash dump of the program in <elf>: a console log with the text dump of
+        m68k_rt::crash or a panic report, or the binary dump. Reads standard input without
+        <dump> or if it is -.
+
+    core <capture> -o <core>
+        Turn a capture of the serial stream of m68k_rt::coredump into an ELF core file, for
+        post-mortem debugging with `m68k-elf-gdb <elf> <core>`.
+";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // Invoked as `cargo m68k ..`
+    if args.first().map(String::as_str) == Some("m68k") {
+        args.remove(0);
+    }
+
+    let result = match args.first().map(String::as_str) {
+        Some("check") => run_check(&args[1..]),
+        Some("stamp") => run_stamp(&args[1..]),
+        Some("rom") => run_rom(&args[1..]),
+        Some("crash") => run_crash(&args[1..]),
+        Some("core") => run_core(&args[1..]),
+        Some("-h" | "--help") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_check(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut elf = None;
+    let mut memory = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--memory" => memory = Some(args.next().ok_or("--memory needs a path")?),
+            _ if elf.is_none() => elf = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg).into()),
+        }
+    }
+    let elf = elf.ok_or("no ELF file given")?;
+
+    let image = Image::read(elf)?;
+    let memory = match memory {
+        Some(path) => MemoryMap::read(path)?,
+        None => MemoryMap::from_image(&image),
+    };
+
+    let report = check::check(&image, &memory);
+    for diagnostic in &report.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    for usage in &report.usage {
+        println!("{}", usage);
+    }
+    Ok(report.is_ok())
+}
+
+fn run_stamp(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut elf = None;
+    let mut output = None;
+    let mut version = None;
+    let mut git_hash = None;
+    let mut timestamp = None;
+    let mut fill = 0xff;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--version" => version = Some(value()?.to_string()),
+            "--git-hash" => git_hash = Some(parse_hash(value()?)?),
+            "--timestamp" => timestamp = Some(parse_u32(value()?)?),
+            "--fill" => {
+                fill = u8::try_from(parse_u32(value()?)?).map_err(|_| "--fill must be a byte")?
+            }
+            _ if elf.is_none() => elf = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument `{}`", arg).into()),
+        }
+    }
+    let elf = elf.ok_or("no ELF file given")?;
+    let output = output.unwrap_or_else(|| elf.clone());
+
+    let build = BuildInfo {
+        version: match version {
+            Some(version) => version,
+            None => git(&["describe", "--tags", "--always", "--dirty"]).unwrap_or_default(),
+        },
+        git_hash: match git_hash {
+            Some(hash) => hash,
+            None => git(&["rev-parse", "HEAD"])
+                .and_then(|hash| parse_hash(&hash).ok())
+                .unwrap_or_default(),
+        },
+        timestamp: match timestamp {
+            Some(timestamp) => timestamp,
+            None => match env::var("SOURCE_DATE_EPOCH") {
+                Ok(epoch) => parse_u32(&epoch)?,
+                Err(_) => SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)?
+                    .as_secs() as u32,
+            },
+        },
+    };
+
+    let mut data = std::fs::read(&elf).map_err(|e| format!("{}: {}", elf.display(), e))?;
+    let header = header::stamp(&mut data, build, fill)?;
+    std::fs::write(&output, data).map_err(|e| format!("{}: {}", output.display(), e))?;
+    println!("{}: {}", output.display(), header);
+    Ok(true)
+}
+
+// Runs git and returns the first line of its output, if it succeeded
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.lines().next()?.trim().to_string())
+}
+
+fn parse_hash(s: &str) -> Result<[u8; 20], String> {
+    let invalid = || format!("invalid git hash `{}`", s);
+    if s.len() != 40 || !s.is_ascii() {
+        return Err(invalid());
+    }
+    let mut hash = [0; 20];
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(hash)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Bin,
+    SRecord(SRecord),
+    IntelHex,
+}
+
+fn run_rom(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut elf = None;
+    let mut output = None;
+    let mut format = None;
+    let mut srec = SRecord::Auto;
+    let mut memory = None;
+    let mut base = None;
+    let mut size = None;
+    let mut fill = 0xff;
+    let mut ways = 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+            "--format" => {
+                format = Some(match value()? {
+                    "bin" => Format::Bin,
+                    "srec" => Format::SRecord(SRecord::Auto),
+                    "ihex" => Format::IntelHex,
+                    f => return Err(format!("unknown format `{}`", f).into()),
+                })
+            }
+            "--srec" => {
+                srec = match value()? {
+                    "s19" => SRecord::S19,
+                    "s28" => SRecord::S28,
+                    "s37" => SRecord::S37,
+                    s => return Err(format!("unknown S-record type `{}`", s).into()),
+                }
+            }
+            "--memory" => memory = Some(value()?),
+            "--base" => base = Some(parse_u32(value()?)?),
+            "--size" => size = Some(parse_u32(value()?)?),
+            "--fill" => {
+                fill = u8::try_from(parse_u32(value()?)?).map_err(|_| "--fill must be a byte")?
+            }
+            "--split" => {
+                ways = match value()? {
+                    "2" => 2,
+                    "4" => 4,
+                    w => return Err(format!("can't split {} ways", w).into()),
+                }
+            }
+            _ if elf.is_none() => elf = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg).into()),
+        }
+    }
+    let elf = elf.ok_or("no ELF file given")?;
+    let output = output.ok_or("no output file given")?;
+
+    let format = match format {
+        Some(Format::SRecord(_)) => Format::SRecord(srec),
+        Some(format) => format,
+        None => guess_format(&output, srec),
+    };
+
+    let image = Image::read(elf)?;
+    let memory = match memory {
+        Some(path) => MemoryMap::read(path)?,
+        None => MemoryMap::from_image(&image),
+    };
+    let region = memory.region("ROM");
+    let base = base.or(region.map(|r| r.origin));
+    let size = size.or(match format {
+        Format::Bin => region.map(|r| r.length),
+        _ => None,
+    });
+
+    let rom = Rom::from_image(&image, base, size, fill)?;
+    let header = output.file_name().unwrap_or_default().to_string_lossy();
+    let roms = if ways == 1 {
+        vec![(output.clone(), rom)]
+    } else {
+        rom.split(ways)
+            .into_iter()
+            .enumerate()
+            .map(|(lane, rom)| (lane_path(&output, ways, lane), rom))
+            .collect()
+    };
+
+    for (path, rom) in &roms {
+        let contents = match format {
+            Format::Bin => rom.data.clone(),
+            Format::SRecord(kind) => rom.to_srec(kind, &header, 32)?.into_bytes(),
+            Format::IntelHex => rom.to_ihex().into_bytes(),
+        };
+        std::fs::write(path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+        println!(
+            "{}: {} bytes at 0x{:08x}, sum 0x{:04x}, CRC-32 0x{:08x}",
+            path.display(),
+            rom.data.len(),
+            rom.base,
+            rom.sum16(),
+            rom.crc32()
+        );
+    }
+    Ok(true)
+}
+
+fn run_crash(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let (elf, dump) = match args {
+        [elf] => (elf, None),
+        [elf, dump] if dump == "-" => (elf, None),
+        [elf, dump] => (elf, Some(dump)),
+        [] => return Err("no ELF file given".into()),
+        [_, _, arg, ..] => return Err(format!("unexpected argument `{}`", arg).into()),
+    };
+
+    let symbols = Symbolizer::read(elf)?;
+    let data = match dump {
+        Some(path) => std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            let mut data = Vec::new();
+            std::io::stdin().read_to_end(&mut data)?;
+            data
+        }
+    };
+    let dump = Dump::parse(&data)?;
+    print!("{}", crash::report(&dump, &symbols));
+    Ok(true)
+}
+
+fn run_core(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut capture = None;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().ok_or("-o needs a path")?),
+            _ if capture.is_none() => capture = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg).into()),
+        }
+    }
+    let capture = capture.ok_or("no capture given")?;
+    let output = output.ok_or("no output file given")?;
+
+    let data = std::fs::read(capture).map_err(|e| format!("{}: {}", capture, e))?;
+    let dump = CoreDump::parse(&data)?;
+    if dump.bad_frames != 0 {
+        eprintln!("warning: {} corrupted frames were dropped", dump.bad_frames);
+    }
+    match dump.missing_frames {
+        Some(0) => {}
+        Some(n) => eprintln!("warning: {} frames are missing", n),
+        None => eprintln!("warning: the end of the core dump is missing"),
+    }
+
+    std::fs::write(output, dump.to_elf()).map_err(|e| format!("{}: {}", output, e))?;
+    let bytes: usize = dump.memory.iter().map(|(_, bytes)| bytes.len()).sum();
+    println!(
+        "{}: PC 0x{:08x}, {} bytes of memory in {} blocks",
+        output,
+        dump.pc,
+        bytes,
+        dump.memory.len()
+    );
+    Ok(true)
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    number(s)
+        .ok()
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| format!("invalid number `{}`", s))
+}
+
+fn guess_format(path: &Path, srec: SRecord) -> Format {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("s19") if srec == SRecord::Auto => Format::SRecord(SRecord::S19),
+        Some("s28") if srec == SRecord::Auto => Format::SRecord(SRecord::S28),
+        Some("s37") if srec == SRecord::Auto => Format::SRecord(SRecord::S37),
+        Some("s19" | "s28" | "s37" | "srec" | "mot") => Format::SRecord(srec),
+        Some("hex" | "ihex") => Format::IntelHex,
+        _ => Format::Bin,
+    }
+}
+
+// `rom.bin` becomes `rom.even.bin` and `rom.odd.bin`, or `rom.0.bin` to `rom.3.bin`
+fn lane_path(path: &Path, ways: usize, lane: usize) -> PathBuf {
+    let name = match (ways, lane) {
+        (2, 0) => "even".to_string(),
+        (2, _) => "odd".to_string(),
+        _ => lane.to_string(),
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, name, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
+    };
+    path.with_file_name(file)
+}
@@ -0,0 +1,176 @@
+//! Memory regions, from `memory.x` or from the image itself
+
+use std::path::Path;
+
+use crate::elf::Image;
+use crate::Error;
+
+/// A memory region
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Region name, e.g. `ROM`
+    pub name: String,
+    /// Start address
+    pub origin: u32,
+    /// Length in bytes
+    pub length: u32,
+}
+
+impl Region {
+    /// Returns the address one past the end of the region
+    pub fn end(&self) -> u64 {
+        u64::from(self.origin) + u64::from(self.length)
+    }
+
+    /// Returns `true` if `start..end` lies within the region
+    pub fn contains_range(&self, start: u64, end: u64) -> bool {
+        start >= u64::from(self.origin) && end <= self.end()
+    }
+}
+
+/// The memory regions of a device
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub regions: Vec<Region>,
+}
+
+impl MemoryMap {
+    /// Reads the `MEMORY` command of a linker script
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let script =
+            std::fs::read_to_string(path).map_err(|e| Error::Io(path.display().to_string(), e))?;
+        Self::parse(&script)
+    }
+
+    /// Parses the `MEMORY` command of a linker script
+    ///
+    /// Only what `memory.x` files usually contain is understood: decimal, octal and hexadecimal
+    /// numbers with an optional `K` or `M` suffix, added together with `+`.
+    pub fn parse(script: &str) -> Result<Self, Error> {
+        let script = strip_comments(script);
+        let start = script
+            .find("MEMORY")
+            .ok_or_else(|| Error::Memory("no MEMORY command".into()))?;
+        let body = &script[start + "MEMORY".len()..];
+        let open = body
+            .find('{')
+            .ok_or_else(|| Error::Memory("expected `{` after MEMORY".into()))?;
+        let close = body
+            .find('}')
+            .ok_or_else(|| Error::Memory("unterminated MEMORY command".into()))?;
+
+        let mut regions = Vec::new();
+        let mut rest = body[open + 1..close].trim();
+        while !rest.is_empty() {
+            // NAME [(ATTR)] : ORIGIN = expr, LENGTH = expr
+            let colon = rest
+                .find(':')
+                .ok_or_else(|| Error::Memory(format!("expected `:` in `{}`", rest)))?;
+            let name = rest[..colon].split('(').next().unwrap().trim().to_string();
+            rest = &rest[colon + 1..];
+
+            let (origin, r) = assignment(rest, &["ORIGIN", "org", "o"])?;
+            let r = r.trim_start().strip_prefix(',').ok_or_else(|| {
+                Error::Memory(format!("expected `,` after the origin of {}", name))
+            })?;
+            let (length, r) = assignment(r, &["LENGTH", "len", "l"])?;
+            rest = r.trim_start();
+
+            regions.push(Region {
+                name,
+                origin: origin as u32,
+                length: length as u32,
+            });
+        }
+
+        Ok(MemoryMap { regions })
+    }
+
+    /// Builds the map from the `__rom_start`, `__rom_end`, `__ram_start` and `__ram_end` symbols
+    /// that the `m68k-rt` linker scripts define
+    pub fn from_image(image: &Image) -> Self {
+        let mut regions = Vec::new();
+        for name in ["ROM", "RAM"] {
+            let lower = name.to_lowercase();
+            let start = image.symbol(&format!("__{}_start", lower));
+            let end = image.symbol(&format!("__{}_end", lower));
+            if let (Some(start), Some(end)) = (start, end) {
+                regions.push(Region {
+                    name: name.into(),
+                    origin: start,
+                    length: end.wrapping_sub(start),
+                });
+            }
+        }
+        MemoryMap { regions }
+    }
+
+    /// Returns the region called `name`
+    pub fn region(&self, name: &str) -> Option<&Region> {
+        self.regions.iter().find(|r| r.name == name)
+    }
+
+    /// Returns the region that contains `start..end`
+    pub fn region_containing(&self, start: u64, end: u64) -> Option<&Region> {
+        self.regions.iter().find(|r| r.contains_range(start, end))
+    }
+}
+
+fn strip_comments(script: &str) -> String {
+    let mut out = String::with_capacity(script.len());
+    let mut rest = script;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find("*/") {
+            Some(end) => &rest[start + end + 2..],
+            None => "",
+        };
+        out.push(' ');
+    }
+    out.push_str(rest);
+    out
+}
+
+// Parses `KEYWORD = expr` and returns the value and what follows the expression
+fn assignment<'a>(s: &'a str, keywords: &[&str]) -> Result<(u64, &'a str), Error> {
+    let s = s.trim_start();
+    let keyword = keywords
+        .iter()
+        .find(|k| {
+            s.strip_prefix(**k)
+                .is_some_and(|r| r.trim_start().starts_with('='))
+        })
+        .ok_or_else(|| Error::Memory(format!("expected {} in `{}`", keywords[0], s)))?;
+    let s = s[keyword.len()..].trim_start()[1..].trim_start();
+
+    let end = s.find([',', '\n', '}']).unwrap_or(s.len());
+    let mut value = 0u64;
+    for term in s[..end].split('+') {
+        value += number(term.trim())?;
+    }
+    Ok((value, &s[end..]))
+}
+
+/// Parses a number the way `memory.x` writes them: decimal, octal (`0` prefix) or hexadecimal
+/// (`0x` prefix), optionally followed by `K` or `M`
+pub fn number(s: &str) -> Result<u64, Error> {
+    let (digits, scale) = match s.as_bytes().last() {
+        Some(b'K' | b'k') => (&s[..s.len() - 1], 1024),
+        Some(b'M' | b'm') => (&s[..s.len() - 1], 1024 * 1024),
+        _ => (s, 1),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    value
+        .map(|v| v * scale)
+        .map_err(|_| Error::Memory(format!("can't parse `{}`", s)))
+}
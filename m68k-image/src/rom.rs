@@ -0,0 +1,225 @@
+//! ROM images
+//!
+//! [`Rom::from_image`] lays out everything that has to be stored in ROM (the load image of every
+//! section, i.e. at its LMA) in one contiguous block, filling the gaps. The block can then be
+//! written as a raw binary, Motorola S-records or Intel HEX, and split into byte lanes for boards
+//! that build a 16- or 32-bit bus out of 8-bit EPROMs.
+
+use std::fmt::Write;
+
+use crate::crc32::crc32;
+use crate::elf::Image;
+use crate::Error;
+
+/// A contiguous ROM image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rom {
+    /// Address of the first byte
+    pub base: u32,
+    /// Contents
+    pub data: Vec<u8>,
+    /// Entry point, for the formats that record one
+    pub entry: u32,
+}
+
+impl Rom {
+    /// Lays out the load image of `image`
+    ///
+    /// The ROM starts at `base`, or at the lowest load address if `None`. It is `size` bytes long,
+    /// or just long enough for the image if `None`. Gaps and padding are set to `fill`.
+    pub fn from_image(
+        image: &Image,
+        base: Option<u32>,
+        size: Option<u32>,
+        fill: u8,
+    ) -> Result<Self, Error> {
+        let sections: Vec<_> = image
+            .sections
+            .iter()
+            .filter(|s| s.load && s.size != 0)
+            .collect();
+
+        let base = base.unwrap_or_else(|| sections.iter().map(|s| s.lma).min().unwrap_or(0));
+        let end = sections
+            .iter()
+            .map(|s| u64::from(s.lma) + u64::from(s.size))
+            .max()
+            .unwrap_or(u64::from(base));
+        let len = match size {
+            Some(size) => u64::from(size),
+            None => end.saturating_sub(u64::from(base)),
+        };
+
+        let mut data = vec![fill; len as usize];
+        for section in sections {
+            let start = u64::from(section.lma);
+            let end = start + section.data.len() as u64;
+            if start < u64::from(base) || end > u64::from(base) + len {
+                return Err(Error::Rom(format!(
+                    "{} (0x{:08x}..0x{:08x}) doesn't fit in the ROM (0x{:08x}..0x{:08x})",
+                    section.name,
+                    start,
+                    end,
+                    base,
+                    u64::from(base) + len
+                )));
+            }
+            let offset = (start - u64::from(base)) as usize;
+            data[offset..offset + section.data.len()].copy_from_slice(&section.data);
+        }
+
+        Ok(Rom {
+            base,
+            data,
+            entry: image.entry,
+        })
+    }
+
+    /// Splits the image into `ways` byte lanes
+    ///
+    /// Lane 0 holds the bytes at addresses that are a multiple of `ways`. On the big-endian 68000
+    /// bus that is the most significant byte, so for a 16-bit bus lane 0 is the "even" EPROM on
+    /// D15-D8 and lane 1 the "odd" one on D7-D0. Each lane starts at address 0.
+    pub fn split(&self, ways: usize) -> Vec<Rom> {
+        (0..ways)
+            .map(|lane| Rom {
+                base: 0,
+                data: self.data.iter().skip(lane).step_by(ways).copied().collect(),
+                entry: 0,
+            })
+            .collect()
+    }
+
+    /// Returns the 16-bit sum of all bytes, as shown by most EPROM programmers
+    pub fn sum16(&self) -> u16 {
+        self.data
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)))
+    }
+
+    /// Returns the CRC-32 of the image
+    pub fn crc32(&self) -> u32 {
+        crc32(&self.data)
+    }
+
+    /// Writes the image as Motorola S-records with `bytes_per_record` data bytes per line
+    ///
+    /// Fails if the image or the entry point is out of reach of the addresses of `kind`.
+    pub fn to_srec(
+        &self,
+        kind: SRecord,
+        header: &str,
+        bytes_per_record: usize,
+    ) -> Result<String, Error> {
+        let end = u64::from(self.base) + self.data.len() as u64;
+        let max = end.saturating_sub(1).max(u64::from(self.entry));
+        let kind = kind.fit(max);
+        let (data_type, addr_len, term_type) = match kind {
+            SRecord::S19 => (1, 2, 9),
+            SRecord::S28 => (2, 3, 8),
+            SRecord::S37 | SRecord::Auto => (3, 4, 7),
+        };
+        if max >> (8 * addr_len) != 0 {
+            return Err(Error::Rom(format!(
+                "address 0x{:08x} doesn't fit in {:?} records",
+                max, kind
+            )));
+        }
+
+        let mut out = String::new();
+        srec_line(&mut out, 0, 0, 2, header.as_bytes());
+
+        let mut count = 0u32;
+        for (i, chunk) in self.data.chunks(bytes_per_record).enumerate() {
+            let addr = self.base + (i * bytes_per_record) as u32;
+            srec_line(&mut out, data_type, addr, addr_len, chunk);
+            count += 1;
+        }
+
+        if count <= 0xFFFF {
+            srec_line(&mut out, 5, count, 2, &[]);
+        } else if count <= 0xFF_FFFF {
+            srec_line(&mut out, 6, count, 3, &[]);
+        }
+        srec_line(&mut out, term_type, self.entry, addr_len, &[]);
+        Ok(out)
+    }
+
+    /// Writes the image as Intel HEX with 32-bit addressing and 16 data bytes per line
+    pub fn to_ihex(&self) -> String {
+        let mut out = String::new();
+        let mut upper = None;
+        let mut addr = self.base;
+        let mut rest = &self.data[..];
+        while !rest.is_empty() {
+            if upper != Some(addr >> 16) {
+                upper = Some(addr >> 16);
+                ihex_line(&mut out, 0, 4, &((addr >> 16) as u16).to_be_bytes());
+            }
+            // Records don't cross a 64K boundary
+            let len = rest
+                .len()
+                .min(16)
+                .min(0x1_0000 - (addr & 0xFFFF) as usize);
+            ihex_line(&mut out, addr as u16, 0, &rest[..len]);
+            addr = addr.wrapping_add(len as u32);
+            rest = &rest[len..];
+        }
+        ihex_line(&mut out, 0, 5, &self.entry.to_be_bytes());
+        ihex_line(&mut out, 0, 1, &[]);
+        out
+    }
+}
+
+/// S-record address size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SRecord {
+    /// The smallest size that fits the image
+    Auto,
+    /// 16-bit addresses (S1 data, S9 termination)
+    S19,
+    /// 24-bit addresses (S2 data, S8 termination)
+    S28,
+    /// 32-bit addresses (S3 data, S7 termination)
+    S37,
+}
+
+impl SRecord {
+    fn fit(self, max: u64) -> SRecord {
+        match self {
+            SRecord::Auto if max <= 0xFFFF => SRecord::S19,
+            SRecord::Auto if max <= 0xFF_FFFF => SRecord::S28,
+            SRecord::Auto => SRecord::S37,
+            kind => kind,
+        }
+    }
+}
+
+fn srec_line(out: &mut String, kind: u8, addr: u32, addr_len: usize, data: &[u8]) {
+    let addr = &addr.to_be_bytes()[4 - addr_len..];
+    let count = (addr_len + data.len() + 1) as u8;
+    let sum = addr
+        .iter()
+        .chain(data)
+        .fold(count, |sum, &b| sum.wrapping_add(b));
+
+    write!(out, "S{}{:02X}", kind, count).unwrap();
+    for b in addr.iter().chain(data) {
+        write!(out, "{:02X}", b).unwrap();
+    }
+    writeln!(out, "{:02X}", !sum).unwrap();
+}
+
+fn ihex_line(out: &mut String, addr: u16, kind: u8, data: &[u8]) {
+    let header = [data.len() as u8, (addr >> 8) as u8, addr as u8, kind];
+    let sum = header
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, &b| sum.wrapping_add(b));
+
+    out.push(':');
+    for b in header.iter().chain(data) {
+        write!(out, "{:02X}", b).unwrap();
+    }
+    writeln!(out, "{:02X}", sum.wrapping_neg()).unwrap();
+}
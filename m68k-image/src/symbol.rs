@@ -0,0 +1,208 @@
+//! Turning addresses into functions and source lines
+//!
+//! The symbol table names the function an address is in; the DWARF debug information, if the
+//! image was built with it, adds the file and line, and the functions inlined there. Rust names
+//! are demangled, without their hashes.
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+use addr2line::Context;
+use gimli::{EndianRcSlice, RunTimeEndian};
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+use crate::Error;
+
+type Reader = EndianRcSlice<RunTimeEndian>;
+
+/// A place in the program
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    /// Demangled name of the function
+    pub function: Option<String>,
+    /// Offset of the address from the start of the function, when it is known from the symbol
+    /// table
+    pub offset: Option<u32>,
+    /// Source file
+    pub file: Option<String>,
+    /// Line in `file`
+    pub line: Option<u32>,
+    /// Column in `line`
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.function.as_deref().unwrap_or("??"))?;
+        if let Some(offset) = self.offset {
+            write!(f, " + 0x{:x}", offset)?;
+        }
+        if let Some(file) = &self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Symbol {
+    address: u32,
+    size: u32,
+    name: String,
+    code: bool,
+}
+
+/// Symbols and debug information of an ELF file
+pub struct Symbolizer {
+    /// Sorted by address
+    symbols: Vec<Symbol>,
+    code: Vec<Range<u32>>,
+    dwarf: Option<Context<Reader>>,
+}
+
+impl Symbolizer {
+    /// Reads the ELF file at `path`
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|e| Error::Io(path.display().to_string(), e))?;
+        Self::parse(&data)
+    }
+
+    /// Parses the contents of an ELF file
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let file = object::File::parse(data)?;
+        if file.architecture() != object::Architecture::M68k {
+            return Err(Error::NotM68k);
+        }
+
+        let code: Vec<Range<u32>> = file
+            .sections()
+            .filter(|s| s.kind() == SectionKind::Text)
+            .map(|s| s.address() as u32..(s.address() + s.size()) as u32)
+            .collect();
+
+        // Labels of assembly code have no type
+        let mut symbols: Vec<Symbol> = file
+            .symbols()
+            .filter(|s| {
+                matches!(
+                    s.kind(),
+                    SymbolKind::Text | SymbolKind::Data | SymbolKind::Unknown
+                )
+            })
+            .filter_map(|s| {
+                let address = s.address() as u32;
+                Some(Symbol {
+                    address,
+                    size: s.size() as u32,
+                    name: demangle(s.name().ok()?),
+                    code: s.kind() == SymbolKind::Text
+                        || code.iter().any(|range| range.contains(&address)),
+                })
+            })
+            .filter(|s| !s.name.is_empty())
+            .collect();
+        symbols.sort_by_key(|s| (s.address, std::cmp::Reverse(s.size)));
+
+        let dwarf = if file.section_by_name(".debug_info").is_some() {
+            let endian = if file.is_little_endian() {
+                RunTimeEndian::Little
+            } else {
+                RunTimeEndian::Big
+            };
+            let dwarf = gimli::Dwarf::load(|id| -> Result<Reader, gimli::Error> {
+                let data = file
+                    .section_by_name(id.name())
+                    .and_then(|s| s.data().ok())
+                    .unwrap_or(&[]);
+                Ok(Reader::new(Rc::from(data), endian))
+            })?;
+            Some(Context::from_dwarf(dwarf)?)
+        } else {
+            None
+        };
+
+        Ok(Symbolizer {
+            symbols,
+            code,
+            dwarf,
+        })
+    }
+
+    /// Returns `true` if `address` is in an executable section
+    pub fn is_code(&self, address: u32) -> bool {
+        self.code.iter().any(|range| range.contains(&address))
+    }
+
+    /// Returns the symbol `address` is in, and the offset from its start
+    ///
+    /// Symbols without a size, like the labels of assembly code, are taken to extend to the next
+    /// symbol if `address` is in code.
+    pub fn symbol(&self, address: u32) -> Option<(&str, u32)> {
+        let end = self.symbols.partition_point(|s| s.address <= address);
+        let symbols = &self.symbols[..end];
+        let symbol = symbols
+            .iter()
+            .rev()
+            .find(|s| address - s.address < s.size)
+            .or_else(|| {
+                let last = symbols.last()?;
+                (last.size == 0 && last.code && self.is_code(address)).then_some(last)
+            })?;
+        Some((&symbol.name, address - symbol.address))
+    }
+
+    /// Returns where `address` is, innermost inlined function first
+    ///
+    /// The list is empty if nothing is known about `address`.
+    pub fn locate(&self, address: u32) -> Vec<Location> {
+        let mut locations = Vec::new();
+        if let Some(dwarf) = &self.dwarf {
+            if let Ok(mut frames) = dwarf.find_frames(address.into()).skip_all_loads() {
+                while let Ok(Some(frame)) = frames.next() {
+                    let function = frame
+                        .function
+                        .as_ref()
+                        .and_then(|f| f.raw_name().ok())
+                        .map(|name| demangle(&name));
+                    let (file, line, column) = match frame.location {
+                        Some(l) => (l.file.map(String::from), l.line, l.column),
+                        None => (None, None, None),
+                    };
+                    locations.push(Location {
+                        function,
+                        offset: None,
+                        file,
+                        line,
+                        column,
+                    });
+                }
+            }
+        }
+
+        // The symbol table names the outermost function, and knows where it starts
+        if let Some((name, offset)) = self.symbol(address) {
+            if locations.is_empty() {
+                locations.push(Location::default());
+            }
+            let outermost = locations.last_mut().unwrap();
+            outermost.function = Some(name.to_string());
+            outermost.offset = Some(offset);
+        }
+        locations
+    }
+}
+
+fn demangle(name: &str) -> String {
+    match rustc_demangle::try_demangle(name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.to_string(),
+    }
+}
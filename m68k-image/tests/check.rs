@@ -0,0 +1,223 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::*;
+use m68k_image::check::{check, is_reserved, Report};
+use m68k_image::{Image, MemoryMap};
+
+fn run(builder: ElfBuilder) -> Report {
+    let image = Image::parse(&builder.build()).unwrap();
+    check(&image, &MemoryMap::from_image(&image))
+}
+
+fn assert_error(report: &Report, needle: &str) {
+    assert!(
+        report.errors().any(|e| e.message.contains(needle)),
+        "expected an error containing `{}`, got {:?}",
+        needle,
+        report.diagnostics
+    );
+}
+
+#[test]
+fn parse() {
+    let image = Image::parse(&image().build()).unwrap();
+    let data = image.section(".data").unwrap();
+    assert_eq!(data.addr, RAM_START);
+    assert_eq!(data.lma, 0x68);
+    assert!(data.load && data.write);
+    let bss = image.section(".bss").unwrap();
+    assert!(!bss.load);
+    assert_eq!(bss.size, 0x10);
+    assert_eq!(image.symbol("Reset"), Some(RESET));
+    assert_eq!(image.read_u32(4), Some(RESET));
+    assert_eq!(image.read_u32(0x6c), Some(2));
+}
+
+#[test]
+fn valid_image() {
+    let report = run(image());
+    assert!(report.diagnostics.is_empty(), "{:?}", report.diagnostics);
+    assert_eq!(report.usage[0].region, "ROM");
+    assert_eq!(report.usage[0].used, 0x70);
+    assert_eq!(report.usage[1].region, "RAM");
+    assert_eq!(report.usage[1].used, 0x18);
+}
+
+#[test]
+fn odd_stack_pointer() {
+    let mut v = vectors();
+    v[0] = RAM_END - 1;
+    assert_error(&run(image_with_vectors(&v)), "is odd");
+}
+
+#[test]
+fn stack_pointer_outside_ram() {
+    let mut v = vectors();
+    v[0] = RAM_END + 4;
+    assert_error(&run(image_with_vectors(&v)), "outside RAM");
+    v[0] = RAM_START;
+    assert_error(&run(image_with_vectors(&v)), "outside RAM");
+}
+
+#[test]
+fn reset_vector() {
+    let mut v = vectors();
+    v[1] = HANDLER;
+    assert_error(&run(image_with_vectors(&v)), "doesn't point at Reset");
+}
+
+#[test]
+fn boot_overlay_reset_vector() {
+    let mut v = vectors();
+    v[1] = 0x00f8_0040;
+    let builder = image_with_vectors(&v).symbol("__boot_reset", 0x00f8_0040);
+    assert!(run(builder).is_ok());
+}
+
+#[test]
+fn vector_outside_text() {
+    let mut v = vectors();
+    v[4] = 0x64;
+    assert_error(&run(image_with_vectors(&v)), "points into .rodata");
+    v[4] = 0x1000;
+    assert_error(&run(image_with_vectors(&v)), "doesn't point into any section");
+}
+
+#[test]
+fn vector_in_ramtext() {
+    // A `#[ramfunc]` handler runs from RAM
+    let mut v = vectors();
+    v[4] = RAM_START + 0x100;
+    let builder =
+        image_with_vectors(&v).section(".ramtext", RAM_START + 0x100, 0x70, TEXT, vec![0x4e, 0x73]);
+    let report = run(builder);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+}
+
+#[test]
+fn zero_vectors() {
+    let mut v = vectors();
+    v[14] = 0;
+    assert_error(&run(image_with_vectors(&v)), "vector 14 is zero");
+
+    // Reserved vectors may be zero
+    let mut v = vectors();
+    v.resize(64, 0);
+    v[1] = 0x100;
+    for (n, vector) in v.iter_mut().enumerate().skip(2) {
+        *vector = if is_reserved(n) { 0 } else { 0x104 };
+    }
+    let builder = ElfBuilder::new(0x100)
+        .section(".vector_table", 0, 0, RODATA, longs(&v))
+        .section(".text", 0x100, 0x100, TEXT, vec![0; 0x10])
+        .symbol("Reset", 0x100)
+        .symbol("__rom_start", 0)
+        .symbol("__rom_end", 0x4_0000)
+        .symbol("__ram_start", RAM_START)
+        .symbol("__ram_end", RAM_END);
+    let report = run(builder);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+}
+
+#[test]
+fn misaligned_data() {
+    let builder = image()
+        .replace(".data", longs(&[1]))
+        .section(".data2", RAM_START + 0x100, 0x6e, DATA, vec![0; 4]);
+    let report = run(builder);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+
+    let builder = ElfBuilder::new(RESET)
+        .section(".vector_table", 0, 0, RODATA, longs(&vectors()))
+        .section(".boot", 0x40, 0x40, TEXT, vec![0; 4])
+        .section(".text", 0x44, 0x44, TEXT, vec![0; 0x20])
+        .section(".data", RAM_START + 2, 0x66, DATA, vec![0; 6])
+        .symbol("Reset", RESET)
+        .symbol("__sdata", RAM_START)
+        .symbol("__sidata", 0x64)
+        .symbol("__rom_start", 0)
+        .symbol("__rom_end", 0x4_0000)
+        .symbol("__ram_start", RAM_START)
+        .symbol("__ram_end", RAM_END);
+    let report = run(builder);
+    assert_error(&report, ".data VMA 0x20000002 is not 4-byte aligned");
+    assert_error(&report, ".data LMA 0x00000066 is not 4-byte aligned");
+    assert_error(&report, "__sdata");
+    assert_error(&report, "__sidata");
+}
+
+#[test]
+fn got() {
+    let builder = image().section(".got", RAM_START + 0x20, RAM_START + 0x20, DATA, vec![0; 8]);
+    assert_error(&run(builder), ".got is not empty");
+}
+
+#[test]
+fn overflow() {
+    let builder = image().nobits(".bss2", RAM_END - 8, 0x10);
+    assert_error(&run(builder), "overflows RAM by 8 bytes");
+
+    let builder = image().section(".fastram", 0x0030_0000, 0x70, DATA, vec![0; 4]);
+    assert_error(&run(builder), "VMA of .fastram (0x00300000..0x00300004) is outside every");
+}
+
+#[test]
+fn memory_x() {
+    let memory = MemoryMap::parse(
+        "MEMORY
+        {
+          /* ROM and RAM are mandatory memory regions */
+          ROM (RX)  : ORIGIN = 0x00000000, LENGTH = 256K
+          RAM (RWX) : ORIGIN = 0x20000000, LENGTH = 64K
+          /* CCRAM : ORIGIN = 0x10000000, LENGTH = 8K */
+          FASTRAM : org = 0x00f00000, len = 16K + 16K
+        }",
+    )
+    .unwrap();
+    assert_eq!(memory.regions.len(), 3);
+    assert_eq!(memory.region("ROM").unwrap().length, 256 * 1024);
+    assert_eq!(memory.region("RAM").unwrap().origin, RAM_START);
+    assert_eq!(memory.region("FASTRAM").unwrap().length, 32 * 1024);
+
+    let image = Image::parse(
+        &image()
+            .section(".fastram", 0x00f0_0000, 0x70, DATA, vec![0; 4])
+            .build(),
+    )
+    .unwrap();
+    let report = check(&image, &memory);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+    assert_eq!(report.usage[2].used, 4);
+}
+
+#[test]
+fn ram_app() {
+    let builder = ElfBuilder::new(0x0001_0000)
+        .section(".text", 0x0001_0000, 0x0001_0000, TEXT, vec![0; 0x20])
+        .section(".data", 0x0001_0020, 0x0001_0020, DATA, vec![0; 4])
+        .symbol("Reset", 0x0001_0000)
+        .symbol("__stext", 0x0001_0000)
+        .symbol("__sdata", 0x0001_0020)
+        .symbol("__eapp", 0x0001_0024)
+        .symbol("__ram_start", 0x0001_0000)
+        .symbol("__ram_end", 0x0009_0000);
+    let report = run(builder);
+    assert!(report.diagnostics.is_empty(), "{:?}", report.diagnostics);
+}
+
+/// Checks the `minimal` example in `tests/firmware`, or the one at the path in `M68K_MINIMAL_ELF`,
+/// e.g. `target/m68k-unknown-none/release/examples/minimal` after
+/// `cargo xbuild -p m68k-rt --examples --release`
+#[test]
+fn minimal_example() {
+    let path = std::env::var_os("M68K_MINIMAL_ELF").map_or_else(
+        || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/firmware/minimal"),
+        PathBuf::from,
+    );
+    let image = Image::read(&path).unwrap();
+    let memory = MemoryMap::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../memory.x")).unwrap();
+    let report = check(&image, &memory);
+    assert!(report.is_ok(), "{:?}", report.diagnostics);
+}
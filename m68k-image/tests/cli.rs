@@ -0,0 +1,186 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use common::*;
+
+fn cargo_m68k() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-m68k"));
+    // As invoked by cargo
+    command.arg("m68k");
+    command
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("m68k-image-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn check() {
+    let dir = temp_dir("check");
+    let elf = dir.join("good.elf");
+    std::fs::write(&elf, image().build()).unwrap();
+    let output = cargo_m68k().arg("check").arg(&elf).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("ROM"));
+
+    let mut v = vectors();
+    v[1] = 0;
+    let elf = dir.join("bad.elf");
+    std::fs::write(&elf, image_with_vectors(&v).build()).unwrap();
+    let output = cargo_m68k().arg("check").arg(&elf).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't point at Reset"));
+}
+
+#[test]
+fn rom_split() {
+    let dir = temp_dir("rom");
+    let elf = dir.join("image.elf");
+    std::fs::write(&elf, image().build()).unwrap();
+
+    let output = cargo_m68k()
+        .args(["rom", "--split", "2", "--fill", "0", "-o"])
+        .arg(dir.join("rom.bin"))
+        .arg(&elf)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    // Padded to the 256K ROM, half of it in each lane
+    let even = std::fs::read(dir.join("rom.even.bin")).unwrap();
+    let odd = std::fs::read(dir.join("rom.odd.bin")).unwrap();
+    assert_eq!(even.len(), 128 * 1024);
+    assert_eq!(odd.len(), 128 * 1024);
+    // The reset vector, 0x00000040, is at byte 4
+    assert_eq!(&even[2..4], &[0x00, 0x00]);
+    assert_eq!(&odd[2..4], &[0x00, 0x40]);
+}
+
+#[test]
+fn rom_formats() {
+    let dir = temp_dir("formats");
+    let elf = dir.join("image.elf");
+    std::fs::write(&elf, image().build()).unwrap();
+
+    for (file, first) in [("rom.s19", "S1"), ("rom.s37", "S3"), ("rom.hex", ":02")] {
+        let output = cargo_m68k()
+            .args(["rom", "-o"])
+            .arg(dir.join(file))
+            .arg(&elf)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let text = std::fs::read_to_string(dir.join(file)).unwrap();
+        let line = text.lines().find(|l| !l.starts_with("S0")).unwrap();
+        assert!(line.starts_with(first), "{}: {}", file, line);
+    }
+}
+
+#[test]
+fn stamp() {
+    let dir = temp_dir("stamp");
+    let elf = dir.join("image.elf");
+    std::fs::write(
+        &elf,
+        image()
+            .section(".image_header", 0x100, 0x100, RODATA, vec![0; 68])
+            .symbol("__image_start", 0)
+            .build(),
+    )
+    .unwrap();
+
+    let stamped = dir.join("stamped.elf");
+    let output = cargo_m68k()
+        .args([
+            "stamp",
+            "--version",
+            "0.1.0",
+            "--timestamp",
+            "42",
+            "--git-hash",
+        ])
+        .arg("0123456789abcdef0123456789abcdef01234567")
+        .arg("-o")
+        .arg(&stamped)
+        .arg(&elf)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("\"0.1.0\""), "{}", stdout);
+    assert!(
+        stdout.contains("0123456789abcdef0123456789abcdef01234567"),
+        "{}",
+        stdout
+    );
+
+    let image = m68k_image::Image::read(&stamped).unwrap();
+    let header = m68k_image::header::read(&image).unwrap();
+    assert_eq!(header.build.timestamp, 42);
+    assert_eq!(header.length, 0x144);
+    // The input is left alone
+    let image = m68k_image::Image::read(&elf).unwrap();
+    assert_eq!(m68k_image::header::read(&image), None);
+}
+
+#[test]
+fn crash() {
+    let dir = temp_dir("crash");
+    let elf = dir.join("image.elf");
+    std::fs::write(&elf, image().build()).unwrap();
+    let log = dir.join("console.log");
+    std::fs::write(
+        &log,
+        "panicked at src/main.rs:3:5:\nexplicit panic\nSR: 0x2700\nbacktrace:\n   0: 0x00000046\n",
+    )
+    .unwrap();
+
+    let output = cargo_m68k()
+        .arg("crash")
+        .arg(&elf)
+        .arg(&log)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("explicit panic"), "{}", stdout);
+    // DefaultHandler is the closest symbol before the call
+    assert!(stdout.contains("DefaultHandler + 0x1"), "{}", stdout);
+}
+
+#[test]
+fn core() {
+    let dir = temp_dir("core");
+    let capture = dir.join("capture.bin");
+    let frame = |kind: u8, payload: &[u8]| {
+        let mut body = vec![kind];
+        body.extend((payload.len() as u16).to_be_bytes());
+        body.extend(payload);
+        let mut frame = b"CORE".to_vec();
+        frame.extend(&body);
+        frame.extend(m68k_image::crc32::crc32(&body).to_be_bytes());
+        frame
+    };
+    let mut stream = frame(1, &[1, 3]);
+    stream.extend(frame(2, &[0; 74]));
+    stream.extend(frame(3, &longs(&[RAM_START, 0xdead_beef])));
+    std::fs::write(&capture, stream).unwrap();
+
+    let core = dir.join("core");
+    let output = cargo_m68k()
+        .arg("core")
+        .arg(&capture)
+        .arg("-o")
+        .arg(&core)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("4 bytes of memory in 1 blocks"));
+    // Without its end frame
+    assert!(String::from_utf8_lossy(&output.stderr).contains("end of the core dump is missing"));
+    assert_eq!(&std::fs::read(&core).unwrap()[..4], b"\x7fELF");
+}
@@ -0,0 +1,265 @@
+//! Builds small m68k ELF executables shaped like `m68k-rt` images
+
+#![allow(dead_code)]
+
+use object::elf::{
+    EM_68K, ET_EXEC, PF_R, PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHN_ABS, SHT_NOBITS,
+    SHT_PROGBITS, STB_GLOBAL, STT_FUNC, STT_NOTYPE,
+};
+use object::write::elf::{FileHeader, ProgramHeader, SectionHeader, Sym, Writer};
+use object::Endianness;
+
+pub const TEXT: u32 = SHF_ALLOC | SHF_EXECINSTR;
+pub const RODATA: u32 = SHF_ALLOC;
+pub const DATA: u32 = SHF_ALLOC | SHF_WRITE;
+
+struct Section {
+    name: String,
+    addr: u32,
+    lma: u32,
+    flags: u32,
+    data: Vec<u8>,
+    nobits: Option<u32>,
+}
+
+struct Symbol {
+    name: String,
+    value: u32,
+    size: u32,
+    kind: u8,
+}
+
+pub struct ElfBuilder {
+    entry: u32,
+    sections: Vec<Section>,
+    symbols: Vec<Symbol>,
+}
+
+impl ElfBuilder {
+    pub fn new(entry: u32) -> Self {
+        ElfBuilder {
+            entry,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Adds a section whose contents are loaded at `lma` and run at `addr`
+    pub fn section(mut self, name: &str, addr: u32, lma: u32, flags: u32, data: Vec<u8>) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            lma,
+            flags,
+            data,
+            nobits: None,
+        });
+        self
+    }
+
+    /// Adds a `NOLOAD` section
+    pub fn nobits(mut self, name: &str, addr: u32, size: u32) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            lma: addr,
+            flags: DATA,
+            data: Vec::new(),
+            nobits: Some(size),
+        });
+        self
+    }
+
+    /// Adds a non-allocated section, like the DWARF debug information
+    pub fn debug(self, name: &str, data: Vec<u8>) -> Self {
+        self.section(name, 0, 0, 0, data)
+    }
+
+    pub fn symbol(mut self, name: &str, value: u32) -> Self {
+        self.symbols.push(Symbol {
+            name: name.into(),
+            value,
+            size: 0,
+            kind: STT_NOTYPE,
+        });
+        self
+    }
+
+    /// Adds a function symbol
+    pub fn function(mut self, name: &str, value: u32, size: u32) -> Self {
+        self.symbols.push(Symbol {
+            name: name.into(),
+            value,
+            size,
+            kind: STT_FUNC,
+        });
+        self
+    }
+
+    /// Replaces the contents of a section
+    pub fn replace(mut self, name: &str, data: Vec<u8>) -> Self {
+        let section = self.sections.iter_mut().find(|s| s.name == name).unwrap();
+        section.data = data;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut w = Writer::new(Endianness::Big, false, &mut buffer);
+
+        let loaded: Vec<&Section> = self
+            .sections
+            .iter()
+            .filter(|s| s.nobits.is_none())
+            .collect();
+        let segments = loaded.iter().filter(|s| s.flags & SHF_ALLOC != 0).count();
+
+        w.reserve_file_header();
+        w.reserve_program_headers(segments as u32);
+        let offsets: Vec<usize> = loaded.iter().map(|s| w.reserve(s.data.len(), 4)).collect();
+
+        w.reserve_null_section_index();
+        let names: Vec<_> = self
+            .sections
+            .iter()
+            .map(|s| {
+                w.reserve_section_index();
+                w.add_section_name(s.name.as_bytes())
+            })
+            .collect();
+
+        w.reserve_null_symbol_index();
+        let strings: Vec<_> = self
+            .symbols
+            .iter()
+            .map(|symbol| {
+                w.reserve_symbol_index(None);
+                w.add_string(symbol.name.as_bytes())
+            })
+            .collect();
+        w.reserve_symtab_section_index();
+        w.reserve_symtab();
+        w.reserve_strtab_section_index();
+        w.reserve_strtab();
+        w.reserve_shstrtab_section_index();
+        w.reserve_shstrtab();
+        w.reserve_section_headers();
+
+        w.write_file_header(&FileHeader {
+            os_abi: 0,
+            abi_version: 0,
+            e_type: ET_EXEC,
+            e_machine: EM_68K,
+            e_entry: self.entry.into(),
+            e_flags: 0,
+        })
+        .unwrap();
+
+        w.write_align_program_headers();
+        for (section, &offset) in loaded.iter().zip(&offsets) {
+            if section.flags & SHF_ALLOC == 0 {
+                continue;
+            }
+            w.write_program_header(&ProgramHeader {
+                p_type: PT_LOAD,
+                p_flags: PF_R,
+                p_offset: offset as u64,
+                p_vaddr: section.addr.into(),
+                p_paddr: section.lma.into(),
+                p_filesz: section.data.len() as u64,
+                p_memsz: section.data.len() as u64,
+                p_align: 4,
+            });
+        }
+        for section in &loaded {
+            w.write_align(4);
+            w.write(&section.data);
+        }
+
+        w.write_null_symbol();
+        for (symbol, &name) in self.symbols.iter().zip(&strings) {
+            w.write_symbol(&Sym {
+                name: Some(name),
+                section: None,
+                st_info: (STB_GLOBAL << 4) | symbol.kind,
+                st_other: 0,
+                st_shndx: SHN_ABS,
+                st_value: symbol.value.into(),
+                st_size: symbol.size.into(),
+            });
+        }
+        w.write_strtab();
+        w.write_shstrtab();
+
+        w.write_null_section_header();
+        let mut offsets = offsets.iter();
+        for (section, &name) in self.sections.iter().zip(&names) {
+            let (sh_type, sh_offset, sh_size) = match section.nobits {
+                Some(size) => (SHT_NOBITS, 0, size),
+                None => (
+                    SHT_PROGBITS,
+                    *offsets.next().unwrap(),
+                    section.data.len() as u32,
+                ),
+            };
+            w.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type,
+                sh_flags: section.flags.into(),
+                sh_addr: section.addr.into(),
+                sh_offset: sh_offset as u64,
+                sh_size: sh_size.into(),
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        w.write_symtab_section_header(1);
+        w.write_strtab_section_header();
+        w.write_shstrtab_section_header();
+
+        buffer
+    }
+}
+
+pub fn longs(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+pub const RAM_START: u32 = 0x2000_0000;
+pub const RAM_END: u32 = 0x2001_0000;
+pub const RESET: u32 = 0x40;
+pub const HANDLER: u32 = 0x44;
+
+/// The vector table of a 16-vector `m68k-rt` image, like the one of the `minimal` example
+pub fn vectors() -> Vec<u32> {
+    let mut v = vec![RAM_END, RESET];
+    for n in 2..16 {
+        v.push(if matches!(n, 12 | 13 | 15) { 0 } else { HANDLER });
+    }
+    v
+}
+
+/// A well-formed image with 256K ROM at 0 and 64K RAM at 0x2000_0000
+pub fn image() -> ElfBuilder {
+    image_with_vectors(&vectors())
+}
+
+pub fn image_with_vectors(vectors: &[u32]) -> ElfBuilder {
+    ElfBuilder::new(RESET)
+        .section(".vector_table", 0, 0, RODATA, longs(vectors))
+        .section(".boot", 0x40, 0x40, TEXT, vec![0x4e, 0x71, 0x4e, 0x71])
+        .section(".text", 0x44, 0x44, TEXT, [0x4e, 0x71].repeat(0x10))
+        .section(".rodata", 0x64, 0x64, RODATA, longs(&[0xdead_beef]))
+        .section(".data", RAM_START, 0x68, DATA, longs(&[1, 2]))
+        .nobits(".bss", RAM_START + 8, 0x10)
+        .symbol("Reset", RESET)
+        .symbol("DefaultHandler", HANDLER)
+        .symbol("__sdata", RAM_START)
+        .symbol("__sidata", 0x68)
+        .symbol("__rom_start", 0)
+        .symbol("__rom_end", 0x4_0000)
+        .symbol("__ram_start", RAM_START)
+        .symbol("__ram_end", RAM_END)
+}
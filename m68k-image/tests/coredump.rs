@@ -0,0 +1,131 @@
+use object::elf::{FileHeader32, ELFOSABI_LINUX, EM_68K, ET_CORE, NT_PRSTATUS, PT_LOAD, PT_NOTE};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::Endianness;
+
+use m68k_image::coredump::{CoreDump, SYNC};
+use m68k_image::crc32::crc32;
+
+// Frames as `m68k_rt::coredump::write` sends them
+fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![kind];
+    body.extend((payload.len() as u16).to_be_bytes());
+    body.extend(payload);
+    let mut frame = SYNC.to_vec();
+    frame.extend(&body);
+    frame.extend(crc32(&body).to_be_bytes());
+    frame
+}
+
+fn registers() -> Vec<u8> {
+    let mut payload = Vec::new();
+    for register in 0..16u32 {
+        payload.extend((0x100 + register).to_be_bytes());
+    }
+    payload.extend(0x1234u32.to_be_bytes());
+    payload.extend(0x2704u16.to_be_bytes());
+    payload.extend(0x2000_8000u32.to_be_bytes());
+    payload
+}
+
+fn memory(address: u32, bytes: &[u8]) -> Vec<u8> {
+    let mut payload = address.to_be_bytes().to_vec();
+    payload.extend(bytes);
+    frame(3, &payload)
+}
+
+fn stream() -> Vec<Vec<u8>> {
+    vec![
+        frame(1, &[1, 2]),
+        frame(2, &registers()),
+        memory(0x2000_0000, &[1, 2, 3, 4]),
+        memory(0x2000_0004, &[5, 6]),
+        memory(0x2000_fff0, &[0xaa; 16]),
+        frame(4, &5u32.to_be_bytes()),
+    ]
+}
+
+#[test]
+fn parse() {
+    let mut capture = b"*** bus error\r\nCORE dump follows\r\n".to_vec();
+    capture.extend(stream().concat());
+
+    let dump = CoreDump::parse(&capture).unwrap();
+    assert_eq!(dump.vector, Some(2));
+    assert_eq!(dump.d[0], 0x100);
+    assert_eq!(dump.a[7], 0x10f);
+    assert_eq!(dump.pc, 0x1234);
+    assert_eq!(dump.sr, 0x2704);
+    assert_eq!(dump.usp, 0x2000_8000);
+    assert_eq!(
+        dump.memory,
+        [
+            (0x2000_0000, vec![1, 2, 3, 4, 5, 6]),
+            (0x2000_fff0, vec![0xaa; 16])
+        ]
+    );
+    assert_eq!(dump.bad_frames, 0);
+    assert_eq!(dump.missing_frames, Some(0));
+}
+
+#[test]
+fn corrupted_frames() {
+    let mut frames = stream();
+    frames[3][10] ^= 0x40;
+    let dump = CoreDump::parse(&frames.concat()).unwrap();
+    assert_eq!(dump.bad_frames, 1);
+    assert_eq!(dump.missing_frames, Some(1));
+    assert_eq!(dump.memory[0], (0x2000_0000, vec![1, 2, 3, 4]));
+
+    // Cut short before the end
+    let frames = stream();
+    let dump = CoreDump::parse(&frames[..4].concat()).unwrap();
+    assert_eq!(dump.missing_frames, None);
+
+    // Without the registers
+    let mut frames = stream();
+    frames[1][12] ^= 1;
+    assert!(CoreDump::parse(&frames.concat()).is_err());
+
+    assert!(CoreDump::parse(b"no core dump here").is_err());
+}
+
+#[test]
+fn elf_core() {
+    let dump = CoreDump::parse(&stream().concat()).unwrap();
+    let elf = dump.to_elf();
+
+    let header = FileHeader32::<Endianness>::parse(&*elf).unwrap();
+    let endian = header.endian().unwrap();
+    assert_eq!(endian, Endianness::Big);
+    assert_eq!(header.e_ident().os_abi, ELFOSABI_LINUX);
+    assert_eq!(header.e_type(endian), ET_CORE);
+    assert_eq!(header.e_machine(endian), EM_68K);
+
+    let segments = header.program_headers(endian, &*elf).unwrap();
+    assert_eq!(segments.len(), 3);
+
+    assert_eq!(segments[0].p_type(endian), PT_NOTE);
+    let mut notes = segments[0].notes(endian, &*elf).unwrap().unwrap();
+    let note = notes.next().unwrap().unwrap();
+    assert_eq!(note.name(), b"CORE");
+    assert_eq!(note.n_type(endian), NT_PRSTATUS);
+    let prstatus = note.desc();
+    assert_eq!(prstatus.len(), 154);
+    let long = |offset: usize| u32::from_be_bytes(prstatus[offset..offset + 4].try_into().unwrap());
+    // SIGBUS, for the bus error
+    assert_eq!(long(0), 7);
+    // pr_reg: d1-d7, a0-a6, d0, sp, orig_d0, sr, pc, format and vector
+    assert_eq!(long(70), 0x101);
+    assert_eq!(long(70 + 7 * 4), 0x108);
+    assert_eq!(long(70 + 14 * 4), 0x100);
+    assert_eq!(long(70 + 15 * 4), 0x10f);
+    assert_eq!(long(70 + 17 * 4), 0x2704);
+    assert_eq!(long(70 + 18 * 4), 0x1234);
+    assert_eq!(long(70 + 19 * 4), 8 << 16);
+
+    assert_eq!(segments[1].p_type(endian), PT_LOAD);
+    assert_eq!(segments[1].p_vaddr(endian), 0x2000_0000);
+    assert_eq!(segments[1].data(endian, &*elf).unwrap(), [1, 2, 3, 4, 5, 6]);
+    assert_eq!(segments[2].p_vaddr(endian), 0x2000_fff0);
+    assert_eq!(segments[2].data(endian, &*elf).unwrap(), [0xaa; 16]);
+}
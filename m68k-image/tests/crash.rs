@@ -0,0 +1,401 @@
+mod common;
+
+use gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections,
+};
+use gimli::{BigEndian, Encoding, Format, LineEncoding};
+
+use m68k_image::crash::{report, Dump};
+use m68k_image::frame::{Access, Frame};
+use m68k_image::symbol::Symbolizer;
+
+use common::*;
+
+const MAIN: &str = "_ZN3app4main17h0123456789abcdefE";
+const HELPER: &str = "_ZN3app6helper17h0123456789abcdefE";
+
+// `main` at 0x44, with `helper` inlined at 0x48..0x4c, and `handler` at 0x54
+fn dwarf() -> Vec<(&'static str, Vec<u8>)> {
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+
+    let directory = LineString::String(b"/src/app".to_vec());
+    let file = LineString::String(b"src/main.rs".to_vec());
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        directory,
+        file.clone(),
+        None,
+    );
+    let directory = program.default_directory();
+    let file_id = program.add_file(file, directory, None);
+    program.begin_sequence(Some(Address::Constant(0x44)));
+    for (offset, line) in [(0, 10), (4, 3), (8, 12)] {
+        program.row().file = file_id;
+        program.row().address_offset = offset;
+        program.row().line = line;
+        program.row().column = 5;
+        program.generate_row();
+    }
+    program.end_sequence(0x10);
+    dwarf.unit.line_program = program;
+
+    let root = dwarf.unit.root();
+    let unit = dwarf.unit.get_mut(root);
+    unit.set(
+        gimli::DW_AT_name,
+        AttributeValue::String(b"src/main.rs".to_vec()),
+    );
+    unit.set(
+        gimli::DW_AT_comp_dir,
+        AttributeValue::String(b"/src/app".to_vec()),
+    );
+    unit.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0x44)),
+    );
+    unit.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x10));
+    unit.set(gimli::DW_AT_stmt_list, AttributeValue::LineProgramRef);
+
+    let helper = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(helper);
+    entry.set(
+        gimli::DW_AT_linkage_name,
+        AttributeValue::String(HELPER.into()),
+    );
+    entry.set(
+        gimli::DW_AT_inline,
+        AttributeValue::Inline(gimli::DW_INL_inlined),
+    );
+
+    let main = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(main);
+    entry.set(
+        gimli::DW_AT_linkage_name,
+        AttributeValue::String(MAIN.into()),
+    );
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0x44)),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x10));
+
+    let inlined = dwarf.unit.add(main, gimli::DW_TAG_inlined_subroutine);
+    let entry = dwarf.unit.get_mut(inlined);
+    entry.set(
+        gimli::DW_AT_abstract_origin,
+        AttributeValue::UnitRef(helper),
+    );
+    entry.set(
+        gimli::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0x48)),
+    );
+    entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(4));
+    entry.set(
+        gimli::DW_AT_call_file,
+        AttributeValue::FileIndex(Some(file_id)),
+    );
+    entry.set(gimli::DW_AT_call_line, AttributeValue::Udata(11));
+    entry.set(gimli::DW_AT_call_column, AttributeValue::Udata(9));
+
+    let mut sections = Sections::new(EndianVec::new(BigEndian));
+    dwarf.write(&mut sections).unwrap();
+    let mut out = Vec::new();
+    sections
+        .for_each(|id, data| {
+            if !data.slice().is_empty() {
+                out.push((id.name(), data.slice().to_vec()));
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    out
+}
+
+fn program(debug: bool) -> Vec<u8> {
+    let mut elf = image()
+        .function(MAIN, 0x44, 0x10)
+        .function("handler", 0x54, 0x10);
+    if debug {
+        for (name, data) in dwarf() {
+            elf = elf.debug(name, data);
+        }
+    }
+    elf.build()
+}
+
+const TEXT_DUMP: &str = "\
+> g 40
+*** address error at 00000056
+crash dump: vector 3
+D0 00000000  D1 00000001  D2 00000002  D3 00000003
+D4 00000004  D5 00000005  D6 00000006  D7 00000007
+A0 20000000  A1 00000000  A2 00000000  A3 00000000
+A4 00000000  A5 00000000  A6 2000ffe8  A7 2000ffd6
+PC 00000056  SR 2704  USP 00000000
+frame: 001d 2000 0001 4e75 2704 0000 0056
+backtrace:
+   0: 0x00000056
+   1: 0x0000004c
+stack: 0x2000ffd6
+2000ffd6: 00 00 00 4c 20 00 ff f8 00 00 00 00 00 00 00 00
+2000ffe6: 00 00
+end of crash dump
+>
+";
+
+#[test]
+fn text() {
+    let dump = Dump::parse(TEXT_DUMP.as_bytes()).unwrap();
+    assert_eq!(dump.vector, Some(3));
+    assert_eq!(dump.d[7], Some(7));
+    assert_eq!(dump.a[6], Some(0x2000_ffe8));
+    assert_eq!(dump.pc, Some(0x56));
+    assert_eq!(dump.sr, Some(0x2704));
+    assert_eq!(dump.usp, Some(0));
+    assert_eq!(dump.frame.len(), 14);
+    assert_eq!(dump.backtrace, [0x56, 0x4c]);
+    assert_eq!(dump.stack_address, 0x2000_ffd6);
+    assert_eq!(dump.stack.len(), 18);
+    assert!(dump.panic.is_empty());
+
+    let frame = dump.exception_frame().unwrap().unwrap();
+    assert_eq!(frame.pc, 0x56);
+    assert_eq!(
+        frame.access,
+        Some(Access {
+            address: 0x2000_0001,
+            read: true,
+            fetch: false,
+            function_code: Some(5),
+        })
+    );
+    assert_eq!(frame.instruction_register, Some(0x4e75));
+}
+
+#[test]
+fn noise() {
+    // Line noise decodes to U+FFFD, which is more than one byte long
+    let mut log = b"\xff 1f\r\n\xe9\x80 00000000\r\n".to_vec();
+    log.extend(TEXT_DUMP.as_bytes());
+    let dump = Dump::parse(&log).unwrap();
+    assert_eq!(dump.pc, Some(0x56));
+    assert_eq!(dump.d[7], Some(7));
+}
+
+#[test]
+fn binary() {
+    let mut data = b"console output\r\n".to_vec();
+    data.extend(b"CRSH\x01\x02");
+    for register in 0..16u32 {
+        data.extend(register.to_be_bytes());
+    }
+    data.extend(0x46u32.to_be_bytes());
+    data.extend(0x2000u16.to_be_bytes());
+    data.extend(0x1234u32.to_be_bytes());
+    data.extend(8u16.to_be_bytes());
+    data.extend([0x20, 0x00, 0, 0, 0, 0x46, 0x00, 0x08]);
+    data.extend(1u16.to_be_bytes());
+    data.extend(0x46u32.to_be_bytes());
+    data.extend(0x2000_fff0u32.to_be_bytes());
+    data.extend(4u16.to_be_bytes());
+    data.extend(0x4cu32.to_be_bytes());
+
+    let dump = Dump::parse(&data).unwrap();
+    assert_eq!(dump.vector, Some(2));
+    assert_eq!(dump.d[3], Some(3));
+    assert_eq!(dump.a[7], Some(15));
+    assert_eq!(dump.pc, Some(0x46));
+    assert_eq!(dump.sr, Some(0x2000));
+    assert_eq!(dump.usp, Some(0x1234));
+    assert_eq!(dump.backtrace, [0x46]);
+    assert_eq!(dump.stack_address, 0x2000_fff0);
+    assert_eq!(dump.stack, [0, 0, 0, 0x4c]);
+
+    let frame = dump.exception_frame().unwrap().unwrap();
+    assert_eq!(frame.format, Some(0));
+    assert_eq!(frame.vector, Some(2));
+
+    assert!(Dump::parse(&data[..data.len() - 1]).is_err());
+}
+
+#[test]
+fn panic_report() {
+    let log = "\
+booting
+panicked at src/main.rs:12:5:
+the answer is 42
+SR: 0x2700
+backtrace:
+   0: 0x0000004c
+   1: 0x00000044
+";
+    let dump = Dump::parse(log.as_bytes()).unwrap();
+    assert_eq!(
+        dump.panic,
+        ["panicked at src/main.rs:12:5:", "the answer is 42"]
+    );
+    assert_eq!(dump.sr, Some(0x2700));
+    assert_eq!(dump.backtrace, [0x4c, 0x44]);
+    assert_eq!(dump.pc, None);
+
+    assert!(Dump::parse(b"nothing to see here\n").is_err());
+}
+
+#[test]
+fn monitor_registers() {
+    let log = "\
+> r
+D0 00000000  D1 00000001  D2 00000002  D3 00000003
+D4 00000004  D5 00000005  D6 00000006  D7 00000007
+A0 00000000  A1 00000000  A2 00000000  A3 00000000
+A4 00000000  A5 00000000  A6 00000000  A7 2000fff0
+PC 00000048  SR 2704 (-S I7 --Z--)  USP 00000000
+";
+    let dump = Dump::parse(log.as_bytes()).unwrap();
+    assert_eq!(dump.pc, Some(0x48));
+    assert_eq!(dump.sr, Some(0x2704));
+    assert_eq!(dump.usp, Some(0));
+    assert_eq!(dump.a[7], Some(0x2000_fff0));
+}
+
+fn frame(words: &[u16]) -> Frame {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    Frame::parse(&bytes).unwrap()
+}
+
+#[test]
+fn frames() {
+    let short = frame(&[0x2704, 0, 0x1234]);
+    assert_eq!((short.sr, short.pc, short.format), (0x2704, 0x1234, None));
+
+    // Format 2, CHK from 0x1230
+    let chk = frame(&[0x0000, 0, 0x1234, 0x2018, 0, 0x1230]);
+    assert_eq!(chk.format, Some(2));
+    assert_eq!(chk.vector, Some(6));
+    assert_eq!(chk.instruction, Some(0x1230));
+
+    // Format 8, 68010 data write to 0x00f00000
+    let mut words = vec![0x2000, 0, 0x1234, 0x8008, 0x0005, 0x00f0, 0x0000];
+    words.resize(29, 0);
+    let bus = frame(&words);
+    assert_eq!(bus.format, Some(8));
+    assert_eq!(bus.vector, Some(2));
+    assert_eq!(
+        bus.access,
+        Some(Access {
+            address: 0x00f0_0000,
+            read: false,
+            fetch: false,
+            function_code: Some(5),
+        })
+    );
+
+    // Format A, 68020 data read fault at 0x00f00004
+    let mut words = vec![0x2000, 0, 0x1234, 0xa008, 0, 0x0145, 0, 0, 0x00f0, 0x0004];
+    words.resize(16, 0);
+    let access = frame(&words).access.unwrap();
+    assert_eq!(
+        (access.address, access.read, access.fetch),
+        (0x00f0_0004, true, false)
+    );
+
+    // Format B, 68020 fault on stage B, which is at 0x2000
+    let mut words = vec![0x2000, 0, 0x1234, 0xb008, 0, 0x4006];
+    words.resize(18, 0);
+    words.extend([0, 0x2000]);
+    words.resize(46, 0);
+    let access = frame(&words).access.unwrap();
+    assert_eq!((access.address, access.fetch), (0x2000, true));
+
+    // Format 7, 68040 write to 0x00f00008
+    let mut words = vec![0x2000, 0, 0x1234, 0x7008, 0, 0x00f0, 0x0005];
+    words.resize(10, 0);
+    words.extend([0x00f0, 0x0008]);
+    words.resize(30, 0);
+    let access = frame(&words);
+    assert_eq!(access.effective_address, Some(0x00f0));
+    let access = access.access.unwrap();
+    assert_eq!((access.address, access.read), (0x00f0_0008, false));
+
+    // Too short for its format
+    assert!(Frame::parse(&[0x20, 0, 0, 0, 0x12, 0x34, 0x80, 0x08]).is_err());
+}
+
+#[test]
+fn symbols() {
+    let symbols = Symbolizer::parse(&program(false)).unwrap();
+    assert_eq!(symbols.symbol(0x4c), Some(("app::main", 8)));
+    assert_eq!(symbols.symbol(0x56), Some(("handler", 2)));
+    // Reset has no size, but is the closest symbol in code
+    assert_eq!(symbols.symbol(0x42), Some(("Reset", 2)));
+    assert!(symbols.is_code(0x44));
+    assert!(!symbols.is_code(0x64));
+
+    let locations = symbols.locate(0x4a);
+    assert_eq!(locations.len(), 1);
+    assert_eq!(locations[0].to_string(), "app::main + 0x6");
+}
+
+#[test]
+fn dwarf_lines() {
+    let symbols = Symbolizer::parse(&program(true)).unwrap();
+
+    let locations = symbols.locate(0x44);
+    assert_eq!(locations.len(), 1);
+    assert_eq!(
+        locations[0].to_string(),
+        "app::main + 0x0 at /src/app/src/main.rs:10:5"
+    );
+
+    // Inlined `helper`, called from line 11 of `main`
+    let locations = symbols.locate(0x4a);
+    assert_eq!(locations.len(), 2);
+    assert_eq!(locations[0].function.as_deref(), Some("app::helper"));
+    assert_eq!(locations[0].line, Some(3));
+    assert_eq!(locations[1].function.as_deref(), Some("app::main"));
+    assert_eq!(locations[1].offset, Some(6));
+    assert_eq!(locations[1].line, Some(11));
+    assert_eq!(locations[1].column, Some(9));
+}
+
+#[test]
+fn symbolized_report() {
+    let symbols = Symbolizer::parse(&program(true)).unwrap();
+    let dump = Dump::parse(TEXT_DUMP.as_bytes()).unwrap();
+    let report = report(&dump, &symbols);
+
+    assert!(report.contains("address error (vector 3)"), "{}", report);
+    assert!(report.contains("read data at 0x20000001"), "{}", report);
+    assert!(
+        report.contains("SR 0x2704  supervisor, interrupt mask 7, --Z--"),
+        "{}",
+        report
+    );
+    // The exception happened at 0x56; 0x4c returns into `main` after the call on line 11
+    assert!(
+        report.contains("   0: 0x00000056\n      handler + 0x2\n"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("app::helper at /src/app/src/main.rs:3:5 (inlined)"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("app::main + 0x7 at /src/app/src/main.rs:11:9"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("  0x2000ffd6: 0x0000004c  app::helper"),
+        "{}",
+        report
+    );
+}
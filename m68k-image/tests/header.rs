@@ -0,0 +1,203 @@
+mod common;
+
+use common::*;
+use m68k_image::crc32::crc32;
+use m68k_image::header::{self, BuildInfo, Header, SIZE};
+use m68k_image::rom::Rom;
+use m68k_image::Image;
+
+const HEADER: u32 = 0x40;
+const BOOT: u32 = HEADER + SIZE as u32;
+
+/// An image with a header after the vector table and a gap before `.rodata`
+fn image() -> ElfBuilder {
+    let mut v = vectors();
+    v[1] = BOOT;
+    ElfBuilder::new(BOOT)
+        .section(".vector_table", 0, 0, RODATA, longs(&v))
+        .section(".image_header", HEADER, HEADER, RODATA, vec![0; SIZE])
+        .section(".boot", BOOT, BOOT, TEXT, vec![0x4e, 0x71, 0x4e, 0x71])
+        .section(".text", BOOT + 4, BOOT + 4, TEXT, [0x4e, 0x71].repeat(0x10))
+        .section(".rodata", 0x100, 0x100, RODATA, longs(&[0xdead_beef]))
+        .section(".data", RAM_START, 0x104, DATA, longs(&[1, 2]))
+        .nobits(".bss", RAM_START + 8, 0x10)
+        .symbol("Reset", BOOT)
+        .symbol("__image_start", 0)
+        .symbol("__rom_start", 0)
+        .symbol("__rom_end", 0x4_0000)
+        .symbol("__ram_start", RAM_START)
+        .symbol("__ram_end", RAM_END)
+}
+
+fn build() -> BuildInfo {
+    let mut git_hash = [0; 20];
+    git_hash[0] = 0xab;
+    git_hash[19] = 0xcd;
+    BuildInfo {
+        version: "1.2.3".into(),
+        git_hash,
+        timestamp: 1_700_000_000,
+    }
+}
+
+// The CRC as the reset handler computes it
+fn expected_crc(elf: &[u8], fill: u8) -> u32 {
+    let image = Image::parse(elf).unwrap();
+    let mut data = Rom::from_image(&image, Some(0), None, fill).unwrap().data;
+    let crc = HEADER as usize + 8;
+    data.drain(crc..crc + 4);
+    crc32(&data)
+}
+
+#[test]
+fn stamp() {
+    let mut elf = image().build();
+    assert_eq!(header::read(&Image::parse(&elf).unwrap()), None);
+
+    let stamped = header::stamp(&mut elf, build(), 0xff).unwrap();
+    let header = header::read(&Image::parse(&elf).unwrap()).unwrap();
+    assert_eq!(header, stamped);
+    assert_eq!(header.build, build());
+    // Up to the end of the .data load image
+    assert_eq!(header.length, 0x10c);
+    assert_eq!(header.crc, expected_crc(&elf, 0xff));
+}
+
+#[test]
+fn layout() {
+    let mut elf = image().build();
+    header::stamp(&mut elf, build(), 0xff).unwrap();
+    let image = Image::parse(&elf).unwrap();
+    let bytes = &image.section(".image_header").unwrap().data;
+
+    assert_eq!(&bytes[0..4], b"IMAG");
+    assert_eq!(&bytes[4..8], &0x10cu32.to_be_bytes());
+    assert_eq!(&bytes[12..16], &1_700_000_000u32.to_be_bytes());
+    assert_eq!(bytes[16], 0xab);
+    assert_eq!(bytes[35], 0xcd);
+    assert_eq!(&bytes[36..41], b"1.2.3");
+    assert!(bytes[41..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn fill() {
+    // The gap before .rodata counts as the fill byte
+    let mut elf = image().build();
+    let ff = header::stamp(&mut elf, build(), 0xff).unwrap();
+    let zero = header::stamp(&mut elf, build(), 0).unwrap();
+    assert_ne!(ff.crc, zero.crc);
+    assert_eq!(zero.crc, expected_crc(&elf, 0));
+}
+
+#[test]
+fn crc_covers_contents() {
+    let mut a = image().build();
+    let mut b = image().replace(".rodata", longs(&[0xdead_bee0])).build();
+    let a = header::stamp(&mut a, build(), 0xff).unwrap();
+    let b = header::stamp(&mut b, build(), 0xff).unwrap();
+    assert_ne!(a.crc, b.crc);
+
+    // The build information is covered too
+    let mut c = image().build();
+    let c = header::stamp(
+        &mut c,
+        BuildInfo {
+            timestamp: 0,
+            ..build()
+        },
+        0xff,
+    )
+    .unwrap();
+    assert_ne!(a.crc, c.crc);
+}
+
+#[test]
+fn restamp() {
+    let mut elf = image().build();
+    header::stamp(&mut elf, build(), 0xff).unwrap();
+    let second = header::stamp(
+        &mut elf,
+        BuildInfo {
+            version: "2.0".into(),
+            ..build()
+        },
+        0xff,
+    )
+    .unwrap();
+    let header = header::read(&Image::parse(&elf).unwrap()).unwrap();
+    assert_eq!(header.build.version, "2.0");
+    assert_eq!(header, second);
+    assert_eq!(header.crc, expected_crc(&elf, 0xff));
+}
+
+#[test]
+fn ram_app() {
+    // As `app.x.in` links it: Reset, the header and the rest of the program in RAM
+    const APP: u32 = RAM_START;
+    const TEXT_START: u32 = APP + 8 + SIZE as u32;
+    let mut elf = ElfBuilder::new(APP)
+        .section(".reset", APP, APP, TEXT, [0x4e, 0x71].repeat(4))
+        .section(".image_header", APP + 8, APP + 8, RODATA, vec![0; SIZE])
+        .section(
+            ".text",
+            TEXT_START,
+            TEXT_START,
+            TEXT,
+            [0x4e, 0x71].repeat(0x10),
+        )
+        .section(
+            ".data",
+            TEXT_START + 0x20,
+            TEXT_START + 0x20,
+            DATA,
+            longs(&[1, 2]),
+        )
+        .nobits(".bss", TEXT_START + 0x28, 0x10)
+        .symbol("Reset", APP)
+        .symbol("__image_start", APP)
+        .symbol("__ram_start", RAM_START)
+        .symbol("__ram_end", RAM_END)
+        .build();
+
+    let stamped = header::stamp(&mut elf, build(), 0xff).unwrap();
+    let header = header::read(&Image::parse(&elf).unwrap()).unwrap();
+    assert_eq!(header, stamped);
+    assert_eq!(header.length, TEXT_START + 0x28 - APP);
+
+    let image = Image::parse(&elf).unwrap();
+    let mut data = Rom::from_image(&image, Some(APP), None, 0xff).unwrap().data;
+    data.drain(8 + 8..8 + 12);
+    assert_eq!(header.crc, crc32(&data));
+}
+
+#[test]
+fn round_trip() {
+    let header = Header {
+        build: build(),
+        length: 0x1234,
+        crc: 0xcafe_f00d,
+    };
+    assert_eq!(Header::parse(&header.to_bytes()), Some(header));
+    assert_eq!(Header::parse(&[0; SIZE]), None);
+    assert_eq!(Header::parse(&[0; 4]), None);
+}
+
+#[test]
+fn errors() {
+    let mut elf = image().build();
+    let long = BuildInfo {
+        version: "x".repeat(33),
+        ..build()
+    };
+    let error = header::stamp(&mut elf, long, 0xff).unwrap_err();
+    assert!(error.to_string().contains("longer than 32"), "{}", error);
+
+    // Linked against an older m68k-rt
+    let mut elf = common::image().symbol("__image_start", 0).build();
+    let error = header::stamp(&mut elf, build(), 0xff).unwrap_err();
+    assert!(error.to_string().contains("no .image_header"), "{}", error);
+
+    let mut elf = image().replace(".image_header", vec![0; 4]).build();
+    let error = header::stamp(&mut elf, build(), 0xff).unwrap_err();
+    assert!(error.to_string().contains("instead of 68"), "{}", error);
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::*;
+use m68k_image::rom::{Rom, SRecord};
+use m68k_image::Image;
+
+fn rom(builder: ElfBuilder, base: Option<u32>, size: Option<u32>) -> Rom {
+    let image = Image::parse(&builder.build()).unwrap();
+    Rom::from_image(&image, base, size, 0xff).unwrap()
+}
+
+#[test]
+fn layout() {
+    let rom = rom(image(), None, None);
+    assert_eq!(rom.base, 0);
+    // Everything up to the end of the .data load image
+    assert_eq!(rom.data.len(), 0x70);
+    assert_eq!(&rom.data[4..8], &RESET.to_be_bytes());
+    assert_eq!(&rom.data[0x64..0x68], &[0xde, 0xad, 0xbe, 0xef]);
+    // .data is stored at its LMA, .bss not at all
+    assert_eq!(&rom.data[0x68..0x70], &longs(&[1, 2])[..]);
+    assert_eq!(rom.entry, RESET);
+}
+
+#[test]
+fn padding_and_gaps() {
+    let builder = image().section(".extra", 0x80, 0x80, RODATA, vec![1, 2, 3, 4]);
+    let rom = rom(builder, Some(0), Some(0x100));
+    assert_eq!(rom.data.len(), 0x100);
+    assert!(rom.data[0x70..0x80].iter().all(|&b| b == 0xff));
+    assert_eq!(&rom.data[0x80..0x84], &[1, 2, 3, 4]);
+    assert!(rom.data[0x84..].iter().all(|&b| b == 0xff));
+}
+
+#[test]
+fn too_small() {
+    let image = Image::parse(&image().build()).unwrap();
+    let error = Rom::from_image(&image, Some(0), Some(0x40), 0xff).unwrap_err();
+    assert!(error.to_string().contains(".boot"), "{}", error);
+    let error = Rom::from_image(&image, Some(0x10), None, 0xff).unwrap_err();
+    assert!(error.to_string().contains(".vector_table"), "{}", error);
+}
+
+#[test]
+fn split() {
+    let rom = Rom {
+        base: 0,
+        data: (0..16).collect(),
+        entry: 0,
+    };
+    let lanes = rom.split(2);
+    assert_eq!(lanes[0].data, [0, 2, 4, 6, 8, 10, 12, 14]);
+    assert_eq!(lanes[1].data, [1, 3, 5, 7, 9, 11, 13, 15]);
+    let lanes = rom.split(4);
+    assert_eq!(lanes[0].data, [0, 4, 8, 12]);
+    assert_eq!(lanes[3].data, [3, 7, 11, 15]);
+}
+
+#[test]
+fn checksums() {
+    let rom = Rom {
+        base: 0,
+        data: b"123456789".to_vec(),
+        entry: 0,
+    };
+    assert_eq!(rom.crc32(), 0xCBF4_3926);
+    assert_eq!(rom.sum16(), 0x01dd);
+}
+
+#[test]
+fn srec() {
+    let mut data = vec![0x0a, 0x0a, 0x0d];
+    data.resize(16, 0);
+    let rom = Rom {
+        base: 0x7af0,
+        data,
+        entry: 0,
+    };
+    let srec = rom.to_srec(SRecord::Auto, "hello     \0\0", 16).unwrap();
+    let lines: Vec<&str> = srec.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "S00F000068656C6C6F202020202000003C",
+            "S1137AF00A0A0D0000000000000000000000000061",
+            "S5030001FB",
+            "S9030000FC",
+        ]
+    );
+}
+
+#[test]
+fn srec_address_sizes() {
+    let rom = Rom {
+        base: 0x0001_0000,
+        data: vec![0x4e, 0x71],
+        entry: 0x0001_0000,
+    };
+    let srec = rom.to_srec(SRecord::Auto, "", 32).unwrap();
+    assert!(srec.lines().nth(1).unwrap().starts_with("S206010000"));
+    assert!(srec.lines().last().unwrap().starts_with("S804010000"));
+
+    let srec = rom.to_srec(SRecord::S37, "", 32).unwrap();
+    assert!(srec.lines().nth(1).unwrap().starts_with("S30700010000"));
+    assert!(srec.lines().last().unwrap().starts_with("S70500010000"));
+
+    // Every record's checksum makes the byte sum 0xff
+    for line in srec.lines() {
+        let bytes: Vec<u8> = (2..line.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&line[i..i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(bytes.iter().fold(0u8, |s, &b| s.wrapping_add(b)), 0xff);
+    }
+}
+
+#[test]
+fn srec_too_small() {
+    let rom = Rom {
+        base: 0xfff0,
+        data: vec![0; 0x20],
+        entry: 0xfff0,
+    };
+    let error = rom.to_srec(SRecord::S19, "", 32).unwrap_err();
+    assert!(error.to_string().contains("0x0001000f"), "{}", error);
+    assert!(rom.to_srec(SRecord::S28, "", 32).is_ok());
+
+    // The entry point counts too
+    let rom = Rom {
+        base: 0,
+        data: vec![0; 0x10],
+        entry: 0x0100_0000,
+    };
+    assert!(rom.to_srec(SRecord::S28, "", 32).is_err());
+    assert!(rom.to_srec(SRecord::S37, "", 32).is_ok());
+}
+
+#[test]
+fn ihex() {
+    let rom = Rom {
+        base: 0x0100,
+        data: vec![
+            0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7e, 0xfe, 0x09, 0xd2,
+            0x19, 0x01,
+        ],
+        entry: 0xcd,
+    };
+    let lines: Vec<String> = rom.to_ihex().lines().map(String::from).collect();
+    assert_eq!(
+        lines,
+        [
+            ":020000040000FA",
+            ":10010000214601360121470136007EFE09D2190140",
+            ":04000005000000CD2A",
+            ":00000001FF",
+        ]
+    );
+}
+
+#[test]
+fn ihex_64k_boundary() {
+    let rom = Rom {
+        base: 0x0001_fff8,
+        data: vec![0; 16],
+        entry: 0,
+    };
+    let hex = rom.to_ihex();
+    let lines: Vec<&str> = hex.lines().collect();
+    assert_eq!(lines[0], ":020000040001F9");
+    assert!(lines[1].starts_with(":08FFF800"));
+    assert_eq!(lines[2], ":020000040002F8");
+    assert!(lines[3].starts_with(":08000000"));
+}
@@ -0,0 +1,124 @@
+//! Console and timer services of the `m68k-rom` monitor
+//!
+//! Programs loaded into RAM by the monitor use its drivers instead of bundling their own. Each
+//! service is a `TRAP #15` with the function number in `d0` and the arguments in `d1` and `a0`;
+//! the result, if any, comes back in `d0`. Every other register is preserved.
+//!
+//! | `d0` | Function          | Arguments                    | Result                    |
+//! |------|-------------------|------------------------------|---------------------------|
+//! | 0    | [`EXIT`]          | `d1`: exit code              | doesn't return            |
+//! | 1    | [`PUTC`]          | `d1.b`: character            |                           |
+//! | 2    | [`GETC`]          |                              | `d0.b`: character         |
+//! | 3    | [`PUTS`]          | `a0`: string, `d1`: length   |                           |
+//! | 4    | [`TICKS`]         |                              | `d0`: ticks since reset   |
+//!
+//! Unknown function numbers return [`UNKNOWN`] in `d0`. The numbers are stable: new services get
+//! new numbers.
+//!
+//! The tick counter runs at [`TICKS_PER_SECOND`] and wraps around.
+//!
+//! ```no_run
+//! use core::fmt::Write;
+//!
+//! let start = m68k_monitor::ticks();
+//! m68k_monitor::puts("press a key\n");
+//! let key = m68k_monitor::getc();
+//! let elapsed = m68k_monitor::ticks().wrapping_sub(start);
+//! let _ = writeln!(m68k_monitor::Console, "{:?} after {} ticks", key as char, elapsed);
+//! m68k_monitor::exit(0);
+//! ```
+
+#![no_std]
+#![deny(clippy::missing_inline_in_public_items)]
+#![feature(asm_experimental_arch)]
+
+use core::arch::asm;
+use core::fmt;
+
+/// Returns to the monitor's prompt
+pub const EXIT: u32 = 0;
+/// Writes a character to the console
+pub const PUTC: u32 = 1;
+/// Waits for a character from the console
+pub const GETC: u32 = 2;
+/// Writes a string to the console
+pub const PUTS: u32 = 3;
+/// Reads the tick counter
+pub const TICKS: u32 = 4;
+
+/// What unknown function numbers return
+pub const UNKNOWN: u32 = 0xffff_ffff;
+
+/// Rate of the tick counter
+pub const TICKS_PER_SECOND: u32 = 100;
+
+/// Calls a monitor service
+///
+/// # Safety
+///
+/// `a0` must be valid for the function, e.g. point at `d1` bytes for [`PUTS`].
+#[inline]
+pub unsafe fn call(function: u32, d1: u32, a0: usize) -> u32 {
+    let d0;
+    asm!(
+        "trap #15",
+        inlateout("d0") function => d0,
+        in("d1") d1,
+        in("a0") a0,
+    );
+    d0
+}
+
+/// Returns to the monitor, which prints `code`
+#[inline]
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        asm!(
+            "trap #15",
+            in("d0") EXIT,
+            in("d1") code,
+            options(noreturn),
+        )
+    }
+}
+
+/// Writes a byte to the console
+///
+/// `\n` is sent as it is; use [`puts`] or [`Console`] for `\r\n` line endings.
+#[inline]
+pub fn putc(byte: u8) {
+    unsafe {
+        call(PUTC, byte.into(), 0);
+    }
+}
+
+/// Waits for a byte from the console
+#[inline]
+pub fn getc() -> u8 {
+    unsafe { call(GETC, 0, 0) as u8 }
+}
+
+/// Writes a string to the console, turning `\n` into `\r\n`
+#[inline]
+pub fn puts(s: &str) {
+    unsafe {
+        call(PUTS, s.len() as u32, s.as_ptr() as usize);
+    }
+}
+
+/// Returns the number of ticks since the monitor started
+#[inline]
+pub fn ticks() -> u32 {
+    unsafe { call(TICKS, 0, 0) }
+}
+
+/// The console, for `write!`
+pub struct Console;
+
+impl fmt::Write for Console {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        puts(s);
+        Ok(())
+    }
+}
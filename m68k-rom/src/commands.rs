@@ -0,0 +1,226 @@
+//! The monitor's commands
+//!
+//! Numbers are hexadecimal, with an optional `0x` or `$` prefix.
+
+use core::ptr;
+use core::str::SplitWhitespace;
+
+use crate::{console, exception, load};
+
+const HELP: &str = "\
+d [addr] [len]            dump memory
+f <addr> <len> <byte>     fill memory
+m[.b|.w|.l] <addr> [val]  modify memory; without values, one at a time
+                          (Enter keeps a value, `.` stops)
+r                         show the registers saved at the last fault
+l                         load an S-record or Intel HEX file into RAM
+g [addr]                  jump to addr, or the entry point of the last load
+c <addr>                  call addr as a subroutine and show d0
+help                      show this list";
+
+/// Bytes shown by `d` without a length
+const DUMP_LEN: u32 = 64;
+
+struct State {
+    /// Where `d` without an address continues
+    next_dump: u32,
+    /// Entry point of the last program loaded
+    entry: Option<u32>,
+}
+
+static mut STATE: State = State {
+    next_dump: 0,
+    entry: None,
+};
+
+fn state() -> &'static mut State {
+    // Only the command loop uses it, and it doesn't nest
+    unsafe { &mut *ptr::addr_of_mut!(STATE) }
+}
+
+/// Parses a number
+fn number(s: &str) -> Result<u32, &'static str> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u32::from_str_radix(digits, 16).map_err(|_| "invalid number")
+}
+
+/// Parses the next argument as a number, if there is one
+fn optional(args: &mut SplitWhitespace) -> Result<Option<u32>, &'static str> {
+    args.next().map(number).transpose()
+}
+
+/// Parses the next argument as a number
+fn required(args: &mut SplitWhitespace, usage: &'static str) -> Result<u32, &'static str> {
+    optional(args)?.ok_or(usage)
+}
+
+/// Runs one command line
+pub fn run(line: &str) -> Result<(), &'static str> {
+    let mut args = line.split_whitespace();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    match command {
+        "d" => dump(&mut args),
+        "f" => fill(&mut args),
+        "m" | "m.b" => modify(&mut args, 1),
+        "m.w" => modify(&mut args, 2),
+        "m.l" => modify(&mut args, 4),
+        "r" => {
+            match exception::last() {
+                Some(registers) => exception::dump(&registers),
+                None => println!("no fault yet"),
+            }
+            Ok(())
+        }
+        "l" => {
+            let (start, end) = load::user_ram();
+            println!("RAM {:08x}-{:08x}", start, end - 1);
+            if let Some(summary) = load::load() {
+                print!("{} bytes in {} records", summary.bytes, summary.records);
+                match summary.entry {
+                    Some(entry) => println!(", entry {:08x}", entry),
+                    None => println!(),
+                }
+                state().entry = summary.entry;
+                if let Some(entry) = summary.entry {
+                    state().next_dump = entry;
+                }
+            }
+            Ok(())
+        }
+        "g" => {
+            let address = optional(&mut args)?
+                .or(state().entry)
+                .ok_or("usage: g <addr> (nothing loaded)")?;
+            unsafe { exception::go(address) }
+        }
+        "c" => {
+            let address = required(&mut args, "usage: c <addr>")?;
+            let d0 = unsafe { exception::call(address) };
+            println!("returned {:08x}", d0);
+            Ok(())
+        }
+        "help" | "?" => {
+            println!("{}", HELP);
+            Ok(())
+        }
+        _ => Err("unknown command, try `help`"),
+    }
+}
+
+fn dump(args: &mut SplitWhitespace) -> Result<(), &'static str> {
+    let start = optional(args)?.unwrap_or(state().next_dump);
+    let len = optional(args)?.unwrap_or(DUMP_LEN);
+    let end = start.saturating_add(len);
+
+    let mut line = start & !0xf;
+    while line < end {
+        print!("{:08x}:", line);
+        let mut ascii = [b' '; 16];
+        for i in 0..16 {
+            let address = line + i;
+            if address < start || address >= end {
+                print!("   ");
+                continue;
+            }
+            let byte = unsafe { ptr::read_volatile(address as *const u8) };
+            print!(" {:02x}", byte);
+            if byte.is_ascii_graphic() || byte == b' ' {
+                ascii[i as usize] = byte;
+            } else {
+                ascii[i as usize] = b'.';
+            }
+        }
+        println!("  {}", core::str::from_utf8(&ascii).unwrap_or(""));
+        line = match line.checked_add(16) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    state().next_dump = end;
+    Ok(())
+}
+
+fn fill(args: &mut SplitWhitespace) -> Result<(), &'static str> {
+    const USAGE: &str = "usage: f <addr> <len> <byte>";
+    let start = required(args, USAGE)?;
+    let len = required(args, USAGE)?;
+    let value = required(args, USAGE)?;
+    let value = u8::try_from(value).map_err(|_| "value doesn't fit in a byte")?;
+    for address in start..start.saturating_add(len) {
+        unsafe { ptr::write_volatile(address as *mut u8, value) };
+    }
+    Ok(())
+}
+
+fn read(address: u32, size: u32) -> u32 {
+    unsafe {
+        match size {
+            1 => ptr::read_volatile(address as *const u8).into(),
+            2 => ptr::read_volatile(address as *const u16).into(),
+            _ => ptr::read_volatile(address as *const u32),
+        }
+    }
+}
+
+fn write(address: u32, size: u32, value: u32) -> Result<(), &'static str> {
+    if value >> (size * 8 - 1) >> 1 != 0 {
+        return Err("value too large");
+    }
+    unsafe {
+        match size {
+            1 => ptr::write_volatile(address as *mut u8, value as u8),
+            2 => ptr::write_volatile(address as *mut u16, value as u16),
+            _ => ptr::write_volatile(address as *mut u32, value),
+        }
+    }
+    Ok(())
+}
+
+fn modify(args: &mut SplitWhitespace, size: u32) -> Result<(), &'static str> {
+    let mut address = required(args, "usage: m[.b|.w|.l] <addr> [values...]")?;
+    if size > 1 && address & 1 != 0 {
+        return Err("address must be even");
+    }
+
+    let mut values = args.peekable();
+    if values.peek().is_some() {
+        for value in values {
+            write(address, size, number(value)?)?;
+            address = address.wrapping_add(size);
+        }
+        return Ok(());
+    }
+
+    let mut buffer = [0; 16];
+    loop {
+        let width = size as usize * 2;
+        print!(
+            "{:08x}: {:0width$x} ",
+            address,
+            read(address, size),
+            width = width
+        );
+        let input = match console::read_line(&mut buffer) {
+            Some(input) => input.trim(),
+            None => return Ok(()),
+        };
+        match input {
+            "." => return Ok(()),
+            "" => {}
+            value => {
+                if let Err(error) = number(value).and_then(|value| write(address, size, value)) {
+                    println!("{}", error);
+                    continue;
+                }
+            }
+        }
+        address = address.wrapping_add(size);
+    }
+}
@@ -0,0 +1,78 @@
+//! Line-oriented console on the UART
+
+use core::fmt;
+
+use crate::uart;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+const CTRL_C: u8 = 0x03;
+
+/// Writes to the UART, turning `\n` into `\r\n`
+pub struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Writes bytes to the UART, turning `\n` into `\r\n`
+pub fn write_bytes(bytes: &[u8]) {
+    for &byte in bytes {
+        if byte == b'\n' {
+            uart::write_byte(b'\r');
+        }
+        uart::write_byte(byte);
+    }
+}
+
+macro_rules! print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let _ = write!($crate::console::Console, $($arg)*);
+    }};
+}
+
+macro_rules! println {
+    () => {
+        print!("\n")
+    };
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let _ = writeln!($crate::console::Console, $($arg)*);
+    }};
+}
+
+/// Reads a line into `buffer`, with echo and backspace
+///
+/// Returns `None` if the line was cancelled with Ctrl-C. Characters that don't fit are dropped.
+pub fn read_line(buffer: &mut [u8]) -> Option<&str> {
+    let mut len = 0;
+    loop {
+        match uart::read_byte() {
+            b'\r' | b'\n' => {
+                println!();
+                // Only printable ASCII is stored
+                return Some(core::str::from_utf8(&buffer[..len]).unwrap_or(""));
+            }
+            BACKSPACE | DELETE => {
+                if len > 0 {
+                    len -= 1;
+                    print!("\x08 \x08");
+                }
+            }
+            CTRL_C => {
+                println!("^C");
+                return None;
+            }
+            byte @ b' '..=b'~' if len < buffer.len() => {
+                buffer[len] = byte;
+                len += 1;
+                uart::write_byte(byte);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Exception handling and running programs
+//!
+//! Every exception the monitor doesn't otherwise use ends up in `__rom_fault`, which prints a
+//! crash dump and goes back to the command prompt on a fresh stack. The handlers are the ones the
+//! `m68k-rt` vector table refers to (`BusError`, `AddressError`, ...), plus the interrupt and
+//! TRAP vectors in `.vector_table.interrupts`, except for the timer's interrupt and TRAP #15. Each
+//! entry pushes its vector number, since the 68000 exception frame doesn't record it.
+
+use core::arch::{asm, global_asm};
+use core::{mem, ptr, slice};
+
+use m68k::backtrace::Backtrace;
+use m68k::register::sr::Sr;
+use m68k_rt::crash::Dump;
+
+use crate::console::Console;
+
+global_asm!(
+    ".section .text.__rom_exception, \"ax\"",
+    // One entry per vector: push the vector number and join the common path
+    ".irp n, 2,3,4,5,6,7,8,9,10,11,14,24,25,26,27,28,29,30,31,32,33,34,35,36,37,38,39,40,41,42,43,44,45,46",
+    "__rom_vector_\\n:",
+    "    move.l  #\\n, -(%sp)",
+    "    bra     __rom_exception",
+    ".endr",
+    // Registers are saved below the vector number, so `__rom_fault` sees `Saved` followed by the
+    // exception frame
+    "__rom_exception:",
+    "    movem.l %d0-%d7/%a0-%a6, -(%sp)",
+    // move.w #0x2700, %sr
+    "    .short  0x46fc, 0x2700",
+    "    move.l  %sp, -(%sp)",
+    "    jsr     __rom_fault",
+    // The names the `m68k-rt` vector table uses
+    ".global BusError, AddressError, IllegalInstruction, ZeroDivide, CHKInstruction",
+    ".global TRAPVInstruction, PrivilegeViolation, Trace, Line1010Emulator, Line1111Emulator",
+    ".global FormatError",
+    ".set BusError, __rom_vector_2",
+    ".set AddressError, __rom_vector_3",
+    ".set IllegalInstruction, __rom_vector_4",
+    ".set ZeroDivide, __rom_vector_5",
+    ".set CHKInstruction, __rom_vector_6",
+    ".set TRAPVInstruction, __rom_vector_7",
+    ".set PrivilegeViolation, __rom_vector_8",
+    ".set Trace, __rom_vector_9",
+    ".set Line1010Emulator, __rom_vector_10",
+    ".set Line1111Emulator, __rom_vector_11",
+    ".set FormatError, __rom_vector_14",
+    // Vectors 16 to 47, after the ones in `m68k-rt`
+    ".section .vector_table.interrupts, \"a\"",
+    ".global __INTERRUPTS",
+    "__INTERRUPTS:",
+    // 16-23: reserved
+    "    .long   0, 0, 0, 0, 0, 0, 0, 0",
+    // 24: spurious interrupt, 25-31: autovectors, 32-46: TRAP #0 to #14. The DUART's level goes
+    // to the timer.
+    ".irp n, 24,25,26,27,28,29,30,31,32,33,34,35,36,37,38,39,40,41,42,43,44,45,46",
+    ".if \\n == 24 + {timer}",
+    "    .long   __rom_timer",
+    ".else",
+    "    .long   __rom_vector_\\n",
+    ".endif",
+    ".endr",
+    // 47: TRAP #15, the services for programs
+    "    .long   __rom_trap15",
+    // Where a program started with `go` returns to, with its exit code in d0
+    ".section .text.__rom_returned, \"ax\"",
+    "__rom_returned:",
+    "    move.l  %d0, -(%sp)",
+    "    jsr     __rom_exited",
+    timer = const crate::timer::LEVEL,
+);
+
+/// The registers of a program, at a fault or when it was stopped
+#[derive(Clone, Copy)]
+pub struct Registers {
+    pub d: [u32; 8],
+    /// A7 is the supervisor stack pointer from before the exception
+    pub a: [u32; 8],
+    pub usp: u32,
+    pub sr: u16,
+    pub pc: u32,
+}
+
+// Registers of the last program that faulted
+static mut LAST: Option<Registers> = None;
+
+/// Returns the registers saved at the last fault
+pub fn last() -> Option<Registers> {
+    unsafe { *ptr::addr_of!(LAST) }
+}
+
+/// Prints the registers
+pub fn dump(r: &Registers) {
+    for (i, d) in r.d.iter().enumerate() {
+        print!("D{} {:08x}{}", i, d, if i % 4 == 3 { "\n" } else { "  " });
+    }
+    for (i, a) in r.a.iter().enumerate() {
+        print!("A{} {:08x}{}", i, a, if i % 4 == 3 { "\n" } else { "  " });
+    }
+    let sr = Sr::from_bits(r.sr);
+    let ccr = r.sr as u8;
+    let flag = |bit: u8, name: char| if ccr & (1 << bit) != 0 { name } else { '-' };
+    println!(
+        "PC {:08x}  SR {:04x} ({}{} I{} {}{}{}{}{})  USP {:08x}",
+        r.pc,
+        r.sr,
+        if sr.t() { 'T' } else { '-' },
+        if sr.s() { 'S' } else { 'U' },
+        sr.i(),
+        flag(4, 'X'),
+        flag(3, 'N'),
+        flag(2, 'Z'),
+        flag(1, 'V'),
+        flag(0, 'C'),
+        r.usp
+    );
+}
+
+fn name(vector: u32) -> &'static str {
+    match vector {
+        2 => "bus error",
+        3 => "address error",
+        4 => "illegal instruction",
+        5 => "divide by zero",
+        6 => "CHK instruction",
+        7 => "TRAPV instruction",
+        8 => "privilege violation",
+        9 => "trace",
+        10 => "line 1010 emulator",
+        11 => "line 1111 emulator",
+        14 => "format error",
+        24 => "spurious interrupt",
+        25..=31 => "autovector interrupt",
+        32..=47 => "TRAP",
+        _ => "exception",
+    }
+}
+
+/// What `__rom_exception` pushes on top of the exception frame
+#[repr(C)]
+struct Saved {
+    d: [u32; 8],
+    a: [u32; 7],
+    vector: u32,
+}
+
+#[no_mangle]
+extern "C" fn __rom_fault(saved: &Saved) -> ! {
+    let frame = saved as *const Saved as usize + mem::size_of::<Saved>();
+    let read_u16 = |offset: usize| unsafe { ptr::read_volatile((frame + offset) as *const u16) };
+    let read_u32 = |offset: usize| unsafe { ptr::read_volatile((frame + offset) as *const u32) };
+
+    // Bus and address errors have a longer frame: status word, access address and instruction
+    // register before the status register and program counter
+    let group0 = matches!(saved.vector, 2 | 3);
+    let (sr, pc, size) = if group0 {
+        (read_u16(8), read_u32(10), 14)
+    } else {
+        (read_u16(0), read_u32(2), 6)
+    };
+
+    let usp: u32;
+    // move.l %usp, %a0
+    unsafe { asm!(".short 0x4e68", out("a0") usp, options(nomem, nostack)) };
+
+    let mut a = [0; 8];
+    a[..7].copy_from_slice(&saved.a);
+    a[7] = (frame + size) as u32;
+    let registers = Registers {
+        d: saved.d,
+        a,
+        usp,
+        sr,
+        pc,
+    };
+    unsafe { *ptr::addr_of_mut!(LAST) = Some(registers) };
+
+    println!();
+    match saved.vector {
+        32..=47 => println!("*** TRAP #{} at {:08x}", saved.vector - 32, pc),
+        25..=31 => println!("*** level {} interrupt at {:08x}", saved.vector - 24, pc),
+        v => println!("*** {} at {:08x}", name(v), pc),
+    }
+    if group0 {
+        let status = read_u16(0);
+        println!(
+            "    {} {} at {:08x}, FC {}, IR {:04x}",
+            if status & (1 << 4) != 0 {
+                "read"
+            } else {
+                "write"
+            },
+            if status & (1 << 3) != 0 {
+                "data"
+            } else {
+                "instruction"
+            },
+            read_u32(2),
+            status & 0b111,
+            read_u16(6)
+        );
+    }
+
+    // The rest is a crash dump `cargo m68k crash` can symbolize. The backtrace only gets past
+    // the faulting function if the program's code is the monitor's.
+    let mut backtrace = [0; BACKTRACE];
+    let mut depth = 0;
+    for (slot, address) in backtrace
+        .iter_mut()
+        .zip(Backtrace::from_exception(pc, saved.a[6]).skip(1))
+    {
+        *slot = address;
+        depth += 1;
+    }
+    let top = a[7] as usize;
+    let len = STACK.min(stack_start().saturating_sub(top));
+    let stack = unsafe { slice::from_raw_parts(top as *const u8, len) };
+    let dump = Dump {
+        vector: Some(saved.vector as u8),
+        d: saved.d,
+        a,
+        usp,
+        sr,
+        pc,
+        frame: unsafe { slice::from_raw_parts(frame as *const u8, size) },
+        backtrace: &backtrace[..depth],
+        stack_address: a[7],
+        stack,
+    };
+    let _ = dump.write_text(&mut Console);
+    restart()
+}
+
+/// Most return addresses in a crash dump
+const BACKTRACE: usize = 16;
+
+/// Most bytes of the stack in a crash dump
+const STACK: usize = 64;
+
+/// Top of the monitor's stack
+fn stack_start() -> usize {
+    extern "C" {
+        static _stack_start: u32;
+    }
+
+    ptr::addr_of!(_stack_start) as usize & !0b11
+}
+
+/// Reports that a program exited and returns to the prompt
+#[export_name = "__rom_exited"]
+pub extern "C" fn exited(code: u32) -> ! {
+    println!("\nprogram exited with {:08x}", code);
+    restart()
+}
+
+/// Returns to the command prompt on an empty stack, with interrupts masked
+pub fn restart() -> ! {
+    let top = stack_start();
+    unsafe {
+        asm!(
+            // move.w #0x2700, %sr
+            ".short 0x46fc, 0x2700",
+            "move.l %a0, %sp",
+            "jmp (%a1)",
+            in("a0") top,
+            in("a1") crate::prompt as fn() -> ! as usize,
+            options(noreturn),
+        )
+    }
+}
+
+/// Calls `address` as a subroutine and returns what it left in d0
+///
+/// The monitor's registers and status register are saved around the call, so the subroutine only
+/// has to keep the stack balanced.
+pub unsafe fn call(address: u32) -> u32 {
+    let d0: u32;
+    asm!(
+        "move.w %sr, -(%sp)",
+        "movem.l %d2-%d7/%a2-%a6, -(%sp)",
+        "jsr (%a0)",
+        "movem.l (%sp)+, %d2-%d7/%a2-%a6",
+        "move.w (%sp)+, %sr",
+        inout("a0") address => _,
+        lateout("d0") d0,
+        out("d1") _,
+        out("a1") _,
+    );
+    d0
+}
+
+/// Jumps to `address` on an empty stack
+///
+/// If the program returns, its exit code is printed and the monitor starts over.
+pub unsafe fn go(address: u32) -> ! {
+    let top = stack_start();
+    asm!(
+        "move.l %a1, %sp",
+        "lea __rom_returned, %a1",
+        "move.l %a1, -(%sp)",
+        "jmp (%a0)",
+        in("a0") address,
+        in("a1") top,
+        options(noreturn),
+    )
+}
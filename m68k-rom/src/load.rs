@@ -0,0 +1,85 @@
+//! S-record download into RAM
+
+use core::ptr;
+
+use m68k_srec::{Loader, Memory, Summary};
+
+use crate::uart;
+
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1b;
+
+/// Stack kept for the monitor at the top of RAM, which programs can't be loaded into
+pub const STACK_RESERVE: u32 = 4 * 1024;
+
+/// Returns the RAM programs can be loaded into: from the end of the monitor's `.bss` up to its
+/// stack
+pub fn user_ram() -> (u32, u32) {
+    extern "C" {
+        static __ebss: u32;
+        static _stack_start: u32;
+    }
+
+    let start = ptr::addr_of!(__ebss) as u32;
+    let end = ptr::addr_of!(_stack_start) as u32 - STACK_RESERVE;
+    (start, end)
+}
+
+struct UserRam {
+    start: u32,
+    end: u32,
+}
+
+impl Memory for UserRam {
+    fn valid(&mut self, address: u32, len: usize) -> bool {
+        address >= self.start
+            && address
+                .checked_add(len as u32)
+                .is_some_and(|end| end <= self.end)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            unsafe { ptr::write_volatile((address as usize + i) as *mut u8, byte) };
+        }
+    }
+}
+
+/// Loads an S-record or Intel HEX file sent over the UART
+///
+/// Returns `None` if the download failed or was cancelled with Ctrl-C or Escape.
+pub fn load() -> Option<Summary> {
+    let (start, end) = user_ram();
+    let mut memory = UserRam { start, end };
+    let mut loader = Loader::new();
+    println!("send the file, Ctrl-C to cancel");
+    loop {
+        let byte = uart::read_byte();
+        if matches!(byte, CTRL_C | ESCAPE) {
+            println!("cancelled");
+            return None;
+        }
+        match loader.push(byte, &mut memory) {
+            Ok(Some(summary)) => return Some(summary),
+            Ok(None) => {}
+            Err(error) => {
+                // Throw away the rest of the file before printing anything
+                drain();
+                println!("{}", error);
+                return None;
+            }
+        }
+    }
+}
+
+/// Discards input until the line has been quiet for a while
+fn drain() {
+    let mut idle = 0u32;
+    while idle < 100_000 {
+        if uart::try_read_byte().is_some() {
+            idle = 0;
+        } else {
+            idle += 1;
+        }
+    }
+}
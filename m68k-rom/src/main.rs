@@ -1,35 +1,54 @@
+//! Serial monitor
+//!
+//! Talks to a terminal on channel A of an MC68681 DUART at 9600 baud, 8N1. Programs are
+//! downloaded into the RAM above the monitor's `.bss` as S-records or Intel HEX, and run with `g`
+//! (built with the `ram-app` feature of `m68k-rt`, they return to the monitor when they exit) or
+//! called as subroutines with `c`. Programs use the console and a tick counter through the
+//! TRAP #15 services in `m68k-monitor`.
+//!
+//! Any other exception prints the registers and returns to the prompt; `r` shows them
+//! again.
+
+#![feature(asm_experimental_arch)]
 #![no_main]
 #![no_std]
 
-use m68k_rt::entry;
-use m68k::asm::{Trap, trap};
 extern crate panic_abort;
 
-struct RegisterBlock {
-    pub reg: u8,
-}
+#[macro_use]
+mod console;
+mod commands;
+mod exception;
+mod load;
+mod service;
+mod timer;
+mod uart;
 
-struct Device {
-    pub p: &'static mut RegisterBlock,
-}
+use m68k_rt::entry;
 
-impl Device {
-    fn new() -> Device {
-        Device {
-            p: unsafe { &mut *(0x0080_0000 as *mut RegisterBlock) }
-        }
+#[entry]
+fn main() -> ! {
+    uart::init();
+    timer::init();
+    println!("\nm68k-rom {}", env!("CARGO_PKG_VERSION"));
+    if let Some(info) = m68k_rt::image::info() {
+        println!("image {}", info.version());
     }
+    println!("type `help` for a list of commands");
+    prompt()
 }
 
-pub fn putc() {
-    let mut uart = Device::new();
-    
-    uart.p.reg = 5;
+/// The command loop, which `exception::restart` also jumps to
+fn prompt() -> ! {
+    // Let the timer in
+    unsafe { m68k::interrupt::set(timer::LEVEL - 1) };
+    let mut buffer = [0; 80];
+    loop {
+        print!("> ");
+        if let Some(line) = console::read_line(&mut buffer) {
+            if let Err(error) = commands::run(line) {
+                println!("{}", error);
+            }
+        }
+    }
 }
-
-#[entry]
-unsafe fn main() -> ! {
-    trap(Trap::Trap0);
-    putc();
-    loop {}
-}
\ No newline at end of file
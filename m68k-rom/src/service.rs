@@ -0,0 +1,45 @@
+//! Services for programs, over TRAP #15
+//!
+//! The function numbers and registers are described in `m68k-monitor`, which is what programs
+//! call them through.
+
+use core::arch::global_asm;
+use core::slice;
+
+use m68k_monitor::{EXIT, GETC, PUTC, PUTS, TICKS, UNKNOWN};
+
+use crate::{console, exception, timer, uart};
+
+global_asm!(
+    ".section .text.__rom_trap15, \"ax\"",
+    ".global __rom_trap15",
+    "__rom_trap15:",
+    // Only d0 changes; the rest of the registers `__rom_service` may use are saved here
+    "    movem.l %d1/%a0-%a1, -(%sp)",
+    "    move.l  %a0, -(%sp)",
+    "    move.l  %d1, -(%sp)",
+    "    move.l  %d0, -(%sp)",
+    "    jsr     __rom_service",
+    "    lea     (12, %sp), %sp",
+    "    movem.l (%sp)+, %d1/%a0-%a1",
+    "    rte",
+);
+
+#[no_mangle]
+extern "C" fn __rom_service(function: u32, d1: u32, a0: u32) -> u32 {
+    match function {
+        EXIT => exception::exited(d1),
+        PUTC => {
+            uart::write_byte(d1 as u8);
+            0
+        }
+        GETC => uart::read_byte().into(),
+        PUTS => {
+            let bytes = unsafe { slice::from_raw_parts(a0 as *const u8, d1 as usize) };
+            console::write_bytes(bytes);
+            0
+        }
+        TICKS => timer::ticks(),
+        _ => UNKNOWN,
+    }
+}
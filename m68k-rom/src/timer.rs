@@ -0,0 +1,72 @@
+//! Tick counter on the DUART's counter/timer
+//!
+//! The counter/timer runs in timer mode from the 3.6864 MHz crystal divided by 16 and interrupts
+//! [`m68k_monitor::TICKS_PER_SECOND`] times a second. The board is expected to autovector the
+//! DUART's interrupt at [`LEVEL`].
+
+use core::arch::global_asm;
+use core::ptr;
+
+use crate::uart;
+
+/// Interrupt level of the DUART
+pub const LEVEL: u8 = 5;
+
+// Register numbers
+const ACR: usize = 0x4; // auxiliary control (write)
+const IMR: usize = 0x5; // interrupt mask (write)
+const CTUR: usize = 0x6; // counter/timer preload, upper byte (write)
+const CTLR: usize = 0x7; // counter/timer preload, lower byte (write)
+const START: usize = 0xe; // start counter command (read)
+const STOP: usize = 0xf; // stop counter command (read)
+
+// IMR bits
+const COUNTER_READY: u8 = 1 << 3;
+
+/// Counter/timer clock
+const CLOCK: u32 = 3_686_400 / 16;
+
+// The timer's square wave changes level every `PRELOAD` clocks and interrupts once per full
+// cycle, so each tick is `2 * PRELOAD` clocks
+const PRELOAD: u32 = CLOCK / (2 * m68k_monitor::TICKS_PER_SECOND);
+
+global_asm!(
+    ".section .text.__rom_timer, \"ax\"",
+    ".global __rom_timer",
+    "__rom_timer:",
+    "    movem.l %d0-%d1/%a0-%a1, -(%sp)",
+    "    jsr     __rom_tick",
+    "    movem.l (%sp)+, %d0-%d1/%a0-%a1",
+    "    rte",
+);
+
+// Written only by the interrupt handler. A `move.l` can't be interrupted halfway, so the other
+// readers see whole values.
+static mut TICKS: u32 = 0;
+
+/// Starts the timer and enables its interrupt in the DUART
+///
+/// The interrupt reaches the CPU once the interrupt mask is below [`LEVEL`].
+pub fn init() {
+    // Timer mode, crystal divided by 16, baud rate set 1 as set up by `uart::init`
+    uart::write(ACR, 0x70);
+    uart::write(CTUR, (PRELOAD >> 8) as u8);
+    uart::write(CTLR, PRELOAD as u8);
+    uart::read(START);
+    uart::write(IMR, COUNTER_READY);
+}
+
+/// Returns the number of ticks since the timer was started
+pub fn ticks() -> u32 {
+    unsafe { ptr::read_volatile(ptr::addr_of!(TICKS)) }
+}
+
+#[no_mangle]
+extern "C" fn __rom_tick() {
+    unsafe {
+        let ticks = ptr::addr_of_mut!(TICKS);
+        ptr::write_volatile(ticks, ptr::read_volatile(ticks).wrapping_add(1));
+    }
+    // In timer mode this only acknowledges the interrupt; the timer keeps running
+    uart::read(STOP);
+}
@@ -0,0 +1,79 @@
+//! Polled driver for channel A of an MC68681 DUART
+//!
+//! The DUART sits on the low byte of the 16-bit data bus, so its registers are at the odd
+//! addresses `BASE + 2 * n + 1`. The channel runs at 9600 baud, 8 data bits, no parity and one stop
+//! bit.
+
+use core::ptr;
+
+/// Base address of the DUART
+pub const BASE: usize = 0x0080_0000;
+
+// Register numbers; reads and writes of the same number reach different registers
+const MRA: usize = 0x0; // mode registers 1 and 2, through a pointer
+const SRA: usize = 0x1; // status (read)
+const CSRA: usize = 0x1; // clock select (write)
+const CRA: usize = 0x2; // command (write)
+const RBA: usize = 0x3; // receive buffer (read)
+const TBA: usize = 0x3; // transmit buffer (write)
+const ACR: usize = 0x4; // auxiliary control (write)
+const IMR: usize = 0x5; // interrupt mask (write)
+
+// Status register bits
+const RXRDY: u8 = 1 << 0;
+const TXRDY: u8 = 1 << 2;
+
+/// Reads DUART register `register`
+#[inline]
+pub fn read(register: usize) -> u8 {
+    unsafe { ptr::read_volatile((BASE + 2 * register + 1) as *const u8) }
+}
+
+/// Writes DUART register `register`
+#[inline]
+pub fn write(register: usize, value: u8) {
+    unsafe { ptr::write_volatile((BASE + 2 * register + 1) as *mut u8, value) }
+}
+
+/// Resets and configures channel A
+pub fn init() {
+    // Reset the receiver, the transmitter and the mode register pointer
+    write(CRA, 0x20);
+    write(CRA, 0x30);
+    write(CRA, 0x10);
+    // MR1A: no RTS control, 8 bits, no parity. MR2A: normal mode, 1 stop bit
+    write(MRA, 0x13);
+    write(MRA, 0x07);
+    // Baud rate set 1, 9600 baud both ways. `timer::init` writes ACR again, with the
+    // same baud rate set.
+    write(ACR, 0x00);
+    write(CSRA, 0xbb);
+    // No interrupts; the monitor polls. `timer::init` enables the counter's.
+    write(IMR, 0x00);
+    // Enable the receiver and the transmitter
+    write(CRA, 0x05);
+}
+
+/// Sends a byte, waiting for room in the transmitter
+pub fn write_byte(byte: u8) {
+    while read(SRA) & TXRDY == 0 {}
+    write(TBA, byte);
+}
+
+/// Returns a received byte, if there is one
+pub fn try_read_byte() -> Option<u8> {
+    if read(SRA) & RXRDY != 0 {
+        Some(read(RBA))
+    } else {
+        None
+    }
+}
+
+/// Waits for a byte
+pub fn read_byte() -> u8 {
+    loop {
+        if let Some(byte) = try_read_byte() {
+            return byte;
+        }
+    }
+}
@@ -25,7 +25,21 @@ fn main() {
     
     // Put the linker script somewhere the linker can find it
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    let link_x = include_bytes!("link.x.in");
+    // With `ram-app`, the program is loaded into RAM by a monitor instead of booting from ROM
+    let link_x = if env::var_os("CARGO_FEATURE_RAM_APP").is_some() {
+        include_str!("app.x.in")
+    } else {
+        include_str!("link.x.in")
+    };
+
+    // With `run-from-ram`, code is linked to RAM and loaded from ROM
+    let code_region = if env::var_os("CARGO_FEATURE_RUN_FROM_RAM").is_some() {
+        "RAM AT>ROM"
+    } else {
+        "ROM"
+    };
+    let link_x = link_x.replace("$CODE_REGION", code_region);
+    let link_x = link_x.as_bytes();
     if env::var_os("CARGO_FEATURE_DEVICE").is_some() {
         let mut f = File::create(out.join("link.x")).unwrap();
         
@@ -52,5 +66,6 @@ INCLUDE device.x"#
     println!("cargo:rustc-link-search={}", out.display());
     println!("cargo:rerun-if-changed=build.rs");
     println!("cargo:rerun-if-changed=link.x.in");
+    println!("cargo:rerun-if-changed=app.x.in");
     
 }
\ No newline at end of file
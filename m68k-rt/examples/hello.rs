@@ -0,0 +1,19 @@
+//! Prints through semihosting and exits, e.g. in `m68k-emu --semihosting`
+
+#![no_main]
+#![no_std]
+
+extern crate m68k_rt as rt;
+extern crate panic_m68k_semihosting;
+
+use m68k_semihosting::hprintln;
+use rt::entry;
+
+#[entry]
+fn main() -> ! {
+    let mut buffer = [0; 128];
+    let command_line = m68k_semihosting::command_line(&mut buffer).unwrap_or("");
+    hprintln!("Hello, world!");
+    hprintln!("command line: {}", command_line);
+    m68k_semihosting::exit(m68k_semihosting::EXIT_SUCCESS)
+}
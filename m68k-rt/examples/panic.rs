@@ -0,0 +1,14 @@
+//! Panics, with the report on the host's standard error, e.g. in `m68k-emu --semihosting`
+
+#![no_main]
+#![no_std]
+
+extern crate m68k_rt as rt;
+extern crate panic_m68k_semihosting;
+
+use rt::entry;
+
+#[entry]
+fn main() -> ! {
+    panic!("the answer is {}", 42)
+}
@@ -538,6 +538,45 @@ pub fn pre_init(args: TokenStream, input: TokenStream) -> TokenStream {
     .into()
 }
 
+#[proc_macro_attribute]
+pub fn ramfunc(args: TokenStream, input: TokenStream) -> TokenStream {
+    let f = parse_macro_input!(input as ItemFn);
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    if f.sig.constness.is_some() || f.sig.asyncness.is_some() {
+        return parse::Error::new(
+            f.sig.span(),
+            "`#[ramfunc]` can't be applied to `const` or `async` functions",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    if let Some(attr) = f.attrs.iter().find(|attr| eq(attr, "link_section") || eq(attr, "inline")) {
+        return parse::Error::new(
+            attr.span(),
+            "this attribute is not allowed on a function placed in RAM by m68k-rt",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // One section per function so unused ones can still be discarded by the linker
+    let section = LitStr::new(&format!(".ramtext.{}", f.sig.ident), Span::call_site());
+
+    quote!(
+        #[link_section = #section]
+        #[inline(never)]
+        #f
+    )
+    .into()
+}
+
 /// Extracts `static mut` vars from the beginning of the given statements
 fn extract_static_muts(
     stmts: impl IntoIterator<Item = Stmt>,
@@ -0,0 +1,123 @@
+//! Programs loaded into RAM by a monitor
+//!
+//! With the `ram-app` feature the program is linked with `app.x.in` instead of `link.x.in`. The
+//! whole image goes to the `RAM` region of `memory.x`, starting at `_app_start` (by default
+//! `ORIGIN(RAM)`), which should be the memory the monitor loads programs into:
+//!
+//! ```text
+//! MEMORY
+//! {
+//!   RAM (RWX) : ORIGIN = 0x00010000, LENGTH = 512K
+//! }
+//! ```
+//!
+//! There is no vector table. The monitor calls the reset handler, which is the entry point and the
+//! first thing in the image, as a subroutine. It saves the monitor's registers and status
+//! register, runs `__pre_init`, clears `.bss` (`.data` was loaded in place with the rest of the
+//! image) and other regions in the [init table](crate::region), and calls `main` on the monitor's
+//! stack.
+//!
+//! [`exit`] returns to the monitor, with the exit code in `d0`. `#[entry]` functions don't return,
+//! so a program that is done calls `exit` instead of looping forever.
+//!
+//! # Exception handlers
+//!
+//! The vector table belongs to the monitor, so handlers are installed at run time with
+//! [`set_vector`], which goes through `__monitor_set_vector`. The default routine writes to the
+//! table at `_vector_base` (0 unless `memory.x` says otherwise). A monitor that wants to know about
+//! these changes provides its own routine with the signature
+//! `extern "C" fn(number: u32, handler: u32) -> u32`, returning the previous handler.
+//!
+//! Handlers are not removed when the program exits; restore the previous ones before calling
+//! [`exit`].
+
+use core::arch::asm;
+use core::arch::global_asm;
+
+cfg_global_asm!(
+    ".section .Reset, \"ax\"
+    .global Reset
+    .type Reset,%function
+    Reset:",
+
+    // Save everything the monitor may expect to be preserved. `exit` restores it.
+    "   move.w  %sr, %d0
+        move.w  %d0, -(%sp)
+        movem.l %d2-%d7/%a2-%a6, -(%sp)",
+
+    "   jsr     __pre_init",
+
+    // Clear .bss and initialize any other regions in the init table. `.data` was loaded with the
+    // rest of the image.
+    "   jsr     __m68k_rt_init_regions",
+
+    // The monitor's stack pointer lives in .bss, so it is stored after clearing it
+    "   lea     __m68k_rt_monitor_sp, %a0
+        move.l  %sp, (%a0)",
+
+    #[cfg(feature = "verify-image")]
+    "   jsr     __m68k_rt_verify_image",
+
+    "   jsr     main
+        moveq   #0, %d0",
+
+    // Return to the monitor with the exit code in d0
+    "   .global __m68k_rt_exit
+    __m68k_rt_exit:
+        lea     __m68k_rt_monitor_sp, %a0
+        move.l  (%a0), %sp
+        movem.l (%sp)+, %d2-%d7/%a2-%a6
+        move.w  (%sp)+, %d1
+        move.w  %d1, %sr
+        rts",
+);
+
+#[doc(hidden)]
+#[no_mangle]
+static mut __m68k_rt_monitor_sp: u32 = 0;
+
+/// Returns to the monitor
+///
+/// `code` is passed to the monitor in `d0`. The stack is unwound to where the monitor called the
+/// program and its registers and status register are restored.
+#[inline]
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        asm!(
+            "lea __m68k_rt_exit, %a0",
+            "jmp (%a0)",
+            in("d0") code,
+            options(noreturn),
+        )
+    }
+}
+
+/// Installs an exception handler and returns the previous one
+///
+/// `number` is the vector number, e.g. 4 for illegal instruction or 25 for a level 1 autovector
+/// interrupt, and `handler` the address of the handler.
+///
+/// # Safety
+///
+/// `handler` must return with `rte`, and must stay valid for as long as it is installed. In
+/// particular it must be replaced before the program exits.
+pub unsafe fn set_vector(number: u8, handler: usize) -> usize {
+    extern "C" {
+        fn __monitor_set_vector(number: u32, handler: u32) -> u32;
+    }
+
+    __monitor_set_vector(u32::from(number), handler as u32) as usize
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn __m68k_rt_set_vector_default(number: u32, handler: u32) -> u32 {
+    extern "C" {
+        static mut _vector_base: u32;
+    }
+
+    let vector = core::ptr::addr_of_mut!(_vector_base).add(number as usize);
+    let previous = core::ptr::read_volatile(vector);
+    core::ptr::write_volatile(vector, handler);
+    previous
+}
@@ -0,0 +1,165 @@
+//! Core dumps, streamed a byte at a time
+//!
+//! [`write`] sends the registers of a [`crash::Dump`](crate::crash::Dump) and the contents of
+//! memory ranges through a callback, typically the transmit function of a UART, in frames that
+//! carry their own checksum. On the host, `cargo m68k core <capture> -o <core>` turns a capture of
+//! the stream into an ELF core file, for post-mortem debugging with GDB and the program's ELF
+//! file:
+//!
+//! ```text
+//! $ cargo m68k core capture.bin -o core
+//! $ m68k-elf-gdb target/m68k-unknown-none/release/examples/app core
+//! ```
+//!
+//! Every frame is:
+//!
+//! | Size | Contents                                                 |
+//! |------|----------------------------------------------------------|
+//! | 4    | [`SYNC`]                                                 |
+//! | 1    | kind                                                     |
+//! | 2    | length of the payload, big-endian                        |
+//! | n    | payload                                                  |
+//! | 4    | CRC-32 of the kind, length and payload, big-endian       |
+//!
+//! The stream is a [`START`] frame with the version and the vector (0 for none), a [`REGISTERS`]
+//! frame with `d0` to `d7`, `a0` to `a7`, `pc`, `sr` and `usp`, [`MEMORY`] frames with an address
+//! followed by at most [`CHUNK`] bytes from there, and an [`END`] frame with the number of frames
+//! before it. A corrupted frame loses only its own bytes: the host looks for the next [`SYNC`].
+//!
+//! The memory is read as it is, so the ranges must only cover memory that can be read without
+//! side effects, and without faulting.
+//!
+//! ```no_run
+//! use m68k_rt::{coredump, crash::Dump};
+//!
+//! fn fatal(dump: &Dump, transmit: fn(u8)) {
+//!     coredump::write(dump, &[coredump::ram()], &mut |byte| transmit(byte));
+//! }
+//! ```
+
+use core::arch::asm;
+use core::ops::Range;
+use core::ptr;
+
+use crate::crash::Dump;
+use crate::crc32::Crc32;
+
+/// Start of every frame
+pub const SYNC: [u8; 4] = *b"CORE";
+
+/// Version of the stream
+pub const VERSION: u8 = 1;
+
+/// Frame kind: start of a dump, with the version and vector
+pub const START: u8 = 1;
+/// Frame kind: registers
+pub const REGISTERS: u8 = 2;
+/// Frame kind: memory contents, after their address
+pub const MEMORY: u8 = 3;
+/// Frame kind: end of a dump, with the number of frames before it
+pub const END: u8 = 4;
+
+/// Most memory bytes in a frame
+pub const CHUNK: usize = 1024;
+
+/// The `RAM` region of `memory.x`
+pub fn ram() -> Range<u32> {
+    extern "C" {
+        static _ram_start: u8;
+        static _ram_end: u8;
+    }
+
+    ptr::addr_of!(_ram_start) as u32..ptr::addr_of!(_ram_end) as u32
+}
+
+/// Sends the registers in `dump` and the memory in `regions` to `out`
+///
+/// The exception frame, backtrace and stack of `dump` are not sent: they are in the memory, if
+/// `regions` covers the stack.
+pub fn write(dump: &Dump, regions: &[Range<u32>], out: &mut dyn FnMut(u8)) {
+    let mut w = Writer {
+        out,
+        crc: Crc32::new(),
+        frames: 0,
+    };
+
+    w.begin(START, 2);
+    w.bytes(&[VERSION, dump.vector.unwrap_or(0)]);
+    w.end();
+
+    w.begin(REGISTERS, 16 * 4 + 4 + 2 + 4);
+    for register in dump.d.iter().chain(&dump.a) {
+        w.bytes(&register.to_be_bytes());
+    }
+    w.bytes(&dump.pc.to_be_bytes());
+    w.bytes(&dump.sr.to_be_bytes());
+    w.bytes(&dump.usp.to_be_bytes());
+    w.end();
+
+    for region in regions {
+        let mut address = region.start;
+        while address < region.end {
+            let end = region.end.min(address.saturating_add(CHUNK as u32));
+            w.begin(MEMORY, 4 + (end - address) as u16);
+            w.bytes(&address.to_be_bytes());
+            for a in address..end {
+                w.byte(read(a));
+            }
+            w.end();
+            address = end;
+        }
+    }
+
+    let frames = w.frames;
+    w.begin(END, 4);
+    w.bytes(&frames.to_be_bytes());
+    w.end();
+}
+
+// Reads a byte with an instruction of its own rather than through a pointer: the memory may
+// start at address 0, e.g. with the `rom-shadow` feature, which a null pointer can't read
+fn read(address: u32) -> u8 {
+    let byte: u8;
+    unsafe {
+        asm!(
+            "move.b ({address}), {byte}",
+            address = in(reg_addr) address,
+            byte = out(reg_data) byte,
+            options(nostack, readonly),
+        );
+    }
+    byte
+}
+
+struct Writer<'a> {
+    out: &'a mut dyn FnMut(u8),
+    crc: Crc32,
+    frames: u32,
+}
+
+impl Writer<'_> {
+    fn begin(&mut self, kind: u8, length: u16) {
+        for byte in SYNC {
+            (self.out)(byte);
+        }
+        self.crc = Crc32::new();
+        self.byte(kind);
+        self.bytes(&length.to_be_bytes());
+    }
+
+    fn byte(&mut self, byte: u8) {
+        self.crc.update(byte);
+        (self.out)(byte);
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| self.byte(byte));
+    }
+
+    fn end(&mut self) {
+        for byte in self.crc.finish().to_be_bytes() {
+            (self.out)(byte);
+        }
+        self.frames += 1;
+    }
+}
@@ -0,0 +1,151 @@
+//! Crash dumps for `cargo m68k crash`
+//!
+//! A fault or panic handler fills in a [`Dump`] with what it knows: the registers, the exception
+//! frame as the processor pushed it, return addresses from `m68k::backtrace` and the top of the
+//! stack. [`Dump::write_text`] prints it on a console or into a log, and [`Dump::write_binary`]
+//! sends it a byte at a time, e.g. to a host program or into a
+//! [`Persistent`](crate::persistent::Persistent) buffer that survives a warm reset. On the host,
+//! `cargo m68k crash <elf> <dump>` reads either form and symbolizes it with the program's ELF
+//! file.
+//!
+//! The text form is line based, with hexadecimal numbers:
+//!
+//! ```text
+//! crash dump: vector 3
+//! D0 00000000  D1 00000001  D2 00000002  D3 00000003
+//! D4 00000004  D5 00000005  D6 00000006  D7 00000007
+//! A0 00000000  A1 00000000  A2 00000000  A3 00000000
+//! A4 00000000  A5 00000000  A6 0001ffe8  A7 0001ffd6
+//! PC 00001234  SR 2704  USP 00000000
+//! frame: 001d 0000 0001 2080 2704 0000 1234
+//! backtrace:
+//!    0: 0x00001a2c
+//!    1: 0x00001b40
+//! stack: 0x0001ffd6
+//! 0001ffd6: 00 00 1a 2c 00 01 ff f8 00 00 1b 40 00 00 00 00
+//! end of crash dump
+//! ```
+//!
+//! The binary form holds the same, big-endian:
+//!
+//! | Size   | Contents                                             |
+//! |--------|------------------------------------------------------|
+//! | 4      | [`MAGIC`]                                            |
+//! | 1      | [`VERSION`]                                          |
+//! | 1      | vector, 0 if the dump isn't for an exception         |
+//! | 64     | `d0` to `d7`, `a0` to `a7`                           |
+//! | 4      | `pc`                                                 |
+//! | 2      | `sr`                                                 |
+//! | 4      | `usp`                                                |
+//! | 2 + n  | length of the exception frame, then the frame        |
+//! | 2 + 4n | number of return addresses, then the addresses       |
+//! | 4      | address of the stack bytes                           |
+//! | 2 + n  | number of stack bytes, then the bytes                |
+//!
+//! Frames and stacks longer than 65535 bytes are cut short.
+
+use core::fmt;
+
+/// Start of a binary dump
+pub const MAGIC: [u8; 4] = *b"CRSH";
+
+/// Version of the binary form
+pub const VERSION: u8 = 1;
+
+/// What a handler knows about a crash
+#[derive(Clone, Copy, Debug)]
+pub struct Dump<'a> {
+    /// The exception being handled, or `None` for a panic
+    pub vector: Option<u8>,
+    /// Data registers
+    pub d: [u32; 8],
+    /// Address registers; `a[7]` is the stack pointer from before the exception
+    pub a: [u32; 8],
+    /// User stack pointer
+    pub usp: u32,
+    /// Status register, from the exception frame if there is one
+    pub sr: u16,
+    /// Program counter, from the exception frame if there is one
+    pub pc: u32,
+    /// The exception frame, as pushed: 6 or 14 bytes on the 68000, or a frame with a format
+    /// word on later processors
+    pub frame: &'a [u8],
+    /// Return addresses, innermost first
+    pub backtrace: &'a [u32],
+    /// Address of the first byte of `stack`
+    pub stack_address: u32,
+    /// The top of the stack
+    pub stack: &'a [u8],
+}
+
+impl Dump<'_> {
+    /// Writes the text form
+    #[inline]
+    pub fn write_text(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        match self.vector {
+            Some(vector) => writeln!(w, "crash dump: vector {}", vector)?,
+            None => writeln!(w, "crash dump")?,
+        }
+        for (name, registers) in [('D', &self.d), ('A', &self.a)] {
+            for (i, value) in registers.iter().enumerate() {
+                let end = if i % 4 == 3 { "\n" } else { "  " };
+                write!(w, "{}{} {:08x}{}", name, i, value, end)?;
+            }
+        }
+        writeln!(w, "PC {:08x}  SR {:04x}  USP {:08x}", self.pc, self.sr, self.usp)?;
+        if !self.frame.is_empty() {
+            write!(w, "frame:")?;
+            for word in self.frame.chunks(2) {
+                w.write_char(' ')?;
+                for byte in word {
+                    write!(w, "{:02x}", byte)?;
+                }
+            }
+            writeln!(w)?;
+        }
+        writeln!(w, "backtrace:")?;
+        for (i, address) in self.backtrace.iter().enumerate() {
+            writeln!(w, "{:>4}: 0x{:08x}", i, address)?;
+        }
+        if !self.stack.is_empty() {
+            writeln!(w, "stack: 0x{:08x}", self.stack_address)?;
+            for (i, line) in self.stack.chunks(16).enumerate() {
+                write!(w, "{:08x}:", self.stack_address.wrapping_add(16 * i as u32))?;
+                for byte in line {
+                    write!(w, " {:02x}", byte)?;
+                }
+                writeln!(w)?;
+            }
+        }
+        writeln!(w, "end of crash dump")
+    }
+
+    /// Sends the binary form to `out`, a byte at a time
+    #[inline]
+    pub fn write_binary(&self, out: &mut dyn FnMut(u8)) {
+        let mut bytes = |bytes: &[u8]| bytes.iter().for_each(|&byte| out(byte));
+        bytes(&MAGIC);
+        bytes(&[VERSION, self.vector.unwrap_or(0)]);
+        for register in self.d.iter().chain(&self.a) {
+            bytes(&register.to_be_bytes());
+        }
+        bytes(&self.pc.to_be_bytes());
+        bytes(&self.sr.to_be_bytes());
+        bytes(&self.usp.to_be_bytes());
+
+        let frame = &self.frame[..self.frame.len().min(u16::MAX as usize)];
+        bytes(&(frame.len() as u16).to_be_bytes());
+        bytes(frame);
+
+        let backtrace = &self.backtrace[..self.backtrace.len().min(u16::MAX as usize)];
+        bytes(&(backtrace.len() as u16).to_be_bytes());
+        for address in backtrace {
+            bytes(&address.to_be_bytes());
+        }
+
+        let stack = &self.stack[..self.stack.len().min(u16::MAX as usize)];
+        bytes(&self.stack_address.to_be_bytes());
+        bytes(&(stack.len() as u16).to_be_bytes());
+        bytes(stack);
+    }
+}
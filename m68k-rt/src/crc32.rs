@@ -0,0 +1,46 @@
+//! CRC-32 (IEEE 802.3), as used by zlib and PNG
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// Running CRC-32
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Crc32 { crc: 0xFFFF_FFFF }
+    }
+
+    #[inline]
+    pub(crate) fn update(&mut self, byte: u8) {
+        self.crc = TABLE[((self.crc ^ byte as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+    }
+
+    #[inline]
+    pub(crate) fn finish(self) -> u32 {
+        !self.crc
+    }
+}
@@ -0,0 +1,187 @@
+//! Build information embedded in the image
+//!
+//! Every image has a header in ROM, in the `.image_header` section right after the vector table.
+//! It is empty when linked and is filled in by the `cargo m68k stamp` host tool, which records the
+//! version, git commit and build time, and a CRC-32 of the whole image:
+//!
+//! ``` text
+//! $ cargo m68k stamp target/m68k-unknown-none/release/app --version 1.4.0
+//! ```
+//!
+//! [`info`] returns the header so the firmware can report which ROM it is running.
+//!
+//! # Boot-time integrity check
+//!
+//! With the `verify-image` feature the reset handler computes the CRC-32 of the image after RAM
+//! has been initialized and before `main` is called. On a mismatch, or if the image was never
+//! stamped, it calls `ImageMismatch`, which by default halts. Define your own to report the error
+//! and either halt or return to boot anyway:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub fn ImageMismatch(error: &m68k_rt::image::Error) {
+//!     // blink an LED, log the error, ...
+//!     loop {}
+//! }
+//! ```
+//!
+//! The CRC covers the ROM from the start of the vector table up to the end of the last section
+//! stored in it, with the CRC field itself skipped. Gaps between sections are part of it, so the
+//! ROM has to be programmed with the same fill byte `cargo m68k stamp` assumed (0xff by default).
+
+use core::fmt;
+use core::ptr;
+use core::str;
+
+use crate::crc32::Crc32;
+
+const MAGIC: u32 = 0x494D_4147; // "IMAG"
+
+/// The image header
+///
+/// The layout is shared with the `m68k-image` host tools.
+#[repr(C)]
+pub struct Info {
+    magic: u32,
+    length: u32,
+    crc: u32,
+    timestamp: u32,
+    git_hash: [u8; 20],
+    version: [u8; 32],
+}
+
+impl Info {
+    /// Returns the version string
+    pub fn version(&self) -> &str {
+        let len = self
+            .version
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(self.version.len());
+        str::from_utf8(&self.version[..len]).unwrap_or("")
+    }
+
+    /// Returns the SHA-1 of the git commit the image was built from, or zeros if unknown
+    pub fn git_hash(&self) -> &[u8; 20] {
+        &self.git_hash
+    }
+
+    /// Returns the build time, in seconds since the Unix epoch
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Returns the length of the image in bytes
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Returns the CRC-32 of the image
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+}
+
+impl fmt::Debug for Info {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct Hash<'a>(&'a [u8; 20]);
+        impl fmt::Debug for Hash<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+        }
+        f.debug_struct("Info")
+            .field("version", &self.version())
+            .field("git_hash", &Hash(&self.git_hash))
+            .field("timestamp", &self.timestamp)
+            .field("length", &self.length)
+            .field("crc", &format_args!("0x{:08x}", self.crc))
+            .finish()
+    }
+}
+
+/// Reasons the image failed verification
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The header was never filled in by `cargo m68k stamp`
+    NotStamped,
+    /// The contents of the ROM don't match the CRC in the header
+    Crc { expected: u32, actual: u32 },
+}
+
+// Reserved by the linker script; filled in after linking
+#[doc(hidden)]
+#[link_section = ".image_header"]
+#[no_mangle]
+#[used]
+pub static __IMAGE_HEADER: Info = Info {
+    magic: 0,
+    length: 0,
+    crc: 0,
+    timestamp: 0,
+    git_hash: [0; 20],
+    version: [0; 32],
+};
+
+extern "C" {
+    // The same header, as far as the compiler knows with unknown contents
+    static __image_header: Info;
+    static __image_start: u8;
+}
+
+/// Returns the image header, or `None` if the image was never stamped
+pub fn info() -> Option<&'static Info> {
+    let info = unsafe { &*ptr::addr_of!(__image_header) };
+    if info.magic == MAGIC {
+        Some(info)
+    } else {
+        None
+    }
+}
+
+/// Checks the image against the CRC in its header
+pub fn verify() -> Result<(), Error> {
+    let info = info().ok_or(Error::NotStamped)?;
+    let start = ptr::addr_of!(__image_start);
+    let skip = ptr::addr_of!(info.crc) as usize - start as usize;
+
+    let mut crc = Crc32::new();
+    for i in 0..info.length as usize {
+        if !(skip..skip + 4).contains(&i) {
+            crc.update(unsafe { ptr::read_volatile(start.add(i)) });
+        }
+    }
+
+    let actual = crc.finish();
+    if actual == info.crc {
+        Ok(())
+    } else {
+        Err(Error::Crc {
+            expected: info.crc,
+            actual,
+        })
+    }
+}
+
+// Called by the reset handler with the `verify-image` feature
+#[cfg(feature = "verify-image")]
+#[doc(hidden)]
+#[no_mangle]
+pub extern "C" fn __m68k_rt_verify_image() {
+    extern "Rust" {
+        fn ImageMismatch(error: &Error);
+    }
+
+    if let Err(error) = verify() {
+        unsafe { ImageMismatch(&error) };
+    }
+}
+
+#[cfg(feature = "verify-image")]
+#[doc(hidden)]
+#[no_mangle]
+#[allow(non_snake_case)]
+pub fn DefaultImageMismatch(_error: &Error) {
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
@@ -7,6 +7,7 @@
 extern crate m68k_rt_macros as macros;
 
 use core::arch::asm;
+#[cfg(not(feature = "ram-app"))]
 use core::arch::global_asm;
 use core::fmt;
 
@@ -30,10 +31,12 @@ macro_rules! cfg_global_asm {
 }
 
 // This reset vector is the initial entry point after a system reset.
-// Calls an optional user-provided __pre_init and then initialized RAM.
-// If the target has an FPU, it is enabled.
+// If enabled, leaves the boot overlay and tests RAM before anything is stored in it.
+// Calls an optional user-provided __pre_init and then initializes RAM.
 // Finally jumps to the user main function.
+// Programs loaded by a monitor (`ram-app`) use the reset handler in `app` instead.
 
+#[cfg(not(feature = "ram-app"))]
 cfg_global_asm! {
     /*
     "section Reset,code",
@@ -45,25 +48,119 @@ cfg_global_asm! {
     .type Reset,%function
     Reset:",
 
+    // If enabled, the processor starts out running from the boot overlay. Jump to the address the
+    // image is linked at and turn the overlay off. The hook can't use the stack either.
+    #[cfg(feature = "rom-shadow")]
+    "   lea     1f, %a0
+        jmp     (%a0)
+    1:  lea     1f, %a6
+        lea     __boot_overlay_disable, %a0
+        jmp     (%a0)
+    1:",
+
+    // If enabled, test RAM. This can't use the stack, so the return address is passed in a6. It
+    // comes before `__pre_init`, whose `jsr` already pushes onto the untested stack and whose
+    // stores the test would overwrite.
+    #[cfg(feature = "ram-test")]
+    "   lea     1f, %a6
+        lea     __m68k_rt_ram_test, %a0
+        jmp     (%a0)
+    1:",
+
+    // If enabled, copy .text and .rodata to RAM. Everything from here on runs from there.
+    #[cfg(feature = "run-from-ram")]
+    "   lea     __stext, %a0
+        lea     __sitext, %a1
+        move.l  #__erodata, %d1
+        bra     2f
+    1:  move.l  (%a1)+, (%a0)+
+    2:  move.l  %a0, %d0
+        cmp.l   %d1, %d0
+        bcs     1b",
+
     // Run user pre-init code which must be executed immediately after startup,
     // before the potentially time-consuming memory initiliazation takes place.
-    "   bra     __pre_init",
-
-    // If enabled, initialize RAM with zeros.
+    "   jsr     __pre_init",
+
+    // If enabled, initialize RAM with zeros. `.uninit` sits at the start of RAM and is left alone,
+    // and so are .text and .rodata after it when they were copied there.
+    #[cfg(all(feature = "zero-init-ram", not(feature = "run-from-ram")))]
+    "   lea     __euninit, %a0",
+    #[cfg(all(feature = "zero-init-ram", feature = "run-from-ram"))]
+    "   lea     __erodata, %a0",
     #[cfg(feature = "zero-init-ram")]
-    "   ; TODO: Init ram routine with _ram_start and _ram_end",
-    
-    // Initialize .bss memory. `__sbss` and `__ebss` come from the linker script.
-    // Note: sbss = Pointer to the start of .bss, ebss = Pointer to the
-    // inclusive end of the .bss section.
-    #[cfg(not(feature = "zero-init-ram"))]
-    "   ; TODO: Copy .bss section to some spot",
+    "   move.l  #_ram_end, %d1
+        moveq   #0, %d2
+        bra     2f
+    1:  move.l  %d2, (%a0)+
+    2:  move.l  %a0, %d0
+        cmp.l   %d1, %d0
+        bcs     1b",
+
+    // Initialize .data, .ramtext, .bss and any other regions listed in the init table the
+    // linker script emits. See `region`.
+    "   jsr     __m68k_rt_init_regions",
+
+    // The 68040 may have the copied code in its data cache, or stale code in its instruction cache.
+    // `cpusha %bc` (not supported by the LLVM assembler) writes back and invalidates both.
+    #[cfg(feature = "m68040")]
+    "   .short  0xf4f8",
+
+    // If enabled, check the image against the CRC in its header.
+    #[cfg(feature = "verify-image")]
+    "   jsr     __m68k_rt_verify_image",
+
+    // If enabled, paint the unused stack so `stack::high_water` can measure how much of it is used.
+    // Nothing on the stack is live at this point, so everything below the stack pointer is painted.
+    #[cfg(feature = "paint-stack")]
+    "   lea     _stack_end, %a0
+        move.l  %sp, %d1
+        move.l  #0xCCCCCCCC, %d2
+        bra     2f
+    1:  move.l  %d2, (%a0)+
+    2:  move.l  %a0, %d0
+        cmp.l   %d1, %d0
+        bcs     1b",
 
     // Jump to user main function. 
     "   jsr main
         illegal",
 }
 
+#[cfg(all(
+    feature = "ram-app",
+    any(
+        feature = "zero-init-ram",
+        feature = "ram-test",
+        feature = "paint-stack",
+        feature = "rom-shadow",
+        feature = "run-from-ram"
+    )
+))]
+compile_error!("the `ram-app` feature can't be combined with features that boot from ROM or own RAM");
+
+#[cfg(feature = "ram-app")]
+pub mod app;
+
+#[cfg(feature = "ram-test")]
+pub mod ram_test;
+
+#[cfg(feature = "paint-stack")]
+pub mod stack;
+
+pub mod shadow;
+
+pub mod persistent;
+
+pub mod crash;
+
+pub mod coredump;
+
+pub mod image;
+
+pub mod region;
+
+mod crc32;
 
 /// Attribute to declare the entry point of the program
 ///
@@ -150,6 +247,31 @@ pub use macros::entry;
 /// [rfc1414]: https://github.com/rust-lang/rfcs/blob/master/text/1414-rvalue_static_promotion.md
 pub use macros::pre_init;
 
+/// Attribute to run a function from RAM
+///
+/// The function is placed in the `.ramtext` section, which is linked to RAM and stored in ROM. The
+/// reset handler copies it to RAM together with `.data`, before `main` is called.
+///
+/// Use this for code that runs too slowly from ROM, like hot interrupt handlers or inner loops.
+/// The function is never inlined, since an inlined copy would run from wherever its caller is.
+/// Functions called from it still run from ROM unless they are marked as well.
+///
+/// With the `m68040` feature the data cache is pushed and both caches are invalidated after the
+/// copy, so the instruction cache can't hold stale contents of that RAM.
+///
+/// # Examples
+///
+/// ```
+/// # use m68k_rt::ramfunc;
+/// #[ramfunc]
+/// fn checksum(data: &[u8]) -> u8 {
+///     data.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+/// }
+///
+/// # fn main() {}
+/// ```
+pub use macros::ramfunc;
+
 /// Attribute to declare an exception handler
 ///
 /// # Syntax
@@ -272,6 +394,9 @@ pub use macros::pre_init;
 /// ```
 // pub use macros::exception;
 
+/// The exception frame of the 68000, other than for bus and address errors
+///
+/// It is also the last six bytes of an [`ExceptionFrame`], and what `rte` pops.
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct LowerExceptionFrame {
@@ -279,6 +404,32 @@ pub struct LowerExceptionFrame {
     pc: u32,
 }
 
+impl LowerExceptionFrame {
+    /// Status register at the time of the exception
+    #[inline]
+    pub fn sr(&self) -> u16 {
+        self.sr
+    }
+
+    /// Program counter to return to
+    #[inline]
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// Sets the status register `rte` restores
+    #[inline]
+    pub fn set_sr(&mut self, sr: u16) {
+        self.sr = sr;
+    }
+
+    /// Sets where `rte` returns to
+    #[inline]
+    pub fn set_pc(&mut self, pc: u32) {
+        self.pc = pc;
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct AccessInformation {
     bits: u16,
@@ -313,6 +464,20 @@ pub struct ExceptionFrame {
     pc: u32,
 }
 
+impl ExceptionFrame {
+    /// Status register at the time of the exception
+    #[inline]
+    pub fn sr(&self) -> u16 {
+        self.sr
+    }
+
+    /// Program counter at the time of the exception, near the instruction that faulted
+    #[inline]
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+}
+
 impl fmt::Debug for ExceptionFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         struct Hex(u32);
@@ -348,7 +513,10 @@ pub enum Exception {
 
 pub use self::Exception as exception;
 
+// Without a vector table, handlers are installed through `app::set_vector`
+#[cfg(not(feature = "ram-app"))]
 extern "C" {
+    #[cfg(not(feature = "rom-shadow"))]
     fn Reset() -> !;
 
     fn BusError();
@@ -374,15 +542,80 @@ extern "C" {
     fn FormatError();
 }
 
+#[cfg_attr(feature = "ram-app", allow(dead_code))]
 pub union Vector {
     handler: unsafe extern "C" fn(),
     reserved: usize,
 }
 
+/// Returns a pointer to the start of the heap
+///
+/// The returned pointer is guaranteed to be 4-byte aligned.
+#[inline]
+pub fn heap_start() -> *mut u32 {
+    extern "C" {
+        static mut _sheap: u32;
+    }
+
+    core::ptr::addr_of_mut!(_sheap)
+}
+
+/// Returns a pointer to the end of the heap
+///
+/// The heap is empty unless `_heap_size` is set in `memory.x`.
+#[inline]
+pub fn heap_end() -> *mut u32 {
+    extern "C" {
+        static mut _eheap: u32;
+    }
+
+    core::ptr::addr_of_mut!(_eheap)
+}
+
+/// Restarts the program as a reset would, without power-cycling
+///
+/// Interrupts are disabled and the `reset` instruction resets the peripherals. The stack pointer
+/// and program counter are then loaded from the first two entries of the vector table, like the
+/// processor does on reset. Persistent data in `.uninit` survives (see [`persistent`]).
+///
+/// With the `rom-shadow` feature the reset vector points into the boot overlay, which the board
+/// must turn back on when the peripherals are reset. With the `ram-app` feature the vector table
+/// is the monitor's, at `_vector_base`, so this restarts the monitor.
+#[inline]
+pub fn warm_reset() -> ! {
+    unsafe {
+        asm!(
+            // `ori.w #0x0700,%sr` and `reset`, which the LLVM assembler doesn't accept yet
+            ".short 0x007c, 0x0700",
+            ".short 0x4e70",
+            #[cfg(not(feature = "ram-app"))]
+            "lea     __vector_table, %a0",
+            #[cfg(feature = "ram-app")]
+            "lea     _vector_base, %a0",
+            "move.l  (%a0)+, %sp",
+            "move.l  (%a0), %a0",
+            "jmp     (%a0)",
+            options(noreturn),
+        )
+    }
+}
+
+#[cfg(not(any(feature = "rom-shadow", feature = "ram-app")))]
 #[link_section = ".vector_table.reset_vector"]
 #[no_mangle]
 pub static __RESET_VECTOR: unsafe extern "C" fn() -> ! = Reset;
 
+// `Reset` as seen through the boot overlay, defined by the linker script
+#[cfg(feature = "rom-shadow")]
+extern "C" {
+    fn __boot_reset() -> !;
+}
+
+#[cfg(feature = "rom-shadow")]
+#[link_section = ".vector_table.reset_vector"]
+#[no_mangle]
+pub static __RESET_VECTOR: unsafe extern "C" fn() -> ! = __boot_reset;
+
 #[doc(hidden)]
 #[no_mangle]
 pub unsafe extern "C" fn DefaultHandler_() -> ! {
@@ -394,6 +627,15 @@ pub unsafe extern "C" fn DefaultHandler_() -> ! {
 #[no_mangle]
 pub unsafe extern "C" fn DefaultPreInit() {}
 
+// rustc makes unreachable code trap, and LLVM's m68k backend traps by calling `abort`, e.g. after
+// every `asm!` with `options(noreturn)`. Nothing gets there, but the call has to link.
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn abort() -> ! {
+    asm!("illegal", options(noreturn, nomem, nostack))
+}
+
+#[cfg(not(feature = "ram-app"))]
 #[link_section = ".vector_table.exceptions"]
 #[no_mangle]
 pub static __EXCEPTIONS: [Vector; 14] = [
@@ -0,0 +1,119 @@
+//! Data that survives a warm reset
+//!
+//! The `.uninit` section is placed at the start of RAM and is never written by the reset handler,
+//! not even with the `zero-init-ram` or `ram-test` features. After a watchdog or warm reset it
+//! still holds whatever was stored there before; after power-up it holds garbage.
+//!
+//! [`Persistent`] tells the two apart by storing a magic number and a CRC-32 of the value next to
+//! it, which makes it suitable for crash logs, boot counters and reset reasons.
+//!
+//! # Example
+//!
+//! ``` no_run
+//! # #![no_main]
+//! use core::ptr;
+//!
+//! use m68k_rt::{entry, persistent::Persistent};
+//!
+//! #[link_section = ".uninit.BOOT_COUNT"]
+//! static mut BOOT_COUNT: Persistent<u32> = Persistent::uninit();
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     // Only `main` uses it, and nothing else runs yet
+//!     let boot_count = unsafe { &mut *ptr::addr_of_mut!(BOOT_COUNT) };
+//!
+//!     // Starts at 1 after power-up and counts warm resets from there
+//!     let boots = boot_count.get().unwrap_or(0) + 1;
+//!     boot_count.set(boots);
+//!
+//!     loop {}
+//! }
+//! ```
+//!
+//! A `Persistent` must be placed in a `.uninit.*` section, otherwise it is zeroed or initialized
+//! by the reset handler like any other static and never holds a valid value after reset.
+
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+
+use crate::crc32::Crc32;
+
+const MAGIC: u32 = 0x5045_5253; // "PERS"
+
+/// A value with a header that tells whether it survived a reset intact
+///
+/// The header is invalidated whenever the layout of `T` changes size, so a firmware update that
+/// changes `T` doesn't misinterpret the old contents. Changes that keep the size the same are not
+/// detected; bump a version field in `T` for those.
+#[repr(C)]
+pub struct Persistent<T: Copy> {
+    magic: u32,
+    checksum: u32,
+    value: MaybeUninit<T>,
+}
+
+impl<T: Copy> Persistent<T> {
+    /// Creates a `Persistent` with no valid value, for use as the initializer of a static
+    ///
+    /// The initializer is never actually stored because `.uninit` is not loaded.
+    #[inline]
+    pub const fn uninit() -> Self {
+        Persistent {
+            magic: 0,
+            checksum: 0,
+            value: MaybeUninit::uninit(),
+        }
+    }
+
+    #[inline]
+    fn magic() -> u32 {
+        MAGIC ^ size_of::<T>() as u32
+    }
+
+    fn checksum(&self) -> u32 {
+        let bytes = self.value.as_ptr() as *const u8;
+        let mut crc = Crc32::new();
+        for i in 0..size_of::<T>() {
+            // Volatile, because as far as the compiler knows these bytes were never written
+            crc.update(unsafe { ptr::read_volatile(bytes.add(i)) });
+        }
+        crc.finish()
+    }
+
+    /// Returns `true` if the stored value is intact
+    pub fn is_valid(&self) -> bool {
+        unsafe {
+            ptr::read_volatile(&self.magic) == Self::magic()
+                && ptr::read_volatile(&self.checksum) == self.checksum()
+        }
+    }
+
+    /// Returns the stored value, or `None` if it didn't survive
+    pub fn get(&self) -> Option<T> {
+        if self.is_valid() {
+            Some(unsafe { ptr::read_volatile(self.value.as_ptr()) })
+        } else {
+            None
+        }
+    }
+
+    /// Stores a value
+    ///
+    /// The header is invalidated first, so a reset in the middle of this call leaves no valid
+    /// value rather than a corrupted one.
+    pub fn set(&mut self, value: T) {
+        unsafe {
+            ptr::write_volatile(&mut self.magic, 0);
+            ptr::write_volatile(self.value.as_mut_ptr(), value);
+            ptr::write_volatile(&mut self.checksum, self.checksum());
+            ptr::write_volatile(&mut self.magic, Self::magic());
+        }
+    }
+
+    /// Discards the stored value
+    #[inline]
+    pub fn invalidate(&mut self) {
+        unsafe { ptr::write_volatile(&mut self.magic, 0) };
+    }
+}
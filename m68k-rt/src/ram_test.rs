@@ -0,0 +1,247 @@
+//! Power-on RAM self-test
+//!
+//! When the `ram-test` feature is enabled the reset handler tests RAM before `__pre_init` runs and
+//! before `.data` and `.bss` are initialized. Nothing in RAM can be trusted at that point, not even
+//! the stack, so the tests only use registers and are written in assembly.
+//!
+//! It can't run any later: calling `__pre_init` already pushes its return address onto the stack,
+//! and the test overwrites the whole range, stack included. RAM must therefore work without
+//! anything `__pre_init` sets up; with the `rom-shadow` feature, `__boot_overlay_disable` runs
+//! before the test and can set up e.g. a DRAM controller.
+//!
+//! Three tests run in order:
+//!
+//! 1. Data bus: walking ones written to the first long word of the tested range.
+//! 2. Address bus: power-of-two word offsets are checked for address lines stuck high, stuck low
+//!    or shorted together.
+//! 3. March C-: `⇑(w0); ⇑(r0,w1); ⇑(r1,w0); ⇓(r0,w1); ⇓(r1,w0); ⇑(r0)` over every long word.
+//!
+//! The tested range is `_ram_test_start` to `_ram_test_end`, which default to the end of
+//! `.uninit` (so [persistent](crate::persistent) data survives) and `_ram_end`. Both must be 4-byte
+//! aligned. Override them in `memory.x` to skip memory-mapped peripherals or other RAM that must
+//! survive a reset.
+//!
+//! # Reporting failures
+//!
+//! On the first failure the reset handler jumps to `__ram_test_report` with:
+//!
+//! - `d0`: the failure class ([`DATA_BUS`], [`ADDRESS_BUS`] or [`MARCH`])
+//! - `d1`: the failing address
+//! - `d2`: the expected value
+//! - `d3`: the value read back
+//! - `a6`: the return address
+//!
+//! The report routine must not touch RAM (there is no stack), must preserve `d0`-`d3` and `a6`,
+//! and returns with `jmp (%a6)`. It may also never return, for example to blink an error code
+//! forever. The default routine returns immediately.
+//!
+//! ```ignore
+//! core::arch::global_asm!(
+//!     ".section .boot.__ram_test_report, \"ax\"",
+//!     ".global __ram_test_report",
+//!     "__ram_test_report:",
+//!     "    lea     0x00f00001, %a0",   // LED port
+//!     "    move.b  %d0, (%a0)",
+//!     "    jmp     (%a6)",
+//! );
+//! ```
+//!
+//! After reporting, the processor spins in a loop that is specific to the failure class
+//! (`__m68k_rt_ram_test_halt_data_bus`, `__m68k_rt_ram_test_halt_address_bus` or
+//! `__m68k_rt_ram_test_halt_march`) with interrupts still masked, so the failure can be told apart
+//! on a logic analyzer or with a debugger even when the report routine shows nothing.
+
+use core::arch::global_asm;
+
+/// Failure class: a data line is stuck or shorted
+pub const DATA_BUS: u32 = 1;
+
+/// Failure class: an address line is stuck or shorted
+pub const ADDRESS_BUS: u32 = 2;
+
+/// Failure class: a memory cell failed the March C- test
+pub const MARCH: u32 = 3;
+
+// Register usage:
+//
+// a1 = start of the tested range, d6 = end of the tested range, a6 = return address into Reset.
+// d0-d3 carry the failure report, the remaining registers are scratch. Offsets are added to
+// addresses explicitly because indexed addressing would sign-extend them from 16 bits.
+global_asm!(
+    ".section .boot.__m68k_rt_ram_test, \"ax\"
+    .global __m68k_rt_ram_test
+    .type __m68k_rt_ram_test,%function
+    __m68k_rt_ram_test:",
+    "   lea     _ram_test_start, %a1",
+    "   move.l  #_ram_test_end, %d6",
+
+    // Data bus: walking ones.
+    "   moveq   #1, %d2",
+    "1: move.l  %d2, (%a1)",
+    "   move.l  (%a1), %d3",
+    "   cmp.l   %d2, %d3",
+    "   bne     90f",
+    "   lsl.l   #1, %d2",
+    "   bne     1b",
+
+    // Address bus: write the pattern at every power-of-two word offset.
+    "   move.l  %d6, %d5",
+    "   move.l  %a1, %d0",
+    "   sub.l   %d0, %d5",
+    "   move.l  #0xAAAA, %d2",
+    "   move.l  #0x5555, %d7",
+    "   moveq   #2, %d4",
+    "2: cmp.l   %d5, %d4",
+    "   bcc     3f",
+    "   move.l  %a1, %a2",
+    "   adda.l  %d4, %a2",
+    "   move.w  %d2, (%a2)",
+    "   lsl.l   #1, %d4",
+    "   bra     2b",
+
+    // Address lines stuck high: writing the base must not disturb any offset.
+    "3: move.w  %d7, (%a1)",
+    "   moveq   #2, %d4",
+    "4: cmp.l   %d5, %d4",
+    "   bcc     5f",
+    "   move.l  %a1, %a2",
+    "   adda.l  %d4, %a2",
+    "   moveq   #0, %d3",
+    "   move.w  (%a2), %d3",
+    "   cmp.w   %d2, %d3",
+    "   bne     91f",
+    "   lsl.l   #1, %d4",
+    "   bra     4b",
+
+    // Address lines stuck low or shorted: writing one offset must not disturb any other.
+    "5: move.w  %d2, (%a1)",
+    "   moveq   #2, %d4",
+    "6: cmp.l   %d5, %d4",
+    "   bcc     10f",
+    "   move.l  %a1, %a2",
+    "   adda.l  %d4, %a2",
+    "   move.w  %d7, (%a2)",
+    "   moveq   #0, %d3",
+    "   move.w  (%a1), %d3",
+    "   cmp.w   %d2, %d3",
+    "   bne     92f",
+    "   moveq   #2, %d1",
+    "7: cmp.l   %d5, %d1",
+    "   bcc     9f",
+    "   cmp.l   %d4, %d1",
+    "   beq     8f",
+    "   move.l  %a1, %a3",
+    "   adda.l  %d1, %a3",
+    "   moveq   #0, %d3",
+    "   move.w  (%a3), %d3",
+    "   cmp.w   %d2, %d3",
+    "   bne     93f",
+    "8: lsl.l   #1, %d1",
+    "   bra     7b",
+    "9: move.w  %d2, (%a2)",
+    "   lsl.l   #1, %d4",
+    "   bra     6b",
+
+    // March C-, M0: ⇑(w0)
+    "10: moveq  #0, %d2",
+    "   move.l  %a1, %a0",
+    "11: move.l %a0, %d0",
+    "   cmp.l   %d6, %d0",
+    "   bcc     12f",
+    "   move.l  %d2, (%a0)+",
+    "   bra     11b",
+    // M1: ⇑(r0,w1)
+    "12: moveq  #0, %d4",
+    "   moveq   #-1, %d5",
+    "   lea     13f, %a5",
+    "   bra     50f",
+    // M2: ⇑(r1,w0)
+    "13: moveq  #-1, %d4",
+    "   moveq   #0, %d5",
+    "   lea     14f, %a5",
+    "   bra     50f",
+    // M3: ⇓(r0,w1)
+    "14: moveq  #0, %d4",
+    "   moveq   #-1, %d5",
+    "   lea     15f, %a5",
+    "   bra     60f",
+    // M4: ⇓(r1,w0)
+    "15: moveq  #-1, %d4",
+    "   moveq   #0, %d5",
+    "   lea     16f, %a5",
+    "   bra     60f",
+    // M5: ⇑(r0)
+    "16: moveq  #0, %d4",
+    "   moveq   #0, %d5",
+    "   lea     17f, %a5",
+    "   bra     50f",
+    "17: jmp    (%a6)",
+
+    // Ascending march element: read and compare with d4, write d5, return through a5.
+    "50: move.l %a1, %a0",
+    "51: move.l %a0, %d0",
+    "   cmp.l   %d6, %d0",
+    "   bcc     52f",
+    "   move.l  (%a0), %d3",
+    "   cmp.l   %d4, %d3",
+    "   bne     94f",
+    "   move.l  %d5, (%a0)+",
+    "   bra     51b",
+    "52: jmp    (%a5)",
+
+    // Descending march element: read and compare with d4, write d5, return through a5.
+    "60: move.l %d6, %a0",
+    "61: move.l %a0, %d0",
+    "   move.l  %a1, %d1",
+    "   cmp.l   %d1, %d0",
+    "   bls     62f",
+    "   move.l  -(%a0), %d3",
+    "   cmp.l   %d4, %d3",
+    "   bne     94f",
+    "   move.l  %d5, (%a0)",
+    "   bra     61b",
+    "62: jmp    (%a5)",
+
+    // Failures: load the report registers and report.
+    "90: move.l %a1, %d1",
+    "   moveq   #1, %d0",
+    "   bra     99f",
+    "91: move.l %a1, %d1",
+    "   add.l   %d4, %d1",
+    "   moveq   #2, %d0",
+    "   bra     99f",
+    "92: move.l %a1, %d1",
+    "   moveq   #2, %d0",
+    "   bra     99f",
+    "93: move.l %a1, %d2",
+    "   add.l   %d2, %d1",
+    "   move.l  #0xAAAA, %d2",
+    "   moveq   #2, %d0",
+    "   bra     99f",
+    "94: move.l %a0, %d1",
+    "   move.l  %d4, %d2",
+    "   moveq   #3, %d0",
+
+    "99: lea    100f, %a6",
+    "   lea     __ram_test_report, %a0",
+    "   jmp     (%a0)",
+    "100: cmpi.l #1, %d0",
+    "   beq     __m68k_rt_ram_test_halt_data_bus",
+    "   cmpi.l  #2, %d0",
+    "   beq     __m68k_rt_ram_test_halt_address_bus",
+    "   .global __m68k_rt_ram_test_halt_march
+    __m68k_rt_ram_test_halt_march:
+        bra     __m68k_rt_ram_test_halt_march",
+    "   .global __m68k_rt_ram_test_halt_address_bus
+    __m68k_rt_ram_test_halt_address_bus:
+        bra     __m68k_rt_ram_test_halt_address_bus",
+    "   .global __m68k_rt_ram_test_halt_data_bus
+    __m68k_rt_ram_test_halt_data_bus:
+        bra     __m68k_rt_ram_test_halt_data_bus",
+
+    // Default report routine: report nothing.
+    ".global __m68k_rt_ram_test_report_default
+    .type __m68k_rt_ram_test_report_default,%function
+    __m68k_rt_ram_test_report_default:
+        jmp     (%a6)",
+);
@@ -0,0 +1,139 @@
+//! Additional memory regions
+//!
+//! Besides `ROM` and `RAM`, `memory.x` can declare any number of other regions, like fast SRAM,
+//! video RAM or battery-backed RAM, and place sections in them. Statics are moved there with
+//! `#[link_section]`.
+//!
+//! The reset handler doesn't know about these regions by name. It initializes RAM by walking the
+//! init table, which the linker script emits into ROM: a list of entries of four longs each,
+//!
+//! - kind: [`COPY`] or [`ZERO`]
+//! - load address, for `COPY`; unused for `ZERO`
+//! - start address
+//! - size in bytes, a multiple of 4
+//!
+//! terminated by a zero long. The table starts with the entries for `.data`, `.ramtext` and
+//! `.bss`. A region gets its own init or zero handling by adding entries to it from `memory.x`,
+//! in an output section inserted after `.init_table`.
+//!
+//! # Example
+//!
+//! A fast SRAM with both initialized and zeroed statics, video RAM that is cleared at reset, and
+//! battery-backed RAM that is left alone:
+//!
+//! ```text
+//! MEMORY
+//! {
+//!   ROM (RX)      : ORIGIN = 0x00000000, LENGTH = 256K
+//!   RAM (RWX)     : ORIGIN = 0x00100000, LENGTH = 1M
+//!   FASTRAM (RWX) : ORIGIN = 0x00F00000, LENGTH = 32K
+//!   VRAM (RW)     : ORIGIN = 0x00800000, LENGTH = 128K
+//!   BBRAM (RW)    : ORIGIN = 0x00E00000, LENGTH = 8K
+//! }
+//!
+//! SECTIONS
+//! {
+//!   .fastram : ALIGN(4)
+//!   {
+//!     *(.fastram .fastram.*);
+//!     . = ALIGN(4);
+//!   } > FASTRAM AT>ROM
+//!
+//!   .fastram_bss (NOLOAD) : ALIGN(4)
+//!   {
+//!     *(.fastram_bss .fastram_bss.*);
+//!     . = ALIGN(4);
+//!   } > FASTRAM
+//!
+//!   .vram (NOLOAD) : ALIGN(4)
+//!   {
+//!     *(.vram .vram.*);
+//!     . = ALIGN(4);
+//!   } > VRAM
+//!
+//!   .bbram (NOLOAD) : ALIGN(4)
+//!   {
+//!     *(.bbram .bbram.*);
+//!     . = ALIGN(4);
+//!   } > BBRAM
+//! } INSERT AFTER .got;
+//!
+//! SECTIONS
+//! {
+//!   .init_table.board : ALIGN(4)
+//!   {
+//!     LONG(1); LONG(LOADADDR(.fastram)); LONG(ADDR(.fastram)); LONG(SIZEOF(.fastram));
+//!     LONG(2); LONG(0); LONG(ADDR(.fastram_bss)); LONG(SIZEOF(.fastram_bss));
+//!     LONG(2); LONG(0); LONG(ADDR(.vram)); LONG(SIZEOF(.vram));
+//!   } > ROM
+//! } INSERT AFTER .init_table;
+//! ```
+//!
+//! ```ignore
+//! #[link_section = ".fastram.SAMPLES"]
+//! static mut SAMPLES: [i16; 4] = [1, 2, 3, 4];
+//!
+//! #[link_section = ".vram.FRAMEBUFFER"]
+//! static mut FRAMEBUFFER: [u8; 64 * 1024] = [0; 64 * 1024];
+//!
+//! #[link_section = ".bbram.SETTINGS"]
+//! static mut SETTINGS: Persistent<Settings> = Persistent::uninit();
+//! ```
+//!
+//! Statics in a zeroed or uninitialized region must not have a nonzero initializer, since nothing
+//! copies it there. Battery-backed data is best wrapped in a
+//! [`Persistent`](crate::persistent::Persistent) so it can be validated.
+//!
+//! The entries are processed in order, after `__pre_init` and after the `zero-init-ram` feature
+//! has cleared RAM. With the `ram-app` feature there is no ROM: the table is emitted into RAM, and
+//! sections that are copied are loaded `AT>RAM` by the monitor.
+
+use core::arch::global_asm;
+
+/// Init table entry kind: copy `size` bytes from the load address to the start address
+pub const COPY: u32 = 1;
+
+/// Init table entry kind: zero `size` bytes at the start address
+pub const ZERO: u32 = 2;
+
+// Walks the init table. Called by Reset with a valid stack; clobbers d0-d2 and a0-a2.
+global_asm!(
+    ".section .text.__m68k_rt_init_regions, \"ax\"
+    .global __m68k_rt_init_regions
+    .type __m68k_rt_init_regions,%function
+    __m68k_rt_init_regions:
+        lea     __init_table, %a0",
+
+    // Next entry: kind, load address, start, size. A zero kind ends the table.
+    "10:
+        move.l  (%a0)+, %d0
+        beq     30f
+        move.l  (%a0)+, %a1
+        move.l  (%a0)+, %a2
+        move.l  (%a0)+, %d1
+        move.l  %a2, %d2
+        add.l   %d2, %d1
+        cmpi.l  #1, %d0
+        beq     20f",
+
+    // ZERO
+    "   moveq   #0, %d2
+        bra     2f
+    1:  move.l  %d2, (%a2)+
+    2:  move.l  %a2, %d0
+        cmp.l   %d1, %d0
+        bcs     1b
+        bra     10b",
+
+    // COPY
+    "20:
+        bra     2f
+    1:  move.l  (%a1)+, (%a2)+
+    2:  move.l  %a2, %d0
+        cmp.l   %d1, %d0
+        bcs     1b
+        bra     10b",
+
+    "30:
+        rts",
+);
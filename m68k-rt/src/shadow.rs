@@ -0,0 +1,55 @@
+//! Booting through a ROM overlay
+//!
+//! Many boards map the ROM at address 0 during reset, so the processor can fetch its initial
+//! stack pointer and program counter, and map it at a higher address for normal operation. With
+//! the `rom-shadow` feature the image is linked to run at `ORIGIN(ROM)`, the high alias, while
+//! the reset vector points into the copy at `_boot_rom_start`:
+//!
+//! ```text
+//! MEMORY
+//! {
+//!   ROM (RX)  : ORIGIN = 0x00F80000, LENGTH = 256K
+//!   RAM (RWX) : ORIGIN = 0x00000000, LENGTH = 1M
+//! }
+//!
+//! _boot_rom_start = 0x00000000;
+//! ```
+//!
+//! The reset handler first jumps to the high alias and then calls `__boot_overlay_disable`, which
+//! should do whatever the board needs to turn the overlay off. Like the RAM test report routine it
+//! runs before anything is stored in RAM, so it can't use the stack: it gets its return address in
+//! `a6`, may use `d0`-`d7` and `a0`-`a5`, and returns with `jmp (%a6)`. The default routine does
+//! nothing.
+//!
+//! ```ignore
+//! core::arch::global_asm!(
+//!     ".section .boot.__boot_overlay_disable, \"ax\"",
+//!     ".global __boot_overlay_disable",
+//!     "__boot_overlay_disable:",
+//!     "    lea     0x00e00000, %a0",   // any write to the control latch
+//!     "    move.b  %d0, (%a0)",
+//!     "    jmp     (%a6)",
+//! );
+//! ```
+//!
+//! Exceptions are always taken through the vectors at address 0. Once the overlay is off that is
+//! usually RAM, so copy the vector table there (from `__vector_table`, it is `_stext -
+//! ORIGIN(ROM)` bytes long) in `#[pre_init]` before enabling interrupts.
+//!
+//! # Running from RAM
+//!
+//! With the `run-from-ram` feature, `.text` and `.rodata` are linked to RAM and stored in ROM, and
+//! the reset handler copies them to RAM right after the RAM test. Everything from `__pre_init`
+//! onwards runs from RAM. Only code in the `.boot` section runs from ROM: the reset handler
+//! itself, the RAM test and any register-only routines it calls, which therefore must be placed
+//! in a `.boot.*` section as in the example above. The feature can be used without `rom-shadow`.
+
+use core::arch::global_asm;
+
+global_asm!(
+    ".section .boot.__m68k_rt_boot_overlay_disable_default, \"ax\"
+    .global __m68k_rt_boot_overlay_disable_default
+    .type __m68k_rt_boot_overlay_disable_default,%function
+    __m68k_rt_boot_overlay_disable_default:
+        jmp     (%a6)",
+);
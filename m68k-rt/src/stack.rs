@@ -0,0 +1,111 @@
+//! Stack usage measurement
+//!
+//! With the `paint-stack` feature the reset handler fills the stack region, from `_stack_end` up to
+//! the initial stack pointer, with [`PAINT`] before calling `main`. Any word that no longer holds
+//! the pattern has been used by the stack at some point, so scanning upwards from `_stack_end`
+//! finds the deepest the stack has ever been.
+//!
+//! The bottom `_stack_guard_size` bytes of the region (256 by default, override it in `memory.x`)
+//! are a guard region. The stack should never reach it; [`check_guard`] reports when it has.
+//!
+//! Measurements are only as good as the pattern: a stack slot that happens to be written with
+//! [`PAINT`] looks unused.
+
+use core::fmt;
+use core::ptr;
+
+/// Value the unused stack is filled with at reset
+pub const PAINT: u32 = 0xCCCC_CCCC;
+
+extern "C" {
+    static _stack_start: u32;
+    static _stack_end: u32;
+    static _stack_guard_size: u8;
+}
+
+#[inline]
+fn top() -> usize {
+    ptr::addr_of!(_stack_start) as usize & !0b11
+}
+
+#[inline]
+fn bottom() -> usize {
+    ptr::addr_of!(_stack_end) as usize
+}
+
+#[inline]
+fn guard_size() -> usize {
+    ptr::addr_of!(_stack_guard_size) as usize
+}
+
+/// Returns the address of the lowest word that no longer holds the paint pattern
+fn lowest_used() -> usize {
+    let mut addr = bottom();
+    while addr < top() && unsafe { ptr::read_volatile(addr as *const u32) } == PAINT {
+        addr += 4;
+    }
+    addr
+}
+
+/// Returns the size of the stack region in bytes, including the guard region
+#[inline]
+pub fn size() -> usize {
+    top() - bottom()
+}
+
+/// Returns the largest number of bytes the stack has used since reset
+pub fn high_water() -> usize {
+    top() - lowest_used()
+}
+
+/// Returns the number of bytes of stack that have never been used since reset
+///
+/// This includes the guard region.
+pub fn free() -> usize {
+    lowest_used() - bottom()
+}
+
+/// The stack has grown into the guard region
+#[derive(Clone, Copy)]
+pub struct GuardViolation {
+    lowest: usize,
+}
+
+impl GuardViolation {
+    /// Returns the lowest address the stack has written to
+    #[inline]
+    pub fn lowest(&self) -> usize {
+        self.lowest
+    }
+
+    /// Returns how many bytes of the guard region have been overwritten
+    #[inline]
+    pub fn depth(&self) -> usize {
+        bottom() + guard_size() - self.lowest
+    }
+}
+
+impl fmt::Debug for GuardViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "stack overflowed {} bytes into the guard region (lowest address 0x{:08x})",
+            self.depth(),
+            self.lowest
+        )
+    }
+}
+
+/// Checks that the guard region at the bottom of the stack still holds the paint pattern
+///
+/// Call this periodically, for example from the main loop or a timer interrupt, and report the
+/// error through whatever channel the board has.
+pub fn check_guard() -> Result<(), GuardViolation> {
+    let lowest = lowest_used();
+
+    if lowest < bottom() + guard_size() {
+        Err(GuardViolation { lowest })
+    } else {
+        Ok(())
+    }
+}
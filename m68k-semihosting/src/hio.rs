@@ -0,0 +1,96 @@
+//! Host I/O: the console and the host's files
+
+use core::fmt;
+
+use crate::{syscall, syscall_block, Error, CLOSE, OPEN, READ, WRITE};
+
+/// How [`open`] opens a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// An existing file, for reading
+    Read = 0,
+    /// Created, or truncated if it exists, for writing
+    Write = 1,
+    /// Created if needed, for writing at the end
+    Append = 2,
+    /// An existing file, for reading and writing
+    ReadWrite = 3,
+}
+
+/// A handle to the host's console or to a file
+///
+/// Handles aren't closed when they are dropped; call [`HostStream::close`] on files.
+pub struct HostStream {
+    handle: u32,
+}
+
+impl HostStream {
+    /// The host's number for the handle
+    #[inline]
+    pub fn handle(&self) -> u32 {
+        self.handle
+    }
+
+    /// Reads into `buffer`, returning the number of bytes read, 0 at the end of a file
+    #[inline]
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let block = [self.handle, buffer.as_mut_ptr() as u32, buffer.len() as u32];
+        unsafe { syscall_block(READ, &block).map(|n| n as usize) }
+    }
+
+    /// Writes all of `buffer`
+    #[inline]
+    pub fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), Error> {
+        while !buffer.is_empty() {
+            let block = [self.handle, buffer.as_ptr() as u32, buffer.len() as u32];
+            let n = unsafe { syscall_block(WRITE, &block)? } as usize;
+            if n == 0 || n > buffer.len() {
+                return Err(Error::io());
+            }
+            buffer = &buffer[n..];
+        }
+        Ok(())
+    }
+
+    /// Closes the handle
+    #[inline]
+    pub fn close(self) -> Result<(), Error> {
+        match unsafe { syscall(CLOSE, self.handle as usize) } {
+            0 => Ok(()),
+            _ => Err(Error::last()),
+        }
+    }
+}
+
+impl fmt::Write for HostStream {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// Opens a file on the host, relative to the host's working directory
+#[inline]
+pub fn open(path: &str, mode: Mode) -> Result<HostStream, Error> {
+    let block = [path.as_ptr() as u32, path.len() as u32, mode as u32];
+    let handle = unsafe { syscall_block(OPEN, &block)? };
+    Ok(HostStream { handle })
+}
+
+/// The host's standard input
+#[inline]
+pub fn hstdin() -> HostStream {
+    HostStream { handle: 0 }
+}
+
+/// The host's standard output
+#[inline]
+pub fn hstdout() -> HostStream {
+    HostStream { handle: 1 }
+}
+
+/// The host's standard error
+#[inline]
+pub fn hstderr() -> HostStream {
+    HostStream { handle: 2 }
+}
@@ -0,0 +1,206 @@
+//! Host calls over `TRAP #14`
+//!
+//! Semihosting lets a program use the console and files of the machine it is debugged or
+//! emulated from, before it has drivers of its own, and exit with a status that tests can check.
+//! `m68k-emu --semihosting` serves the calls; on hardware, a debugger or monitor has to.
+//!
+//! Each call is a `TRAP #14` with the operation in `d0` and its argument in `d1`: a value, or the
+//! address of a block of long words. The result comes back in `d0`, or [`FAILED`] for an error
+//! whose `errno` [`ERRNO`] returns. Every other register is preserved.
+//!
+//! | `d0` | Operation        | `d1`                                  | Result in `d0`                 |
+//! |------|------------------|---------------------------------------|--------------------------------|
+//! | 0    | [`EXIT`]         | exit code                             | doesn't return                 |
+//! | 1    | [`OPEN`]         | block: path, path length, mode        | handle                         |
+//! | 2    | [`CLOSE`]        | handle                                | 0                              |
+//! | 3    | [`READ`]         | block: handle, buffer, buffer length  | bytes read, 0 at end of file   |
+//! | 4    | [`WRITE`]        | block: handle, data, data length      | bytes written                  |
+//! | 5    | [`TIME`]         |                                       | seconds since 1970             |
+//! | 6    | [`CLOCK`]        |                                       | hundredths of a second since reset |
+//! | 7    | [`COMMAND_LINE`] | block: buffer, buffer length          | length of the command line     |
+//! | 8    | [`ERRNO`]        |                                       | `errno` of the last failed call |
+//!
+//! Handles 0, 1 and 2 are standard input, output and error, and are always open. Reading
+//! standard input waits for at least one byte. The modes of [`OPEN`] are those of [`hio::Mode`].
+//! The numbers are stable: new operations get new numbers.
+//!
+//! ```no_run
+//! use m68k_semihosting::{hio, hprintln};
+//!
+//! hprintln!("started {} seconds after 1970", m68k_semihosting::time());
+//! let mut file = hio::open("out.txt", hio::Mode::Write).unwrap();
+//! file.write_all(b"written by the target\n").unwrap();
+//! file.close().unwrap();
+//! m68k_semihosting::exit(0);
+//! ```
+
+#![no_std]
+#![deny(clippy::missing_inline_in_public_items)]
+#![feature(asm_experimental_arch)]
+
+use core::arch::asm;
+use core::fmt;
+use core::str;
+
+#[macro_use]
+mod macros;
+
+pub mod hio;
+
+/// Ends the program with an exit code
+pub const EXIT: u32 = 0;
+/// Opens a host file
+pub const OPEN: u32 = 1;
+/// Closes a handle
+pub const CLOSE: u32 = 2;
+/// Reads from a handle
+pub const READ: u32 = 3;
+/// Writes to a handle
+pub const WRITE: u32 = 4;
+/// Returns the host's time
+pub const TIME: u32 = 5;
+/// Returns the time since reset
+pub const CLOCK: u32 = 6;
+/// Copies the command line
+pub const COMMAND_LINE: u32 = 7;
+/// Returns the `errno` of the last failed call
+pub const ERRNO: u32 = 8;
+
+/// What failed calls return
+pub const FAILED: u32 = 0xffff_ffff;
+
+// `errno` for an I/O error, as on POSIX hosts
+const EIO: u32 = 5;
+
+/// Exit code for success
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code for failure
+pub const EXIT_FAILURE: i32 = 1;
+
+/// A failed call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    errno: u32,
+}
+
+impl Error {
+    /// The host's `errno` for the failure
+    #[inline]
+    pub fn errno(self) -> u32 {
+        self.errno
+    }
+
+    fn last() -> Self {
+        Error {
+            errno: unsafe { syscall(ERRNO, 0) },
+        }
+    }
+
+    // For a result the call can't have, e.g. a write of none or more than all of the bytes
+    fn io() -> Self {
+        Error { errno: EIO }
+    }
+}
+
+impl fmt::Display for Error {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "host call failed with errno {}", self.errno)
+    }
+}
+
+/// Makes a host call
+///
+/// # Safety
+///
+/// `argument` must be valid for the operation, e.g. point at a block whose buffer can take the
+/// bytes [`READ`] stores.
+#[inline]
+pub unsafe fn syscall(operation: u32, argument: usize) -> u32 {
+    let d0;
+    asm!(
+        "trap #14",
+        inlateout("d0") operation => d0,
+        in("d1") argument,
+    );
+    d0
+}
+
+/// Makes a host call with a block of arguments, turning [`FAILED`] into an error
+///
+/// # Safety
+///
+/// As for [`syscall`].
+#[inline]
+pub unsafe fn syscall_block<const N: usize>(
+    operation: u32,
+    block: &[u32; N],
+) -> Result<u32, Error> {
+    check(syscall(operation, block.as_ptr() as usize))
+}
+
+fn check(result: u32) -> Result<u32, Error> {
+    match result {
+        FAILED => Err(Error::last()),
+        result => Ok(result),
+    }
+}
+
+/// Ends the program, with `code` as the host's exit status
+#[inline]
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        asm!(
+            "trap #14",
+            in("d0") EXIT,
+            in("d1") code,
+            options(noreturn),
+        )
+    }
+}
+
+/// Returns the host's time, in seconds since 1970
+#[inline]
+pub fn time() -> u32 {
+    unsafe { syscall(TIME, 0) }
+}
+
+/// Returns the time since reset, in hundredths of a second
+#[inline]
+pub fn clock() -> u32 {
+    unsafe { syscall(CLOCK, 0) }
+}
+
+/// Copies the command line into `buffer`, and returns it
+///
+/// It is the program and its arguments separated by spaces. The call fails with `E2BIG` if it
+/// doesn't fit.
+#[inline]
+pub fn command_line(buffer: &mut [u8]) -> Result<&str, Error> {
+    let block = [buffer.as_mut_ptr() as u32, buffer.len() as u32];
+    let len = unsafe { syscall_block(COMMAND_LINE, &block)? };
+    // The host passes UTF-8; cut at a broken character rather than fail
+    let line = &buffer[..len as usize];
+    Ok(match str::from_utf8(line) {
+        Ok(line) => line,
+        Err(e) => str::from_utf8(&line[..e.valid_up_to()]).unwrap_or_default(),
+    })
+}
+
+/// Used by the macros. Do not use directly.
+#[doc(hidden)]
+pub mod export {
+    use core::fmt::{self, Write};
+
+    use crate::hio;
+
+    #[inline]
+    pub fn hstdout_fmt(args: fmt::Arguments) {
+        let _ = hio::hstdout().write_fmt(args);
+    }
+
+    #[inline]
+    pub fn hstderr_fmt(args: fmt::Arguments) {
+        let _ = hio::hstderr().write_fmt(args);
+    }
+}
@@ -0,0 +1,45 @@
+/// Prints to the host's standard output
+///
+/// Errors are ignored.
+#[macro_export]
+macro_rules! hprint {
+    ($($arg:tt)*) => {
+        $crate::export::hstdout_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Prints to the host's standard output, with a newline
+///
+/// Errors are ignored.
+#[macro_export]
+macro_rules! hprintln {
+    () => {
+        $crate::hprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::export::hstdout_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Prints to the host's standard error
+///
+/// Errors are ignored.
+#[macro_export]
+macro_rules! heprint {
+    ($($arg:tt)*) => {
+        $crate::export::hstderr_fmt(format_args!($($arg)*))
+    };
+}
+
+/// Prints to the host's standard error, with a newline
+///
+/// Errors are ignored.
+#[macro_export]
+macro_rules! heprintln {
+    () => {
+        $crate::heprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::export::hstderr_fmt(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,404 @@
+//! Streaming Motorola S-record and Intel HEX parser
+//!
+//! [`Parser`] takes its input one byte at a time, as it arrives from a serial port, and returns
+//! each [`Record`] as soon as its checksum has been read. It needs no allocator and keeps at most
+//! one record in memory, so the same code runs in a ROM monitor and in host tools.
+//!
+//! Both formats are accepted, record by record: records starting with `S` are S-records and
+//! records starting with `:` Intel HEX. Whitespace between records, including `\r\n` line
+//! endings, is ignored; anything else is an error. Line endings are optional.
+//!
+//! [`Loader`] sits on top of the parser and turns the records into writes to a [`Memory`], which
+//! decides which addresses may be written.
+//!
+//! # Example
+//!
+//! ```
+//! use m68k_srec::{Parser, Record};
+//!
+//! let mut parser = Parser::new();
+//! for &byte in b"S1070010DEADBEEFB0\r\nS9030010EC\r\n" {
+//!     match parser.push(byte).unwrap() {
+//!         Some(Record::Data { address, data }) => {
+//!             assert_eq!(address, 0x10);
+//!             assert_eq!(data, [0xde, 0xad, 0xbe, 0xef]);
+//!         }
+//!         Some(Record::End { entry }) => assert_eq!(entry, Some(0x10)),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+#![no_std]
+
+mod loader;
+
+use core::fmt;
+
+pub use crate::loader::{Loader, Memory, Region, Summary};
+
+/// Longest record, in bytes after hex decoding: an Intel HEX record with 255 data bytes
+const MAX_RECORD: usize = 4 + 255 + 1;
+
+/// A decoded record
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Record<'a> {
+    /// S0 header, usually the name of the file
+    Header(&'a [u8]),
+    /// `data` is to be written at `address`
+    ///
+    /// Intel HEX addresses already include the extended segment or linear address.
+    Data { address: u32, data: &'a [u8] },
+    /// S5 or S6 record: the number of data records so far
+    Count(u32),
+    /// Intel HEX start address (type 03 or 05)
+    ///
+    /// A type 03 start segment address is converted to a linear address, `CS * 16 + IP`.
+    Entry(u32),
+    /// End of file: an S7, S8 or S9 record, which carry the entry point, or an Intel HEX end of
+    /// file record (type 01), which doesn't
+    End { entry: Option<u32> },
+}
+
+/// What went wrong
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A character that isn't valid at this point
+    InvalidCharacter(u8),
+    /// A record type this parser doesn't know, like S4 or Intel HEX type 06
+    UnknownType(u8),
+    /// The line ended before the number of bytes given by the record's length field
+    TooShort,
+    /// The line goes on after the number of bytes given by the record's length field
+    TooLong,
+    /// The length field is impossible for the record type
+    InvalidLength,
+    /// The checksum doesn't match the contents of the record
+    Checksum { expected: u8, actual: u8 },
+    /// The data runs past the end of the address range of the record type
+    AddressOverflow,
+    /// [`Memory::valid`] refused a write
+    Rejected { address: u32, len: usize },
+    /// An S5 or S6 record doesn't match the number of data records
+    Count { expected: u32, actual: u32 },
+    /// The input ended before an end of file record
+    MissingEnd,
+}
+
+/// A parse error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error {
+    /// Line the error was found on, starting at 1
+    pub line: u32,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::InvalidCharacter(c) if c.is_ascii_graphic() => {
+                write!(f, "unexpected character `{}`", c as char)
+            }
+            ErrorKind::InvalidCharacter(c) => write!(f, "unexpected character 0x{:02x}", c),
+            ErrorKind::UnknownType(t) => write!(f, "unknown record type {}", t),
+            ErrorKind::TooShort => f.write_str("record shorter than its length field"),
+            ErrorKind::TooLong => f.write_str("record longer than its length field"),
+            ErrorKind::InvalidLength => f.write_str("invalid length for the record type"),
+            ErrorKind::Checksum { expected, actual } => write!(
+                f,
+                "checksum mismatch: record says 0x{:02x}, contents sum to 0x{:02x}",
+                expected, actual
+            ),
+            ErrorKind::AddressOverflow => {
+                f.write_str("data runs past the end of the address range")
+            }
+            ErrorKind::Rejected { address, len } => write!(
+                f,
+                "{} bytes at 0x{:08x} are outside the writable memory",
+                len, address
+            ),
+            ErrorKind::Count { expected, actual } => write!(
+                f,
+                "record count mismatch: count record says {}, found {}",
+                expected, actual
+            ),
+            ErrorKind::MissingEnd => f.write_str("no end of file record"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Between records
+    Idle,
+    /// After `S`, waiting for the type digit
+    Type,
+    /// Reading hex digit pairs
+    Bytes,
+    /// The record is complete; only whitespace may follow on this line
+    Trailer,
+    /// After an error, up to the end of the line
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    SRecord,
+    IntelHex,
+}
+
+/// Streaming record parser
+pub struct Parser {
+    state: State,
+    format: Format,
+    kind: u8,
+    buffer: [u8; MAX_RECORD],
+    len: usize,
+    expected: usize,
+    high: Option<u8>,
+    line: u32,
+    /// Intel HEX extended segment or linear address
+    base: u32,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    /// Creates a parser at the start of a file
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Idle,
+            format: Format::SRecord,
+            kind: 0,
+            buffer: [0; MAX_RECORD],
+            len: 0,
+            expected: 0,
+            high: None,
+            line: 1,
+            base: 0,
+        }
+    }
+
+    /// Forgets any partial record and starts over at line 1
+    pub fn reset(&mut self) {
+        *self = Parser::new();
+    }
+
+    /// Returns the current line number, starting at 1
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// Returns `true` if the parser is between records, i.e. the input may end here
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::Idle | State::Trailer)
+    }
+
+    /// Feeds one byte of input
+    ///
+    /// Returns the record that `byte` completes, if any. After an error the rest of the line is
+    /// skipped, so the parser can carry on with the next record.
+    pub fn push(&mut self, byte: u8) -> Result<Option<Record<'_>>, Error> {
+        let line = self.line;
+        if byte == b'\n' {
+            self.line = self.line.wrapping_add(1);
+        }
+        match self.step(byte) {
+            Ok(true) => self.record().map_err(|kind| Error { line, kind }),
+            Ok(false) => Ok(None),
+            Err(kind) => {
+                self.state = if matches!(byte, b'\n' | b'\r') {
+                    State::Idle
+                } else {
+                    State::Skip
+                };
+                Err(Error { line, kind })
+            }
+        }
+    }
+
+    // Advances the state machine; returns `true` when a record is complete
+    fn step(&mut self, byte: u8) -> Result<bool, ErrorKind> {
+        match self.state {
+            State::Skip => {
+                if matches!(byte, b'\n' | b'\r') {
+                    self.state = State::Idle;
+                }
+                Ok(false)
+            }
+            State::Idle | State::Trailer => match byte {
+                b'S' | b's' => {
+                    self.format = Format::SRecord;
+                    self.state = State::Type;
+                    Ok(false)
+                }
+                b':' => {
+                    self.format = Format::IntelHex;
+                    self.start();
+                    Ok(false)
+                }
+                b'\n' | b'\r' => {
+                    self.state = State::Idle;
+                    Ok(false)
+                }
+                b' ' | b'\t' => Ok(false),
+                _ if self.state == State::Trailer => Err(ErrorKind::TooLong),
+                _ => Err(ErrorKind::InvalidCharacter(byte)),
+            },
+            State::Type => match byte {
+                b'0'..=b'9' => {
+                    self.kind = byte - b'0';
+                    if self.kind == 4 {
+                        return Err(ErrorKind::UnknownType(4));
+                    }
+                    self.start();
+                    Ok(false)
+                }
+                b'\n' | b'\r' => Err(ErrorKind::TooShort),
+                _ => Err(ErrorKind::InvalidCharacter(byte)),
+            },
+            State::Bytes => {
+                let nibble = match byte {
+                    b'0'..=b'9' => byte - b'0',
+                    b'a'..=b'f' => byte - b'a' + 10,
+                    b'A'..=b'F' => byte - b'A' + 10,
+                    b'\n' | b'\r' => return Err(ErrorKind::TooShort),
+                    _ => return Err(ErrorKind::InvalidCharacter(byte)),
+                };
+                let Some(high) = self.high.take() else {
+                    self.high = Some(nibble);
+                    return Ok(false);
+                };
+                self.buffer[self.len] = (high << 4) | nibble;
+                self.len += 1;
+
+                if self.len == 1 {
+                    // The length field gives the size of the rest of the record
+                    let count = usize::from(self.buffer[0]);
+                    self.expected = match self.format {
+                        // There is always at least the checksum
+                        Format::SRecord if count == 0 => return Err(ErrorKind::InvalidLength),
+                        Format::SRecord => 1 + count,
+                        Format::IntelHex => 1 + 3 + count + 1,
+                    };
+                }
+                if self.len < self.expected {
+                    return Ok(false);
+                }
+
+                self.state = State::Trailer;
+                let sum = self.buffer[..self.len]
+                    .iter()
+                    .fold(0u8, |sum, &b| sum.wrapping_add(b));
+                let checksum = self.buffer[self.len - 1];
+                let (ok, actual) = match self.format {
+                    // One's complement of the sum of everything but the checksum
+                    Format::SRecord => (sum == 0xff, !sum.wrapping_sub(checksum)),
+                    // Two's complement
+                    Format::IntelHex => (sum == 0, sum.wrapping_sub(checksum).wrapping_neg()),
+                };
+                if ok {
+                    Ok(true)
+                } else {
+                    Err(ErrorKind::Checksum {
+                        expected: checksum,
+                        actual,
+                    })
+                }
+            }
+        }
+    }
+
+    fn start(&mut self) {
+        self.state = State::Bytes;
+        self.len = 0;
+        self.expected = usize::MAX;
+        self.high = None;
+    }
+
+    // Interprets the complete record in the buffer. Intel HEX extended address records only
+    // change the state of the parser and don't return anything.
+    fn record(&mut self) -> Result<Option<Record<'_>>, ErrorKind> {
+        match self.format {
+            Format::SRecord => self.srecord().map(Some),
+            Format::IntelHex => self.intel_hex(),
+        }
+    }
+
+    fn srecord(&self) -> Result<Record<'_>, ErrorKind> {
+        let address_len = match self.kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            _ => 4,
+        };
+        // Length field, address, data, checksum
+        let body = &self.buffer[1..self.len - 1];
+        if body.len() < address_len {
+            return Err(ErrorKind::InvalidLength);
+        }
+        let address = body[..address_len]
+            .iter()
+            .fold(0u32, |a, &b| (a << 8) | u32::from(b));
+        let data = &body[address_len..];
+
+        match self.kind {
+            0 => Ok(Record::Header(data)),
+            1..=3 => {
+                let end = u64::from(address) + data.len() as u64;
+                if end > 1 << (8 * address_len) {
+                    return Err(ErrorKind::AddressOverflow);
+                }
+                Ok(Record::Data { address, data })
+            }
+            _ if !data.is_empty() => Err(ErrorKind::InvalidLength),
+            5 | 6 => Ok(Record::Count(address)),
+            _ => Ok(Record::End {
+                entry: Some(address),
+            }),
+        }
+    }
+
+    fn intel_hex(&mut self) -> Result<Option<Record<'_>>, ErrorKind> {
+        let offset = u32::from(u16::from_be_bytes([self.buffer[1], self.buffer[2]]));
+        let kind = self.buffer[3];
+        let data = &self.buffer[4..self.len - 1];
+        let value = data.iter().fold(0u32, |a, &b| (a << 8) | u32::from(b));
+
+        match kind {
+            0 => {
+                let address = u64::from(self.base) + u64::from(offset);
+                if address + data.len() as u64 > 1 << 32 {
+                    return Err(ErrorKind::AddressOverflow);
+                }
+                Ok(Some(Record::Data {
+                    address: address as u32,
+                    data,
+                }))
+            }
+            1 if data.is_empty() => {
+                self.base = 0;
+                Ok(Some(Record::End { entry: None }))
+            }
+            2 | 4 if data.len() == 2 => {
+                self.base = if kind == 2 { value << 4 } else { value << 16 };
+                Ok(None)
+            }
+            3 if data.len() == 4 => {
+                Ok(Some(Record::Entry(((value >> 16) << 4) + (value & 0xffff))))
+            }
+            5 if data.len() == 4 => Ok(Some(Record::Entry(value))),
+            1..=5 => Err(ErrorKind::InvalidLength),
+            _ => Err(ErrorKind::UnknownType(kind)),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Loading records into memory
+
+use crate::{Error, ErrorKind, Parser, Record};
+
+/// Where a [`Loader`] writes the data records
+pub trait Memory {
+    /// Returns `true` if `len` bytes may be written at `address`
+    ///
+    /// Called before every write, so the loader can refuse a file that would overwrite the
+    /// monitor, the vector table or I/O space before any of it is written.
+    fn valid(&mut self, address: u32, len: usize) -> bool;
+
+    /// Writes `data` at `address`
+    fn write(&mut self, address: u32, data: &[u8]);
+}
+
+/// A [`Memory`] backed by a byte slice that stands for the addresses starting at `base`
+pub struct Region<'a> {
+    base: u32,
+    memory: &'a mut [u8],
+}
+
+impl<'a> Region<'a> {
+    pub fn new(base: u32, memory: &'a mut [u8]) -> Self {
+        Region { base, memory }
+    }
+}
+
+impl Memory for Region<'_> {
+    fn valid(&mut self, address: u32, len: usize) -> bool {
+        address >= self.base
+            && ((address - self.base) as usize)
+                .checked_add(len)
+                .is_some_and(|end| end <= self.memory.len())
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) {
+        let offset = (address - self.base) as usize;
+        self.memory[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+/// What a file contained
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Entry point, from the S7, S8 or S9 record or the Intel HEX start address record
+    ///
+    /// Many tools write an S9 record with address 0 when there is no entry point.
+    pub entry: Option<u32>,
+    /// Number of data records
+    pub records: u32,
+    /// Number of bytes written
+    pub bytes: u32,
+}
+
+/// Writes the data records of a file to a [`Memory`]
+///
+/// ```
+/// use m68k_srec::{Loader, Region};
+///
+/// let mut ram = [0; 16];
+/// let summary = Loader::load(
+///     b"S1070010DEADBEEFB0\nS9030010EC\n",
+///     &mut Region::new(0x10, &mut ram),
+/// )
+/// .unwrap();
+/// assert_eq!(&ram[..4], [0xde, 0xad, 0xbe, 0xef]);
+/// assert_eq!(summary.entry, Some(0x10));
+/// ```
+#[derive(Default)]
+pub struct Loader {
+    parser: Parser,
+    summary: Summary,
+}
+
+impl Loader {
+    /// Creates a loader at the start of a file
+    pub const fn new() -> Self {
+        Loader {
+            parser: Parser::new(),
+            summary: Summary {
+                entry: None,
+                records: 0,
+                bytes: 0,
+            },
+        }
+    }
+
+    /// Forgets any partial file and starts over
+    pub fn reset(&mut self) {
+        *self = Loader::new();
+    }
+
+    /// Returns the current line number, starting at 1
+    pub fn line(&self) -> u32 {
+        self.parser.line()
+    }
+
+    /// Feeds one byte of input
+    ///
+    /// Returns the summary of the file once its end of file record has been read. The loader is
+    /// then ready for the next file.
+    pub fn push<M>(&mut self, byte: u8, memory: &mut M) -> Result<Option<Summary>, Error>
+    where
+        M: Memory + ?Sized,
+    {
+        let line = self.parser.line();
+        let error = |kind| Error { line, kind };
+        match self.parser.push(byte)? {
+            Some(Record::Data { address, data }) => {
+                if !memory.valid(address, data.len()) {
+                    return Err(error(ErrorKind::Rejected {
+                        address,
+                        len: data.len(),
+                    }));
+                }
+                memory.write(address, data);
+                self.summary.records += 1;
+                self.summary.bytes += data.len() as u32;
+            }
+            Some(Record::Count(count)) => {
+                if count != self.summary.records {
+                    return Err(error(ErrorKind::Count {
+                        expected: count,
+                        actual: self.summary.records,
+                    }));
+                }
+            }
+            Some(Record::Entry(entry)) => self.summary.entry = Some(entry),
+            Some(Record::End { entry }) => {
+                let mut summary = core::mem::take(&mut self.summary);
+                summary.entry = entry.or(summary.entry);
+                return Ok(Some(summary));
+            }
+            Some(Record::Header(_)) | None => {}
+        }
+        Ok(None)
+    }
+
+    /// Loads a whole file
+    ///
+    /// Anything after the end of file record is ignored.
+    pub fn load<M>(input: &[u8], memory: &mut M) -> Result<Summary, Error>
+    where
+        M: Memory + ?Sized,
+    {
+        let mut loader = Loader::new();
+        for &byte in input {
+            if let Some(summary) = loader.push(byte, memory)? {
+                return Ok(summary);
+            }
+        }
+        Err(Error {
+            line: loader.line(),
+            kind: ErrorKind::MissingEnd,
+        })
+    }
+}
@@ -0,0 +1,101 @@
+//! Helpers shared by the tests
+
+#![allow(dead_code)]
+
+use m68k_srec::{Error, Parser, Record};
+
+/// An owned [`Record`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rec {
+    Header(Vec<u8>),
+    Data(u32, Vec<u8>),
+    Count(u32),
+    Entry(u32),
+    End(Option<u32>),
+}
+
+impl From<Record<'_>> for Rec {
+    fn from(record: Record) -> Self {
+        match record {
+            Record::Header(data) => Rec::Header(data.to_vec()),
+            Record::Data { address, data } => Rec::Data(address, data.to_vec()),
+            Record::Count(count) => Rec::Count(count),
+            Record::Entry(entry) => Rec::Entry(entry),
+            Record::End { entry } => Rec::End(entry),
+        }
+    }
+}
+
+/// Parses all of `input`, carrying on after errors
+pub fn parse_all(input: &[u8]) -> (Vec<Rec>, Vec<Error>) {
+    let mut parser = Parser::new();
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for &byte in input {
+        match parser.push(byte) {
+            Ok(Some(record)) => records.push(record.into()),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    (records, errors)
+}
+
+/// Parses `input`, which must be free of errors
+pub fn parse(input: &[u8]) -> Vec<Rec> {
+    let (records, errors) = parse_all(input);
+    assert_eq!(errors, [], "{}", String::from_utf8_lossy(input));
+    records
+}
+
+/// Returns the first error in `input`
+pub fn error(input: &[u8]) -> Error {
+    let (_, errors) = parse_all(input);
+    *errors
+        .first()
+        .unwrap_or_else(|| panic!("no error in {}", String::from_utf8_lossy(input)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// An S-record of type `kind` with a correct length field and checksum
+pub fn srec(kind: u8, address: &[u8], data: &[u8]) -> String {
+    let count = (address.len() + data.len() + 1) as u8;
+    let sum = address
+        .iter()
+        .chain(data)
+        .fold(count, |sum, &b| sum.wrapping_add(b));
+    format!(
+        "S{}{:02X}{}{}{:02X}\n",
+        kind,
+        count,
+        hex(address),
+        hex(data),
+        !sum
+    )
+}
+
+/// An Intel HEX record of type `kind` with a correct length field and checksum
+pub fn ihex(kind: u8, offset: u16, data: &[u8]) -> String {
+    let header = [data.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+    let sum = header
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, &b| sum.wrapping_add(b));
+    format!(":{}{}{:02X}\n", hex(&header), hex(data), sum.wrapping_neg())
+}
+
+/// Deterministic test data
+pub fn pattern(len: usize, seed: u32) -> Vec<u8> {
+    let mut x = seed.wrapping_mul(2_654_435_761).wrapping_add(1);
+    (0..len)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x as u8
+        })
+        .collect()
+}
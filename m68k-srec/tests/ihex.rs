@@ -0,0 +1,233 @@
+mod common;
+
+use common::*;
+use m68k_image::rom::Rom;
+use m68k_srec::ErrorKind;
+
+/// The example from Wikipedia's Intel HEX article
+const WIKIPEDIA: &str = "\
+:10010000214601360121470136007EFE09D2190140
+:100110002146017E17C20001FF5F16002148011928
+:10012000194E79234623965778239EDA3F01B2CAA7
+:100130003F0156702B5E712B722B732146013421C7
+:00000001FF
+";
+
+#[test]
+fn wikipedia() {
+    let records = parse(WIKIPEDIA.as_bytes());
+    assert_eq!(records.len(), 5);
+    assert_eq!(
+        records[0],
+        Rec::Data(
+            0x0100,
+            vec![
+                0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7e, 0xfe, 0x09, 0xd2,
+                0x19, 0x01
+            ]
+        )
+    );
+    for (i, record) in records[1..4].iter().enumerate() {
+        assert!(
+            matches!(record, Rec::Data(address, data) if *address == 0x0110 + 0x10 * i as u32 && data.len() == 16)
+        );
+    }
+    assert_eq!(records[4], Rec::End(None));
+}
+
+#[test]
+fn extended_linear_address() {
+    let input = [
+        ihex(0, 0x0010, &[1]),
+        ihex(4, 0, &[0x12, 0x34]),
+        ihex(0, 0x5678, &[2]),
+        ihex(4, 0, &[0xff, 0xff]),
+        ihex(0, 0xfffe, &[3, 4]),
+    ]
+    .concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [
+            Rec::Data(0x0010, vec![1]),
+            Rec::Data(0x1234_5678, vec![2]),
+            Rec::Data(0xffff_fffe, vec![3, 4]),
+        ]
+    );
+}
+
+#[test]
+fn extended_segment_address() {
+    let input = [ihex(2, 0, &[0x12, 0x34]), ihex(0, 0x0010, &[1])].concat();
+    assert_eq!(parse(input.as_bytes()), [Rec::Data(0x12350, vec![1])]);
+}
+
+#[test]
+fn start_addresses() {
+    let input = [
+        ihex(5, 0, &[0x00, 0x01, 0x02, 0x00]),
+        ihex(3, 0, &[0x12, 0x34, 0x00, 0x10]),
+    ]
+    .concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [Rec::Entry(0x0001_0200), Rec::Entry(0x12350)]
+    );
+}
+
+#[test]
+fn end_of_file_resets_the_base() {
+    let input = [
+        ihex(4, 0, &[0x00, 0x01]),
+        ihex(1, 0, &[]),
+        ihex(0, 0x0010, &[1]),
+    ]
+    .concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [Rec::End(None), Rec::Data(0x0010, vec![1])]
+    );
+}
+
+#[test]
+fn longest_record() {
+    let data = pattern(255, 2);
+    let input = ihex(0, 0x1000, &data);
+    assert_eq!(parse(input.as_bytes()), [Rec::Data(0x1000, data)]);
+}
+
+#[test]
+fn mixed_formats() {
+    let input = format!("{}{}", srec(1, &[0, 0x10], &[1]), ihex(0, 0x20, &[2]));
+    assert_eq!(
+        parse(input.as_bytes()),
+        [Rec::Data(0x10, vec![1]), Rec::Data(0x20, vec![2])]
+    );
+}
+
+#[test]
+fn checksum() {
+    assert_eq!(
+        error(b":00000001FE\n").kind,
+        ErrorKind::Checksum {
+            expected: 0xfe,
+            actual: 0xff
+        }
+    );
+}
+
+#[test]
+fn every_corrupted_byte_is_caught() {
+    let line = ihex(0, 0x1234, &[0x10, 0x20, 0x30, 0x40]);
+    let line = line.trim_end();
+    // Flip one bit in every hex digit after the length field
+    for i in 3..line.len() {
+        let mut bytes = line.as_bytes().to_vec();
+        let digit = (bytes[i] as char).to_digit(16).unwrap() ^ 1;
+        bytes[i] = char::from_digit(digit, 16).unwrap().to_ascii_uppercase() as u8;
+        let kind = error(&bytes).kind;
+        assert!(
+            matches!(kind, ErrorKind::Checksum { .. }),
+            "{}: {:?}",
+            String::from_utf8_lossy(&bytes),
+            kind
+        );
+    }
+}
+
+#[test]
+fn truncated() {
+    let line = ihex(0, 0x0010, &[0xaa, 0xbb]);
+    let line = line.trim_end();
+    for len in 1..line.len() {
+        let input = format!("{}\r\n", &line[..len]);
+        assert_eq!(
+            error(input.as_bytes()).kind,
+            ErrorKind::TooShort,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn too_long() {
+    assert_eq!(error(b":00000001FF00\n").kind, ErrorKind::TooLong);
+}
+
+#[test]
+fn unknown_types() {
+    for kind in [6, 0x10, 0xff] {
+        let input = ihex(kind, 0, &[]);
+        assert_eq!(error(input.as_bytes()).kind, ErrorKind::UnknownType(kind));
+    }
+}
+
+#[test]
+fn invalid_lengths() {
+    for input in [
+        ihex(1, 0, &[0]),
+        ihex(2, 0, &[0]),
+        ihex(2, 0, &[0, 0, 0]),
+        ihex(3, 0, &[0, 0]),
+        ihex(4, 0, &[]),
+        ihex(5, 0, &[0, 0, 0, 0, 0]),
+    ] {
+        assert_eq!(
+            error(input.as_bytes()).kind,
+            ErrorKind::InvalidLength,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn address_overflow() {
+    let input = [ihex(4, 0, &[0xff, 0xff]), ihex(0, 0xfffe, &[1, 2, 3])].concat();
+    assert_eq!(error(input.as_bytes()).kind, ErrorKind::AddressOverflow);
+}
+
+#[test]
+fn invalid_characters() {
+    assert_eq!(
+        error(b":0G000001FF\n").kind,
+        ErrorKind::InvalidCharacter(b'G')
+    );
+    assert_eq!(
+        error(b";00000001FF\n").kind,
+        ErrorKind::InvalidCharacter(b';')
+    );
+    assert_eq!(error(b":00000001FF\x1a").kind, ErrorKind::TooLong);
+}
+
+#[test]
+fn round_trip() {
+    for (base, len) in [
+        (0x0000, 1),
+        (0x0100, 1000),
+        (0xfff0, 0x20),
+        (0x0001_fffe, 0x2_0004),
+        (0x1234_5678, 3000),
+        (0xffff_ff00, 0x100),
+    ] {
+        let rom = Rom {
+            base,
+            data: pattern(len, base),
+            entry: 0x1234_5678,
+        };
+        let records = parse(rom.to_ihex().as_bytes());
+
+        let mut data = Vec::new();
+        for record in &records {
+            if let Rec::Data(address, bytes) = record {
+                assert_eq!(*address as usize, base as usize + data.len());
+                data.extend_from_slice(bytes);
+            }
+        }
+        assert_eq!(data, rom.data, "0x{:x}", base);
+        assert_eq!(
+            records[records.len() - 2..],
+            [Rec::Entry(0x1234_5678), Rec::End(None)]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+mod common;
+
+use common::*;
+use m68k_srec::{ErrorKind, Loader, Memory, Region, Summary};
+
+/// Records every call
+#[derive(Default)]
+struct Log {
+    writes: Vec<(u32, Vec<u8>)>,
+    checks: Vec<(u32, usize)>,
+    limit: u32,
+}
+
+impl Memory for Log {
+    fn valid(&mut self, address: u32, len: usize) -> bool {
+        self.checks.push((address, len));
+        address as usize + len <= self.limit as usize
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) {
+        self.writes.push((address, data.to_vec()));
+    }
+}
+
+#[test]
+fn load_into_region() {
+    let input = [
+        srec(0, &[0, 0], b"app"),
+        srec(1, &[0x10, 0x00], &[1, 2, 3, 4]),
+        srec(1, &[0x10, 0x04], &[5, 6]),
+        srec(5, &[0, 2], &[]),
+        srec(9, &[0x10, 0x00], &[]),
+    ]
+    .concat();
+    let mut ram = [0xff; 8];
+    let summary = Loader::load(input.as_bytes(), &mut Region::new(0x1000, &mut ram)).unwrap();
+    assert_eq!(ram, [1, 2, 3, 4, 5, 6, 0xff, 0xff]);
+    assert_eq!(
+        summary,
+        Summary {
+            entry: Some(0x1000),
+            records: 2,
+            bytes: 6,
+        }
+    );
+}
+
+#[test]
+fn region_bounds() {
+    let mut ram = [0; 8];
+    let mut region = Region::new(0x1000, &mut ram);
+    assert!(region.valid(0x1000, 8));
+    assert!(region.valid(0x1007, 1));
+    assert!(region.valid(0x1008, 0));
+    assert!(!region.valid(0x0fff, 1));
+    assert!(!region.valid(0x1004, 5));
+    assert!(!region.valid(0x1008, 1));
+    assert!(!region.valid(0xffff_ffff, 2));
+    assert!(!region.valid(0x1004, usize::MAX));
+}
+
+#[test]
+fn rejected_before_writing() {
+    let input = [
+        srec(1, &[0x00, 0x00], &[1, 2]),
+        srec(1, &[0x00, 0xfe], &[3, 4, 5]),
+        srec(9, &[0, 0], &[]),
+    ]
+    .concat();
+    let mut memory = Log {
+        limit: 0x100,
+        ..Log::default()
+    };
+    let error = Loader::load(input.as_bytes(), &mut memory).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::Rejected {
+            address: 0xfe,
+            len: 3
+        }
+    );
+    assert_eq!(error.line, 2);
+    assert_eq!(memory.checks, [(0, 2), (0xfe, 3)]);
+    assert_eq!(memory.writes, [(0, vec![1, 2])]);
+}
+
+#[test]
+fn count_mismatch() {
+    let input = [
+        srec(1, &[0, 0], &[1]),
+        srec(5, &[0, 2], &[]),
+        srec(9, &[0, 0], &[]),
+    ]
+    .concat();
+    let mut ram = [0; 4];
+    let error = Loader::load(input.as_bytes(), &mut Region::new(0, &mut ram)).unwrap_err();
+    assert_eq!(
+        error.kind,
+        ErrorKind::Count {
+            expected: 2,
+            actual: 1
+        }
+    );
+    assert_eq!(error.line, 2);
+}
+
+#[test]
+fn intel_hex_entry() {
+    let input = [
+        ihex(4, 0, &[0x00, 0x01]),
+        ihex(0, 0x0000, &[1, 2]),
+        ihex(5, 0, &[0x00, 0x01, 0x00, 0x00]),
+        ihex(1, 0, &[]),
+    ]
+    .concat();
+    let mut ram = [0; 2];
+    let summary = Loader::load(input.as_bytes(), &mut Region::new(0x1_0000, &mut ram)).unwrap();
+    assert_eq!(ram, [1, 2]);
+    assert_eq!(summary.entry, Some(0x1_0000));
+}
+
+#[test]
+fn termination_overrides_entry() {
+    let input = [ihex(5, 0, &[0, 0, 0, 1]), srec(9, &[0, 2], &[])].concat();
+    let summary = Loader::load(input.as_bytes(), &mut Log::default()).unwrap();
+    assert_eq!(summary.entry, Some(2));
+}
+
+#[test]
+fn missing_end() {
+    let input = srec(1, &[0, 0], &[1]);
+    let error = Loader::load(
+        input.as_bytes(),
+        &mut Log {
+            limit: 0x100,
+            ..Log::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(error.kind, ErrorKind::MissingEnd);
+    assert_eq!(error.line, 2);
+    assert_eq!(error.to_string(), "line 2: no end of file record");
+}
+
+#[test]
+fn parse_errors_pass_through() {
+    let error = Loader::load(b"S1040010AA42\n", &mut Log::default()).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::Checksum { .. }));
+}
+
+#[test]
+fn consecutive_files() {
+    let first = [srec(1, &[0, 0], &[1]), srec(9, &[0, 0], &[])].concat();
+    let second = [
+        srec(1, &[0, 1], &[2]),
+        srec(1, &[0, 2], &[3]),
+        srec(5, &[0, 2], &[]),
+        srec(9, &[0, 1], &[]),
+    ]
+    .concat();
+    let input = [first, second].concat();
+
+    let mut ram = [0; 3];
+    let mut region = Region::new(0, &mut ram);
+    // Through a trait object, as a monitor with several memory maps might
+    let memory: &mut dyn Memory = &mut region;
+    let mut loader = Loader::new();
+    let mut summaries = Vec::new();
+    for &byte in input.as_bytes() {
+        if let Some(summary) = loader.push(byte, memory).unwrap() {
+            summaries.push(summary);
+        }
+    }
+    assert_eq!(
+        summaries,
+        [
+            Summary {
+                entry: Some(0),
+                records: 1,
+                bytes: 1,
+            },
+            Summary {
+                entry: Some(1),
+                records: 2,
+                bytes: 2,
+            },
+        ]
+    );
+    assert_eq!(ram, [1, 2, 3]);
+}
+
+#[test]
+fn reset() {
+    let mut loader = Loader::new();
+    let mut memory = Log {
+        limit: 0x100,
+        ..Log::default()
+    };
+    for &byte in srec(1, &[0, 0], &[1]).as_bytes() {
+        loader.push(byte, &mut memory).unwrap();
+    }
+    loader.reset();
+    assert_eq!(loader.line(), 1);
+    let mut summary = None;
+    for &byte in srec(9, &[0, 0], &[]).as_bytes() {
+        summary = summary.or(loader.push(byte, &mut memory).unwrap());
+    }
+    assert_eq!(summary.unwrap().records, 0);
+}
@@ -0,0 +1,331 @@
+mod common;
+
+use common::*;
+use m68k_image::rom::{Rom, SRecord};
+use m68k_srec::{ErrorKind, Parser, Record};
+
+/// The example from Wikipedia's SREC article
+const WIKIPEDIA: &str = "\
+S00F000068656C6C6F202020202000003C
+S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026
+S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9
+S111003848656C6C6F20776F726C642E0A0042
+S5030003F9
+S9030000FC
+";
+
+#[test]
+fn wikipedia() {
+    let records = parse(WIKIPEDIA.as_bytes());
+    assert_eq!(records.len(), 6);
+    assert_eq!(records[0], Rec::Header(b"hello     \0\0".to_vec()));
+    assert!(matches!(&records[1], Rec::Data(0x0000, data) if data.len() == 28));
+    assert!(matches!(&records[2], Rec::Data(0x001c, data) if data.len() == 28));
+    assert_eq!(records[3], Rec::Data(0x0038, b"Hello world.\n\0".to_vec()));
+    assert_eq!(records[4], Rec::Count(3));
+    assert_eq!(records[5], Rec::End(Some(0)));
+}
+
+#[test]
+fn address_sizes() {
+    let data = [1, 2, 3];
+    let input = [
+        srec(1, &[0x12, 0x34], &data),
+        srec(2, &[0x12, 0x34, 0x56], &data),
+        srec(3, &[0x12, 0x34, 0x56, 0x78], &data),
+    ]
+    .concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [
+            Rec::Data(0x1234, data.to_vec()),
+            Rec::Data(0x12_3456, data.to_vec()),
+            Rec::Data(0x1234_5678, data.to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn counts_and_entries() {
+    let input = [
+        srec(5, &[0x12, 0x34], &[]),
+        srec(6, &[0x12, 0x34, 0x56], &[]),
+        srec(9, &[0x12, 0x34], &[]),
+        srec(8, &[0x12, 0x34, 0x56], &[]),
+        srec(7, &[0x12, 0x34, 0x56, 0x78], &[]),
+    ]
+    .concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [
+            Rec::Count(0x1234),
+            Rec::Count(0x12_3456),
+            Rec::End(Some(0x1234)),
+            Rec::End(Some(0x12_3456)),
+            Rec::End(Some(0x1234_5678)),
+        ]
+    );
+}
+
+#[test]
+fn empty_records() {
+    let input = [srec(0, &[0, 0], &[]), srec(1, &[0x10, 0x00], &[])].concat();
+    assert_eq!(
+        parse(input.as_bytes()),
+        [Rec::Header(vec![]), Rec::Data(0x1000, vec![])]
+    );
+}
+
+#[test]
+fn longest_record() {
+    // A length field of 0xff: 4 address bytes, 250 data bytes and the checksum
+    let data = pattern(250, 1);
+    let input = srec(3, &[0, 0, 0x10, 0], &data);
+    assert_eq!(parse(input.as_bytes()), [Rec::Data(0x1000, data)]);
+}
+
+#[test]
+fn lowercase() {
+    let input = srec(1, &[0xab, 0xcd], &[0xef]).to_lowercase();
+    assert_eq!(parse(input.as_bytes()), [Rec::Data(0xabcd, vec![0xef])]);
+}
+
+#[test]
+fn line_endings_and_whitespace() {
+    let line = srec(1, &[0, 0x10], &[0xaa]);
+    let line = line.trim_end();
+    for input in [
+        format!("{}\r\n{}\r\n", line, line),
+        format!("{}\r{}\r", line, line),
+        format!("{}{}", line, line),
+        format!("\n\n  {} \t\r\n\t{}", line, line),
+    ] {
+        assert_eq!(
+            parse(input.as_bytes()),
+            [Rec::Data(0x10, vec![0xaa]), Rec::Data(0x10, vec![0xaa])],
+            "{:?}",
+            input
+        );
+    }
+}
+
+#[test]
+fn record_on_last_digit() {
+    let line = srec(1, &[0, 0x10], &[0xaa]);
+    let line = line.trim_end().as_bytes();
+    let mut parser = Parser::new();
+    for &byte in &line[..line.len() - 1] {
+        assert_eq!(parser.push(byte), Ok(None));
+        assert!(!parser.is_idle());
+    }
+    assert_eq!(
+        parser.push(line[line.len() - 1]),
+        Ok(Some(Record::Data {
+            address: 0x10,
+            data: &[0xaa]
+        }))
+    );
+    assert!(parser.is_idle());
+}
+
+#[test]
+fn checksum() {
+    let mut input = srec(1, &[0, 0x10], &[0xaa]);
+    // S1 04 0010 AA 41
+    assert!(input.starts_with("S1040010AA41"), "{}", input);
+    input.replace_range(10..12, "42");
+    let error = error(input.as_bytes());
+    assert_eq!(
+        error.kind,
+        ErrorKind::Checksum {
+            expected: 0x42,
+            actual: 0x41
+        }
+    );
+    assert_eq!(error.line, 1);
+}
+
+#[test]
+fn every_corrupted_byte_is_caught() {
+    let line = srec(2, &[0x01, 0x02, 0x03], &[0x10, 0x20, 0x30, 0x40]);
+    let line = line.trim_end();
+    // Flip one bit in every hex digit after the length field
+    for i in 4..line.len() {
+        let mut bytes = line.as_bytes().to_vec();
+        let digit = (bytes[i] as char).to_digit(16).unwrap() ^ 1;
+        bytes[i] = char::from_digit(digit, 16).unwrap().to_ascii_uppercase() as u8;
+        assert!(
+            matches!(error(&bytes).kind, ErrorKind::Checksum { .. }),
+            "{}",
+            String::from_utf8_lossy(&bytes)
+        );
+    }
+}
+
+#[test]
+fn invalid_characters() {
+    assert_eq!(
+        error(b"S1040010AG41\n").kind,
+        ErrorKind::InvalidCharacter(b'G')
+    );
+    assert_eq!(
+        error(b"S1040010 AA41\n").kind,
+        ErrorKind::InvalidCharacter(b' ')
+    );
+    assert_eq!(
+        error(b"SX040010AA41\n").kind,
+        ErrorKind::InvalidCharacter(b'X')
+    );
+    assert_eq!(
+        error(b"X1040010AA41\n").kind,
+        ErrorKind::InvalidCharacter(b'X')
+    );
+    assert_eq!(
+        error(b"\x001040010AA41\n").kind,
+        ErrorKind::InvalidCharacter(0)
+    );
+}
+
+#[test]
+fn unknown_type() {
+    assert_eq!(error(b"S4030000FC\n").kind, ErrorKind::UnknownType(4));
+}
+
+#[test]
+fn truncated() {
+    let line = srec(1, &[0, 0x10], &[0xaa, 0xbb]);
+    let line = line.trim_end();
+    for len in 1..line.len() {
+        let input = format!("{}\n", &line[..len]);
+        assert_eq!(
+            error(input.as_bytes()).kind,
+            ErrorKind::TooShort,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn too_long() {
+    let input = format!("{}00\n", srec(1, &[0, 0x10], &[0xaa]).trim_end());
+    assert_eq!(error(input.as_bytes()).kind, ErrorKind::TooLong);
+    // A second record on the same line is fine
+    let line = srec(1, &[0, 0x10], &[0xaa]);
+    let input = format!("{}{}", line.trim_end(), line);
+    assert_eq!(parse(input.as_bytes()).len(), 2);
+}
+
+#[test]
+fn invalid_lengths() {
+    // No room for the checksum
+    assert_eq!(error(b"S100\n").kind, ErrorKind::InvalidLength);
+    // No room for the address
+    assert_eq!(error(b"S10200FD\n").kind, ErrorKind::InvalidLength);
+    assert_eq!(
+        error(srec(3, &[0, 0, 0], &[]).as_bytes()).kind,
+        ErrorKind::InvalidLength
+    );
+    // Count and termination records carry no data
+    for kind in [5, 6, 7, 8, 9] {
+        let address = match kind {
+            5 | 9 => 2,
+            6 | 8 => 3,
+            _ => 4,
+        };
+        let input = srec(kind, &vec![0; address], &[1]);
+        assert_eq!(
+            error(input.as_bytes()).kind,
+            ErrorKind::InvalidLength,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn address_overflow() {
+    assert_eq!(
+        parse(srec(1, &[0xff, 0xfe], &[1, 2]).as_bytes()),
+        [Rec::Data(0xfffe, vec![1, 2])]
+    );
+    for input in [
+        srec(1, &[0xff, 0xfe], &[1, 2, 3]),
+        srec(2, &[0xff, 0xff, 0xff], &[1, 2]),
+        srec(3, &[0xff, 0xff, 0xff, 0xff], &[1, 2]),
+    ] {
+        assert_eq!(
+            error(input.as_bytes()).kind,
+            ErrorKind::AddressOverflow,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn line_numbers_and_recovery() {
+    let good = srec(1, &[0, 0x10], &[0xaa]);
+    let good = good.trim_end();
+    let input = format!("{}\r\nS1040010AA42\r\n\r\n{}\nSZ\n{}", good, good, good);
+    let (records, errors) = parse_all(input.as_bytes());
+    assert_eq!(records.len(), 3);
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].line, 2);
+    assert!(matches!(errors[0].kind, ErrorKind::Checksum { .. }));
+    assert_eq!(errors[1].line, 5);
+    assert_eq!(errors[1].kind, ErrorKind::InvalidCharacter(b'Z'));
+    assert_eq!(errors[1].to_string(), "line 5: unexpected character `Z`");
+}
+
+#[test]
+fn reset() {
+    let mut parser = Parser::new();
+    for &byte in b"S1040010\nS10" {
+        let _ = parser.push(byte);
+    }
+    assert_eq!(parser.line(), 2);
+    parser.reset();
+    assert_eq!(parser.line(), 1);
+    assert!(parser.is_idle());
+}
+
+#[test]
+fn round_trip() {
+    for (kind, base, len) in [
+        (SRecord::S19, 0x0000, 1),
+        (SRecord::S19, 0x0100, 1000),
+        (SRecord::S19, 0xff00, 0x100),
+        (SRecord::S28, 0x01_0000, 4097),
+        (SRecord::S28, 0xff_fff0, 0x10),
+        (SRecord::S37, 0x1234_5678, 3000),
+        (SRecord::S37, 0xffff_ff00, 0x100),
+    ] {
+        let rom = Rom {
+            base,
+            data: pattern(len, base),
+            entry: base,
+        };
+        for bytes_per_record in [1, 16, 32, 250 - 4] {
+            let text = rom.to_srec(kind, "test", bytes_per_record).unwrap();
+            let records = parse(text.as_bytes());
+
+            assert_eq!(records[0], Rec::Header(b"test".to_vec()));
+            assert_eq!(*records.last().unwrap(), Rec::End(Some(base)));
+            let mut data = Vec::new();
+            let mut count = 0;
+            for record in &records {
+                match record {
+                    Rec::Data(address, bytes) => {
+                        assert_eq!(*address as usize, base as usize + data.len());
+                        data.extend_from_slice(bytes);
+                        count += 1;
+                    }
+                    Rec::Count(n) => assert_eq!(*n, count),
+                    _ => {}
+                }
+            }
+            assert_eq!(data, rom.data, "{:?} at 0x{:x}", kind, base);
+        }
+    }
+}
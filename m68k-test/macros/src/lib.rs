@@ -0,0 +1,227 @@
+//! Internal implementation details of `m68k-test`.
+//!
+//! Do not use this crate directly
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse, parse_macro_input, spanned::Spanned, AttrStyle, Attribute, FnArg, Ident, Item, ItemFn,
+    ItemMod, LitStr, ReturnType, Type, Visibility,
+};
+
+extern crate proc_macro;
+
+struct Test {
+    ident: Ident,
+    cfgs: Vec<Attribute>,
+    ignored: bool,
+    takes_state: bool,
+}
+
+#[proc_macro_attribute]
+pub fn tests(args: TokenStream, input: TokenStream) -> TokenStream {
+    let module = parse_macro_input!(input as ItemMod);
+
+    if !args.is_empty() {
+        return parse::Error::new(Span::call_site(), "This attribute accepts no arguments")
+            .to_compile_error()
+            .into();
+    }
+
+    let items = match module.content {
+        Some((_, items)) => items,
+        None => {
+            return parse::Error::new(
+                module.span(),
+                "`#[m68k_test::tests]` must be applied to a module with a body: `mod tests { .. }`",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let mut init = None::<(Ident, Type)>;
+    let mut tests = vec![];
+    let mut untouched = vec![];
+    for item in items {
+        let mut f = match item {
+            Item::Fn(f) => f,
+            item => {
+                untouched.push(item);
+                continue;
+            }
+        };
+
+        let is_init = take_attr(&mut f.attrs, "init");
+        let is_test = take_attr(&mut f.attrs, "test");
+        let ignored = take_attr(&mut f.attrs, "ignore");
+
+        if ignored && !is_test {
+            return parse::Error::new(
+                f.sig.ident.span(),
+                "`#[ignore]` can only be applied to a `#[test]` function",
+            )
+            .to_compile_error()
+            .into();
+        }
+
+        if is_init {
+            if is_test {
+                return parse::Error::new(
+                    f.sig.ident.span(),
+                    "a function can't be both `#[init]` and `#[test]`",
+                )
+                .to_compile_error()
+                .into();
+            }
+            if init.is_some() {
+                return parse::Error::new(
+                    f.sig.ident.span(),
+                    "only one `#[init]` function is allowed",
+                )
+                .to_compile_error()
+                .into();
+            }
+            let state = match &f.sig.output {
+                ReturnType::Type(_, ty) if plain(&f) && f.sig.inputs.is_empty() => (**ty).clone(),
+                _ => {
+                    return parse::Error::new(
+                        f.sig.span(),
+                        "`#[init]` function must have signature `fn() -> State`",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+            };
+            init = Some((f.sig.ident.clone(), state));
+        } else if is_test {
+            let takes_state = match f.sig.inputs.len() {
+                0 => false,
+                1 => true,
+                _ => {
+                    return parse::Error::new(
+                        f.sig.span(),
+                        "`#[test]` function must have signature `fn()` or `fn(&mut State)`",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+            };
+            let returns_unit = match &f.sig.output {
+                ReturnType::Default => true,
+                ReturnType::Type(_, ty) => {
+                    matches!(&**ty, Type::Tuple(tuple) if tuple.elems.is_empty())
+                }
+            };
+            if !plain(&f) || !returns_unit || (takes_state && !is_state(&f.sig.inputs[0])) {
+                return parse::Error::new(
+                    f.sig.span(),
+                    "`#[test]` function must have signature `fn()` or `fn(&mut State)`",
+                )
+                .to_compile_error()
+                .into();
+            }
+            tests.push(Test {
+                ident: f.sig.ident.clone(),
+                cfgs: f
+                    .attrs
+                    .iter()
+                    .filter(|attr| eq(attr, "cfg"))
+                    .cloned()
+                    .collect(),
+                ignored,
+                takes_state,
+            });
+        }
+        untouched.push(Item::Fn(f));
+    }
+
+    if init.is_none() {
+        if let Some(test) = tests.iter().find(|test| test.takes_state) {
+            return parse::Error::new(
+                test.ident.span(),
+                "tests can only take a state returned by an `#[init]` function",
+            )
+            .to_compile_error()
+            .into();
+        }
+    }
+
+    let (state, init_call) = match &init {
+        Some((ident, ty)) => (quote!(#ty), quote!(#ident())),
+        None => (quote!(()), quote!(())),
+    };
+
+    let entries = tests.iter().map(|test| {
+        let ident = &test.ident;
+        let cfgs = &test.cfgs;
+        let name = LitStr::new(&ident.to_string(), Span::call_site());
+        let ignored = test.ignored;
+        let run = if test.takes_state {
+            quote!(|state| #ident(state))
+        } else {
+            quote!(|_| #ident())
+        };
+        quote!(
+            #(#cfgs)*
+            m68k_test::export::Test {
+                name: #name,
+                ignored: #ignored,
+                run: #run,
+            },
+        )
+    });
+
+    let attrs = module.attrs;
+    let vis = module.vis;
+    let ident = module.ident;
+
+    quote!(
+        #(#attrs)*
+        #vis mod #ident {
+            #(#untouched)*
+
+            #[m68k_test::export::entry]
+            fn __m68k_test_main() -> ! {
+                static TESTS: &[m68k_test::export::Test<#state>] = &[#(#entries)*];
+
+                m68k_test::export::start(TESTS);
+                let mut state = #init_call;
+                m68k_test::export::run(TESTS, &mut state)
+            }
+        }
+    )
+    .into()
+}
+
+/// Returns `true` for a function that isn't `const`, `async`, `extern` or generic
+fn plain(f: &ItemFn) -> bool {
+    f.sig.constness.is_none()
+        && f.sig.asyncness.is_none()
+        && f.vis == Visibility::Inherited
+        && f.sig.abi.is_none()
+        && f.sig.generics.params.is_empty()
+        && f.sig.generics.where_clause.is_none()
+        && f.sig.variadic.is_none()
+}
+
+/// Returns `true` if the argument is a `&mut` reference, which should be to the state
+fn is_state(arg: &FnArg) -> bool {
+    match arg {
+        FnArg::Typed(arg) => matches!(&*arg.ty, Type::Reference(r) if r.mutability.is_some()),
+        FnArg::Receiver(_) => false,
+    }
+}
+
+/// Removes the `#[name]` attributes, returning `true` if there were any
+fn take_attr(attrs: &mut Vec<Attribute>, name: &str) -> bool {
+    let before = attrs.len();
+    attrs.retain(|attr| !eq(attr, name));
+    attrs.len() != before
+}
+
+/// Returns `true` if `attr.path` matches `name`
+fn eq(attr: &Attribute, name: &str) -> bool {
+    attr.style == AttrStyle::Outer && attr.path().is_ident(name)
+}
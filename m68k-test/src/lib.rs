@@ -0,0 +1,202 @@
+//! Tests that run on the target
+//!
+//! `#[m68k_test::tests]` turns a module of test functions into the program of an integration
+//! test: its `#[test]` functions are collected into a table, and an `m68k-rt` entry point runs
+//! them one after the other, printing a line for each as `cargo test` does. The program exits
+//! with status 0 if they all pass, or 101 at the first one that panics, since there is no
+//! unwinding to carry on after a panic.
+//!
+//! An `#[init]` function runs before the tests, and its return value is passed to the tests that
+//! take a `&mut` argument. `#[ignore]` skips a test, and `#[cfg]` attributes work as usual.
+//!
+//! ``` ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! #[m68k_test::tests]
+//! mod tests {
+//!     struct Counter(u32);
+//!
+//!     #[init]
+//!     fn init() -> Counter {
+//!         Counter(0)
+//!     }
+//!
+//!     #[test]
+//!     fn stateless() {
+//!         assert_eq!(1 + 1, 2);
+//!     }
+//!
+//!     #[test]
+//!     fn with_state(counter: &mut Counter) {
+//!         counter.0 += 1;
+//!         assert_eq!(counter.0, 1);
+//!     }
+//!
+//!     #[test]
+//!     #[ignore]
+//!     fn not_yet() {}
+//! }
+//! ```
+//!
+//! The test target needs `harness = false` in `Cargo.toml`, and `m68k-test` as a
+//! dev-dependency; `m68k-rt` comes with it, and is added too when the tests need its features:
+//!
+//! ``` toml
+//! [[test]]
+//! name = "counter"
+//! harness = false
+//! ```
+//!
+//! # Reporting
+//!
+//! With the `monitor` feature (the default), results are written to the console of the `m68k-rom`
+//! monitor and the exit status goes to its `EXIT` service, through `m68k-monitor`. On hardware,
+//! that is the monitor's serial port; in `m68k-emu --monitor`, which `.cargo/config.toml` sets as
+//! the runner, it is standard output and the exit status of the emulator, so `cargo xtest`
+//! reports the outcome like any other test.
+//!
+//! With the `semihosting` feature, they go to the host's standard output and exit status through
+//! `m68k-semihosting` instead, for programs that don't run under the monitor.
+//!
+//! The `panic-handler` feature (also a default) provides the `#[panic_handler]` that reports the
+//! failed test. Without it, the program brings its own and the tests stop at the first failure
+//! without a summary.
+
+#![no_std]
+#![deny(clippy::missing_inline_in_public_items)]
+
+#[cfg(not(any(feature = "monitor", feature = "semihosting")))]
+compile_error!(
+    "m68k-test needs a way to report results: enable the `monitor` or `semihosting` feature"
+);
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+
+/// Attribute to declare a module of tests that run on the target
+///
+/// See the [crate documentation](crate).
+pub use m68k_test_macros::tests;
+
+/// Progress of the run, for the panic handler
+struct Progress {
+    current: Option<&'static str>,
+    passed: usize,
+    ignored: usize,
+}
+
+struct Shared(UnsafeCell<Progress>);
+
+// There is only one thread, and interrupt handlers don't touch it
+unsafe impl Sync for Shared {}
+
+static PROGRESS: Shared = Shared(UnsafeCell::new(Progress {
+    current: None,
+    passed: 0,
+    ignored: 0,
+}));
+
+/// Updates the progress; the borrow ends before anything that can panic runs
+fn progress<R>(f: impl FnOnce(&mut Progress) -> R) -> R {
+    f(unsafe { &mut *PROGRESS.0.get() })
+}
+
+/// The console the results are written to
+struct Output;
+
+#[cfg(feature = "semihosting")]
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        m68k_semihosting::hio::hstdout().write_str(s)
+    }
+}
+
+#[cfg(feature = "semihosting")]
+fn exit(code: i32) -> ! {
+    m68k_semihosting::exit(code)
+}
+
+#[cfg(all(feature = "monitor", not(feature = "semihosting")))]
+impl Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        m68k_monitor::puts(s);
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "monitor", not(feature = "semihosting")))]
+fn exit(code: i32) -> ! {
+    m68k_monitor::exit(code)
+}
+
+fn summary(result: &str, passed: usize, failed: usize, ignored: usize) {
+    let _ = writeln!(
+        Output,
+        "\ntest result: {}. {} passed; {} failed; {} ignored\n",
+        result, passed, failed, ignored
+    );
+}
+
+/// Used by the code `#[tests]` generates. Do not use directly.
+#[doc(hidden)]
+pub mod export {
+    use core::fmt::Write;
+
+    pub use m68k_rt::entry;
+
+    use super::{exit, progress, summary, Output};
+
+    /// An entry of the test table
+    pub struct Test<S: 'static> {
+        pub name: &'static str,
+        pub ignored: bool,
+        pub run: fn(&mut S),
+    }
+
+    #[inline]
+    pub fn start<S>(tests: &[Test<S>]) {
+        let plural = if tests.len() == 1 { "" } else { "s" };
+        let _ = writeln!(Output, "\nrunning {} test{}", tests.len(), plural);
+    }
+
+    #[inline]
+    pub fn run<S>(tests: &[Test<S>], state: &mut S) -> ! {
+        for test in tests {
+            let _ = write!(Output, "test {} ... ", test.name);
+            if test.ignored {
+                progress(|progress| progress.ignored += 1);
+                let _ = writeln!(Output, "ignored");
+                continue;
+            }
+            progress(|progress| progress.current = Some(test.name));
+            (test.run)(state);
+            progress(|progress| {
+                progress.current = None;
+                progress.passed += 1;
+            });
+            let _ = writeln!(Output, "ok");
+        }
+        let (passed, ignored) = progress(|progress| (progress.passed, progress.ignored));
+        summary("ok", passed, 0, ignored);
+        exit(0)
+    }
+}
+
+#[cfg(feature = "panic-handler")]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    let (current, passed, ignored) =
+        progress(|progress| (progress.current, progress.passed, progress.ignored));
+    match current {
+        Some(name) => {
+            let _ = writeln!(Output, "FAILED\n\n---- {} ----\n{}", name, info);
+            summary("FAILED", passed, 1, ignored);
+        }
+        // In `#[init]`, or before the tests started
+        None => {
+            let _ = writeln!(Output, "\n{}", info);
+        }
+    }
+    exit(101)
+}
@@ -0,0 +1,107 @@
+//! Backtraces along the chain of frame pointers
+//!
+//! Built with frame pointers (`-C force-frame-pointers=yes`, which `.cargo/config.toml` sets),
+//! every function starts with `link %a6, #n`. That pushes the caller's `%a6` and points `%a6` at
+//! it, so `%a6` heads a list of frames, each holding the caller's frame pointer followed by the
+//! return address into the caller.
+//!
+//! [`Backtrace`] walks the list and yields the return addresses, innermost first. It stops at a
+//! frame outside the stack or not above the previous one, and at a return address outside
+//! `.text`, such as into the reset handler. Code built without frame pointers thus cuts the
+//! backtrace short instead of sending it through random memory. The bounds are the `__stext` and
+//! `__etext`, `_stack_end` and `_stack_start` symbols of the `m68k-rt` linker scripts, which the
+//! program must be linked with.
+//!
+//! ```no_run
+//! use m68k::backtrace::Backtrace;
+//!
+//! for address in Backtrace::current() {
+//!     // Print `address`, to be symbolized on the host with the program's ELF file
+//! }
+//! ```
+
+use core::arch::asm;
+use core::ops::Range;
+use core::ptr;
+
+/// Return addresses along the chain of frame pointers
+///
+/// See the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Backtrace {
+    /// Where the exception was taken, yielded first
+    pc: Option<u32>,
+    /// The next frame to read
+    frame: Option<u32>,
+}
+
+impl Backtrace {
+    /// Walks from the function that calls this, starting with its return address
+    #[inline(always)]
+    pub fn current() -> Self {
+        let frame;
+        unsafe { asm!("move.l %a6, {}", out(reg) frame, options(nomem, nostack, preserves_flags)) };
+        Backtrace {
+            pc: None,
+            frame: Some(frame),
+        }
+    }
+
+    /// Walks from where an exception was taken, starting with `pc`
+    ///
+    /// `pc` is the program counter of the exception frame, e.g. `m68k_rt::ExceptionFrame::pc`,
+    /// and `frame_pointer` is the `%a6` of the code the exception interrupted, as saved by the
+    /// entry point of the handler. `pc` is yielded even if it is outside `.text`: it may be where
+    /// the program jumped into the weeds.
+    #[inline]
+    pub fn from_exception(pc: u32, frame_pointer: u32) -> Self {
+        Backtrace {
+            pc: Some(pc),
+            frame: Some(frame_pointer),
+        }
+    }
+}
+
+impl Iterator for Backtrace {
+    type Item = u32;
+
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        if let Some(pc) = self.pc.take() {
+            return Some(pc);
+        }
+        let frame = self.frame.take()?;
+        let (text, stack) = regions();
+        let in_stack = frame & 1 == 0
+            && stack.start <= frame
+            && frame.checked_add(8).is_some_and(|end| end <= stack.end);
+        if !in_stack {
+            return None;
+        }
+        let (caller, address) = unsafe {
+            let frame = frame as *const u32;
+            (ptr::read_volatile(frame), ptr::read_volatile(frame.add(1)))
+        };
+        if !text.contains(&address) {
+            return None;
+        }
+        // Callers' frames are further up the stack, which also ends a chain that loops
+        self.frame = (caller > frame).then_some(caller);
+        Some(address)
+    }
+}
+
+/// `.text` and the stack, from the linker script
+fn regions() -> (Range<u32>, Range<u32>) {
+    extern "C" {
+        static __stext: u8;
+        static __etext: u8;
+        static _stack_end: u8;
+        static _stack_start: u8;
+    }
+
+    (
+        ptr::addr_of!(__stext) as u32..ptr::addr_of!(__etext) as u32,
+        ptr::addr_of!(_stack_end) as u32..ptr::addr_of!(_stack_start) as u32,
+    )
+}
@@ -28,7 +28,8 @@ pub unsafe trait InterruptNumber: Copy {
 #[inline]
 pub fn disable() {
     unsafe {
-        asm!("ori.w #0x0700,%sr", options(nomem, nostack, preserves_flags));
+        // `ori.w #0x0700,%sr`, which the LLVM assembler doesn't accept yet
+        asm!(".short 0x007c, 0x0700", options(nomem, nostack, preserves_flags));
     }
     
     // Ensure no subsequent memory accesses are reordered to before interrupts are disabled.
@@ -42,17 +43,25 @@ pub fn disable() {
 /// - Do not call this function inside a critical section.
 #[inline]
 pub unsafe fn set(mask: u8) {
-    let mask_shifted: u16 = (mask as u16) << 8;
+    // Ensure no preceding memory accesses are reordered to after interrupts are enabled.
+    compiler_fence(Ordering::SeqCst);
+
+    let mask_shifted: u16 = ((mask as u16) & 0x07) << 8;
     asm!(
-        "andi.w #0xF8FF,%sr",
-        "ori.w {},%sr",
-        in(reg) mask_shifted,
+        "move.w %sr,{sr}",
+        "and.w #0xF8FF,{sr}",
+        "or.w {mask},{sr}",
+        "move.w {sr},%sr",
+        sr = out(reg_data) _,
+        mask = in(reg_data) mask_shifted,
+        options(nomem, nostack),
     );
 }
 
 /// Get the interrupt mask
-pub unsafe fn get() -> u8 {
+#[inline]
+pub fn get() -> u8 {
     let sr: u16;
-    asm!("move.w %sr,{}", out(reg) sr);
+    unsafe { asm!("move.w %sr,{}", out(reg) sr, options(nomem, nostack, preserves_flags)) };
     ((sr >> 8) & 0x07) as u8
 }
\ No newline at end of file
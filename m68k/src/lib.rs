@@ -13,11 +13,14 @@ mod macros;
 
 pub mod asm;
 
+pub mod backtrace;
+
 pub mod register;
 
-/*
 pub mod interrupt;
 
+pub mod panic;
+
 #[cfg(feature = "critical-section-single-core")]
 mod critical_section;
 
@@ -25,5 +28,4 @@ mod critical_section;
 #[doc(hidden)]
 pub mod _export {
     pub use critical_section;
-}
-*/
\ No newline at end of file
+}
\ No newline at end of file
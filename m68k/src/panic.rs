@@ -0,0 +1,85 @@
+//! Panic reports, for panic handlers
+//!
+//! [`report`] writes the location and message of a panic, the status register and a backtrace,
+//! so that every panic handler prints the same thing whatever it prints on:
+//!
+//! ```text
+//! panicked at src/main.rs:12:5:
+//! index out of bounds: the len is 3 but the index is 7
+//! SR: 0x2704
+//! backtrace:
+//!    0: 0x00001a2c
+//!    1: 0x00001b40
+//! ```
+//!
+//! The backtrace lists return addresses, innermost first, from [`crate::backtrace`]. It needs frame
+//! pointers (`-C force-frame-pointers=yes`); otherwise it is cut short.
+//!
+//! A panic handler only has to provide the writer, and to stop afterwards, e.g. with [`halt`]:
+//!
+//! ```no_run
+//! # #![no_std]
+//! # #![no_main]
+//! # mod console { pub struct Console; impl core::fmt::Write for Console {
+//! #     fn write_str(&mut self, _: &str) -> core::fmt::Result { Ok(()) } } }
+//! use core::panic::PanicInfo;
+//!
+//! #[panic_handler]
+//! fn panic(info: &PanicInfo) -> ! {
+//!     m68k::panic::report(info, || Some(console::Console));
+//!     m68k::panic::halt()
+//! }
+//! ```
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+use crate::backtrace::Backtrace;
+use crate::interrupt;
+use crate::register::sr::{self, Sr};
+
+static mut PANICKING: bool = false;
+
+/// Reports the panic on the writer `open` returns
+///
+/// The status register is read first, then every interrupt is masked, for good. Nothing is
+/// written if `open` returns `None`, or by a panic while the report is written.
+#[inline]
+pub fn report<W: Write>(info: &PanicInfo, open: impl FnOnce() -> Option<W>) {
+    let sr = sr::read();
+    interrupt::disable();
+    unsafe {
+        if *ptr::addr_of!(PANICKING) {
+            return;
+        }
+        *ptr::addr_of_mut!(PANICKING) = true;
+    }
+    if let Some(mut w) = open() {
+        let _ = write(&mut w, info, sr);
+    }
+}
+
+fn write(w: &mut dyn Write, info: &PanicInfo, sr: Sr) -> fmt::Result {
+    match info.location() {
+        Some(location) => writeln!(w, "panicked at {}:", location)?,
+        None => writeln!(w, "panicked:")?,
+    }
+    writeln!(w, "{}", info.message())?;
+    writeln!(w, "SR: 0x{:04x}", sr.bits())?;
+    writeln!(w, "backtrace:")?;
+    for (i, address) in Backtrace::current().enumerate() {
+        writeln!(w, "{:>4}: 0x{:08x}", i, address)?;
+    }
+    Ok(())
+}
+
+/// Halts the processor with `stop #0x2700`, with every interrupt masked
+#[inline]
+pub fn halt() -> ! {
+    loop {
+        // stop #0x2700, which the LLVM assembler doesn't accept yet
+        unsafe { asm!(".short 0x4e72, 0x2700", options(nomem, nostack)) };
+    }
+}
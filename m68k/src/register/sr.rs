@@ -23,6 +23,12 @@ impl Sr {
         }
     }
 
+    /// Returns the contents of the register as raw bits
+    #[inline]
+    pub fn bits(self) -> u16 {
+        (self.bits as u16) << 8 | self.ccr.bits() as u16
+    }
+
     /// Returns the Interrupt Mask value
     #[inline]
     pub fn i(self) -> u8 {
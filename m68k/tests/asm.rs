@@ -0,0 +1,22 @@
+//! `m68k::asm` on the processor
+
+#![no_std]
+#![no_main]
+
+#[m68k_test::tests]
+mod tests {
+    use m68k::asm;
+    use m68k::register::sr;
+
+    #[test]
+    fn nop() {
+        let before = sr::read();
+        for _ in 0..8 {
+            asm::nop();
+        }
+        let after = sr::read();
+        assert_eq!(after.i(), before.i());
+        assert_eq!(after.s(), before.s());
+        assert_eq!(after.t(), before.t());
+    }
+}
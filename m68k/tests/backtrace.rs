@@ -0,0 +1,61 @@
+//! `m68k::backtrace` on the processor, built with the frame pointers `.cargo/config.toml` turns on
+
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+
+use m68k::backtrace::Backtrace;
+
+#[inline(never)]
+fn callee() -> Option<u32> {
+    Backtrace::current().next()
+}
+
+/// Returns the return address into itself, and its own address
+#[inline(never)]
+fn caller() -> (Option<u32>, u32) {
+    // `black_box` keeps the call from becoming a jump
+    (black_box(callee()), caller as *const () as u32)
+}
+
+#[m68k_test::tests]
+mod tests {
+    use m68k::backtrace::Backtrace;
+
+    #[test]
+    fn current() {
+        let (address, caller) = super::caller();
+        let address = address.unwrap();
+        assert!(caller < address && address < caller + 0x40);
+    }
+
+    #[test]
+    fn exception() {
+        // Frames laid out by hand on the stack: the caller's frame pointer, then the return address
+        let text = super::caller as *const () as u32;
+        let mut frames = [0, text + 2, 0, text + 4];
+        frames[0] = &frames[2] as *const u32 as u32;
+        let frames = &frames as *const [u32; 4] as u32;
+
+        let mut backtrace = Backtrace::from_exception(0x1234, frames);
+        assert_eq!(backtrace.next(), Some(0x1234));
+        assert_eq!(backtrace.next(), Some(text + 2));
+        assert_eq!(backtrace.next(), Some(text + 4));
+        // The last frame's caller is 0, below it
+        assert_eq!(backtrace.next(), None);
+    }
+
+    #[test]
+    fn stops_outside_the_stack_and_text() {
+        // `pc` comes first regardless
+        let mut backtrace = Backtrace::from_exception(0, 0);
+        assert_eq!(backtrace.next(), Some(0));
+        assert_eq!(backtrace.next(), None);
+
+        // A return address below `.text`
+        let frames = [0u32, 0x0000_0002];
+        let frames = &frames as *const [u32; 2] as u32;
+        assert_eq!(Backtrace::from_exception(0, frames).nth(1), None);
+    }
+}
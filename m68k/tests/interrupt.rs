@@ -0,0 +1,52 @@
+//! `m68k::interrupt` on the processor
+//!
+//! Nothing requests interrupts on the board the tests run on, so lowering the mask is harmless.
+
+#![no_std]
+#![no_main]
+
+#[m68k_test::tests]
+mod tests {
+    use m68k::interrupt;
+    use m68k::register::sr;
+
+    #[test]
+    fn masked_after_reset() {
+        assert_eq!(interrupt::get(), 7);
+    }
+
+    #[test]
+    fn set_and_get() {
+        for mask in 0..8 {
+            unsafe { interrupt::set(mask) };
+            assert_eq!(interrupt::get(), mask);
+        }
+        interrupt::disable();
+    }
+
+    #[test]
+    fn set_keeps_the_rest_of_sr() {
+        unsafe { interrupt::set(0x0c) };
+        // Only the low three bits are the mask
+        assert_eq!(interrupt::get(), 4);
+        assert!(sr::read().s());
+        interrupt::disable();
+    }
+
+    #[test]
+    fn disable() {
+        unsafe { interrupt::set(1) };
+        interrupt::disable();
+        assert_eq!(interrupt::get(), 7);
+        assert!(sr::read().s());
+    }
+
+    #[test]
+    #[cfg(feature = "critical-section-single-core")]
+    fn critical_section_restores_the_mask() {
+        unsafe { interrupt::set(2) };
+        critical_section::with(|_| assert_eq!(interrupt::get(), 7));
+        assert_eq!(interrupt::get(), 2);
+        interrupt::disable();
+    }
+}
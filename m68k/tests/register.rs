@@ -0,0 +1,53 @@
+//! `m68k::register` on the processor
+
+#![no_std]
+#![no_main]
+
+#[m68k_test::tests]
+mod tests {
+    use m68k::interrupt;
+    use m68k::register::ccr::Ccr;
+    use m68k::register::sr::{self, Sr};
+
+    #[test]
+    fn sr_after_reset() {
+        // The reset handler runs in supervisor mode, with interrupts masked
+        let sr = sr::read();
+        assert!(sr.s());
+        assert!(!sr.t());
+        assert_eq!(sr.i(), 7);
+    }
+
+    #[test]
+    fn sr_from_bits() {
+        let sr = Sr::from_bits(0xa51f);
+        assert_eq!(sr.bits(), 0xa51f);
+        assert!(sr.t());
+        assert!(sr.s());
+        assert_eq!(sr.i(), 5);
+
+        let sr = Sr::from_bits(0x001f);
+        assert!(!sr.t());
+        assert!(!sr.s());
+        assert_eq!(sr.i(), 0);
+    }
+
+    #[test]
+    fn ccr_from_bits() {
+        let ccr = Ccr::from_bits(0x15);
+        assert_eq!(ccr.bits(), 0x15);
+        assert!(ccr.c());
+        assert!(!ccr.v());
+        assert!(ccr.z());
+        assert!(!ccr.n());
+        assert!(ccr.x());
+    }
+
+    #[test]
+    fn sr_follows_the_interrupt_mask() {
+        unsafe { interrupt::set(3) };
+        assert_eq!(sr::read().i(), 3);
+        interrupt::disable();
+        assert_eq!(sr::read().i(), 7);
+    }
+}
@@ -6,4 +6,7 @@ MEMORY
 
   /* More memory regions can declared: for example this is a second RAM region */
   /* CCRAM : ORIGIN = 0x10000000, LENGTH = 8K */
-}
\ No newline at end of file
+}
+
+/* Sections in other regions are placed with `SECTIONS { .. } INSERT AFTER .got;`
+   and initialized by adding entries to the init table. See `m68k_rt::region`. */
\ No newline at end of file
@@ -0,0 +1,33 @@
+//! Panic handler that reports on the host's standard error, through `m68k-semihosting`
+//!
+//! The report of [`m68k::panic`] goes to the standard error of the debugger or emulator serving
+//! the host calls, e.g. `m68k-emu --semihosting`. The processor then halts with
+//! [`m68k::panic::halt`], or restarts with [`m68k_rt::warm_reset`] with the `warm-reset` feature.
+//! `m68k-emu` ends the run with a failure when it halts.
+//!
+//! ```no_run
+//! # #![no_main]
+//! use m68k_rt::entry;
+//! use panic_m68k_semihosting as _;
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     panic!("reported on the host");
+//! }
+//! ```
+
+#![no_std]
+
+use core::panic::PanicInfo;
+
+#[cfg(not(feature = "warm-reset"))]
+use m68k::panic::halt as end;
+#[cfg(feature = "warm-reset")]
+use m68k_rt::warm_reset as end;
+use m68k_semihosting::hio;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    m68k::panic::report(info, || Some(hio::hstderr()));
+    end()
+}
@@ -0,0 +1,66 @@
+//! Panic handler that reports over a serial port
+//!
+//! The report of [`m68k::panic`] is written a byte at a time through the function passed to
+//! [`install`], usually the transmit routine of a polled UART driver. `\n` is sent as `\r\n`.
+//! The processor then halts with [`m68k::panic::halt`], or restarts with
+//! [`m68k_rt::warm_reset`] with the `warm-reset` feature.
+//!
+//! A panic before [`install`] is called halts without a report.
+//!
+//! ```no_run
+//! # #![no_main]
+//! # mod uart { pub fn write_byte(_: u8) {} }
+//! use m68k_rt::entry;
+//! use panic_m68k_serial as _;
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     unsafe { panic_m68k_serial::install(uart::write_byte) };
+//!     panic!("reported over the UART");
+//! }
+//! ```
+
+#![no_std]
+#![deny(clippy::missing_inline_in_public_items)]
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+
+#[cfg(not(feature = "warm-reset"))]
+use m68k::panic::halt as end;
+#[cfg(feature = "warm-reset")]
+use m68k_rt::warm_reset as end;
+
+static mut WRITE: Option<fn(u8)> = None;
+
+/// Makes `write` the function the report is sent through
+///
+/// # Safety
+///
+/// Must not be called while a panic is being reported, e.g. from an interrupt handler.
+#[inline]
+pub unsafe fn install(write: fn(u8)) {
+    *ptr::addr_of_mut!(WRITE) = Some(write);
+}
+
+/// Sends through the installed function, turning `\n` into `\r\n`
+struct Serial(fn(u8));
+
+impl Write for Serial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                (self.0)(b'\r');
+            }
+            (self.0)(byte);
+        }
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    m68k::panic::report(info, || unsafe { *ptr::addr_of!(WRITE) }.map(Serial));
+    end()
+}